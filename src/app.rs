@@ -4,8 +4,14 @@
 
 use crate::crypto;
 use crate::fsxg;
-use crate::metadata::PackageMetadata;
-use anyhow::{Context, Result};
+use crate::metadata::{
+    FILE_MODE_MASK, PackageMetadata, SIGNATURE_FILE, format_file_entry, parse_file_entry,
+};
+use crate::path::{ensure_within, get_keys_dir, to_manifest_path};
+use crate::version::{self, BumpLevel};
+use anyhow::{Context, Result, bail};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use serde::Serialize;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -45,11 +51,11 @@ pub fn init<P: AsRef<Path>>(package_path: P) -> Result<()> {
         metadata.author = "Unknown".to_string();
         metadata.r#type = "webapp".to_string();
         metadata.category = "utility".to_string();
-        metadata.entry = "index.html".to_string();
+        metadata.entry = "index.html".into();
 
         let metadata_json =
             serde_json::to_string_pretty(&metadata).with_context(|| "无法序列化元数据")?;
-        fs::write(&metadata_path, metadata_json)
+        fsxg::atomic_write(&metadata_path, metadata_json)
             .with_context(|| format!("无法写入元数据文件: {}", metadata_path.display()))?;
     }
 
@@ -70,44 +76,159 @@ pub fn init<P: AsRef<Path>>(package_path: P) -> Result<()> {
 /// # 参数
 /// - `package_id`: 应用包的唯一标识符
 /// - `base_dir`: 基础目录路径，新包将创建在此目录下
+/// - `force`: 目标目录已有 metadata.json 时用默认配置覆盖
 ///
 /// # 返回值
 /// 返回 Result<PathBuf, anyhow::Error>，成功时返回新创建的包路径，失败时返回错误
 ///
 /// # 流程
 /// 1. 构建新包的完整路径
-/// 2. 调用 init() 在新目录中初始化应用包
-pub fn new<S: AsRef<str>, P: AsRef<Path>>(package_id: S, base_dir: P) -> Result<PathBuf> {
+/// 2. 目标目录已有 metadata.json 时报错（`force` 为 true 时删除后重新生成）
+/// 3. 调用 init() 在新目录中初始化应用包
+pub fn new<S: AsRef<str>, P: AsRef<Path>>(
+    package_id: S,
+    base_dir: P,
+    force: bool,
+) -> Result<PathBuf> {
     let package_id = package_id.as_ref();
     let base_dir = base_dir.as_ref();
     let package_path = base_dir.join(package_id);
 
+    // 已存在的软件包不会被 init() 修改，直接报错以免误以为创建成功
+    let metadata_path = package_path.join("metadata.json");
+    if metadata_path.exists() {
+        if !force {
+            return Err(anyhow::anyhow!(
+                "软件包已存在: {}\n如需在现有目录中补全文件请使用 `pageos-pkgr app init`，或换用其它软件包ID；使用 --force 覆盖 metadata.json",
+                package_path.display()
+            ));
+        }
+        fs::remove_file(&metadata_path)
+            .with_context(|| format!("无法删除元数据文件: {}", metadata_path.display()))?;
+    }
+
     init(&package_path).with_context(|| format!("无法初始化新应用包: {package_id}"))?;
 
     Ok(package_path)
 }
 
-/// 添加文件到包清单
+/// 添加文件到包清单的结果
+///
+/// 各列表按处理顺序排列
+#[derive(Serialize, Debug, Default)]
+pub struct AddReport {
+    /// 清单中原本没有的文件
+    pub added: Vec<String>,
+    /// 哈希、大小或权限与已有记录不同的文件
+    pub updated: Vec<String>,
+    /// 与已有记录完全一致的文件
+    pub unchanged: Vec<String>,
+    /// 跳过的符号链接
+    pub skipped: Vec<PathBuf>,
+}
+
+impl AddReport {
+    /// 清单是否有变化
+    pub fn is_changed(&self) -> bool {
+        !self.added.is_empty() || !self.updated.is_empty()
+    }
+
+    /// 与已有记录比较后写入清单，记录文件属于哪一类
+    fn record(
+        &mut self,
+        metadata: &mut PackageMetadata,
+        path: String,
+        hash: &str,
+        size: u64,
+        mode: Option<u32>,
+    ) {
+        let entry = format_file_entry(hash, size);
+        match metadata.get_file_hash(&path) {
+            None => self.added.push(path.clone()),
+            Some(existing)
+                if crypto::hash_eq(existing, hash)
+                    && metadata.all_files[&path] == entry
+                    && metadata.get_file_mode(&path) == mode =>
+            {
+                self.unchanged.push(path);
+                return;
+            }
+            Some(_) => self.updated.push(path.clone()),
+        }
+        metadata.set_file_mode(&path, mode);
+        metadata.add_file(path, entry);
+    }
+}
+
+/// 递归添加目录时默认排除的文件（与 .pkgrignore 的模式语法相同，可在 .pkgrignore 中用 `!` 取消）
+const DEFAULT_ADD_EXCLUDES: &[&str] = &[
+    "/metadata.json",
+    "/metadata.sig",
+    ".git/",
+    ".gitignore",
+    ".pkgrignore",
+];
+
+/// 添加文件到包清单并报告哈希进度
 ///
-/// 将指定文件或目录（递归）添加到包的 metadata.json 文件的 all_files 字段中
+/// 将指定文件或目录（递归）添加到包的 metadata.json 文件的 all_files 字段中，
+/// 每计算完一个文件的哈希后调用 `progress`，参数为（已完成文件数, 文件总数, 已处理的累计字节数）。
 ///
 /// # 参数
 /// - `path`: 要添加的文件或目录路径
 /// - `package_path`: 应用包的根目录路径
 ///
 /// # 返回值
-/// 返回 Result<(), anyhow::Error>，成功时返回 Ok(())，失败时返回错误
+/// 返回 Result<AddReport, anyhow::Error>，成功时返回各文件的变化和跳过的符号链接，失败时返回错误
 ///
 /// # 流程
 /// 1. 读取现有的 metadata.json 文件
-/// 2. 对于文件：计算 SHA256 哈希值，添加到 all_files 映射中
-/// 3. 对于目录：递归遍历所有文件，计算每个文件的哈希值并添加
-/// 4. 保存更新后的 metadata.json 文件
-pub fn add_file<P: AsRef<Path>>(path: P, package_path: P) -> Result<()> {
+/// 2. 对于文件：计算 SHA256 哈希值，以 `哈希:大小` 的形式添加到 all_files 映射中
+/// 3. 对于目录：递归遍历所有文件（跳过匹配排除模式的文件），计算每个文件的哈希值和大小并添加
+/// 4. 与已有记录比较，分为新增、已更新和未变化的文件
+/// 5. 有新增或更新时保存 metadata.json 文件，否则不写入
+///
+/// 符号链接：`follow_symlinks` 为 `false` 时跳过所有符号链接（包括 `path` 本身），不写入清单，
+/// 在返回值中列出；为 `true` 时跟随符号链接，目标必须位于包目录之内，否则报错，
+/// 清单以链接所在的路径记录目标文件的内容（加入仓库和安装后均为普通文件）。
+///
+/// 排除：递归添加目录时跳过匹配 [`DEFAULT_ADD_EXCLUDES`]、包目录下 .pkgrignore 或 `exclude`
+/// 中模式的文件和符号链接（模式语法见 [`build_ignore`]）；直接指定的单个文件不受影响
+pub fn add_file_with_progress<P: AsRef<Path>>(
+    path: P,
+    package_path: P,
+    follow_symlinks: bool,
+    exclude: &[String],
+    mut progress: impl FnMut(usize, usize, u64),
+) -> Result<AddReport> {
     let path = path.as_ref();
     let package_path = package_path.as_ref();
-    let abs_path =
-        fs::canonicalize(path).with_context(|| format!("无法解析路径: {}", path.display()))?;
+    let is_symlink = fs::symlink_metadata(path)
+        .with_context(|| format!("无法解析路径: {}", path.display()))?
+        .file_type()
+        .is_symlink();
+    if is_symlink && !follow_symlinks {
+        return Ok(AddReport {
+            skipped: vec![path.to_path_buf()],
+            ..AddReport::default()
+        });
+    }
+
+    // 符号链接只解析其所在目录，保留链接本身的路径
+    let abs_path = if is_symlink {
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let file_name = path
+            .file_name()
+            .with_context(|| format!("无法解析路径: {}", path.display()))?;
+        fs::canonicalize(parent)
+            .with_context(|| format!("无法解析路径: {}", path.display()))?
+            .join(file_name)
+    } else {
+        fs::canonicalize(path).with_context(|| format!("无法解析路径: {}", path.display()))?
+    };
 
     // 读取现有的元数据
     let metadata_path = package_path.join("metadata.json");
@@ -135,25 +256,73 @@ pub fn add_file<P: AsRef<Path>>(path: P, package_path: P) -> Result<()> {
         .with_context(|| "无法计算相对于包目录的路径")?
         .to_path_buf();
 
-    if path.is_file() {
+    let mut report = AddReport::default();
+    if abs_path.is_file() {
         // 处理单个文件
-        let hash = crypto::file_hash(path.to_str().unwrap())
+        ensure_within(&package_abs_path, &abs_path)?;
+        let hash = crypto::file_hash(&abs_path)
             .with_context(|| format!("无法计算文件哈希: {}", path.display()))?;
-        let relative_path_str = relative_path.to_string_lossy().replace("\\", "/");
-        metadata.add_file(relative_path_str.to_string(), hash);
-    } else if path.is_dir() {
+        let size = fs::metadata(&abs_path)?.len();
+        let relative_path_str = to_manifest_path(&relative_path)?;
+        report.record(
+            &mut metadata,
+            relative_path_str.to_string(),
+            &hash,
+            size,
+            executable_mode(&abs_path)?,
+        );
+        progress(1, 1, size);
+    } else if abs_path.is_dir() {
         // 处理目录，递归添加所有文件
-        let files = fsxg::get_directory_files(path, true)
+        let (files, symlinks) = fsxg::walk_files(&abs_path, follow_symlinks)
             .with_context(|| format!("无法获取目录文件: {}", path.display()))?;
 
+        let ignore = build_ignore(&package_abs_path, DEFAULT_ADD_EXCLUDES, exclude)?;
+        let is_excluded = |file_path: &Path| -> Result<bool> {
+            let file_relative_path = file_path
+                .strip_prefix(&package_abs_path)
+                .with_context(|| "无法计算相对于包目录的路径")?;
+            let excluded = is_ignored(&ignore, file_relative_path);
+            if excluded {
+                log::debug!("已排除: {}", file_relative_path.display());
+            }
+            Ok(excluded)
+        };
+        let mut files_to_add = Vec::new();
         for file_path in files {
-            let hash = crypto::file_hash(file_path.to_str().unwrap())
-                .with_context(|| format!("无法计算文件哈希: {}", file_path.display()))?;
+            if !is_excluded(&file_path)? {
+                files_to_add.push(file_path);
+            }
+        }
+        for symlink in symlinks {
+            if !is_excluded(&symlink)? {
+                report.skipped.push(symlink);
+            }
+        }
+        let files = files_to_add;
+
+        let total = files.len();
+        let mut hashed_bytes = 0;
+        for (done, file_path) in files.into_iter().enumerate() {
+            if follow_symlinks {
+                ensure_within(&package_abs_path, &file_path)?;
+            }
             let file_relative_path = file_path
                 .strip_prefix(&package_abs_path)
                 .with_context(|| "无法计算相对于包目录的路径")?;
-            let relative_path_str = file_relative_path.to_string_lossy().replace("\\", "/");
-            metadata.add_file(relative_path_str.to_string(), hash);
+            let relative_path_str = to_manifest_path(file_relative_path)?;
+            let hash = crypto::file_hash(&file_path)
+                .with_context(|| format!("无法计算文件哈希: {}", file_path.display()))?;
+            let size = fs::metadata(&file_path)?.len();
+            hashed_bytes += size;
+            progress(done + 1, total, hashed_bytes);
+            report.record(
+                &mut metadata,
+                relative_path_str.to_string(),
+                &hash,
+                size,
+                executable_mode(&file_path)?,
+            );
         }
     } else {
         return Err(anyhow::anyhow!(
@@ -162,13 +331,22 @@ pub fn add_file<P: AsRef<Path>>(path: P, package_path: P) -> Result<()> {
         ));
     }
 
-    // 保存更新后的元数据
-    let metadata_json =
-        serde_json::to_string_pretty(&metadata).with_context(|| "无法序列化元数据")?;
-    fs::write(&metadata_path, metadata_json)
-        .with_context(|| format!("无法写入元数据文件: {}", metadata_path.display()))?;
+    // 清单有变化时才保存更新后的元数据
+    if report.is_changed() {
+        let metadata_json =
+            serde_json::to_string_pretty(&metadata).with_context(|| "无法序列化元数据")?;
+        fsxg::atomic_write(&metadata_path, metadata_json)
+            .with_context(|| format!("无法写入元数据文件: {}", metadata_path.display()))?;
+    }
 
-    Ok(())
+    Ok(report)
+}
+
+/// 可执行文件的权限位（只保留安装时会应用的位），不可执行的文件（及非 Unix 平台）返回 `None`
+fn executable_mode(path: &Path) -> Result<Option<u32>> {
+    Ok(fsxg::file_mode(path)?
+        .filter(|mode| mode & 0o111 != 0)
+        .map(|mode| mode & FILE_MODE_MASK))
 }
 
 /// 从包清单移除文件
@@ -221,7 +399,7 @@ pub fn remove_file<P: AsRef<Path>>(path: P, package_path: P) -> Result<()> {
 
     if path.is_file() {
         // 处理单个文件
-        let relative_path_str = relative_path.to_string_lossy().replace("\\", "/");
+        let relative_path_str = to_manifest_path(&relative_path)?;
         metadata.remove_file(&relative_path_str);
     } else if path.is_dir() {
         // 处理目录，递归移除所有文件的条目
@@ -232,7 +410,7 @@ pub fn remove_file<P: AsRef<Path>>(path: P, package_path: P) -> Result<()> {
             let file_relative_path = file_path
                 .strip_prefix(&package_abs_path)
                 .with_context(|| "无法计算相对于包目录的路径")?;
-            let relative_path_str = file_relative_path.to_string_lossy().replace("\\", "/");
+            let relative_path_str = to_manifest_path(file_relative_path)?;
             metadata.remove_file(&relative_path_str);
         }
     } else {
@@ -245,14 +423,452 @@ pub fn remove_file<P: AsRef<Path>>(path: P, package_path: P) -> Result<()> {
     // 保存更新后的元数据
     let metadata_json =
         serde_json::to_string_pretty(&metadata).with_context(|| "无法序列化元数据")?;
-    fs::write(&metadata_path, metadata_json)
+    fsxg::atomic_write(&metadata_path, metadata_json)
         .with_context(|| format!("无法写入元数据文件: {}", metadata_path.display()))?;
 
     Ok(())
 }
 
+/// 软件包清单状态
+///
+/// 各列表均按路径排序
+#[derive(Serialize, Debug, Default)]
+pub struct PackageStatus {
+    /// 已跟踪但哈希已变化的文件
+    pub modified: Vec<String>,
+    /// 已跟踪但已不存在的文件
+    pub missing: Vec<String>,
+    /// 存在于目录中但未被跟踪的文件
+    pub untracked: Vec<String>,
+}
+
+impl PackageStatus {
+    /// 清单与工作目录是否一致
+    pub fn is_clean(&self) -> bool {
+        self.modified.is_empty() && self.missing.is_empty() && self.untracked.is_empty()
+    }
+}
+
+/// 比较包清单与工作目录
+///
+/// 类似 `git status`，对比 metadata.json 的 all_files 与包目录中的实际文件
+///
+/// # 参数
+/// - `package_path`: 应用包的根目录路径
+///
+/// # 返回值
+/// 返回 Result<PackageStatus, anyhow::Error>，成功时返回清单状态，失败时返回错误
+///
+/// # 流程
+/// 1. 读取现有的 metadata.json 文件
+/// 2. 对已跟踪文件：检查是否存在，并比较 SHA256 哈希值
+/// 3. 遍历包目录，找出未被跟踪的文件（忽略 metadata.json、点开头的文件和目录、
+///    target 目录以及 .pkgrignore 中列出的模式）
+pub fn status<P: AsRef<Path>>(package_path: P) -> Result<PackageStatus> {
+    let package_path = package_path.as_ref();
+    metadata_status(&load_metadata(package_path)?, package_path)
+}
+
+/// 对已读取的元数据运行 [`status`] 的比较
+fn metadata_status(metadata: &PackageMetadata, package_path: &Path) -> Result<PackageStatus> {
+    // 获取包的根目录的绝对路径
+    let package_abs_path = fs::canonicalize(package_path)
+        .with_context(|| format!("无法解析包路径: {}", package_path.display()))?;
+
+    let mut status = PackageStatus::default();
+
+    // 检查已跟踪文件（metadata.json 随清单变化，不比较其哈希）
+    for (file_path, entry) in &metadata.all_files {
+        if file_path == "metadata.json" {
+            continue;
+        }
+
+        let abs_path = package_abs_path.join(file_path);
+        if !abs_path.is_file() {
+            status.missing.push(file_path.clone());
+            continue;
+        }
+
+        let (expected_hash, _) = parse_file_entry(entry);
+        let actual_hash = crypto::file_hash_like(&abs_path, expected_hash)
+            .with_context(|| format!("无法计算文件哈希: {}", abs_path.display()))?;
+        if !crypto::hash_eq(&actual_hash, expected_hash) {
+            status.modified.push(file_path.clone());
+        }
+    }
+
+    // 查找未跟踪文件
+    let ignore = build_ignore(&package_abs_path, &[], &[])?;
+    let files = fsxg::get_directory_files(&package_abs_path, true)
+        .with_context(|| format!("无法获取目录文件: {}", package_abs_path.display()))?;
+    for file_path in files {
+        let file_relative_path = file_path
+            .strip_prefix(&package_abs_path)
+            .with_context(|| "无法计算相对于包目录的路径")?;
+        let relative_path_str = to_manifest_path(file_relative_path)?;
+
+        if relative_path_str == "metadata.json"
+            || relative_path_str == SIGNATURE_FILE
+            || relative_path_str.starts_with("target/")
+            || relative_path_str
+                .split('/')
+                .any(|part| part.starts_with('.'))
+            || is_ignored(&ignore, file_relative_path)
+        {
+            continue;
+        }
+
+        if !metadata.has_file(&relative_path_str) {
+            status.untracked.push(relative_path_str);
+        }
+    }
+
+    status.modified.sort();
+    status.missing.sort();
+    status.untracked.sort();
+
+    Ok(status)
+}
+
+/// 检查软件包的一致性
+///
+/// 目前检查 metadata.json 中的 `id` 是否与软件包目录名一致：
+/// `repo add` 以 `metadata.id` 作为仓库中的目录名，目录改名后若未同步修改 `id`，
+/// 软件包会以旧 ID 加入仓库，与目录名不符
+///
+/// # 参数
+/// - `package_path`: 应用包的根目录路径
+///
+/// # 返回值
+/// 返回 Result<Vec<String>, anyhow::Error>，成功时返回发现的问题（为空表示检查通过），失败时返回错误
+pub fn validate<P: AsRef<Path>>(package_path: P) -> Result<Vec<String>> {
+    let package_path = package_path.as_ref();
+    validate_metadata(&load_metadata(package_path)?, package_path)
+}
+
+/// 对已读取的元数据运行 [`validate`] 的检查
+fn validate_metadata(metadata: &PackageMetadata, package_path: &Path) -> Result<Vec<String>> {
+    // 解析为绝对路径，以便 `.` 等路径也能取得目录名
+    let package_abs_path = fs::canonicalize(package_path)
+        .with_context(|| format!("无法解析包路径: {}", package_path.display()))?;
+    let dir_name = package_abs_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let mut issues = Vec::new();
+    if metadata.id.is_empty() {
+        issues
+            .push("metadata.json 中的 id 为空，repo add 无法确定软件包在仓库中的路径".to_string());
+    } else if metadata.id != dir_name {
+        issues.push(format!(
+            "metadata.json 中的 id \"{}\" 与目录名 \"{}\" 不一致，repo add 将以 packages/{}/ 存放该软件包；\
+             如果目录是改名而来，请同步修改 id",
+            metadata.id, dir_name, metadata.id
+        ));
+    }
+
+    Ok(issues)
+}
+
+/// 软件包检查结果
+#[derive(Serialize, Debug, Default)]
+pub struct PackageCheck {
+    /// 文件清单中的文件数
+    pub files: usize,
+    /// 元数据问题（见 `PackageMetadata::problems`）
+    pub problems: Vec<String>,
+    /// [`validate`] 发现的问题（如 id 与目录名不一致），为警告
+    pub issues: Vec<String>,
+    /// 清单与工作目录的比较结果：已修改和已缺失的文件为错误，未跟踪的文件为警告
+    pub status: PackageStatus,
+}
+
+impl PackageCheck {
+    /// 错误数（元数据问题、哈希不匹配和缺失的文件）
+    pub fn error_count(&self) -> usize {
+        self.problems.len() + self.status.modified.len() + self.status.missing.len()
+    }
+
+    /// 警告数（[`validate`] 发现的问题和未跟踪的文件）
+    pub fn warning_count(&self) -> usize {
+        self.issues.len() + self.status.untracked.len()
+    }
+}
+
+/// 检查软件包能否加入仓库
+///
+/// 运行与 `repo add` 相同的元数据校验和 [`validate`] 的检查，重新计算文件清单中每个文件的哈希，
+/// 并找出未加入清单的文件，便于在发布前发现问题，无需网络
+///
+/// # 参数
+/// - `package_path`: 应用包的根目录路径
+///
+/// # 返回值
+/// 返回 Result<PackageCheck, anyhow::Error>，成功时返回检查结果，无法读取软件包时返回错误
+pub fn check<P: AsRef<Path>>(package_path: P) -> Result<PackageCheck> {
+    let package_path = package_path.as_ref();
+    let metadata = load_metadata(package_path)?;
+
+    Ok(PackageCheck {
+        files: metadata.all_files.len(),
+        problems: metadata.problems(package_path),
+        issues: validate_metadata(&metadata, package_path)?,
+        status: metadata_status(&metadata, package_path)?,
+    })
+}
+
+/// 对软件包签名
+///
+/// 用 Ed25519 私钥对 metadata.json 的元数据哈希签名，写入 metadata.json 的 `signature` 字段，
+/// 并在同一目录写入分离签名文件 metadata.sig。
+/// 签名覆盖文件清单中的哈希，之后修改软件包（如 `app add`）需要重新签名
+///
+/// # 参数
+/// - `package_path`: 应用包的根目录路径
+/// - `secret_key`: 32 字节的 Ed25519 私钥种子
+///
+/// # 返回值
+/// 返回 Result<Vec<u8>, anyhow::Error>，成功时返回对应的公钥（供软件源配置为 `trusted_key`），失败时返回错误
+pub fn sign<P: AsRef<Path>>(package_path: P, secret_key: &[u8]) -> Result<Vec<u8>> {
+    let metadata_path = package_path.as_ref().join("metadata.json");
+    let mut metadata = load_metadata(package_path.as_ref())?;
+
+    metadata.sign(secret_key)?;
+
+    let metadata_json =
+        serde_json::to_string_pretty(&metadata).with_context(|| "无法序列化元数据")?;
+    fsxg::atomic_write(&metadata_path, metadata_json)
+        .with_context(|| format!("无法写入元数据文件: {}", metadata_path.display()))?;
+
+    let signature_path = package_path.as_ref().join(SIGNATURE_FILE);
+    let signature = metadata.signature.unwrap_or_default();
+    fsxg::atomic_write(&signature_path, format!("{signature}\n"))
+        .with_context(|| format!("无法写入签名文件: {}", signature_path.display()))?;
+
+    crypto::public_key(secret_key)
+}
+
+/// 递增软件包版本号
+///
+/// 按语义化版本解析 `version`，递增指定部分并将低位归零，预发布标识和构建元数据一并清空。
+/// 版本号会改变元数据哈希，已有签名随之失效
+///
+/// # 参数
+/// - `package_path`: 应用包的根目录路径
+/// - `level`: 要递增的版本号部分
+///
+/// # 返回值
+/// 返回 Result<(String, String), anyhow::Error>，成功时返回原版本号和新版本号，
+/// 当前版本号不是有效的语义化版本时返回错误
+pub fn bump<P: AsRef<Path>>(package_path: P, level: BumpLevel) -> Result<(String, String)> {
+    let mut versions = (String::new(), String::new());
+    update_metadata(package_path.as_ref(), |metadata| {
+        let current = version::parse(&metadata.version).with_context(|| {
+            format!(
+                "当前版本 \"{}\" 不是有效的语义化版本，请先手动修改 metadata.json",
+                metadata.version
+            )
+        })?;
+        let new_version = current.bump(level).to_string();
+        let old_version = std::mem::replace(&mut metadata.version, new_version.clone());
+        versions = (old_version, new_version);
+        Ok(())
+    })?;
+    Ok(versions)
+}
+
+/// 添加或更新软件包的依赖
+///
+/// # 参数
+/// - `package_path`: 软件包路径
+/// - `id`: 依赖的软件包ID
+/// - `version_req`: 版本约束，如 `^1.2.0`、`>=1.0, <2.0` 或 `*`
+///
+/// # 返回值
+/// 返回 Result<(), anyhow::Error>，软件包ID不合法或版本约束无法解析时返回错误
+pub fn add_dependency<P: AsRef<Path>>(package_path: P, id: &str, version_req: &str) -> Result<()> {
+    update_metadata(package_path.as_ref(), |metadata| {
+        metadata.add_dependency(id.to_string(), version_req.to_string())
+    })
+}
+
+/// 移除软件包的依赖
+///
+/// # 返回值
+/// 返回 Result<(), anyhow::Error>，软件包没有该依赖时返回错误
+pub fn remove_dependency<P: AsRef<Path>>(package_path: P, id: &str) -> Result<()> {
+    update_metadata(package_path.as_ref(), |metadata| {
+        metadata
+            .remove_dependency(id)
+            .map(|_| ())
+            .ok_or_else(|| anyhow::anyhow!("软件包没有依赖 {id}"))
+    })
+}
+
+/// 读取并解析软件包目录中的 metadata.json
+fn load_metadata(package_path: &Path) -> Result<PackageMetadata> {
+    let metadata_path = package_path.join("metadata.json");
+    let metadata_content = fs::read_to_string(&metadata_path)
+        .with_context(|| format!("无法读取元数据文件: {}", metadata_path.display()))?;
+    serde_json::from_str(&metadata_content).with_context(|| "无法解析元数据 JSON")
+}
+
+/// 读取软件包的 metadata.json，修改成功后写回
+fn update_metadata(
+    package_path: &Path,
+    update: impl FnOnce(&mut PackageMetadata) -> Result<()>,
+) -> Result<()> {
+    let metadata_path = package_path.join("metadata.json");
+    let mut metadata = load_metadata(package_path)?;
+
+    update(&mut metadata)?;
+
+    let metadata_json =
+        serde_json::to_string_pretty(&metadata).with_context(|| "无法序列化元数据")?;
+    fsxg::atomic_write(&metadata_path, metadata_json)
+        .with_context(|| format!("无法写入元数据文件: {}", metadata_path.display()))
+}
+
+/// 解析私钥位置
+///
+/// `key` 为已存在的文件路径时直接使用，否则视为密钥名，对应密钥目录下的 `<key>.key`
+pub fn key_path(key: &str) -> PathBuf {
+    let path = PathBuf::from(key);
+    if path.is_file() {
+        path
+    } else {
+        get_keys_dir().join(format!("{key}.key"))
+    }
+}
+
+/// 读取私钥文件（内容为 32 字节私钥种子的十六进制）
+pub fn load_secret_key(key: &str) -> Result<Vec<u8>> {
+    let path = key_path(key);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("无法读取私钥文件: {}", path.display()))?;
+    crypto::hex_decode(&content)
+}
+
+/// 生成新的签名密钥
+///
+/// 在密钥目录下写入私钥 `<name>.key`（仅所有者可读写）和公钥 `<name>.pub`，均为十六进制文本。
+///
+/// # 参数
+/// - `keys_dir`: 密钥目录
+/// - `name`: 密钥名
+/// - `force`: 密钥已存在时是否覆盖
+///
+/// # 返回值
+/// 返回 Result<Vec<u8>, anyhow::Error>，成功时返回公钥，失败时返回错误
+pub fn keygen(keys_dir: &Path, name: &str, force: bool) -> Result<Vec<u8>> {
+    let secret_path = keys_dir.join(format!("{name}.key"));
+    if secret_path.exists() && !force {
+        bail!("密钥已存在: {}（使用 --force 覆盖）", secret_path.display());
+    }
+    fs::create_dir_all(keys_dir)
+        .with_context(|| format!("无法创建密钥目录: {}", keys_dir.display()))?;
+
+    let secret_key = crypto::generate_secret_key()?;
+    let public_key = crypto::public_key(&secret_key)?;
+    fsxg::atomic_write_private(
+        &secret_path,
+        format!("{}\n", crypto::hex_encode(&secret_key)),
+    )
+    .with_context(|| format!("无法写入私钥文件: {}", secret_path.display()))?;
+    let public_path = keys_dir.join(format!("{name}.pub"));
+    fsxg::atomic_write(
+        &public_path,
+        format!("{}\n", crypto::hex_encode(&public_key)),
+    )
+    .with_context(|| format!("无法写入公钥文件: {}", public_path.display()))?;
+
+    Ok(public_key)
+}
+
+/// 构建忽略规则
+///
+/// 依次加入 `defaults`、包目录下 .pkgrignore 和 `extra` 中的模式，语法与 .gitignore 相同，后加入的模式优先：
+/// - 以 `/` 结尾的模式只匹配目录（及其下所有文件）
+/// - 以 `/` 开头或中间含 `/` 的模式匹配相对于包目录的路径，其余模式匹配路径中的任一部分
+/// - 支持 `*`、`?`、`[...]` 和 `**`（任意层目录）通配符
+/// - 以 `!` 开头的模式取消之前的排除；.pkgrignore 中的空行和以 `#` 开头的注释行被忽略
+fn build_ignore(package_path: &Path, defaults: &[&str], extra: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(package_path);
+    for pattern in defaults {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("无效的忽略模式: {pattern}"))?;
+    }
+    let ignore_path = package_path.join(".pkgrignore");
+    if ignore_path.is_file()
+        && let Some(e) = builder.add(&ignore_path)
+    {
+        return Err(anyhow::anyhow!(
+            "无法读取忽略文件 {}: {}",
+            ignore_path.display(),
+            e
+        ));
+    }
+    for pattern in extra {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("无效的忽略模式: {pattern}"))?;
+    }
+    builder.build().with_context(|| "无法构建忽略规则")
+}
+
+/// 判断相对于包目录的文件路径是否被忽略（自身或任一上级目录匹配）
+fn is_ignored(ignore: &Gitignore, relative_path: &Path) -> bool {
+    ignore
+        .matched_path_or_any_parents(relative_path, false)
+        .is_ignore()
+}
+
+/// 简单通配符匹配（`*` 与 `?` 不匹配 `/`）
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == text[t] || (pattern[p] == '?' && text[t] != '/')) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack
+            && text[star_t] != '/'
+        {
+            backtrack = Some((star_p, star_t + 1));
+            p = star_p + 1;
+            t = star_t + 1;
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// 测试用的添加文件入口
+#[cfg(test)]
+pub mod testing {
+    use super::{AddReport, add_file_with_progress};
+    use anyhow::Result;
+    use std::path::Path;
+
+    /// 以默认选项（不跟随符号链接、无额外排除模式、不报告进度）添加文件到包清单
+    pub fn add_file<P: AsRef<Path>>(path: P, package_path: P) -> Result<AddReport> {
+        add_file_with_progress(path, package_path, false, &[], |_, _, _| {})
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::testing::add_file;
     use super::*;
     use std::fs::File;
     use std::io::Write;
@@ -286,6 +902,26 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_new_existing_package() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = new("test-app", temp_dir.path(), false)?;
+        assert!(package_path.join("metadata.json").exists());
+
+        // 修改元数据后再次创建应报错且不改动现有文件
+        let metadata_path = package_path.join("metadata.json");
+        fs::write(&metadata_path, "{}")?;
+        assert!(new("test-app", temp_dir.path(), false).is_err());
+        assert_eq!(fs::read_to_string(&metadata_path)?, "{}");
+
+        // --force 时重新生成默认元数据
+        new("test-app", temp_dir.path(), true)?;
+        let metadata: PackageMetadata = serde_json::from_str(&fs::read_to_string(&metadata_path)?)?;
+        assert_eq!(metadata.id, "test-app");
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_and_remove_file() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -305,6 +941,10 @@ mod tests {
         let metadata_content = std::fs::read_to_string(package_path.join("metadata.json"))?;
         let metadata: PackageMetadata = serde_json::from_str(&metadata_content)?;
         assert!(metadata.has_file("test.txt"));
+        assert_eq!(
+            parse_file_entry(&metadata.all_files["test.txt"]).1,
+            Some(13)
+        );
 
         // 移除文件
         remove_file(&test_file, &package_path)?;
@@ -351,4 +991,440 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_is_ignored() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        create_test_file(
+            temp_dir.path().join(".pkgrignore"),
+            "# 日志\n*.log\n!keep.log\nnode_modules/\ndocs/draft-?.md\nassets/**/*.psd\n",
+        )?;
+        let ignore = build_ignore(temp_dir.path(), DEFAULT_ADD_EXCLUDES, &[])?;
+        let ignored = |path: &str| is_ignored(&ignore, Path::new(path));
+
+        assert!(ignored("debug.log"));
+        assert!(ignored("logs/debug.log"));
+        assert!(ignored("node_modules/lib/index.js"));
+        assert!(ignored("docs/draft-1.md"));
+        assert!(!ignored("docs/draft-10.md"));
+        assert!(!ignored("index.html"));
+        assert!(!ignored("node_modules"));
+        // `**` 匹配任意层目录，`!` 取消之前的排除
+        assert!(ignored("assets/top.psd"));
+        assert!(ignored("assets/a/b/c.psd"));
+        assert!(!ignored("other/c.psd"));
+        assert!(!ignored("logs/keep.log"));
+        // 默认排除仓库目录和根目录的元数据
+        assert!(ignored(".git/HEAD"));
+        assert!(ignored("metadata.json"));
+        assert!(!ignored("docs/metadata.json"));
+
+        // 后加入的 `--exclude` 优先于 .pkgrignore
+        let ignore = build_ignore(temp_dir.path(), &[], &["!debug.log".to_string()])?;
+        assert!(!is_ignored(&ignore, Path::new("debug.log")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_status() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = temp_dir.path().join("test-app");
+
+        // 初始化包并添加文件
+        init(&package_path)?;
+        create_test_file(package_path.join("index.html"), "index")?;
+        create_test_file(package_path.join("app.js"), "app")?;
+        create_test_file(package_path.join("style.css"), "style")?;
+        for file in ["index.html", "app.js", "style.css"] {
+            add_file(&package_path.join(file), &package_path)?;
+        }
+
+        // 初始状态一致（.gitignore 等点文件不计入未跟踪文件）
+        assert!(status(&package_path)?.is_clean());
+
+        // 修改、删除、新增和忽略文件
+        create_test_file(package_path.join("app.js"), "app v2")?;
+        fs::remove_file(package_path.join("style.css"))?;
+        create_test_file(package_path.join("new.js"), "new")?;
+        create_test_file(package_path.join("debug.log"), "log")?;
+        create_test_file(package_path.join(".pkgrignore"), "# 日志\n*.log\n")?;
+
+        let status = status(&package_path)?;
+        assert_eq!(status.modified, vec!["app.js".to_string()]);
+        assert_eq!(status.missing, vec!["style.css".to_string()]);
+        assert_eq!(status.untracked, vec!["new.js".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = new("test-app", temp_dir.path(), false)?;
+        create_test_file(package_path.join("index.html"), "index")?;
+        create_test_file(package_path.join("app.js"), "app")?;
+        add_file(&package_path.join("index.html"), &package_path)?;
+        add_file(&package_path.join("app.js"), &package_path)?;
+
+        let result = check(&package_path)?;
+        assert_eq!(result.files, 2);
+        assert_eq!(result.error_count(), 0);
+
+        // 未跟踪文件只是警告；缺失文件同时是元数据问题（入口不存在）和清单错误
+        create_test_file(package_path.join("extra.js"), "extra")?;
+        create_test_file(package_path.join("app.js"), "app v2")?;
+        fs::remove_file(package_path.join("index.html"))?;
+        let result = check(&package_path)?;
+        assert_eq!(result.problems, vec!["文件不存在: index.html".to_string()]);
+        assert_eq!(result.status.modified, vec!["app.js".to_string()]);
+        assert_eq!(result.status.missing, vec!["index.html".to_string()]);
+        assert_eq!(result.status.untracked, vec!["extra.js".to_string()]);
+        assert_eq!(result.error_count(), 3);
+        assert_eq!(result.warning_count(), 1);
+
+        // 与 app validate 相同检查 id 与目录名是否一致，结果为警告
+        let renamed_path = temp_dir.path().join("renamed-app");
+        fs::rename(&package_path, &renamed_path)?;
+        let result = check(&renamed_path)?;
+        assert_eq!(result.issues, validate(&renamed_path)?);
+        assert_eq!(result.issues.len(), 1);
+        assert_eq!(result.warning_count(), 2);
+        assert_eq!(result.error_count(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_id_matches_directory() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = new("test-app", temp_dir.path(), false)?;
+        assert!(validate(&package_path)?.is_empty());
+
+        // 目录改名后 id 不再一致
+        let renamed_path = temp_dir.path().join("renamed-app");
+        fs::rename(&package_path, &renamed_path)?;
+        let issues = validate(&renamed_path)?;
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].contains("test-app") && issues[0].contains("renamed-app"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bump() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = new("test-app", temp_dir.path(), false)?;
+        let load = || -> Result<PackageMetadata> {
+            Ok(serde_json::from_str(&fs::read_to_string(
+                package_path.join("metadata.json"),
+            )?)?)
+        };
+
+        assert_eq!(
+            bump(&package_path, BumpLevel::Patch)?,
+            ("0.0.0".to_string(), "0.0.1".to_string())
+        );
+        assert_eq!(bump(&package_path, BumpLevel::Minor)?.1, "0.1.0");
+        assert_eq!(bump(&package_path, BumpLevel::Major)?.1, "1.0.0");
+        assert_eq!(load()?.version, "1.0.0");
+
+        // 无法解析的版本号不做猜测
+        let mut metadata = load()?;
+        metadata.version = "v1".to_string();
+        fs::write(
+            package_path.join("metadata.json"),
+            serde_json::to_string_pretty(&metadata)?,
+        )?;
+        let error = format!("{:#}", bump(&package_path, BumpLevel::Patch).unwrap_err());
+        assert!(error.contains("不是有效的语义化版本"));
+        assert!(error.contains("不是数字"));
+        assert_eq!(load()?.version, "v1");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dependency_commands() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = new("test-app", temp_dir.path(), false)?;
+        let load = || -> Result<PackageMetadata> {
+            Ok(serde_json::from_str(&fs::read_to_string(
+                package_path.join("metadata.json"),
+            )?)?)
+        };
+
+        add_dependency(&package_path, "shared-ui", "^1.2.0")?;
+        add_dependency(&package_path, "icons", "*")?;
+        add_dependency(&package_path, "shared-ui", "~1.3")?;
+        let ids: Vec<(String, String)> = load()?
+            .dependencies
+            .into_iter()
+            .map(|dependency| (dependency.id, dependency.version_req))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("shared-ui".to_string(), "~1.3".to_string()),
+                ("icons".to_string(), "*".to_string())
+            ]
+        );
+
+        // 无效的约束不写入 metadata.json
+        assert!(add_dependency(&package_path, "icons", "latest").is_err());
+        assert_eq!(load()?.dependencies[1].version_req, "*");
+
+        remove_dependency(&package_path, "icons")?;
+        assert_eq!(load()?.dependencies.len(), 1);
+        let error = remove_dependency(&package_path, "icons").unwrap_err();
+        assert!(error.to_string().contains("没有依赖 icons"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = new("test-app", temp_dir.path(), false)?;
+        create_test_file(package_path.join("index.html"), "hello")?;
+        add_file(package_path.join("index.html"), package_path.clone())?;
+
+        let public_key = crypto::hex_encode(&sign(&package_path, &[7; 32])?);
+        let load = || -> Result<PackageMetadata> {
+            Ok(serde_json::from_str(&fs::read_to_string(
+                package_path.join("metadata.json"),
+            )?)?)
+        };
+        let metadata = load()?;
+        let signature = metadata.signature.clone().expect("应写入签名");
+        assert!(metadata.verify_signature(&signature, &public_key)?);
+        assert_eq!(
+            fs::read_to_string(package_path.join(SIGNATURE_FILE))?.trim(),
+            signature
+        );
+        // 分离签名文件不算作未跟踪文件
+        assert!(status(&package_path)?.untracked.is_empty());
+
+        // 签名后修改文件清单，签名不再有效
+        create_test_file(package_path.join("app.js"), "console.log(1)")?;
+        add_file(package_path.join("app.js"), package_path.clone())?;
+        assert!(!load()?.verify_signature(&signature, &public_key)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_keygen() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let keys_dir = temp_dir.path().join("keys");
+
+        let public_key = keygen(&keys_dir, "author", false)?;
+        let secret_path = keys_dir.join("author.key");
+        let secret_key = crypto::hex_decode(&fs::read_to_string(&secret_path)?)?;
+        assert_eq!(crypto::public_key(&secret_key)?, public_key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(&secret_path)?.permissions().mode() & 0o777,
+                0o600
+            );
+        }
+        assert_eq!(
+            fs::read_to_string(keys_dir.join("author.pub"))?.trim(),
+            crypto::hex_encode(&public_key)
+        );
+        // 以文件路径指定私钥
+        assert_eq!(load_secret_key(&secret_path.to_string_lossy())?, secret_key);
+
+        // 已存在时需要显式覆盖
+        assert!(keygen(&keys_dir, "author", false).is_err());
+        assert_ne!(keygen(&keys_dir, "author", true)?, public_key);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_file_reports_changes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = temp_dir.path().join("test-app");
+        init(&package_path)?;
+        let src_dir = package_path.join("src");
+        fs::create_dir_all(&src_dir)?;
+        create_test_file(src_dir.join("a.js"), "aaaa")?;
+        create_test_file(src_dir.join("b.js"), "bb")?;
+
+        let report = add_file(&src_dir, &package_path)?;
+        assert_eq!(report.added.len(), 2);
+        assert!(report.is_changed());
+
+        // 没有变化时不重写 metadata.json
+        let metadata_path = package_path.join("metadata.json");
+        let old_time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        File::options()
+            .write(true)
+            .open(&metadata_path)?
+            .set_modified(old_time)?;
+        let report = add_file(&src_dir, &package_path)?;
+        assert!(!report.is_changed());
+        assert_eq!(report.unchanged.len(), 2);
+        assert_eq!(fs::metadata(&metadata_path)?.modified()?, old_time);
+
+        create_test_file(src_dir.join("b.js"), "changed")?;
+        create_test_file(src_dir.join("c.js"), "c")?;
+        let report = add_file(&src_dir, &package_path)?;
+        assert_eq!(report.added, ["src/c.js"]);
+        assert_eq!(report.updated, ["src/b.js"]);
+        assert_eq!(report.unchanged, ["src/a.js"]);
+        assert_ne!(fs::metadata(&metadata_path)?.modified()?, old_time);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_directory_excludes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = temp_dir.path().join("test-app");
+        init(&package_path)?;
+        for file in [
+            "index.html",
+            "js/app.js",
+            "js/app.js.swp",
+            "js/node_modules/lib/index.js",
+            "node_modules/lib/index.js",
+            "build/out.js",
+            ".git/HEAD",
+        ] {
+            let file_path = package_path.join(file);
+            fs::create_dir_all(file_path.parent().unwrap())?;
+            create_test_file(file_path, "content")?;
+        }
+        create_test_file(package_path.join(".pkgrignore"), "node_modules/\n*.swp\n")?;
+
+        add_file_with_progress(
+            &package_path,
+            &package_path,
+            false,
+            &["build/".to_string()],
+            |_, _, _| {},
+        )?;
+
+        let metadata: PackageMetadata =
+            serde_json::from_str(&fs::read_to_string(package_path.join("metadata.json"))?)?;
+        let mut files: Vec<&str> = metadata.all_files.keys().map(String::as_str).collect();
+        files.sort();
+        assert_eq!(files, ["index.html", "js/app.js"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_file_progress() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = temp_dir.path().join("test-app");
+        init(&package_path)?;
+        let src_dir = package_path.join("src");
+        fs::create_dir_all(&src_dir)?;
+        create_test_file(src_dir.join("a.js"), "aaaa")?;
+        create_test_file(src_dir.join("b.js"), "bb")?;
+
+        let mut reports = Vec::new();
+        add_file_with_progress(&src_dir, &package_path, false, &[], |done, total, bytes| {
+            reports.push((done, total, bytes))
+        })?;
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[1], (2, 2, 6));
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_add_symlinks() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new()?;
+        let package_path = temp_dir.path().join("test-app");
+        init(&package_path)?;
+        let assets = package_path.join("assets");
+        fs::create_dir_all(&assets)?;
+        create_test_file(assets.join("app.js"), "console.log(1)")?;
+        create_test_file(temp_dir.path().join("secret.txt"), "secret")?;
+        symlink(assets.join("app.js"), assets.join("inside.js"))?;
+        symlink(
+            temp_dir.path().join("secret.txt"),
+            assets.join("outside.txt"),
+        )?;
+
+        let load = || -> Result<PackageMetadata> {
+            Ok(serde_json::from_str(&fs::read_to_string(
+                package_path.join("metadata.json"),
+            )?)?)
+        };
+
+        // 默认跳过并列出符号链接
+        let mut skipped = add_file(&assets, &package_path)?.skipped;
+        skipped.sort();
+        assert_eq!(
+            skipped,
+            [assets.join("inside.js"), assets.join("outside.txt")]
+        );
+        let metadata = load()?;
+        assert!(metadata.has_file("assets/app.js"));
+        assert!(!metadata.has_file("assets/inside.js"));
+        assert_eq!(
+            add_file(&assets.join("inside.js"), &package_path)?
+                .skipped
+                .len(),
+            1
+        );
+
+        // 跟随时拒绝指向包目录之外的链接，不修改清单
+        let error =
+            add_file_with_progress(&assets, &package_path, true, &[], |_, _, _| {}).unwrap_err();
+        assert!(format!("{error:#}").contains("指向包目录之外"));
+        assert!(!load()?.has_file("assets/inside.js"));
+
+        // 包目录内的链接以链接路径记录目标内容
+        fs::remove_file(assets.join("outside.txt"))?;
+        assert!(
+            add_file_with_progress(&assets, &package_path, true, &[], |_, _, _| {})?
+                .skipped
+                .is_empty()
+        );
+        let metadata = load()?;
+        assert_eq!(
+            metadata.all_files["assets/inside.js"],
+            metadata.all_files["assets/app.js"]
+        );
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_add_non_utf8_path() -> Result<()> {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new()?;
+        let package_path = temp_dir.path().join("test-app");
+        init(&package_path)?;
+
+        let bad_name = OsStr::from_bytes(b"bad\xff.txt");
+        let bad_path = package_path.join(bad_name);
+        if fs::write(&bad_path, "content").is_err() {
+            // 部分文件系统不允许非 UTF-8 文件名
+            return Ok(());
+        }
+
+        // 返回指明路径的错误而不是 panic
+        let error = add_file(&bad_path, &package_path).unwrap_err();
+        assert!(format!("{error:#}").contains("无效的 UTF-8"));
+        assert!(add_file(&package_path, &package_path).is_err());
+        assert!(status(&package_path).is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,645 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::crypto;
+use crate::fsxg;
+use crate::metadata::{PackageMetadata, parse_file_entry};
+use anyhow::{Context, Result, anyhow};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// 归档清单文件名（位于归档顶层）
+pub const MANIFEST_FILE: &str = "MANIFEST.json";
+
+/// 软件包归档的扩展名
+pub const BUNDLE_EXTENSION: &str = ".tar.zst";
+
+/// 软件包归档中元数据的条目名（位于归档顶层，总是第一个条目）
+const BUNDLE_METADATA: &str = "metadata.json";
+
+/// 软件包归档的文件名：`<id>-<version>.tar.zst`
+pub fn bundle_file_name(package_id: &str, version: &str) -> String {
+    format!("{package_id}-{version}{BUNDLE_EXTENSION}")
+}
+
+/// 归档清单
+///
+/// 列出归档中除清单自身外的每个文件及其 SHA256 哈希和大小
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ArchiveManifest {
+    /// 文件清单（归档内路径 -> 文件信息），按路径排序
+    pub files: BTreeMap<String, ManifestEntry>,
+}
+
+/// 归档清单中的文件信息
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    /// SHA256 哈希值
+    pub hash: String,
+    /// 文件大小（字节）
+    pub size: u64,
+}
+
+/// 归档条目的内容来源
+pub enum ArchiveSource {
+    /// 磁盘上的文件
+    File(PathBuf),
+    /// 内存中的数据
+    Bytes(Vec<u8>),
+}
+
+/// 创建 tar.gz 归档
+///
+/// # 参数
+/// - `output`: 输出归档路径
+/// - `entries`: 归档内路径（使用 `/` 分隔）到内容来源的映射
+///
+/// # 返回值
+/// 返回写入归档的清单
+///
+/// # 功能特性
+/// - 顶层写入 `MANIFEST.json`，列出每个文件的哈希和大小
+/// - 条目按路径排序，时间戳、属主和权限固定，相同内容产生相同的归档
+pub fn create(output: &Path, entries: &BTreeMap<String, ArchiveSource>) -> Result<ArchiveManifest> {
+    // 生成清单
+    let mut manifest = ArchiveManifest::default();
+    for (path, source) in entries {
+        let entry = match source {
+            ArchiveSource::File(file) => ManifestEntry {
+                hash: crypto::file_hash(file)
+                    .with_context(|| format!("无法计算文件哈希: {}", file.display()))?,
+                size: fs::metadata(file)
+                    .with_context(|| format!("无法读取文件信息: {}", file.display()))?
+                    .len(),
+            },
+            ArchiveSource::Bytes(data) => ManifestEntry {
+                hash: crypto::bytes_hash(data),
+                size: data.len() as u64,
+            },
+        };
+        manifest.files.insert(path.clone(), entry);
+    }
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+
+    if let Some(parent) = output.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fsxg::create_directory(parent)?;
+    }
+    let file =
+        File::create(output).with_context(|| format!("无法创建归档文件: {}", output.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+
+    // 清单放在最前面
+    append(
+        &mut builder,
+        MANIFEST_FILE,
+        manifest_json.len() as u64,
+        DEFAULT_MODE,
+        &manifest_json[..],
+    )?;
+    for (path, source) in entries {
+        let size = manifest.files[path].size;
+        match source {
+            ArchiveSource::File(file) => {
+                let reader = File::open(file)
+                    .with_context(|| format!("无法打开文件: {}", file.display()))?;
+                append(&mut builder, path, size, DEFAULT_MODE, reader)?;
+            }
+            ArchiveSource::Bytes(data) => {
+                append(&mut builder, path, size, DEFAULT_MODE, &data[..])?
+            }
+        }
+    }
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .with_context(|| format!("无法写入归档文件: {}", output.display()))?;
+
+    Ok(manifest)
+}
+
+/// 创建软件包归档（tar.zst）
+///
+/// # 参数
+/// - `output`: 输出归档路径
+/// - `content`: metadata.json 的原始内容，作为第一个条目写入
+/// - `metadata`: 解析后的元数据，`all_files` 中的文件从 `package_path` 读取
+///
+/// # 功能特性
+/// - 不写入单独的清单，由 metadata.json 的 `all_files` 记录每个文件的哈希
+/// - 条目按路径排序，时间戳和属主固定，权限取自 `file_modes`（未记录的为 644），相同内容产生相同的归档
+pub fn create_bundle(
+    output: &Path,
+    content: &[u8],
+    metadata: &PackageMetadata,
+    package_path: &Path,
+) -> Result<()> {
+    if let Some(parent) = output.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fsxg::create_directory(parent)?;
+    }
+    let file =
+        File::create(output).with_context(|| format!("无法创建归档文件: {}", output.display()))?;
+    let encoder = zstd::Encoder::new(file, 0)
+        .with_context(|| format!("无法写入归档文件: {}", output.display()))?;
+    let mut builder = tar::Builder::new(encoder);
+
+    append(
+        &mut builder,
+        BUNDLE_METADATA,
+        content.len() as u64,
+        DEFAULT_MODE,
+        content,
+    )?;
+    let paths: BTreeSet<&String> = metadata.all_files.keys().collect();
+    for path in paths {
+        let file = package_path.join(path);
+        let size = fs::metadata(&file)
+            .with_context(|| format!("无法读取文件信息: {}", file.display()))?
+            .len();
+        let reader =
+            File::open(&file).with_context(|| format!("无法打开文件: {}", file.display()))?;
+        let mode = metadata.get_file_mode(path).unwrap_or(DEFAULT_MODE);
+        append(&mut builder, path, size, mode, reader)?;
+    }
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .with_context(|| format!("无法写入归档文件: {}", output.display()))?;
+
+    Ok(())
+}
+
+/// 解压软件包归档并按其中的 metadata.json 校验
+///
+/// 归档的第一个条目必须是 metadata.json，其后的每个条目都必须在 `all_files` 中，
+/// 解压时同步按清单中的哈希（含算法前缀）和大小校验，遇到第一个不匹配的文件即中止；全部解压后再检查缺失的文件。
+/// `file_modes` 中记录的权限在解压后应用。出错时 `dest` 中可能留有部分文件，调用方应丢弃该目录。
+///
+/// # 返回值
+/// 返回归档中的元数据（metadata.json 同时写入 `dest`）
+pub fn extract_bundle(archive: &Path, dest: &Path) -> Result<PackageMetadata> {
+    let file =
+        File::open(archive).with_context(|| format!("无法打开归档文件: {}", archive.display()))?;
+    let decoder = zstd::Decoder::new(file).with_context(|| "无法读取归档")?;
+    let (metadata, content) = extract_checked::<PackageMetadata>(decoder, dest)?;
+    fsxg::create_directory(dest)?;
+    fs::write(dest.join(BUNDLE_METADATA), content)?;
+    Ok(metadata)
+}
+
+/// 未记录权限的归档条目使用的权限
+const DEFAULT_MODE: u32 = 0o644;
+
+/// 以固定的时间戳和属主追加一个文件条目
+fn append<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    size: u64,
+    mode: u32,
+    reader: impl Read,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(mode);
+    header.set_mtime(0);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_entry_type(tar::EntryType::Regular);
+    builder
+        .append_data(&mut header, path, reader)
+        .with_context(|| format!("无法写入归档条目: {path}"))
+}
+
+/// 解压归档并按清单校验
+///
+/// 条目按顺序流式解压到 `dest`（应为临时暂存目录），解压时同步计算哈希并立即与 `MANIFEST.json` 比对，
+/// 遇到第一个清单外、大小或哈希不匹配的文件即中止，不再解压后续条目；全部解压后再检查缺失的文件。
+/// 出错时 `dest` 中可能留有部分文件，调用方应丢弃暂存目录，只在校验通过后使用其中的内容。
+///
+/// # 返回值
+/// 返回归档中的清单
+///
+/// # 功能特性
+/// - 清单必须是归档的第一个条目（`create` 总是如此写入）
+/// - 文件内容不在内存中缓存，哈希在写入暂存目录的同时计算
+pub fn extract_verified(archive: &Path, dest: &Path) -> Result<ArchiveManifest> {
+    let file =
+        File::open(archive).with_context(|| format!("无法打开归档文件: {}", archive.display()))?;
+    let (manifest, _) = extract_checked::<ArchiveManifest>(GzDecoder::new(file), dest)?;
+    Ok(manifest)
+}
+
+/// 归档第一个条目中的文件清单
+trait FileList: Sized {
+    /// 清单条目的文件名
+    const NAME: &'static str;
+
+    /// 解析清单条目的内容
+    fn parse(content: &[u8]) -> Result<Self>;
+
+    /// 清单中文件的预期哈希（可带算法前缀）、大小和权限
+    fn expected(&self, path: &str) -> Option<ExpectedFile<'_>>;
+
+    /// 清单中的所有文件路径
+    fn paths(&self) -> Vec<&String>;
+}
+
+/// 清单中单个文件的校验要求
+struct ExpectedFile<'a> {
+    hash: &'a str,
+    size: Option<u64>,
+    mode: Option<u32>,
+}
+
+impl FileList for ArchiveManifest {
+    const NAME: &'static str = MANIFEST_FILE;
+
+    fn parse(content: &[u8]) -> Result<Self> {
+        serde_json::from_slice(content).with_context(|| "无法解析归档清单")
+    }
+
+    fn expected(&self, path: &str) -> Option<ExpectedFile<'_>> {
+        self.files.get(path).map(|entry| ExpectedFile {
+            hash: &entry.hash,
+            size: Some(entry.size),
+            mode: None,
+        })
+    }
+
+    fn paths(&self) -> Vec<&String> {
+        self.files.keys().collect()
+    }
+}
+
+impl FileList for PackageMetadata {
+    const NAME: &'static str = BUNDLE_METADATA;
+
+    fn parse(content: &[u8]) -> Result<Self> {
+        serde_json::from_slice(content).with_context(|| "无法解析归档中的元数据")
+    }
+
+    fn expected(&self, path: &str) -> Option<ExpectedFile<'_>> {
+        self.all_files.get(path).map(|entry| {
+            let (hash, size) = parse_file_entry(entry);
+            ExpectedFile {
+                hash,
+                size,
+                mode: self.get_file_mode(path),
+            }
+        })
+    }
+
+    fn paths(&self) -> Vec<&String> {
+        self.all_files.keys().collect()
+    }
+}
+
+/// 按第一个条目中的清单流式解压并校验归档，返回清单及其原始内容
+///
+/// 条目写入 `dest` 时同步计算哈希，遇到第一个清单外、大小或哈希不匹配的文件即中止；
+/// 全部解压后再检查缺失的文件。清单记录了权限的文件解压后按清单设置权限
+fn extract_checked<L: FileList>(reader: impl Read, dest: &Path) -> Result<(L, Vec<u8>)> {
+    let mut tar = tar::Archive::new(reader);
+
+    let mut list: Option<(L, Vec<u8>)> = None;
+    let mut extracted = BTreeSet::new();
+
+    for entry in tar.entries().with_context(|| "无法读取归档")? {
+        let mut entry = entry.with_context(|| "无法读取归档条目")?;
+        let entry_path = entry.path()?.into_owned();
+        let path = entry_path
+            .to_str()
+            .ok_or_else(|| {
+                anyhow!(
+                    "归档条目路径包含无效的 UTF-8 字符: {}",
+                    entry_path.display()
+                )
+            })?
+            .to_string();
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => continue,
+            tar::EntryType::Regular => {}
+            other => return Err(anyhow!("归档包含不支持的条目类型 {:?}: {}", other, path)),
+        }
+
+        let Some((list, _)) = &list else {
+            if path != L::NAME {
+                return Err(anyhow!("归档的第一个条目应为 {}: {}", L::NAME, path));
+            }
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            list = Some((L::parse(&content)?, content));
+            continue;
+        };
+
+        // 拒绝绝对路径和越出目标目录的路径
+        if !entry_path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow!("归档条目路径不安全: {}", path));
+        }
+
+        // 写入前先检查清单和条目头中的大小
+        let expected = list
+            .expected(&path)
+            .ok_or_else(|| anyhow!("归档校验失败: 清单外的文件: {}", path))?;
+        if !extracted.insert(path.clone()) {
+            return Err(anyhow!("归档校验失败: 重复的文件: {}", path));
+        }
+        if expected
+            .size
+            .is_some_and(|size| entry.header().size().ok() != Some(size))
+        {
+            return Err(anyhow!("归档校验失败: 大小不匹配: {}", path));
+        }
+        let (algorithm, _) = crypto::HashAlgorithm::split(expected.hash)?;
+
+        let dest_path = dest.join(&entry_path);
+        if let Some(parent) = dest_path.parent() {
+            fsxg::create_directory(parent)?;
+        }
+        let out = File::create(&dest_path)
+            .with_context(|| format!("无法创建文件: {}", dest_path.display()))?;
+        let mut writer = HashingWriter::new(out, algorithm);
+        std::io::copy(&mut entry, &mut writer).with_context(|| format!("无法解压文件: {path}"))?;
+        let actual_hash = writer.finish();
+        if !crypto::hash_eq(&actual_hash, expected.hash) {
+            return Err(anyhow!(
+                "归档校验失败: 文件哈希不匹配: {} (预期: {}, 实际: {})",
+                path,
+                expected.hash,
+                actual_hash
+            ));
+        }
+        if let Some(mode) = expected.mode {
+            fsxg::set_file_mode(&dest_path, mode)?;
+        }
+    }
+
+    let (list, content) = list.ok_or_else(|| anyhow!("归档缺少 {}", L::NAME))?;
+
+    // 检查清单中未出现在归档里的文件
+    let mut missing: Vec<&String> = list
+        .paths()
+        .into_iter()
+        .filter(|path| !extracted.contains(*path))
+        .collect();
+    if !missing.is_empty() {
+        missing.sort();
+        return Err(anyhow!(
+            "归档校验失败:\n  {}",
+            missing
+                .iter()
+                .map(|path| format!("缺失: {path}"))
+                .collect::<Vec<_>>()
+                .join("\n  ")
+        ));
+    }
+
+    Ok((list, content))
+}
+
+/// 边写入边计算哈希的写入器
+struct HashingWriter<W> {
+    inner: W,
+    algorithm: crypto::HashAlgorithm,
+    hasher: crypto::Hasher,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W, algorithm: crypto::HashAlgorithm) -> Self {
+        Self {
+            inner,
+            algorithm,
+            hasher: crypto::Hasher::new(algorithm),
+        }
+    }
+
+    /// 结束写入，返回已写入内容的哈希（带算法前缀，SHA256 无前缀）
+    fn finish(self) -> String {
+        self.algorithm.format(&self.hasher.finalize())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn sample_entries(dir: &Path) -> Result<BTreeMap<String, ArchiveSource>> {
+        let file_path = dir.join("app.js");
+        fs::write(&file_path, "console.log(1)")?;
+
+        let mut entries = BTreeMap::new();
+        entries.insert(
+            "packages/app/1.0.0/app.js".to_string(),
+            ArchiveSource::File(file_path),
+        );
+        entries.insert(
+            "index.json".to_string(),
+            ArchiveSource::Bytes(b"{}".to_vec()),
+        );
+        Ok(entries)
+    }
+
+    #[test]
+    fn test_create_is_deterministic() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let entries = sample_entries(temp_dir.path())?;
+
+        let first = temp_dir.path().join("first.tar.gz");
+        let second = temp_dir.path().join("second.tar.gz");
+        create(&first, &entries)?;
+        create(&second, &entries)?;
+
+        assert_eq!(fs::read(first)?, fs::read(second)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_verified() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let entries = sample_entries(temp_dir.path())?;
+        let archive = temp_dir.path().join("export.tar.gz");
+        let manifest = create(&archive, &entries)?;
+
+        let dest = temp_dir.path().join("dest");
+        assert_eq!(extract_verified(&archive, &dest)?, manifest);
+        assert_eq!(
+            fs::read_to_string(dest.join("packages/app/1.0.0/app.js"))?,
+            "console.log(1)"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle_roundtrip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package = temp_dir.path().join("package");
+        fs::create_dir_all(package.join("js"))?;
+        fs::write(package.join("js/app.js"), "console.log(1)")?;
+        fs::write(package.join("run.sh"), "#!/bin/sh")?;
+
+        let mut metadata = PackageMetadata::new();
+        metadata.add_file(
+            "js/app.js".to_string(),
+            crypto::bytes_hash(b"console.log(1)"),
+        );
+        metadata.add_file(
+            "run.sh".to_string(),
+            format!("blake3:{}", blake3::hash(b"#!/bin/sh").to_hex()),
+        );
+        metadata.set_file_mode("run.sh", Some(0o755));
+        let bundle = temp_dir.path().join(bundle_file_name("app", "1.0.0"));
+        create_bundle(
+            &bundle,
+            &serde_json::to_vec(&metadata)?,
+            &metadata,
+            &package,
+        )?;
+
+        let dest = temp_dir.path().join("dest");
+        let extracted = extract_bundle(&bundle, &dest)?;
+        assert!(extracted.has_file("js/app.js"));
+        assert_eq!(
+            fs::read_to_string(dest.join("js/app.js"))?,
+            "console.log(1)"
+        );
+        assert!(dest.join("metadata.json").is_file());
+
+        // 归档条目和解压后的文件保留清单中的权限
+        let mut tar = tar::Archive::new(zstd::Decoder::new(File::open(&bundle)?)?);
+        for entry in tar.entries()? {
+            let entry = entry?;
+            let expected = if entry.path()?.as_ref() == Path::new("run.sh") {
+                0o755
+            } else {
+                DEFAULT_MODE
+            };
+            assert_eq!(entry.header().mode()?, expected);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(dest.join("run.sh"))?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        // 内容与清单不符的归档被拒绝
+        metadata.add_file("js/app.js".to_string(), crypto::bytes_hash(b"other"));
+        create_bundle(
+            &bundle,
+            &serde_json::to_vec(&metadata)?,
+            &metadata,
+            &package,
+        )?;
+        let error = extract_bundle(&bundle, &temp_dir.path().join("corrupt")).unwrap_err();
+        assert!(error.to_string().contains("js/app.js"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_rejects_mismatch() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        // 手工构造清单与内容不一致的归档
+        let mut manifest = ArchiveManifest::default();
+        manifest.files.insert(
+            "index.json".to_string(),
+            ManifestEntry {
+                hash: crypto::bytes_hash(b"{}"),
+                size: 2,
+            },
+        );
+        let manifest_json = serde_json::to_vec(&manifest)?;
+
+        let archive = temp_dir.path().join("corrupt.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&archive)?,
+            Compression::default(),
+        ));
+        append(
+            &mut builder,
+            MANIFEST_FILE,
+            manifest_json.len() as u64,
+            DEFAULT_MODE,
+            &manifest_json[..],
+        )?;
+        append(&mut builder, "index.json", 2, DEFAULT_MODE, &b"[]"[..])?;
+        builder.into_inner()?.finish()?;
+
+        let result = extract_verified(&archive, &temp_dir.path().join("dest"));
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extract_stops_at_first_mismatch() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        // 第一个文件内容与清单不符，其后的文件不应被解压
+        let mut manifest = ArchiveManifest::default();
+        for path in ["a.txt", "b.txt"] {
+            manifest.files.insert(
+                path.to_string(),
+                ManifestEntry {
+                    hash: crypto::bytes_hash(b"ok"),
+                    size: 2,
+                },
+            );
+        }
+        let manifest_json = serde_json::to_vec(&manifest)?;
+
+        let archive = temp_dir.path().join("corrupt.tar.gz");
+        let mut builder = tar::Builder::new(GzEncoder::new(
+            File::create(&archive)?,
+            Compression::default(),
+        ));
+        append(
+            &mut builder,
+            MANIFEST_FILE,
+            manifest_json.len() as u64,
+            DEFAULT_MODE,
+            &manifest_json[..],
+        )?;
+        append(&mut builder, "a.txt", 2, DEFAULT_MODE, &b"no"[..])?;
+        append(&mut builder, "b.txt", 2, DEFAULT_MODE, &b"ok"[..])?;
+        builder.into_inner()?.finish()?;
+
+        let dest = temp_dir.path().join("dest");
+        let error = extract_verified(&archive, &dest).unwrap_err();
+        assert!(error.to_string().contains("a.txt"));
+        assert!(!dest.join("b.txt").exists());
+
+        Ok(())
+    }
+}
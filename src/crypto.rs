@@ -2,10 +2,135 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use anyhow::Result;
-use sha2::{Digest, Sha256};
+use anyhow::{Result, anyhow, bail};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256, Sha512};
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
+
+/// 文件哈希算法
+///
+/// 文件清单中的哈希值可带算法前缀（如 `blake3:abcd...`），无前缀时为 SHA256
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// 算法前缀名
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// 按前缀名解析算法
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            _ => bail!("不支持的哈希算法: {name}"),
+        }
+    }
+
+    /// 拆分带前缀的哈希值，返回算法和十六进制摘要
+    pub fn split(digest: &str) -> Result<(Self, &str)> {
+        match digest.split_once(':') {
+            Some((name, hex)) => Ok((Self::parse(name)?, hex)),
+            None => Ok((HashAlgorithm::Sha256, digest)),
+        }
+    }
+
+    /// 为十六进制摘要加上算法前缀（SHA256 保持无前缀）
+    pub fn format(self, hex: &str) -> String {
+        match self {
+            HashAlgorithm::Sha256 => hex.to_string(),
+            _ => format!("{}:{hex}", self.name()),
+        }
+    }
+}
+
+/// 按算法分派的增量哈希计算
+pub enum Hasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// 结束计算，返回不带前缀的十六进制字符串
+    pub fn finalize(self) -> String {
+        match self {
+            Hasher::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Sha512(hasher) => format!("{:x}", hasher.finalize()),
+            Hasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+        }
+    }
+}
+
+/// 使用指定算法计算文件哈希值，返回不带前缀的十六进制字符串
+pub fn file_hash_with<P: AsRef<Path>>(file_path: P, algorithm: HashAlgorithm) -> Result<String> {
+    let mut file = File::open(file_path)?;
+    let mut hasher = Hasher::new(algorithm);
+    let mut buffer = [0; 8192];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(hasher.finalize())
+}
+
+/// 按期望哈希值的算法前缀计算文件哈希，返回带相同前缀的哈希值
+///
+/// 用于与文件清单中的哈希值比较，结果可直接显示在不匹配的错误信息中
+pub fn file_hash_like<P: AsRef<Path>>(file_path: P, expected_hash: &str) -> Result<String> {
+    let (algorithm, _) = HashAlgorithm::split(expected_hash)?;
+    Ok(algorithm.format(&file_hash_with(file_path, algorithm)?))
+}
+
+/// 比较两个哈希值是否相同
+///
+/// 按 [`HashAlgorithm::split`] 拆分后比较算法和摘要（不区分大小写），
+/// 因此 `sha256:<摘要>` 与无前缀的同一摘要视为相同；任一方的算法无法识别时视为不同
+pub fn hash_eq(actual: &str, expected: &str) -> bool {
+    match (HashAlgorithm::split(actual), HashAlgorithm::split(expected)) {
+        (Ok((actual_algorithm, actual_hex)), Ok((expected_algorithm, expected_hex))) => {
+            actual_algorithm == expected_algorithm && actual_hex.eq_ignore_ascii_case(expected_hex)
+        }
+        _ => false,
+    }
+}
 
 /// 计算文件的 SHA256 哈希值
 ///
@@ -20,28 +145,32 @@ use std::io::Read;
 /// let hash = file_hash("path/to/file.txt")?;
 /// println!("文件哈希: {}", hash);
 /// ```
-pub fn file_hash(file_path: &str) -> Result<String> {
-    let mut file = File::open(file_path)?;
-    let mut hasher = Sha256::new();
-    let mut buffer = [0; 8192];
-
-    loop {
-        let bytes_read = file.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        hasher.update(&buffer[..bytes_read]);
-    }
+pub fn file_hash<P: AsRef<Path>>(file_path: P) -> Result<String> {
+    file_hash_with(file_path, HashAlgorithm::Sha256)
+}
 
-    let result = hasher.finalize();
-    Ok(format!("{result:x}"))
+/// 计算字节数据的 SHA256 哈希值
+///
+/// # 参数
+/// * `data` - 要计算哈希的数据
+///
+/// # 返回
+/// 返回 64 个字符的十六进制字符串
+///
+/// # 示例
+/// ```
+/// let hash = bytes_hash(b"Hello, world!");
+/// ```
+pub fn bytes_hash(data: &[u8]) -> String {
+    let result = Sha256::digest(data);
+    format!("{result:x}")
 }
 
 /// 验证文件的完整性
 ///
 /// # 参数
 /// * `file_path` - 要验证的文件路径
-/// * `expected_hash` - 期望的 SHA256 哈希值（十六进制字符串）
+/// * `expected_hash` - 期望的哈希值（十六进制字符串，可带 `sha512:`/`blake3:` 前缀，无前缀时为 SHA256）
 ///
 /// # 返回
 /// 返回布尔值，true 表示验证通过，false 表示验证失败
@@ -55,9 +184,72 @@ pub fn file_hash(file_path: &str) -> Result<String> {
 ///     println!("文件验证失败");
 /// }
 /// ```
-pub fn verify_file(file_path: &str, expected_hash: &str) -> Result<bool> {
-    let actual_hash = file_hash(file_path)?;
-    Ok(actual_hash.eq_ignore_ascii_case(expected_hash))
+pub fn verify_file<P: AsRef<Path>>(file_path: P, expected_hash: &str) -> Result<bool> {
+    let actual_hash = file_hash_like(file_path, expected_hash)?;
+    Ok(hash_eq(&actual_hash, expected_hash))
+}
+
+/// 用 Ed25519 私钥（32 字节种子）对数据签名，返回 64 字节的分离签名
+pub fn sign(data: &[u8], secret_key: &[u8]) -> Result<Vec<u8>> {
+    Ok(signing_key(secret_key)?.sign(data).to_bytes().to_vec())
+}
+
+/// 由 Ed25519 私钥（32 字节种子）导出 32 字节的公钥
+pub fn public_key(secret_key: &[u8]) -> Result<Vec<u8>> {
+    Ok(signing_key(secret_key)?.verifying_key().to_bytes().to_vec())
+}
+
+/// 验证 Ed25519 分离签名
+///
+/// # 参数
+/// * `data` - 被签名的数据
+/// * `sig` - 64 字节的签名
+/// * `pubkey` - 32 字节的公钥
+///
+/// # 返回
+/// 签名与数据相符时返回 true，不符时返回 false；签名或公钥格式无效时返回错误
+pub fn verify_signature(data: &[u8], sig: &[u8], pubkey: &[u8]) -> Result<bool> {
+    let pubkey: &[u8; 32] = pubkey
+        .try_into()
+        .map_err(|_| anyhow!("Ed25519 公钥应为 32 字节，实际为 {} 字节", pubkey.len()))?;
+    let verifying_key =
+        VerifyingKey::from_bytes(pubkey).map_err(|e| anyhow!("无效的 Ed25519 公钥: {e}"))?;
+    let signature = Signature::from_slice(sig).map_err(|e| anyhow!("无效的 Ed25519 签名: {e}"))?;
+    Ok(verifying_key.verify_strict(data, &signature).is_ok())
+}
+
+/// 从操作系统的随机源（[`OsRng`]）生成新的 Ed25519 私钥（32 字节种子）
+pub fn generate_secret_key() -> Result<[u8; 32]> {
+    let mut seed = [0u8; 32];
+    OsRng
+        .try_fill_bytes(&mut seed)
+        .map_err(|e| anyhow!("无法读取系统随机源: {e}"))?;
+    Ok(seed)
+}
+
+/// 由 32 字节种子构造 Ed25519 签名密钥
+fn signing_key(secret_key: &[u8]) -> Result<SigningKey> {
+    let seed: &[u8; 32] = secret_key
+        .try_into()
+        .map_err(|_| anyhow!("Ed25519 私钥应为 32 字节，实际为 {} 字节", secret_key.len()))?;
+    Ok(SigningKey::from_bytes(seed))
+}
+
+/// 将字节编码为小写十六进制字符串
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// 解码十六进制字符串（不区分大小写，忽略首尾空白）
+pub fn hex_decode(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.trim();
+    if !hex.len().is_multiple_of(2) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("无效的十六进制字符串: {}", hex);
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(Into::into))
+        .collect()
 }
 
 #[cfg(test)]
@@ -74,7 +266,7 @@ mod tests {
         temp_file.flush()?;
 
         // 计算哈希
-        let hash = file_hash(temp_file.path().to_str().unwrap())?;
+        let hash = file_hash(temp_file.path())?;
 
         // 验证哈希值（"Hello, world!" 的 SHA256）
         assert_eq!(
@@ -85,6 +277,14 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_bytes_hash() {
+        assert_eq!(
+            bytes_hash(b"Hello, world!"),
+            "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3"
+        );
+    }
+
     #[test]
     fn test_verify_file() -> Result<()> {
         // 创建临时文件
@@ -94,18 +294,52 @@ mod tests {
 
         // 验证正确的哈希
         let is_valid = verify_file(
-            temp_file.path().to_str().unwrap(),
+            temp_file.path(),
             "315f5bdb76d078c43b8ac0064e4a0164612b1fce77c869345bfc94c75894edd3",
         )?;
         assert!(is_valid);
 
         // 验证错误的哈希
-        let is_invalid = verify_file(temp_file.path().to_str().unwrap(), "invalid_hash_value")?;
+        let is_invalid = verify_file(temp_file.path(), "invalid_hash_value")?;
         assert!(!is_invalid);
 
         Ok(())
     }
 
+    #[test]
+    fn test_file_hash_algorithms() -> Result<()> {
+        let mut temp_file = NamedTempFile::new()?;
+        temp_file.write_all(b"abc")?;
+        temp_file.flush()?;
+
+        assert_eq!(
+            file_hash_with(temp_file.path(), HashAlgorithm::Sha512)?,
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        let blake3 = "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85";
+        assert_eq!(
+            file_hash_with(temp_file.path(), HashAlgorithm::Blake3)?,
+            blake3
+        );
+
+        // 带前缀的期望值按对应算法验证，未知算法报错
+        let expected = format!("blake3:{blake3}");
+        assert_eq!(file_hash_like(temp_file.path(), &expected)?, expected);
+        assert!(verify_file(temp_file.path(), &expected.to_uppercase())?);
+        assert!(!verify_file(temp_file.path(), "sha512:00")?);
+        assert!(verify_file(temp_file.path(), "md5:00").is_err());
+
+        // 显式的 sha256: 前缀与无前缀等价
+        let sha256 = file_hash(temp_file.path())?;
+        assert!(verify_file(temp_file.path(), &format!("sha256:{sha256}"))?);
+        assert!(verify_file(temp_file.path(), &format!("SHA256:{sha256}"))?);
+        assert!(hash_eq(&sha256, &format!("sha256:{sha256}")));
+        assert!(!hash_eq(&sha256, &format!("sha512:{sha256}")));
+
+        Ok(())
+    }
+
     #[test]
     fn test_verify_file_case_insensitive() -> Result<()> {
         // 创建临时文件
@@ -115,11 +349,36 @@ mod tests {
 
         // 测试大小写不敏感的哈希验证
         let is_valid_upper = verify_file(
-            temp_file.path().to_str().unwrap(),
+            temp_file.path(),
             "315F5BDB76D078C43B8AC0064E4A0164612B1FCE77C869345BFC94C75894EDD3",
         )?;
         assert!(is_valid_upper);
 
         Ok(())
     }
+
+    #[test]
+    fn test_ed25519_signature() -> Result<()> {
+        // RFC 8032 测试向量 1（空消息）
+        let secret_key =
+            hex_decode("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60")?;
+        let public = public_key(&secret_key)?;
+        assert_eq!(
+            hex_encode(&public),
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+        );
+        let signature = sign(b"", &secret_key)?;
+        assert_eq!(
+            hex_encode(&signature),
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        );
+        assert!(verify_signature(b"", &signature, &public)?);
+
+        // 数据被修改时验证失败，长度不正确的公钥报错
+        assert!(!verify_signature(b"tampered", &signature, &public)?);
+        assert!(verify_signature(b"", &signature, &public[..31]).is_err());
+        assert!(hex_decode("abc").is_err());
+
+        Ok(())
+    }
 }
@@ -4,6 +4,7 @@
 
 use anyhow::{Context, Result};
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 /// 创建目录
@@ -106,6 +107,8 @@ pub fn remove_directory<P: AsRef<Path>>(path: P) -> Result<()> {
 
 /// 获取目录文件列表
 ///
+/// 只返回普通文件：符号链接（无论指向文件还是目录）既不返回也不进入
+///
 /// # 参数
 /// - `path`: 目录路径
 /// - `recursive`: 是否递归遍历子目录
@@ -156,6 +159,10 @@ pub fn get_directory_files<P: AsRef<Path>>(path: P, recursive: bool) -> Result<V
             fs::read_dir(path).with_context(|| format!("无法读取目录: {}", path.display()))?
         {
             let entry = entry.with_context(|| format!("无法读取目录条目: {}", path.display()))?;
+            // DirEntry::file_type 不跟随符号链接
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
             let entry_path = entry.path();
             // 确保返回绝对路径
             let abs_path = if entry_path.is_absolute() {
@@ -167,15 +174,207 @@ pub fn get_directory_files<P: AsRef<Path>>(path: P, recursive: bool) -> Result<V
                     .with_context(|| format!("无法解析路径: {full_path:?}"))?
             };
 
-            if abs_path.is_file() {
-                files.push(abs_path);
-            }
+            files.push(abs_path);
         }
     }
 
     Ok(files)
 }
 
+/// 递归遍历目录中的文件，并列出遇到的符号链接
+///
+/// # 参数
+/// - `path`: 目录路径，返回的路径都以它为前缀
+/// - `follow_symlinks`: 是否跟随符号链接
+///
+/// # 返回值
+/// 返回（文件路径列表, 符号链接列表）。不跟随时不进入任何符号链接，遇到的符号链接列入后者；
+/// 跟随时进入符号链接，返回经由链接的路径（不解析为目标路径），后者为空；
+/// 链接失效或形成循环时返回错误
+pub fn walk_files<P: AsRef<Path>>(
+    path: P,
+    follow_symlinks: bool,
+) -> Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let path = path.as_ref();
+    if !path.is_dir() {
+        return Err(anyhow::anyhow!("路径不是目录: {}", path.display()));
+    }
+
+    let mut files = Vec::new();
+    let mut symlinks = Vec::new();
+    for entry in walkdir::WalkDir::new(path).follow_links(follow_symlinks) {
+        let entry = entry.with_context(|| format!("无法遍历目录: {}", path.display()))?;
+        if entry.path_is_symlink() && !follow_symlinks {
+            symlinks.push(entry.into_path());
+        } else if entry.file_type().is_file() {
+            files.push(entry.into_path());
+        }
+    }
+    Ok((files, symlinks))
+}
+
+/// 原子写入文件
+///
+/// # 参数
+/// - `path`: 目标文件路径
+/// - `contents`: 要写入的内容
+///
+/// # 返回值
+/// 返回 Result<(), anyhow::Error>，成功时返回 Ok(())，失败时返回错误
+///
+/// # 功能特性
+/// - 先写入同目录下的临时文件并同步到磁盘，再重命名替换目标文件；
+///   中途失败或崩溃时目标文件保持原有内容，不会出现截断的文件
+/// - Unix 上重命名后同步所在目录，确保重命名本身也已落盘
+/// - 保留原文件的权限
+///
+/// # 示例
+/// ```
+/// atomic_write("/tmp/metadata.json", "{}")?;
+/// ```
+pub fn atomic_write<P: AsRef<Path>>(path: P, contents: impl AsRef<[u8]>) -> Result<()> {
+    atomic_write_with(path, |file| Ok(file.write_all(contents.as_ref())?))
+}
+
+/// 原子写入文件，内容由 `write` 直接写入临时文件（如流式序列化）
+///
+/// 与 [`atomic_write`] 相同；`write` 中途返回错误时丢弃已写入部分内容的临时文件，目标文件保持不变
+pub fn atomic_write_with<P: AsRef<Path>>(
+    path: P,
+    write: impl FnOnce(&mut fs::File) -> Result<()>,
+) -> Result<()> {
+    persist_temp_file(path.as_ref(), write_temp_file(path.as_ref(), write, false)?)
+}
+
+/// 原子写入仅所有者可读写的文件（如私钥）
+///
+/// 与 [`atomic_write`] 相同，但临时文件从创建起即为 `0600`，写入过程中不会被其他用户读取；
+/// 覆盖已有文件时也不保留其原有权限
+pub fn atomic_write_private<P: AsRef<Path>>(path: P, contents: impl AsRef<[u8]>) -> Result<()> {
+    persist_temp_file(
+        path.as_ref(),
+        write_temp_file(
+            path.as_ref(),
+            |file| Ok(file.write_all(contents.as_ref())?),
+            true,
+        )?,
+    )
+}
+
+/// 将已写入的临时文件重命名为目标文件，并在 Unix 上同步所在目录
+fn persist_temp_file(path: &Path, temp_file: tempfile::NamedTempFile) -> Result<()> {
+    temp_file
+        .persist(path)
+        .map_err(|e| e.error)
+        .with_context(|| format!("无法替换文件: {}", path.display()))?;
+
+    #[cfg(unix)]
+    {
+        let parent = parent_dir(path);
+        fs::File::open(parent)
+            .and_then(|dir| dir.sync_all())
+            .with_context(|| format!("无法同步目录: {}", parent.display()))?;
+    }
+
+    Ok(())
+}
+
+/// 读取文件的权限位
+///
+/// # 参数
+/// - `path`: 文件路径
+///
+/// # 返回值
+/// Unix 上返回权限位（如 `0o755`），其它平台没有对应概念，返回 `None`
+pub fn file_mode<P: AsRef<Path>>(path: P) -> Result<Option<u32>> {
+    let path = path.as_ref();
+    let metadata =
+        fs::metadata(path).with_context(|| format!("无法读取文件信息: {}", path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(Some(metadata.permissions().mode() & 0o7777))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        Ok(None)
+    }
+}
+
+/// 设置文件的权限位
+///
+/// # 参数
+/// - `path`: 文件路径
+/// - `mode`: 权限位（如 `0o755`），非 Unix 平台上忽略
+pub fn set_file_mode<P: AsRef<Path>>(path: P, mode: u32) -> Result<()> {
+    let path = path.as_ref();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("无法设置文件权限: {}", path.display()))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+
+    Ok(())
+}
+
+/// 目标文件所在目录，相对路径没有父目录时为当前目录
+fn parent_dir(path: &Path) -> &Path {
+    path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+}
+
+/// 在目标文件所在目录写入并同步临时文件（不替换目标文件）
+///
+/// `private` 为真时临时文件以 `0600` 创建并保持该权限
+fn write_temp_file(
+    path: &Path,
+    write: impl FnOnce(&mut fs::File) -> Result<()>,
+    private: bool,
+) -> Result<tempfile::NamedTempFile> {
+    let parent = parent_dir(path);
+    let mut builder = tempfile::Builder::new();
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::PermissionsExt;
+        builder.permissions(fs::Permissions::from_mode(0o600));
+    }
+    let mut temp_file = builder
+        .tempfile_in(parent)
+        .with_context(|| format!("无法创建临时文件: {}", parent.display()))?;
+    let temp_path = temp_file.path().to_path_buf();
+    write(temp_file.as_file_mut())
+        .and_then(|()| Ok(temp_file.as_file().sync_all()?))
+        .with_context(|| format!("无法写入临时文件: {}", temp_path.display()))?;
+    if private {
+        return Ok(temp_file);
+    }
+
+    // 临时文件默认仅用户可读写：保留原文件的权限，新文件使用常规权限
+    match fs::metadata(path) {
+        Ok(metadata) => temp_file
+            .as_file()
+            .set_permissions(metadata.permissions())?,
+        #[cfg(unix)]
+        Err(_) => {
+            use std::os::unix::fs::PermissionsExt;
+            temp_file
+                .as_file()
+                .set_permissions(fs::Permissions::from_mode(0o644))?;
+        }
+        #[cfg(not(unix))]
+        Err(_) => {}
+    }
+
+    Ok(temp_file)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -184,7 +383,6 @@ mod tests {
 
     fn create_test_file<P: AsRef<Path>>(path: P, content: &str) -> Result<()> {
         let mut file = File::create(path).with_context(|| "无法创建测试文件")?;
-        use std::io::Write;
         file.write_all(content.as_bytes())
             .with_context(|| "无法写入测试文件")?;
         Ok(())
@@ -268,4 +466,94 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_directory_files_skip_symlinks() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = TempDir::new()?;
+        let test_dir = temp_dir.path().join("test");
+        create_directory(test_dir.join("subdir"))?;
+        create_test_file(test_dir.join("file.txt"), "content")?;
+        create_test_file(test_dir.join("subdir/inner.txt"), "inner")?;
+        symlink(test_dir.join("file.txt"), test_dir.join("file-link"))?;
+        symlink(test_dir.join("subdir"), test_dir.join("dir-link"))?;
+
+        // 符号链接既不返回也不进入
+        assert_eq!(get_directory_files(&test_dir, false)?.len(), 1);
+        assert_eq!(get_directory_files(&test_dir, true)?.len(), 2);
+
+        let (files, mut symlinks) = walk_files(&test_dir, false)?;
+        assert_eq!(files.len(), 2);
+        symlinks.sort();
+        assert_eq!(
+            symlinks,
+            [test_dir.join("dir-link"), test_dir.join("file-link")]
+        );
+
+        // 跟随时返回经由链接的路径
+        let (files, symlinks) = walk_files(&test_dir, true)?;
+        assert_eq!(files.len(), 4);
+        assert!(files.contains(&test_dir.join("dir-link/inner.txt")));
+        assert!(symlinks.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_atomic_write() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("metadata.json");
+
+        atomic_write(&path, "original")?;
+        assert_eq!(fs::read_to_string(&path)?, "original");
+
+        // 写入中途失败：已写入临时文件的部分内容被丢弃，原文件保持不变，临时文件被清理
+        let result = atomic_write_with(&path, |file| {
+            file.write_all(b"partial")?;
+            Err(anyhow::anyhow!("磁盘已满"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path)?, "original");
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 1);
+
+        // 重命名失败（目标为非空目录）：目标保持不变，临时文件被清理
+        let dir_path = temp_dir.path().join("occupied");
+        create_directory(&dir_path)?;
+        fs::write(dir_path.join("keep"), "kept")?;
+        assert!(atomic_write(&dir_path, "replacement").is_err());
+        assert_eq!(fs::read_to_string(dir_path.join("keep"))?, "kept");
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 2);
+
+        atomic_write(&path, "updated")?;
+        assert_eq!(fs::read_to_string(&path)?, "updated");
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_atomic_write_private() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("secret.key");
+        let mode =
+            |path: &Path| -> Result<u32> { Ok(fs::metadata(path)?.permissions().mode() & 0o777) };
+
+        // 临时文件在写入内容之前就只有所有者可读写
+        let temp_file = write_temp_file(&path, |file| Ok(file.write_all(b"secret")?), true)?;
+        assert_eq!(mode(temp_file.path())?, 0o600);
+        drop(temp_file);
+
+        // 覆盖权限较宽的已有文件时同样收紧
+        fs::write(&path, "old")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644))?;
+        atomic_write_private(&path, "secret")?;
+        assert_eq!(fs::read_to_string(&path)?, "secret");
+        assert_eq!(mode(&path)?, 0o600);
+
+        Ok(())
+    }
 }
@@ -3,48 +3,41 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::config::ConfigManager;
+use crate::model::PackageInfo;
 use crate::net;
+use crate::path::{get_config_path, path_to_str};
 use crate::serde_utils::{load_json, save_json};
-use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
-/// 包信息
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PackageInfo {
-    /// 应用唯一标识
-    pub id: String,
-    /// 应用名称
-    pub name: String,
-    /// 图标路径
-    pub icon: String,
-    /// 作者
-    pub author: String,
-    /// 最新版本号
-    pub latest_version: String,
-    /// 应用描述
-    pub description: String,
-    /// 位置信息（URL或本地路径）
-    pub location: String,
-}
-
 /// 索引管理器
 pub struct IndexManager {
     /// 索引存储路径
     index_dir: PathBuf,
     /// 仓库根目录路径
     repo_path: PathBuf,
+    /// 读取软件源列表的配置，未指定时使用用户配置文件
+    config: Option<ConfigManager>,
 }
 
 impl IndexManager {
     /// 创建新的索引管理器实例
+    ///
+    /// 默认从用户配置文件（[`get_config_path`]）读取软件源，可用 [`Self::with_config`] 指定
     pub fn new(index_dir: PathBuf, repo_path: PathBuf) -> Self {
         Self {
             index_dir,
             repo_path,
+            config: None,
         }
     }
 
+    /// 指定读取软件源列表的配置（包括合并时的底层配置文件）
+    pub fn with_config(mut self, config: ConfigManager) -> Self {
+        self.config = Some(config);
+        self
+    }
+
     /// 获取索引文件路径
     fn get_index_path(&self) -> PathBuf {
         self.index_dir.join("index.json")
@@ -63,8 +56,15 @@ impl IndexManager {
         self.ensure_index_dir()?;
 
         // 加载配置
-        let config_manager = ConfigManager::new("config.toml")?;
-        let config = config_manager.load()?;
+        let config = match &self.config {
+            Some(config_manager) => config_manager.load_merged()?,
+            None => ConfigManager::new(get_config_path())?.load_merged()?,
+        };
+        let retry = net::RetryPolicy {
+            max_retries: config.max_retries(),
+            base_delay: config.retry_base_delay(),
+        };
+        let client = net::Client::new(&config.network, &config.source, retry)?;
 
         // 加载现有索引
         let mut index = load_json(&self.get_index_path())
@@ -84,7 +84,13 @@ impl IndexManager {
 
             // 下载源索引
             let temp_index_path = self.index_dir.join(format!("index_{}.json.tmp", source.id));
-            net::download_file(&source_index_url, temp_index_path.to_str().unwrap()).await?;
+            net::download_file(
+                &client,
+                &source_index_url,
+                path_to_str(&temp_index_path)?,
+                &net::ConsoleProgress::default(),
+            )
+            .await?;
 
             // 读取下载的索引
             let source_index_content = fs::read_to_string(&temp_index_path)?;
@@ -93,15 +99,7 @@ impl IndexManager {
             // 提取源中的包信息并添加到本地索引
             if let Some(source_packages) = source_index["source"].as_array() {
                 for pkg in source_packages {
-                    let package_info = PackageInfo {
-                        id: pkg["id"].as_str().unwrap_or("").to_string(),
-                        name: pkg["name"].as_str().unwrap_or("").to_string(),
-                        icon: pkg["icon"].as_str().unwrap_or("").to_string(),
-                        author: pkg["author"].as_str().unwrap_or("").to_string(),
-                        latest_version: pkg["latest_version"].as_str().unwrap_or("").to_string(),
-                        description: pkg["description"].as_str().unwrap_or("").to_string(),
-                        location: pkg["location"].as_str().unwrap_or("").to_string(),
-                    };
+                    let package_info = Self::package_from_value(pkg)?;
                     index["source"]
                         .as_array_mut()
                         .unwrap()
@@ -114,7 +112,7 @@ impl IndexManager {
         }
 
         // 保存更新后的索引
-        self.save_index(&index)?;
+        self.save_index(&mut index)?;
 
         Ok(())
     }
@@ -131,19 +129,7 @@ impl IndexManager {
             for pkg in source {
                 if let Some(id) = pkg["id"].as_str() {
                     if id == package_id {
-                        let package_info = PackageInfo {
-                            id: id.to_string(),
-                            name: pkg["name"].as_str().unwrap_or("").to_string(),
-                            icon: pkg["icon"].as_str().unwrap_or("").to_string(),
-                            author: pkg["author"].as_str().unwrap_or("").to_string(),
-                            latest_version: pkg["latest_version"]
-                                .as_str()
-                                .unwrap_or("")
-                                .to_string(),
-                            description: pkg["description"].as_str().unwrap_or("").to_string(),
-                            location: pkg["location"].as_str().unwrap_or("").to_string(),
-                        };
-                        return Ok(Some(package_info));
+                        return Ok(Some(Self::package_from_value(pkg)?));
                     }
                 }
             }
@@ -160,17 +146,8 @@ impl IndexManager {
         // 收集源索引中的所有包
         if let Some(source) = index["source"].as_array() {
             for pkg in source {
-                if let Some(id) = pkg["id"].as_str() {
-                    let package_info = PackageInfo {
-                        id: id.to_string(),
-                        name: pkg["name"].as_str().unwrap_or("").to_string(),
-                        icon: pkg["icon"].as_str().unwrap_or("").to_string(),
-                        author: pkg["author"].as_str().unwrap_or("").to_string(),
-                        latest_version: pkg["latest_version"].as_str().unwrap_or("").to_string(),
-                        description: pkg["description"].as_str().unwrap_or("").to_string(),
-                        location: pkg["location"].as_str().unwrap_or("").to_string(),
-                    };
-                    packages.push(package_info);
+                if pkg["id"].is_string() {
+                    packages.push(Self::package_from_value(pkg)?);
                 }
             }
         }
@@ -194,40 +171,48 @@ impl IndexManager {
     }
 
     /// 保存索引文件
-    fn save_index(&self, index: &serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+    ///
+    /// 写入前按包 ID 对 source 排序，保证输出稳定
+    fn save_index(&self, index: &mut serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(source) = index["source"].as_array_mut() {
+            source.sort_by(|a, b| {
+                a["id"]
+                    .as_str()
+                    .unwrap_or("")
+                    .cmp(b["id"].as_str().unwrap_or(""))
+            });
+        }
         save_json(index, &self.get_index_path()).map_err(|e| e.into())
     }
+
+    /// 从索引 JSON 中的包条目读取包信息
+    ///
+    /// 与仓库索引共用 [`PackageInfo`] 的反序列化，新增字段（如 `size`、`signature`、`bundle`）不会丢失
+    fn package_from_value(
+        pkg: &serde_json::Value,
+    ) -> Result<PackageInfo, Box<dyn std::error::Error>> {
+        serde_json::from_value(pkg.clone()).map_err(|e| {
+            format!(
+                "索引中的软件包 {} 无法解析: {}",
+                pkg["id"].as_str().unwrap_or("?"),
+                e
+            )
+            .into()
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::testing::test_source;
     use tempfile::TempDir;
 
     #[tokio::test]
     async fn test_update_source_index() -> Result<(), Box<dyn std::error::Error>> {
         let temp_dir = TempDir::new()?;
-        let index_manager =
-            IndexManager::new(temp_dir.path().to_path_buf(), temp_dir.path().to_path_buf());
-
-        // 创建测试配置
-        let config_dir = temp_dir.path().join("config");
-        std::fs::create_dir_all(&config_dir)?;
-        let config_path = config_dir.join("config.toml");
 
-        let mut config = crate::config::RepositoryConfig::default();
-        config.source.push(crate::config::SourceConfig {
-            id: "test".to_string(),
-            name: "Test Source".to_string(),
-            url: "https://example.com/".to_string(),
-            enabled: true,
-            require_https: true,
-        });
-
-        // The test is incomplete as we cannot set up a real HTTP server
-        // In a real implementation, we would save the config using Config::save()
-
-        // 创建模拟的源索引文件
+        // 创建模拟的源索引文件（本地目录作为软件源）
         let source_index_dir = temp_dir.path().join("source_index");
         std::fs::create_dir_all(&source_index_dir)?;
         let source_index_path = source_index_dir.join("index.json");
@@ -240,8 +225,12 @@ mod tests {
                     "icon": "icon.png",
                     "author": "Test Author",
                     "latest_version": "1.0.0",
+                    "available_versions": ["0.9.0", "1.0.0"],
                     "description": "A test package",
-                    "location": "https://example.com/packages/test.package/1.0.0/"
+                    "location": "https://example.com/packages/test.package/1.0.0/",
+                    "size": 42,
+                    "signature": "abcd",
+                    "bundle": "test.package-1.0.0.tar.zst"
                 }
             ]
         });
@@ -251,8 +240,34 @@ mod tests {
             serde_json::to_string_pretty(&source_index)?,
         )?;
 
-        // 由于我们无法真正启动HTTP服务器进行测试，这里我们只测试代码结构
-        // 在实际实现中，需要设置一个本地HTTP服务器来提供测试索引
+        // 创建测试配置，放在与工作目录和索引目录都无关的位置，不叠加本机的系统配置和用户配置
+        let config_dir = temp_dir.path().join("config");
+        std::fs::create_dir_all(&config_dir)?;
+        let config_path = config_dir.join("config.toml");
+
+        let mut config = crate::config::RepositoryConfig::default();
+        config.source.push(crate::config::SourceConfig {
+            id: "test".to_string(),
+            name: "Test Source".to_string(),
+            ..test_source(format!("{}/", source_index_dir.display()))
+        });
+        ConfigManager::new(&config_path)?.save(&config)?;
+
+        let index_dir = temp_dir.path().join("index");
+        let index_manager = IndexManager::new(index_dir, temp_dir.path().to_path_buf())
+            .with_config(ConfigManager::new(&config_path)?.with_base_paths(Vec::new()));
+        index_manager.update_source_index().await?;
+
+        let package = index_manager.query_package("test.package")?.unwrap();
+        assert_eq!(package.latest_version, "1.0.0");
+        assert_eq!(package.available_versions, vec!["0.9.0", "1.0.0"]);
+        // 可选字段随条目一并保留
+        assert_eq!(package.size, Some(42));
+        assert_eq!(package.signature.as_deref(), Some("abcd"));
+        assert_eq!(
+            package.bundle.as_deref(),
+            Some("test.package-1.0.0.tar.zst")
+        );
         Ok(())
     }
 
@@ -0,0 +1,114 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::serde_utils::{load_json, save_json};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// 默认锁文件名（位于当前工作目录）
+pub const DEFAULT_LOCKFILE: &str = "pkgr.lock";
+
+/// 锁文件
+///
+/// 记录项目所需软件包的固定版本、来源和文件哈希，用于可重复的部署
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct Lockfile {
+    /// 软件包ID -> 锁定信息，按ID排序
+    #[serde(default)]
+    pub packages: BTreeMap<String, LockedPackage>,
+}
+
+/// 锁定的软件包
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LockedPackage {
+    /// 解析后的版本号
+    pub version: String,
+    /// 安装来源的软件源ID
+    pub source: String,
+    /// 文件清单（相对路径 -> 哈希值，格式同元数据清单中的哈希部分）
+    pub files: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// 读取锁文件，不存在时返回空锁文件
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        load_json(path).with_context(|| format!("无法读取锁文件: {}", path.display()))
+    }
+
+    /// 保存锁文件
+    pub fn save(&self, path: &Path) -> Result<()> {
+        save_json(self, path).with_context(|| format!("无法写入锁文件: {}", path.display()))
+    }
+
+    /// 添加或更新软件包的锁定信息
+    pub fn insert(&mut self, package_id: &str, package: LockedPackage) {
+        self.packages.insert(package_id.to_string(), package);
+    }
+
+    /// 移除软件包的锁定信息
+    ///
+    /// 指定版本时仅在锁定的正是该版本时移除，返回是否移除了条目
+    pub fn remove(&mut self, package_id: &str, version: Option<&str>) -> bool {
+        let matches = self
+            .packages
+            .get(package_id)
+            .is_some_and(|package| version.is_none_or(|version| package.version == version));
+        if matches {
+            self.packages.remove(package_id);
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn locked(version: &str) -> LockedPackage {
+        LockedPackage {
+            version: version.to_string(),
+            source: "default".to_string(),
+            files: BTreeMap::from([("index.html".to_string(), "abc123".to_string())]),
+        }
+    }
+
+    #[test]
+    fn test_lockfile_save_load() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join(DEFAULT_LOCKFILE);
+
+        // 不存在时视为空锁文件
+        assert_eq!(Lockfile::load(&path)?, Lockfile::default());
+
+        let mut lockfile = Lockfile::default();
+        lockfile.insert("app", locked("1.0.0"));
+        lockfile.insert("app", locked("1.1.0"));
+        lockfile.save(&path)?;
+
+        let loaded = Lockfile::load(&path)?;
+        assert_eq!(loaded.packages.len(), 1);
+        assert_eq!(loaded.packages["app"].version, "1.1.0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lockfile_remove() {
+        let mut lockfile = Lockfile::default();
+        lockfile.insert("app", locked("1.0.0"));
+
+        // 版本不符时保留条目
+        assert!(!lockfile.remove("app", Some("0.9.0")));
+        assert!(lockfile.packages.contains_key("app"));
+
+        assert!(lockfile.remove("app", Some("1.0.0")));
+        assert!(!lockfile.remove("app", None));
+    }
+}
@@ -0,0 +1,120 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! 分级日志：将 `log` 宏的输出写到标准错误，级别由命令行的 `-v` 次数决定
+
+use crate::style;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::io::{IsTerminal, Write};
+
+/// 输出到标准错误的日志记录器
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        #[cfg(test)]
+        if let Some(captured) = testing::CAPTURED.lock().unwrap().as_mut() {
+            captured.push_str(&format!("{} {}\n", label(record.level()), record.args()));
+            return;
+        }
+        let mut stderr = std::io::stderr().lock();
+        // 终端中先清除当前行，避免与 `\r` 刷新的进度行混在一起，进度在下次更新时重新绘制
+        if stderr.is_terminal() {
+            let _ = write!(stderr, "\r\x1b[2K");
+        }
+        let _ = writeln!(stderr, "{} {}", label(record.level()), record.args());
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// 日志级别的前缀
+fn label(level: Level) -> String {
+    match level {
+        Level::Error => style::red("错误:"),
+        Level::Warn => style::yellow("警告:"),
+        Level::Info => style::green("信息:"),
+        Level::Debug => style::dim("调试:"),
+        Level::Trace => style::dim("跟踪:"),
+    }
+}
+
+/// `-v` 次数对应的日志级别
+///
+/// 默认只输出警告和错误，`-v` 输出主要步骤，`-vv` 输出调试信息（如逐个下载的文件），`-vvv` 输出全部
+pub fn level_filter(verbosity: u8) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// 初始化日志输出
+///
+/// 应在程序启动时调用一次，重复调用时保留首次设置的记录器，只更新级别
+pub fn init(verbosity: u8) {
+    static LOGGER: StderrLogger = StderrLogger;
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level_filter(verbosity));
+}
+
+/// 测试中截获日志输出
+#[cfg(test)]
+pub mod testing {
+    use std::sync::Mutex;
+
+    /// 截获的日志输出，为 `Some` 时日志写入其中而不输出到标准错误
+    pub(super) static CAPTURED: Mutex<Option<String>> = Mutex::new(None);
+
+    /// 修改全局日志级别或截获日志的测试须持有此锁，避免相互干扰
+    pub static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    /// 开始截获日志输出（所有线程）
+    pub fn start_capture() {
+        *CAPTURED.lock().unwrap() = Some(String::new());
+    }
+
+    /// 停止截获，返回截获的输出
+    pub fn finish_capture() -> String {
+        CAPTURED.lock().unwrap().take().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_filter() {
+        assert_eq!(level_filter(0), LevelFilter::Warn);
+        assert_eq!(level_filter(1), LevelFilter::Info);
+        assert_eq!(level_filter(2), LevelFilter::Debug);
+        assert_eq!(level_filter(5), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn test_default_hides_debug_output() {
+        let _lock = testing::LOCK.blocking_lock();
+        // 未加 -v 时安装过程中逐个文件的调试输出不显示，警告照常显示
+        init(0);
+        assert!(!log::log_enabled!(Level::Debug));
+        assert!(!log::log_enabled!(Level::Info));
+        assert!(log::log_enabled!(Level::Warn));
+
+        init(2);
+        assert!(log::log_enabled!(Level::Debug));
+        init(0);
+    }
+}
@@ -2,20 +2,29 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use clap::{Parser, Subcommand};
+use anyhow::Context;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::io::IsTerminal;
 use std::path::PathBuf;
 
 // 引入模块
 mod app;
+mod archive;
 mod config;
 mod crypto;
 mod fsxg;
 mod index;
+mod lockfile;
+mod logging;
 mod metadata;
+mod model;
 mod net;
+mod pack;
 mod path;
 mod repo;
 mod serde_utils;
+mod style;
 mod transaction;
 mod version;
 
@@ -24,10 +33,115 @@ mod version;
 #[command(name = "pageos-pkgr")]
 #[command(about = "PageOS 系统的网页应用仓库管理工具", long_about = None)]
 struct Cli {
+    /// 颜色输出模式
+    #[arg(long, global = true, value_enum, default_value_t = style::ColorChoice::Auto)]
+    color: style::ColorChoice,
+
+    /// 静默模式（不显示进度）
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// 输出更多日志：-v 显示主要步骤，-vv 显示调试信息（默认只显示警告和错误）
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// 仓库被其他进程占用时等待其完成（默认立即报错）
+    #[arg(long, global = true)]
+    wait: bool,
+
+    /// 离线模式：禁止任何网络访问（也可设置环境变量 PAGEOS_PKGR_OFFLINE=1）
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// 输出格式
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Human)]
+    format: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+impl Cli {
+    /// 生效的输出格式
+    ///
+    /// 部分子命令的 `--json` 是 `--format json` 的已弃用别名，使用时输出警告
+    fn output_format(&self) -> OutputFormat {
+        let json = match &self.command {
+            Commands::Repo(
+                RepoCommands::List { json, .. }
+                | RepoCommands::Files { json, .. }
+                | RepoCommands::VerifyLock { json, .. }
+                | RepoCommands::Verify { json, .. }
+                | RepoCommands::Sources(
+                    SourcesCommands::Check { json, .. } | SourcesCommands::Verify { json, .. },
+                )
+                | RepoCommands::Config(ConfigCommands::Show { json, .. }),
+            ) => *json,
+            _ => false,
+        };
+        if json {
+            log::warn!("--json 已弃用，请使用 --format json");
+            OutputFormat::Json
+        } else {
+            self.format
+        }
+    }
+}
+
+/// 输出格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// 便于阅读的文本
+    Human,
+    /// 向标准输出输出 JSON：查询命令输出对应的数据结构，其它命令输出 `{"ok": ..., "message": ...}`
+    Json,
+}
+
+/// JSON 输出模式下命令的执行结果
+#[derive(Serialize)]
+struct CommandStatus<'a> {
+    ok: bool,
+    message: &'a str,
+}
+
+/// `repo config show` 输出的生效配置：合并后的仓库配置加上命令行和环境变量的覆盖项
+#[derive(Serialize)]
+struct EffectiveConfig {
+    /// 离线模式（`--offline` 或环境变量 `PAGEOS_PKGR_OFFLINE`）
+    offline: bool,
+    #[serde(flatten)]
+    config: config::RepositoryConfig,
+}
+
+/// 命令已向标准输出打印 JSON 结果、但仍需以非零状态退出时返回的错误
+///
+/// 每条命令只输出一个 JSON 文档，`main` 遇到该错误时不再追加 `{"ok": false, ...}`
+struct ReportedError(String);
+
+impl std::fmt::Display for ReportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// 与字符串错误一致，`main` 返回错误时打印的是 Debug 形式
+impl std::fmt::Debug for ReportedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for ReportedError {}
+
+/// 命令失败时的错误：已输出 JSON 结果时为 [`ReportedError`]，否则为普通错误
+fn failure(json_output: bool, message: String) -> Box<dyn std::error::Error> {
+    if json_output {
+        ReportedError(message).into()
+    } else {
+        message.into()
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// 应用包管理
@@ -57,6 +171,9 @@ enum AppCommands {
         /// 基础目录
         #[arg(default_value = ".")]
         base_dir: PathBuf,
+        /// 目标目录已有 metadata.json 时覆盖
+        #[arg(long)]
+        force: bool,
     },
 
     /// 添加文件或目录到软件包清单
@@ -67,6 +184,12 @@ enum AppCommands {
         /// 软件包路径
         #[arg(short, long, default_value = ".")]
         package: PathBuf,
+        /// 跟随符号链接（目标须位于软件包目录内），默认跳过符号链接
+        #[arg(long)]
+        follow_symlinks: bool,
+        /// 递归添加目录时排除匹配的文件（语法同 .pkgrignore，可多次指定）
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
     },
 
     /// 从软件包清单移除文件或目录
@@ -78,6 +201,98 @@ enum AppCommands {
         #[arg(short, long, default_value = ".")]
         package: PathBuf,
     },
+
+    /// 比较软件包清单与工作目录
+    Status {
+        /// 软件包路径
+        #[arg(short, long, default_value = ".")]
+        package: PathBuf,
+    },
+
+    /// 检查软件包的一致性
+    Validate {
+        /// 软件包路径
+        #[arg(short, long, default_value = ".")]
+        package: PathBuf,
+        /// 将警告视为错误
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// 检查软件包能否加入仓库（元数据、文件哈希、未加入清单的文件）
+    Check {
+        /// 软件包路径
+        #[arg(short, long, default_value = ".")]
+        package: PathBuf,
+    },
+
+    /// 检查并打包软件包：默认生成 <id>-<version>.tar.zst 归档
+    Pack {
+        /// 软件包路径
+        #[arg(short, long, default_value = ".")]
+        package: PathBuf,
+        /// 输出目录
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        /// 改为输出适合静态托管的目录结构（packages/<id>/<version>/ 及索引片段）
+        #[arg(long)]
+        directory: bool,
+    },
+
+    /// 用 Ed25519 私钥对软件包元数据签名
+    Sign {
+        /// 私钥：密钥目录中的密钥名，或私钥文件路径（内容为 32 字节私钥种子的十六进制，
+        /// 如 `openssl rand -hex 32` 的输出）
+        #[arg(short, long, default_value = "default")]
+        key: String,
+        /// 软件包路径
+        #[arg(short, long, default_value = ".")]
+        package: PathBuf,
+    },
+
+    /// 按语义化版本递增软件包版本号
+    #[command(arg_required_else_help = true)]
+    Bump {
+        /// 要递增的部分
+        #[arg(value_enum)]
+        level: version::BumpLevel,
+        /// 软件包路径
+        #[arg(short, long, default_value = ".")]
+        package: PathBuf,
+    },
+
+    /// 添加或更新软件包依赖
+    #[command(arg_required_else_help = true)]
+    Depend {
+        /// 依赖的软件包ID
+        id: String,
+        /// 版本约束，如 ^1.2.0、">=1.0, <2.0"
+        #[arg(default_value = "*")]
+        version_req: String,
+        /// 软件包路径
+        #[arg(short, long, default_value = ".")]
+        package: PathBuf,
+    },
+
+    /// 移除软件包依赖
+    #[command(arg_required_else_help = true)]
+    Undepend {
+        /// 依赖的软件包ID
+        id: String,
+        /// 软件包路径
+        #[arg(short, long, default_value = ".")]
+        package: PathBuf,
+    },
+
+    /// 生成 Ed25519 签名密钥，保存到配置目录下的 keys/ 中
+    Keygen {
+        /// 密钥名
+        #[arg(default_value = "default")]
+        name: String,
+        /// 覆盖已存在的同名密钥
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -102,40 +317,163 @@ enum RepoCommands {
     /// 清理仓库
     #[command(arg_required_else_help = true)]
     Clean {
-        /// 仓库路径
-        #[arg(short, long, default_value = "~/.local/share/pageos/")]
-        repo: PathBuf,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 每个软件包保留的最新版本数
+        #[arg(
+            long,
+            default_value_t = repo::RepoManager::DEFAULT_KEEP_VERSIONS,
+            value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+        )]
+        keep_versions: usize,
+        /// 只列出将要删除的内容，不实际执行
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// 更新仓库索引
     #[command(arg_required_else_help = true)]
     Update {
-        /// 仓库路径
-        #[arg(short, long, default_value = "~/.local/share/pageos/")]
-        repo: PathBuf,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
         /// 本地更新模式
         #[arg(long)]
         local: bool,
     },
 
+    /// 从磁盘重建索引
+    Reindex {
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 同时重新获取 source 部分
+        #[arg(long)]
+        with_source: bool,
+    },
+
+    /// 用 Ed25519 私钥对仓库索引签名，生成 index.json.sig
+    SignIndex {
+        /// 私钥：密钥目录中的密钥名，或私钥文件路径
+        #[arg(short, long, default_value = "default")]
+        key: String,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+    },
+
+    /// 检查索引与 packages/ 目录、versions.txt 是否一致
+    Doctor {
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 修复发现的问题：清理 versions.txt 中不存在的版本并重新生成索引的 packages 部分
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// 导出仓库为归档
+    #[command(arg_required_else_help = true)]
+    Export {
+        /// 输出归档路径（.tar.gz）
+        output: PathBuf,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 同时导出索引的 source 部分
+        #[arg(long)]
+        include_source: bool,
+    },
+
+    /// 从归档导入软件包
+    #[command(arg_required_else_help = true)]
+    Import {
+        /// 归档路径（.tar.gz）
+        archive: PathBuf,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+    },
+
     /// 添加软件包到仓库
     #[command(arg_required_else_help = true)]
     Add {
         /// 软件包路径
         package_path: PathBuf,
-        /// 仓库路径
-        #[arg(short, long, default_value = "~/.local/share/pageos/")]
-        repo: PathBuf,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 仅更新已有版本中的单个文件（相对于软件包的路径）
+        #[arg(long, value_name = "RELPATH")]
+        update_file: Option<String>,
+        /// 覆盖仓库中已存在的同一版本（先清空该版本目录）
+        #[arg(long, conflicts_with = "update_file")]
+        force: bool,
+    },
+
+    /// 按ID、名称或描述搜索软件包（不区分大小写）
+    #[command(arg_required_else_help = true)]
+    Search {
+        /// 搜索关键字
+        query: String,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 只搜索已安装的软件包
+        #[arg(long)]
+        installed_only: bool,
+    },
+
+    /// 列出软件包（默认列出已安装的软件包）
+    List {
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 列出软件源中尚未安装的软件包（与 --installed 同时使用时列出全部）
+        #[arg(long)]
+        available: bool,
+        /// 列出已安装的软件包（与 --available 同时使用时列出全部）
+        #[arg(long)]
+        installed: bool,
+        /// 已弃用：等同于 `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+
+    /// 查看软件包的详细信息（已安装时读取本地元数据，否则从软件源获取）
+    #[command(arg_required_else_help = true)]
+    Info {
+        /// 软件包ID
+        package_id: String,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
     },
 
     /// 安装软件包
     #[command(arg_required_else_help = true)]
     Install {
-        /// 软件源ID:软件包ID:版本
+        /// 软件源ID:软件包ID:版本（软件包ID可使用 `*`、`?` 通配符批量安装，如 `pageos:pageos-*`；版本可以是约束，如 `^1.2.0`）
         source_package_version: String,
-        /// 仓库路径
-        #[arg(short, long, default_value = "~/.local/share/pageos/")]
-        repo: PathBuf,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 跳过安装后钩子
+        #[arg(long)]
+        no_hooks: bool,
+        /// 校验和覆盖文件（JSON，相对路径 -> 预期哈希），优先于元数据中的哈希
+        #[arg(long, value_name = "FILE")]
+        checksum_override: Option<PathBuf>,
+        /// 安装成功后将锁定信息写入锁文件
+        #[arg(long)]
+        save: bool,
+        /// 锁文件路径（默认为当前目录下的 pkgr.lock）
+        #[arg(long, value_name = "FILE", requires = "save")]
+        lockfile: Option<PathBuf>,
+        /// 版本为约束（如 `^1.2.0`）时选择满足约束的最高或最低版本
+        #[arg(long, value_enum, default_value_t = version::Preference::Newest)]
+        prefer: version::Preference,
     },
 
     /// 卸载软件包
@@ -143,21 +481,113 @@ enum RepoCommands {
     Remove {
         /// 软件包ID:版本
         package_version: String,
-        /// 仓库路径
-        #[arg(short, long, default_value = "~/.local/share/pageos/")]
-        repo: PathBuf,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 卸载成功后从锁文件中移除该软件包
+        #[arg(long)]
+        save: bool,
+        /// 锁文件路径（默认为当前目录下的 pkgr.lock）
+        #[arg(long, value_name = "FILE", requires = "save")]
+        lockfile: Option<PathBuf>,
+        /// 只列出将要删除的内容，不实际执行
+        #[arg(long)]
+        dry_run: bool,
     },
 
     /// 升级软件包
     #[command(arg_required_else_help = true)]
     Upgrade {
+        /// 软件包ID
+        #[arg(required_unless_present = "all")]
+        package_id: Option<String>,
+        /// 升级所有已安装且软件源中有更高版本的软件包（遵循固定版本），单个失败时继续
+        #[arg(long, conflicts_with = "package_id")]
+        all: bool,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+    },
+
+    /// 固定软件包的版本，升级时不超出该版本
+    #[command(arg_required_else_help = true)]
+    Pin {
         /// 软件包ID
         package_id: String,
-        /// 仓库路径
-        #[arg(short, long, default_value = "~/.local/share/pageos/")]
-        repo: PathBuf,
+        /// 版本号或版本约束（如 `1.2.3`、`~1.2.0`）
+        version: String,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+    },
+
+    /// 取消固定软件包的版本
+    #[command(arg_required_else_help = true)]
+    Unpin {
+        /// 软件包ID
+        package_id: String,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
     },
 
+    /// 查看已安装软件包各版本的大小
+    #[command(arg_required_else_help = true)]
+    Size {
+        /// 软件包ID
+        package_id: String,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+    },
+
+    /// 列出已安装软件包的文件
+    #[command(arg_required_else_help = true)]
+    Files {
+        /// 软件包ID:版本（版本可省略，默认为已安装的最高版本）
+        package_version: String,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 同时显示哈希和大小
+        #[arg(short, long)]
+        long: bool,
+        /// 已弃用：等同于 `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+
+    /// 对照锁文件检查已安装的软件包，报告缺失的软件包、版本不符和哈希不符
+    #[command(arg_required_else_help = true)]
+    VerifyLock {
+        /// 锁文件路径
+        lockfile: PathBuf,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 已弃用：等同于 `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+
+    /// 校验所有已安装的软件包：重新计算文件哈希，并按软件源的受信任公钥验证签名
+    Verify {
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 已弃用：等同于 `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+
+    /// 软件源管理
+    #[command(subcommand)]
+    Sources(SourcesCommands),
+
+    /// 仓库配置
+    #[command(subcommand)]
+    Config(ConfigCommands),
+
     /// 同步仓库
     #[command(arg_required_else_help = true)]
     Sync {
@@ -166,86 +596,651 @@ enum RepoCommands {
         /// 镜像同步模式
         #[arg(long)]
         mirror: bool,
-        /// 仓库路径
-        #[arg(short, long, default_value = "~/.local/share/pageos/")]
-        repo: PathBuf,
+        /// 强制切换同步模式并清理遗留的镜像文件
+        #[arg(long)]
+        force: bool,
+        /// 只列出将要下载和修改的内容，不实际执行
+        #[arg(long)]
+        dry_run: bool,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+    },
+
+    /// 列出软件源自上次同步以来新增、更新和移除的软件包
+    Changelog {
+        /// 软件源ID
+        source_id: Option<String>,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SourcesCommands {
+    /// 列出已配置的软件源
+    List {
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+    },
+
+    /// 检查已启用软件源的可用性
+    Check {
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 已弃用：等同于 `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+
+    /// 校验软件源发布的全部软件包（只读，不保存文件）
+    #[command(arg_required_else_help = true)]
+    Verify {
+        /// 软件源ID
+        source_id: String,
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 已弃用：等同于 `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// 显示生效的配置（已填充默认值，隐去 URL 中的凭据）
+    Show {
+        /// 仓库路径（默认从当前目录向上查找，未找到时为 ~/.local/share/pageos/）
+        #[arg(short, long)]
+        repo: Option<PathBuf>,
+        /// 已弃用：等同于 `--format json`
+        #[arg(long, hide = true)]
+        json: bool,
     },
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
+    style::init(cli.color);
+    logging::init(cli.verbose);
+    let format = cli.output_format();
+    let json_output = format == OutputFormat::Json;
+
+    let result = run(&cli, format).await;
+    if let Err(e) = &result
+        && json_output
+        && !e.is::<ReportedError>()
+    {
+        print_json(&CommandStatus {
+            ok: false,
+            message: &e.to_string(),
+        })?;
+    }
+    result
+}
 
+/// 执行命令，`format` 为 JSON 时向标准输出打印且只打印一个 JSON 文档
+async fn run(cli: &Cli, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+    let json_output = format == OutputFormat::Json;
     match &cli.command {
         Commands::App(app_cmd) => match app_cmd {
             AppCommands::Init { package_path } => {
                 app::init(package_path)?;
-                println!("已成功在 {} 初始化应用包", package_path.display());
+                print_success(
+                    json_output,
+                    &format!("已成功在 {} 初始化应用包", package_path.display()),
+                );
             }
             AppCommands::New {
                 package_id,
                 base_dir,
+                force,
             } => {
-                let package_path = app::new(package_id, base_dir)?;
-                println!("已成功创建新应用包: {}", package_path.display());
+                let package_path = app::new(package_id, base_dir, *force)?;
+                print_success(
+                    json_output,
+                    &format!("已成功创建新应用包: {}", package_path.display()),
+                );
             }
-            AppCommands::Add { path, package } => {
-                app::add_file(path, package)?;
-                println!("已成功添加 {} 到软件包清单", path.display());
+            AppCommands::Add {
+                path,
+                package,
+                follow_symlinks,
+                exclude,
+            } => {
+                // 进度输出到标准错误，仅在终端中显示
+                let show_progress = !cli.quiet && std::io::stderr().is_terminal();
+                let report = app::add_file_with_progress(
+                    path,
+                    package,
+                    *follow_symlinks,
+                    exclude,
+                    |done, total, bytes| {
+                        if show_progress {
+                            eprint!(
+                                "\r哈希进度: {done}/{total} 个文件, {}",
+                                style::format_bytes(bytes)
+                            );
+                            if done == total {
+                                eprintln!();
+                            }
+                        }
+                    },
+                )?;
+                for symlink in &report.skipped {
+                    eprintln!(
+                        "{} 已跳过符号链接 {}（使用 --follow-symlinks 跟随）",
+                        style::yellow("警告:"),
+                        symlink.display()
+                    );
+                }
+                if json_output {
+                    print_json(&report)?;
+                } else if report.is_changed() {
+                    for file in &report.added {
+                        println!("{} {file}", style::green("新增:"));
+                    }
+                    for file in &report.updated {
+                        println!("{} {file}", style::yellow("已更新:"));
+                    }
+                    print_success(
+                        json_output,
+                        &format!(
+                            "已成功添加 {} 到软件包清单: 新增 {} 个，更新 {} 个，未变化 {} 个文件",
+                            path.display(),
+                            report.added.len(),
+                            report.updated.len(),
+                            report.unchanged.len()
+                        ),
+                    );
+                } else {
+                    print_success(
+                        json_output,
+                        &format!(
+                            "软件包清单无变化: {} 个文件均未变化",
+                            report.unchanged.len()
+                        ),
+                    );
+                }
+                for issue in app::validate(package)? {
+                    eprintln!("{} {issue}", style::yellow("警告:"));
+                }
             }
             AppCommands::Remove { path, package } => {
                 app::remove_file(path, package)?;
-                println!("已成功从软件包清单移除 {}", path.display());
+                print_success(
+                    json_output,
+                    &format!("已成功从软件包清单移除 {}", path.display()),
+                );
+            }
+            AppCommands::Status { package } => {
+                let status = app::status(package)?;
+                if json_output {
+                    print_json(&status)?;
+                } else if status.is_clean() {
+                    print_success(json_output, "软件包清单与工作目录一致");
+                } else {
+                    for file in &status.modified {
+                        println!("{} {file}", style::yellow("已修改:"));
+                    }
+                    for file in &status.missing {
+                        println!("{} {file}", style::red("已缺失:"));
+                    }
+                    for file in &status.untracked {
+                        println!("{} {file}", style::dim("未跟踪:"));
+                    }
+                    println!(
+                        "使用 `pageos-pkgr app add` 或 `pageos-pkgr app remove` 更新软件包清单"
+                    );
+                }
+            }
+            AppCommands::Validate { package, strict } => {
+                let issues = app::validate(package)?;
+                if issues.is_empty() {
+                    print_success(json_output, "软件包检查通过");
+                } else if *strict {
+                    return Err(format!("软件包检查未通过:\n  {}", issues.join("\n  ")).into());
+                } else {
+                    for issue in &issues {
+                        eprintln!("{} {issue}", style::yellow("警告:"));
+                    }
+                    print_success(
+                        json_output,
+                        &format!("软件包检查通过: {} 个警告", issues.len()),
+                    );
+                }
+            }
+            AppCommands::Check { package } => {
+                let result = app::check(package)?;
+                let errors = result.error_count();
+                let warnings = result.warning_count();
+                if json_output {
+                    print_json(&result)?;
+                    if errors > 0 {
+                        return Err(failure(
+                            json_output,
+                            format!(
+                                "软件包检查未通过: {} 个文件，{errors} 个错误，{warnings} 个警告",
+                                result.files
+                            ),
+                        ));
+                    }
+                    return Ok(());
+                }
+                for problem in &result.problems {
+                    println!("{} {problem}", style::red("错误:"));
+                }
+                for file in &result.status.modified {
+                    println!("{} 哈希不匹配: {file}", style::red("错误:"));
+                }
+                for file in &result.status.missing {
+                    println!("{} 文件缺失: {file}", style::red("错误:"));
+                }
+                for issue in &result.issues {
+                    println!("{} {issue}", style::yellow("警告:"));
+                }
+                for file in &result.status.untracked {
+                    println!("{} 文件未加入清单: {file}", style::yellow("警告:"));
+                }
+
+                if errors > 0 {
+                    return Err(format!(
+                        "软件包检查未通过: {} 个文件，{errors} 个错误，{warnings} 个警告",
+                        result.files
+                    )
+                    .into());
+                }
+                print_success(
+                    json_output,
+                    &format!("软件包检查通过: {} 个文件，{warnings} 个警告", result.files),
+                );
+            }
+            AppCommands::Pack {
+                package,
+                output,
+                directory,
+            } => {
+                if *directory {
+                    let packed = pack::pack_directory(package, output)?;
+                    print_success(
+                        json_output,
+                        &format!(
+                            "已生成目录结构: {}\n索引片段: {}",
+                            packed.version_dir.display(),
+                            packed.fragment.display()
+                        ),
+                    );
+                } else {
+                    let packed = pack::pack_bundle(package, output)?;
+                    print_success(
+                        json_output,
+                        &format!(
+                            "已生成软件包归档: {} ({})\nSHA256: {}",
+                            packed.path.display(),
+                            style::format_bytes(packed.size),
+                            packed.hash
+                        ),
+                    );
+                }
+            }
+            AppCommands::Sign { key, package } => {
+                let public_key = app::sign(package, &app::load_secret_key(key)?)?;
+                print_success_with_key(json_output, "已成功对软件包签名", &public_key);
+            }
+            AppCommands::Bump { level, package } => {
+                let (old_version, new_version) = app::bump(package, *level)?;
+                print_success(
+                    json_output,
+                    &format!("版本号已更新: {old_version} -> {new_version}"),
+                );
+            }
+            AppCommands::Depend {
+                id,
+                version_req,
+                package,
+            } => {
+                app::add_dependency(package, id, version_req)?;
+                print_success(json_output, &format!("已添加依赖 {id} {version_req}"));
+            }
+            AppCommands::Undepend { id, package } => {
+                app::remove_dependency(package, id)?;
+                print_success(json_output, &format!("已移除依赖 {id}"));
+            }
+            AppCommands::Keygen { name, force } => {
+                let keys_dir = path::get_keys_dir();
+                let public_key = app::keygen(&keys_dir, name, *force)?;
+                print_success_with_key(
+                    json_output,
+                    &format!(
+                        "已生成密钥: {}",
+                        keys_dir.join(format!("{name}.key")).display()
+                    ),
+                    &public_key,
+                );
             }
         },
         Commands::Repo(repo_cmd) => {
             match repo_cmd {
                 RepoCommands::Init { repo_path } => {
                     repo::RepoManager::init(repo_path)?;
-                    println!("已成功在 {} 初始化应用仓库", repo_path.display());
+                    print_success(
+                        json_output,
+                        &format!("已成功在 {} 初始化应用仓库", repo_path.display()),
+                    );
                 }
                 RepoCommands::New {
                     repo_name,
                     base_dir,
                 } => {
                     let _repo_manager = repo::RepoManager::new(repo_name, base_dir)?;
-                    println!("已成功创建新应用仓库");
+                    print_success(json_output, "已成功创建新应用仓库");
                 }
-                RepoCommands::Clean { repo } => {
-                    let mut repo_manager = repo::RepoManager::open(repo.clone())?;
-                    repo_manager.clean()?;
-                    println!("已成功清理仓库 {}", repo.display());
+                RepoCommands::Clean {
+                    repo,
+                    keep_versions,
+                    dry_run,
+                } => {
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    let report = repo_manager.clean(*keep_versions, *dry_run)?;
+                    for orphan in &report.orphans {
+                        eprintln!(
+                            "{} packages/{orphan} 未记录在 versions.txt 中，无法判断新旧，已保留",
+                            style::yellow("警告:")
+                        );
+                    }
+                    if *dry_run {
+                        print_planned_changes(json_output, report.changes.changes());
+                    } else {
+                        print_success(
+                            json_output,
+                            &format!("已成功清理仓库 {}", resolve_repo(repo).display()),
+                        );
+                    }
                 }
                 RepoCommands::Update { repo, local } => {
-                    let mut repo_manager = repo::RepoManager::open(repo.clone())?;
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
                     if *local {
                         // 更新本地索引
                         repo_manager.update_local_index()?;
-                        println!("已成功更新本地索引");
+                        print_success(json_output, "已成功更新本地索引");
                     } else {
                         // 更新索引 source 部分
                         repo_manager.update_source_index().await?;
-                        println!("已成功更新源索引");
+                        print_success(json_output, "已成功更新源索引");
+                    }
+                }
+                RepoCommands::Reindex { repo, with_source } => {
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    let rebuilt = repo_manager.reindex(*with_source).await?;
+                    for package_id in &rebuilt {
+                        eprintln!(
+                            "{} 已根据版本目录重建 {} 的 versions.txt",
+                            style::yellow("警告:"),
+                            package_id
+                        );
                     }
+                    print_success(json_output, "已成功重建索引");
                 }
-                RepoCommands::Add { package_path, repo } => {
-                    let mut repo_manager = repo::RepoManager::open(repo.clone())?;
-                    repo_manager.add_package(package_path)?;
-                    println!("已成功添加软件包到仓库");
+                RepoCommands::SignIndex { key, repo } => {
+                    let repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    let public_key = repo_manager.sign_index(&app::load_secret_key(key)?)?;
+                    print_success_with_key(json_output, "已成功对索引签名", &public_key);
+                }
+                RepoCommands::Doctor { repo, fix } => {
+                    let lock_mode = if *fix {
+                        repo::LockMode::Exclusive
+                    } else {
+                        repo::LockMode::Shared
+                    };
+                    let repo_manager = open_repo(repo, lock_mode, cli)?;
+                    let problems = repo_manager.doctor(*fix)?;
+                    if json_output {
+                        print_json(&problems)?;
+                    } else {
+                        for problem in &problems {
+                            println!("{problem}");
+                        }
+                    }
+                    if problems.is_empty() {
+                        if !json_output {
+                            print_success(json_output, "索引与磁盘一致");
+                        }
+                    } else if *fix {
+                        if !json_output {
+                            print_success(
+                                json_output,
+                                &format!("已修复 {} 个问题", problems.len()),
+                            );
+                        }
+                    } else {
+                        return Err(failure(
+                            json_output,
+                            format!("发现 {} 个问题，可使用 --fix 修复", problems.len()),
+                        ));
+                    }
+                }
+                RepoCommands::Export {
+                    output,
+                    repo,
+                    include_source,
+                } => {
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let count = repo_manager.export(output, *include_source)?;
+                    print_success(
+                        json_output,
+                        &format!("已导出 {count} 个文件到 {}", output.display()),
+                    );
+                }
+                RepoCommands::Import { archive, repo } => {
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    let imported = repo_manager.import(archive)?;
+                    if !json_output {
+                        for package in &imported {
+                            println!("{package}");
+                        }
+                    }
+                    print_success(
+                        json_output,
+                        &format!("已导入 {} 个软件包版本", imported.len()),
+                    );
+                }
+                RepoCommands::Add {
+                    package_path,
+                    repo,
+                    update_file,
+                    force,
+                } => {
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    if let Some(relative_path) = update_file {
+                        repo_manager.update_package_file(package_path, relative_path)?;
+                        print_success(
+                            json_output,
+                            &format!("已成功更新软件包文件 {relative_path}"),
+                        );
+                    } else {
+                        repo_manager.add_package(package_path, *force)?;
+                        print_success(json_output, "已成功添加软件包到仓库");
+                    }
+                }
+                RepoCommands::Search {
+                    query,
+                    repo,
+                    installed_only,
+                } => {
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let packages = repo_manager.search_packages(query, *installed_only)?;
+                    if json_output {
+                        print_json(&packages)?;
+                    } else if packages.is_empty() {
+                        println!("没有找到与 {query} 匹配的软件包");
+                    } else {
+                        let rows: Vec<Vec<String>> = packages
+                            .into_iter()
+                            .map(|package| {
+                                let description =
+                                    package.description.lines().next().unwrap_or("").to_string();
+                                vec![package.id, package.latest_version, description]
+                            })
+                            .collect();
+                        println!("{}", style::table(&["ID", "最新版本", "描述"], &rows));
+                    }
+                }
+                RepoCommands::List {
+                    repo,
+                    available,
+                    installed,
+                    ..
+                } => {
+                    let filter = match (*installed, *available) {
+                        (true, true) => repo::ListFilter::All,
+                        (false, true) => repo::ListFilter::Available,
+                        _ => repo::ListFilter::Installed,
+                    };
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let packages = repo_manager.list(filter)?;
+                    if json_output {
+                        println!("{}", serde_json::to_string_pretty(&packages)?);
+                    } else if packages.is_empty() {
+                        println!(
+                            "{}",
+                            match filter {
+                                repo::ListFilter::Installed => "没有已安装的软件包",
+                                repo::ListFilter::Available => "没有可安装的软件包",
+                                repo::ListFilter::All => "索引中没有软件包",
+                            }
+                        );
+                    } else {
+                        print_package_list(&packages, filter);
+                    }
+                }
+                RepoCommands::Info { package_id, repo } => {
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let metadata = repo_manager.package_info(package_id).await?;
+                    let pin = repo_manager.config().pinned.get(package_id);
+                    let installed = repo_manager.installed_package(package_id)?;
+                    let installed_at = installed.as_ref().and_then(|p| p.installed_at.clone());
+                    let installed_from = installed.as_ref().and_then(|p| p.installed_from.clone());
+                    if json_output {
+                        let mut value = serde_json::to_value(&metadata)?;
+                        if let Some(pin) = pin {
+                            value["pinned"] = pin.as_str().into();
+                        }
+                        if let Some(installed_at) = installed_at {
+                            value["installed_at"] = installed_at.into();
+                        }
+                        if let Some(installed_from) = installed_from {
+                            value["installed_from"] = installed_from.into();
+                        }
+                        return print_json(&value);
+                    }
+                    let field = |label: &str, value: &str| {
+                        println!("{} {value}", style::bold(&format!("{label}:")));
+                    };
+                    field("名称", &metadata.name);
+                    field("ID", &metadata.id);
+                    field("版本", &metadata.version);
+                    field("作者", &metadata.author);
+                    field("分类", &metadata.category);
+                    field("描述", &metadata.description);
+                    if metadata.permissions.is_empty() {
+                        field("权限", &style::dim("无"));
+                    } else {
+                        field("权限", &metadata.permissions.join(", "));
+                    }
+                    field("入口", &metadata.entry.paths().join(", "));
+                    field("文件数", &metadata.all_files.len().to_string());
+                    field(
+                        "固定版本",
+                        &pin.cloned().unwrap_or_else(|| style::dim("未固定")),
+                    );
+                    if installed.is_some() {
+                        field(
+                            "安装来源",
+                            &installed_from.unwrap_or_else(|| style::dim("本地添加")),
+                        );
+                        field(
+                            "安装时间",
+                            &installed_at.unwrap_or_else(|| style::dim("未知")),
+                        );
+                    }
                 }
                 RepoCommands::Install {
                     source_package_version,
                     repo,
+                    no_hooks,
+                    checksum_override,
+                    save,
+                    lockfile,
+                    prefer,
                 } => {
-                    let mut repo_manager = repo::RepoManager::open(repo.clone())?;
-                    repo_manager
-                        .install_package(source_package_version, None)
-                        .await?;
-                    println!("已成功安装软件包 {source_package_version}");
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    let checksum_overrides = match checksum_override {
+                        Some(path) => serde_utils::load_json(path).with_context(|| {
+                            format!("无法读取校验和覆盖文件: {}", path.display())
+                        })?,
+                        None => Default::default(),
+                    };
+                    let options = repo::InstallOptions {
+                        no_hooks: *no_hooks,
+                        checksum_overrides,
+                        lockfile: save.then(|| resolve_lockfile(lockfile)),
+                        prefer: *prefer,
+                    };
+                    let specs = repo_manager.expand_package_spec(source_package_version)?;
+                    if let [spec] = specs.as_slice() {
+                        repo_manager.install_package(spec, None, &options).await?;
+                        print_success(json_output, &format!("已成功安装软件包 {spec}"));
+                    } else {
+                        // 批量安装：单个软件包失败不影响其余软件包，最后汇总结果
+                        let mut rows = Vec::new();
+                        let mut failed = 0;
+                        for spec in &specs {
+                            match repo_manager.install_package(spec, None, &options).await {
+                                Ok(()) => rows.push(vec![
+                                    spec.clone(),
+                                    style::green("成功"),
+                                    String::new(),
+                                ]),
+                                Err(e) => {
+                                    failed += 1;
+                                    rows.push(vec![
+                                        spec.clone(),
+                                        style::red("失败"),
+                                        e.to_string(),
+                                    ]);
+                                }
+                            }
+                        }
+                        if json_output {
+                            // 表格不是 JSON，失败原因改为逐条输出到标准错误
+                            for row in rows.iter().filter(|row| !row[2].is_empty()) {
+                                eprintln!("{} {}: {}", style::red("错误:"), row[0], row[2]);
+                            }
+                        } else {
+                            println!("{}", style::table(&["软件包", "结果", "错误"], &rows));
+                        }
+                        if failed > 0 {
+                            return Err(format!(
+                                "批量安装完成: {} 个成功，{} 个失败",
+                                specs.len() - failed,
+                                failed
+                            )
+                            .into());
+                        }
+                        print_success(json_output, &format!("已成功安装 {} 个软件包", specs.len()));
+                    }
                 }
                 RepoCommands::Remove {
                     package_version,
                     repo,
+                    save,
+                    lockfile,
+                    dry_run,
                 } => {
                     // 解析 package:version
                     let parts: Vec<&str> = package_version.split(':').collect();
@@ -256,28 +1251,601 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         None
                     };
 
-                    let mut repo_manager = repo::RepoManager::open(repo.clone())?;
-                    repo_manager.remove_package(package_id, version)?;
-                    println!("已成功卸载软件包 {package_id}");
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    let changes = repo_manager.remove_package(package_id, version, *dry_run)?;
+                    let mut planned = changes.changes().to_vec();
+                    if *save {
+                        let lockfile_path = resolve_lockfile(lockfile);
+                        let mut lockfile = lockfile::Lockfile::load(&lockfile_path)?;
+                        if lockfile.remove(package_id, version) {
+                            if *dry_run {
+                                planned.push(repo::PlannedChange::WriteFile(lockfile_path));
+                            } else {
+                                lockfile.save(&lockfile_path)?;
+                            }
+                        }
+                    }
+                    if *dry_run {
+                        print_planned_changes(json_output, &planned);
+                    } else {
+                        print_success(json_output, &format!("已成功卸载软件包 {package_id}"));
+                    }
                 }
-                RepoCommands::Upgrade { package_id, repo } => {
-                    let mut repo_manager = repo::RepoManager::open(repo.clone())?;
-                    repo_manager.upgrade_package(package_id).await?;
-                    println!("已成功升级软件包 {package_id}");
+                RepoCommands::Upgrade {
+                    package_id, repo, ..
+                } => {
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    if let Some(package_id) = package_id {
+                        let outcome = repo_manager.upgrade_package(package_id).await?;
+                        print_upgrade_outcome(json_output, package_id, &outcome);
+                        return Ok(());
+                    }
+
+                    // 未指定软件包时即 --all
+                    let results = repo_manager.upgrade_all().await?;
+                    if json_output {
+                        print_json(&results)?;
+                    } else {
+                        for result in &results {
+                            match &result.outcome {
+                                Some(outcome) => {
+                                    print_upgrade_outcome(json_output, &result.id, outcome)
+                                }
+                                None => eprintln!(
+                                    "{} {} 升级失败: {}",
+                                    style::red("错误:"),
+                                    result.id,
+                                    result.error.as_deref().unwrap_or_default()
+                                ),
+                            }
+                        }
+                    }
+                    let failed = results.iter().filter(|r| r.error.is_some()).count();
+                    if failed > 0 {
+                        return Err(failure(json_output, format!("{failed} 个软件包升级失败")));
+                    }
+                    if !json_output {
+                        print_success(json_output, &format!("已检查 {} 个软件包", results.len()));
+                    }
+                }
+                RepoCommands::Pin {
+                    package_id,
+                    version,
+                    repo,
+                } => {
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    repo_manager.pin_package(package_id, version)?;
+                    print_success(json_output, &format!("已将 {package_id} 固定为 {version}"));
+                }
+                RepoCommands::Unpin { package_id, repo } => {
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    repo_manager.unpin_package(package_id)?;
+                    print_success(json_output, &format!("已取消固定 {package_id}"));
+                }
+                RepoCommands::Size { package_id, repo } => {
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let sizes = repo_manager.package_sizes(package_id)?;
+                    if json_output {
+                        let sizes: Vec<serde_json::Value> = sizes
+                            .into_iter()
+                            .map(|(version, size)| {
+                                serde_json::json!({ "version": version, "size": size })
+                            })
+                            .collect();
+                        return print_json(&sizes);
+                    }
+                    let rows: Vec<Vec<String>> = sizes
+                        .into_iter()
+                        .map(|(version, size)| {
+                            vec![
+                                version,
+                                size.map(style::format_bytes)
+                                    .unwrap_or_else(|| style::dim("未知")),
+                            ]
+                        })
+                        .collect();
+                    println!("{}", style::table(&["版本", "大小"], &rows));
+                }
+                RepoCommands::Files {
+                    package_version,
+                    repo,
+                    long,
+                    ..
+                } => {
+                    let (package_id, version) = match package_version.split_once(':') {
+                        Some((package_id, version)) => (package_id, Some(version)),
+                        None => (package_version.as_str(), None),
+                    };
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let installed = repo_manager.package_files(package_id, version)?;
+                    if json_output {
+                        println!("{}", serde_json::to_string_pretty(&installed)?);
+                    } else if *long {
+                        let rows: Vec<Vec<String>> = installed
+                            .files
+                            .iter()
+                            .map(|file| {
+                                vec![
+                                    file.path.clone(),
+                                    file.size
+                                        .map(style::format_bytes)
+                                        .unwrap_or_else(|| style::dim("未知")),
+                                    file.hash.clone(),
+                                ]
+                            })
+                            .collect();
+                        println!("{}", style::table(&["文件", "大小", "SHA256"], &rows));
+                    } else {
+                        for file in &installed.files {
+                            println!("{}", file.path);
+                        }
+                    }
+                }
+                RepoCommands::Verify { repo, .. } => {
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let verification = repo_manager.verify_installed()?;
+                    if json_output {
+                        println!("{}", serde_json::to_string_pretty(&verification)?);
+                    } else if !verification.packages.is_empty() {
+                        print_package_verifications(&verification.packages);
+                    }
+                    if verification.failed > 0 {
+                        return Err(failure(
+                            json_output,
+                            format!(
+                                "已安装软件包校验失败: {} 个通过，{} 个失败",
+                                verification.passed, verification.failed
+                            ),
+                        ));
+                    }
+                    if !json_output {
+                        print_success(
+                            json_output,
+                            &format!("已安装软件包校验通过: 共 {} 个", verification.passed),
+                        );
+                    }
+                }
+                RepoCommands::VerifyLock { lockfile, repo, .. } => {
+                    if !lockfile.exists() {
+                        return Err(format!("锁文件不存在: {}", lockfile.display()).into());
+                    }
+                    let locked = lockfile::Lockfile::load(lockfile)?;
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let drifts = repo_manager.verify_lockfile(&locked)?;
+                    if json_output {
+                        println!("{}", serde_json::to_string_pretty(&drifts)?);
+                    } else if !drifts.is_empty() {
+                        let rows: Vec<Vec<String>> = drifts
+                            .iter()
+                            .map(|drift| match drift {
+                                repo::LockDrift::MissingPackage { id, version } => {
+                                    vec![id.clone(), version.clone(), "未安装".to_string()]
+                                }
+                                repo::LockDrift::VersionMismatch {
+                                    id,
+                                    locked,
+                                    installed,
+                                } => vec![
+                                    id.clone(),
+                                    locked.clone(),
+                                    format!("版本不符，已安装: {}", installed.join(", ")),
+                                ],
+                                repo::LockDrift::HashMismatch {
+                                    id,
+                                    version,
+                                    path,
+                                    actual,
+                                    ..
+                                } => vec![
+                                    id.clone(),
+                                    version.clone(),
+                                    match actual {
+                                        Some(_) => format!("哈希不符: {path}"),
+                                        None => format!("文件缺失: {path}"),
+                                    },
+                                ],
+                            })
+                            .collect();
+                        println!("{}", style::table(&["软件包", "锁定版本", "偏差"], &rows));
+                    }
+                    if !drifts.is_empty() {
+                        return Err(failure(
+                            json_output,
+                            format!(
+                                "已安装的软件包与锁文件 {} 不一致: 共 {} 处偏差",
+                                lockfile.display(),
+                                drifts.len()
+                            ),
+                        ));
+                    }
+                    if !json_output {
+                        print_success(
+                            json_output,
+                            &format!(
+                                "已安装的软件包与锁文件一致: 共 {} 个软件包",
+                                locked.packages.len()
+                            ),
+                        );
+                    }
                 }
                 RepoCommands::Sync {
                     source_id,
                     mirror,
+                    force,
+                    dry_run,
                     repo,
                 } => {
                     let source_id = source_id.as_deref().unwrap_or("default");
-                    let mut repo_manager = repo::RepoManager::open(repo.clone())?;
-                    repo_manager.sync_repository(source_id, *mirror).await?;
-                    println!("已成功同步仓库");
+                    let mut repo_manager = open_repo(repo, repo::LockMode::Exclusive, cli)?;
+                    let report = repo_manager
+                        .sync_repository(
+                            source_id,
+                            &repo::SyncOptions {
+                                mirror: *mirror,
+                                force: *force,
+                                dry_run: *dry_run,
+                            },
+                        )
+                        .await?;
+                    if *dry_run {
+                        print_planned_changes(json_output, report.changes.changes());
+                    } else if *mirror {
+                        print_success(json_output, "已成功同步仓库");
+                    } else if json_output {
+                        print_json(&report.changelog)?;
+                    } else {
+                        for change in &report.changelog {
+                            println!("{change}");
+                        }
+                        print_success(
+                            json_output,
+                            &format!(
+                                "已成功同步仓库：{}",
+                                repo::changelog_summary(&report.changelog)
+                            ),
+                        );
+                    }
                 }
+                RepoCommands::Changelog { source_id, repo } => {
+                    let source_id = source_id.as_deref().unwrap_or("default");
+                    let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                    let changelog = repo_manager.changelog(source_id).await?;
+                    if json_output {
+                        return print_json(&changelog);
+                    }
+                    for change in &changelog {
+                        println!("{change}");
+                    }
+                    if changelog.is_empty() {
+                        print_success(json_output, "自上次同步以来没有变化");
+                    } else {
+                        print_success(json_output, &repo::changelog_summary(&changelog));
+                    }
+                }
+                RepoCommands::Sources(sources_cmd) => match sources_cmd {
+                    SourcesCommands::List { repo } => {
+                        let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                        let sources = repo_manager.config().redacted().source;
+                        if json_output {
+                            return print_json(&sources);
+                        }
+                        let rows: Vec<Vec<String>> = sources
+                            .iter()
+                            .map(|source| {
+                                vec![
+                                    source.id.clone(),
+                                    source.name.clone(),
+                                    if source.enabled {
+                                        style::green("启用")
+                                    } else {
+                                        style::dim("禁用")
+                                    },
+                                    source.url.clone(),
+                                    if source.mirrors.is_empty() {
+                                        style::dim("无")
+                                    } else {
+                                        source.mirrors.join(", ")
+                                    },
+                                    source
+                                        .trusted_key
+                                        .clone()
+                                        .unwrap_or_else(|| style::dim("无")),
+                                    source.description.clone().unwrap_or_default(),
+                                ]
+                            })
+                            .collect();
+                        println!(
+                            "{}",
+                            style::table(
+                                &[
+                                    "软件源",
+                                    "名称",
+                                    "状态",
+                                    "URL",
+                                    "镜像",
+                                    "受信任公钥",
+                                    "描述"
+                                ],
+                                &rows
+                            )
+                        );
+                    }
+                    SourcesCommands::Check { repo, .. } => {
+                        let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                        let results = repo_manager.check_sources().await;
+                        if json_output {
+                            println!("{}", serde_json::to_string_pretty(&results)?);
+                        } else {
+                            let rows: Vec<Vec<String>> = results
+                                .iter()
+                                .map(|health| {
+                                    vec![
+                                        health.id.clone(),
+                                        if health.reachable
+                                            && health.index_valid
+                                            && health.index_verified != Some(false)
+                                        {
+                                            style::green("正常")
+                                        } else {
+                                            style::red("异常")
+                                        },
+                                        health
+                                            .status
+                                            .map(|status| status.to_string())
+                                            .unwrap_or_else(|| "-".to_string()),
+                                        health
+                                            .latency_ms
+                                            .map(|ms| format!("{ms} ms"))
+                                            .unwrap_or_else(|| "-".to_string()),
+                                        health.error.clone().unwrap_or_default(),
+                                    ]
+                                })
+                                .collect();
+                            println!(
+                                "{}",
+                                style::table(&["软件源", "状态", "HTTP", "延迟", "错误"], &rows)
+                            );
+                        }
+                    }
+                    SourcesCommands::Verify {
+                        source_id, repo, ..
+                    } => {
+                        let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                        let verification = repo_manager.verify_source(source_id).await?;
+                        if json_output {
+                            println!("{}", serde_json::to_string_pretty(&verification)?);
+                        } else {
+                            print_package_verifications(&verification.summary.packages);
+                        }
+                        if verification.summary.failed > 0 {
+                            return Err(failure(
+                                json_output,
+                                format!(
+                                    "软件源 {source_id} 校验失败: {} 个软件包通过，{} 个失败",
+                                    verification.summary.passed, verification.summary.failed
+                                ),
+                            ));
+                        }
+                        if !json_output {
+                            print_success(
+                                json_output,
+                                &format!(
+                                    "软件源 {source_id} 校验通过: 共 {} 个软件包",
+                                    verification.summary.passed
+                                ),
+                            );
+                        }
+                    }
+                },
+                RepoCommands::Config(config_cmd) => match config_cmd {
+                    ConfigCommands::Show { repo, .. } => {
+                        let repo_manager = open_repo(repo, repo::LockMode::Shared, cli)?;
+                        let config = EffectiveConfig {
+                            offline: repo_manager.is_offline(),
+                            config: repo_manager.config().redacted(),
+                        };
+                        if json_output {
+                            println!("{}", serde_json::to_string_pretty(&config)?);
+                        } else {
+                            print!("{}", toml::to_string_pretty(&config)?);
+                        }
+                    }
+                },
             }
         }
     }
 
     Ok(())
 }
+
+/// 默认仓库路径
+const DEFAULT_REPO_PATH: &str = "~/.local/share/pageos/";
+
+/// 确定仓库路径
+///
+/// 优先使用 `--repo`；未指定时从当前目录向上查找仓库（同时包含 config.toml 和 index.json 的目录），
+/// 均未找到时使用默认路径
+fn resolve_repo(repo: &Option<PathBuf>) -> PathBuf {
+    repo.clone()
+        .or_else(|| {
+            std::env::current_dir()
+                .ok()
+                .and_then(|dir| path::discover_repo(&dir))
+        })
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_REPO_PATH))
+}
+
+/// 打开仓库并获取仓库锁
+///
+/// 只读命令使用共享锁，修改仓库的命令使用独占锁；按全局参数设置离线模式
+fn open_repo(
+    repo: &Option<PathBuf>,
+    mode: repo::LockMode,
+    cli: &Cli,
+) -> anyhow::Result<repo::RepoManager> {
+    let mut repo_manager = match (mode, cli.wait) {
+        (repo::LockMode::Shared, false) => repo::RepoManager::open(resolve_repo(repo))?,
+        _ => repo::RepoManager::open_with_lock(resolve_repo(repo), mode, cli.wait)?,
+    };
+    repo_manager.set_offline(offline_mode(cli));
+    Ok(repo_manager)
+}
+
+/// 是否启用离线模式（`--offline` 或环境变量 `PAGEOS_PKGR_OFFLINE` 为非空且不为 0）
+fn offline_mode(cli: &Cli) -> bool {
+    cli.offline
+        || std::env::var("PAGEOS_PKGR_OFFLINE").is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+/// 确定锁文件路径（默认为当前目录下的 pkgr.lock）
+fn resolve_lockfile(lockfile: &Option<PathBuf>) -> PathBuf {
+    lockfile
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(lockfile::DEFAULT_LOCKFILE))
+}
+
+/// 以 JSON 格式输出到标准输出
+fn print_json(value: &impl Serialize) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// 输出成功信息（JSON 输出模式下为 `{"ok": true, "message": ...}`）
+fn print_success(json_output: bool, message: &str) {
+    if json_output {
+        // 只含布尔值和字符串的结构体序列化不会失败
+        let _ = print_json(&CommandStatus { ok: true, message });
+    } else {
+        println!("{}", style::green(message));
+    }
+}
+
+/// 输出成功信息和公钥（JSON 输出模式下公钥并入信息）
+fn print_success_with_key(json_output: bool, message: &str, public_key: &[u8]) {
+    let public_key = crypto::hex_encode(public_key);
+    if json_output {
+        print_success(json_output, &format!("{message}，公钥: {public_key}"));
+    } else {
+        print_success(json_output, message);
+        println!("{} {public_key}", style::bold("公钥:"));
+    }
+}
+
+/// 输出单个软件包的升级结果
+fn print_upgrade_outcome(json_output: bool, package_id: &str, outcome: &repo::UpgradeOutcome) {
+    match outcome {
+        repo::UpgradeOutcome::Upgraded { from, to } => {
+            print_success(
+                json_output,
+                &format!("已成功升级软件包 {package_id}: {from} -> {to}"),
+            );
+        }
+        repo::UpgradeOutcome::UpToDate { version } => {
+            print_success(json_output, &format!("{package_id} 已是最新版本 {version}"));
+        }
+        repo::UpgradeOutcome::Pinned {
+            version,
+            pin,
+            latest,
+        } => {
+            let message =
+                format!("{package_id} 已固定为 {pin}，跳过升级到 {latest}（当前版本 {version}）");
+            if json_output {
+                print_success(json_output, &message);
+            } else {
+                eprintln!("{} {message}", style::yellow("警告:"));
+            }
+        }
+    }
+}
+
+/// 输出演练（`--dry-run`）计划执行的变更：每项一行输出到标准输出，汇总输出到标准错误
+fn print_planned_changes(json_output: bool, changes: &[repo::PlannedChange]) {
+    if json_output {
+        let _ = print_json(&changes);
+    } else {
+        for change in changes {
+            println!("{change}");
+        }
+    }
+    eprintln!(
+        "{} 演练模式，以上 {} 项变更均未执行",
+        style::yellow("提示:"),
+        changes.len()
+    );
+}
+
+/// 以表格输出软件包列表，列出全部时增加安装状态列
+fn print_package_list(packages: &[repo::ListedPackage], filter: repo::ListFilter) {
+    let version = |version: &Option<String>| version.clone().unwrap_or_else(|| style::dim("无"));
+    let rows: Vec<Vec<String>> = packages
+        .iter()
+        .map(|package| {
+            let description = package.description.lines().next().unwrap_or("").to_string();
+            match filter {
+                repo::ListFilter::Installed => vec![
+                    package.id.clone(),
+                    package.name.clone(),
+                    version(&package.installed_version),
+                    package
+                        .installed_from
+                        .clone()
+                        .unwrap_or_else(|| style::dim("本地")),
+                    package
+                        .installed_at
+                        .clone()
+                        .unwrap_or_else(|| style::dim("未知")),
+                    description,
+                ],
+                repo::ListFilter::Available => vec![
+                    package.id.clone(),
+                    package.name.clone(),
+                    version(&package.available_version),
+                    description,
+                ],
+                repo::ListFilter::All => vec![
+                    package.id.clone(),
+                    package.name.clone(),
+                    if package.installed_version.is_some() {
+                        style::green("已安装")
+                    } else {
+                        style::dim("未安装")
+                    },
+                    version(&package.installed_version),
+                    version(&package.available_version),
+                    description,
+                ],
+            }
+        })
+        .collect();
+    let headers: &[&str] = match filter {
+        repo::ListFilter::Installed => &["ID", "名称", "版本", "来源", "安装时间", "描述"],
+        repo::ListFilter::Available => &["ID", "名称", "最新版本", "描述"],
+        repo::ListFilter::All => &["ID", "名称", "状态", "已安装版本", "最新版本", "描述"],
+    };
+    println!("{}", style::table(headers, &rows));
+}
+
+/// 以表格输出各软件包的校验结果
+fn print_package_verifications(packages: &[repo::PackageVerification]) {
+    let rows: Vec<Vec<String>> = packages
+        .iter()
+        .map(|package| {
+            vec![
+                package.id.clone(),
+                package.version.clone(),
+                if package.passed {
+                    style::green("通过")
+                } else {
+                    style::red("失败")
+                },
+                package.verified_files.to_string(),
+                package.errors.join("; "),
+            ]
+        })
+        .collect();
+    println!(
+        "{}",
+        style::table(&["软件包", "版本", "结果", "文件", "错误"], &rows)
+    );
+}
@@ -2,8 +2,15 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::crypto;
+use crate::version;
+use anyhow::{Result, anyhow};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::Path;
+
+/// 分离签名文件名（与 metadata.json 位于同一目录，内容为十六进制的签名）
+pub const SIGNATURE_FILE: &str = "metadata.sig";
 
 /// 包元数据
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -27,10 +34,126 @@ pub struct PackageMetadata {
     pub category: String,
     /// 权限列表
     pub permissions: Vec<String>,
-    /// 入口文件
-    pub entry: String,
+    /// 入口文件（单个字符串或多个入口的数组）
+    pub entry: Entry,
     /// 文件清单
     pub all_files: HashMap<String, String>,
+    /// 文件权限（相对路径 -> 八进制权限位，如 `755`），仅记录可执行文件；安装后在 Unix 上应用
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub file_modes: HashMap<String, String>,
+    /// 依赖的其它软件包（缺省为空）
+    #[serde(default)]
+    pub dependencies: Vec<Dependency>,
+    /// 元数据自身的 SHA256 哈希（规范化 JSON，不含本字段），缺省时不校验
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_hash: Option<String>,
+    /// 作者对元数据哈希的 Ed25519 签名（十六进制，不参与元数据哈希的计算），由 `app sign` 生成
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// 安装后钩子，按顺序执行
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_install: Vec<PostInstallHook>,
+    /// 处理此软件包所需的最低 pageos-pkgr 版本（可选），缺省时不作要求
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_pkgr_version: Option<String>,
+}
+
+/// 软件包依赖
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    /// 依赖的软件包ID
+    pub id: String,
+    /// 版本约束，如 `^1.2.0`、`>=1.0.0, <2.0.0`
+    pub version_req: String,
+}
+
+/// 入口文件
+///
+/// 单页应用通常只有一个入口，多路由应用可以列出多个 HTML 入口。
+/// 反序列化时接受字符串或数组，序列化时保持原有形式。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Entry {
+    /// 单个入口文件
+    Single(String),
+    /// 多个入口文件
+    Multiple(Vec<String>),
+}
+
+impl Default for Entry {
+    fn default() -> Self {
+        Entry::Single(String::new())
+    }
+}
+
+impl From<&str> for Entry {
+    fn from(entry: &str) -> Self {
+        Entry::Single(entry.to_string())
+    }
+}
+
+impl Entry {
+    /// 所有入口文件（单个入口视为只有一项的列表）
+    pub fn paths(&self) -> &[String] {
+        match self {
+            Entry::Single(entry) => std::slice::from_ref(entry),
+            Entry::Multiple(entries) => entries,
+        }
+    }
+}
+
+/// 安装后钩子
+///
+/// 在软件包文件全部下载并校验通过后执行。
+/// 命令类钩子仅在软件源配置 `allow_hooks = true` 时执行。
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PostInstallHook {
+    /// 在软件包目录中执行命令
+    Command {
+        /// 可执行程序
+        command: String,
+        /// 命令参数
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// 将软件包内的 .desktop 文件安装到用户应用目录
+    DesktopEntry {
+        /// 桌面条目文件（相对于软件包）
+        file: String,
+    },
+}
+
+/// 生成文件清单项的值
+///
+/// `all_files` 中的值格式为 `哈希:大小`，大小以字节为单位
+pub fn format_file_entry(hash: &str, size: u64) -> String {
+    format!("{hash}:{size}")
+}
+
+/// 解析文件清单项的值，返回（哈希, 大小）
+///
+/// 兼容仅记录哈希的旧格式，此时大小为 `None`
+pub fn parse_file_entry(value: &str) -> (&str, Option<u64>) {
+    match value.rsplit_once(':') {
+        Some((hash, size)) => match size.parse() {
+            Ok(size) => (hash, Some(size)),
+            Err(_) => (value, None),
+        },
+        None => (value, None),
+    }
+}
+
+/// 应用清单中的文件权限时保留的权限位
+///
+/// 清单来自远程的软件源，不应用 setuid、setgid、粘滞位以及组和其他用户的写权限
+pub const FILE_MODE_MASK: u32 = 0o755;
+
+/// 解析八进制的文件权限（如 `755`），超出 `7777` 时视为无效
+fn parse_file_mode(mode: &str) -> Option<u32> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
 }
 
 /// 文件清单
@@ -92,23 +215,270 @@ impl PackageMetadata {
         self.all_files.insert(path, hash);
     }
 
-    /// 从清单中移除文件
+    /// 从清单中移除文件（连同记录的权限）
     pub fn remove_file(&mut self, path: &str) -> Option<String> {
+        self.file_modes.remove(path);
         self.all_files.remove(path)
     }
 
+    /// 记录文件权限，`None` 时移除已有记录
+    pub fn set_file_mode(&mut self, path: &str, mode: Option<u32>) {
+        match mode {
+            Some(mode) => {
+                self.file_modes
+                    .insert(path.to_string(), format!("{mode:o}"));
+            }
+            None => {
+                self.file_modes.remove(path);
+            }
+        }
+    }
+
+    /// 获取记录的文件权限（只保留 [`FILE_MODE_MASK`] 中的位），未记录或格式无效时返回 `None`
+    pub fn get_file_mode(&self, path: &str) -> Option<u32> {
+        self.file_modes
+            .get(path)
+            .and_then(|mode| parse_file_mode(mode))
+            .map(|mode| mode & FILE_MODE_MASK)
+    }
+
+    /// 添加依赖，已存在同ID的依赖时替换其版本约束
+    ///
+    /// 软件包ID不合法或版本约束无法解析时返回错误
+    pub fn add_dependency(&mut self, id: String, version_req: String) -> Result<()> {
+        if !is_valid_id(&id) {
+            return Err(anyhow!("依赖的软件包ID无效: {id}"));
+        }
+        if !version::is_valid_constraint(&version_req) {
+            return Err(anyhow!("依赖 {id} 的版本约束无效: {version_req}"));
+        }
+        match self
+            .dependencies
+            .iter_mut()
+            .find(|dependency| dependency.id == id)
+        {
+            Some(dependency) => dependency.version_req = version_req,
+            None => self.dependencies.push(Dependency { id, version_req }),
+        }
+        Ok(())
+    }
+
+    /// 移除依赖
+    pub fn remove_dependency(&mut self, id: &str) -> Option<Dependency> {
+        let position = self
+            .dependencies
+            .iter()
+            .position(|dependency| dependency.id == id)?;
+        Some(self.dependencies.remove(position))
+    }
+
     /// 检查文件是否在清单中
     pub fn has_file(&self, path: &str) -> bool {
         self.all_files.contains_key(path)
     }
 
     /// 获取文件的哈希值
-    pub fn get_file_hash(&self, path: &str) -> Option<&String> {
-        self.all_files.get(path)
+    pub fn get_file_hash(&self, path: &str) -> Option<&str> {
+        self.all_files
+            .get(path)
+            .map(|value| parse_file_entry(value).0)
+    }
+
+    /// 计算清单中所有文件的总大小（字节）
+    ///
+    /// 任一文件大小未知时返回 `None`
+    pub fn total_size(&self) -> Option<u64> {
+        self.all_files
+            .values()
+            .map(|value| parse_file_entry(value).1)
+            .sum()
+    }
+
+    /// 获取不在文件清单中的入口文件
+    ///
+    /// 空入口视为未设置，不参与检查
+    pub fn missing_entries(&self) -> Vec<&str> {
+        self.entry
+            .paths()
+            .iter()
+            .filter(|entry| !entry.is_empty() && !self.has_file(entry))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// 校验元数据是否完整、有效
+    ///
+    /// 检查项见 [`PackageMetadata::problems`]
+    ///
+    /// # 返回值
+    /// 全部通过时返回 Ok，否则返回列出所有问题的错误
+    pub fn validate(&self, package_root: &Path) -> Result<()> {
+        let problems = self.problems(package_root);
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("元数据校验失败:\n  - {}", problems.join("\n  - ")))
+        }
+    }
+
+    /// 列出元数据中的问题（为空表示校验通过）
+    ///
+    /// 检查 `id`/`name`/`version` 非空，`id` 仅由小写字母、数字和 `.`、`_`、`-` 组成且以字母或数字开头，
+    /// 入口文件和图标（如已设置）都在文件清单中且存在于 `package_root` 下
+    pub fn problems(&self, package_root: &Path) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        for (field, value) in [
+            ("id", &self.id),
+            ("name", &self.name),
+            ("version", &self.version),
+        ] {
+            if value.trim().is_empty() {
+                problems.push(format!("{field} 不能为空"));
+            }
+        }
+        if !self.id.is_empty() && !is_valid_id(&self.id) {
+            problems.push(format!(
+                "id 无效: {}（只能包含小写字母、数字和 .、_、-，且以字母或数字开头）",
+                self.id
+            ));
+        }
+        if self.version.contains(['/', '\\']) || self.version == "." || self.version == ".." {
+            problems.push(format!("version 无效: {}", self.version));
+        }
+
+        let missing_entries = self.missing_entries();
+        if !missing_entries.is_empty() {
+            problems.push(format!(
+                "入口文件不在 metadata.all_files 中: {}",
+                missing_entries.join(", ")
+            ));
+        }
+        if !self.icon.is_empty() && !self.has_file(&self.icon) {
+            problems.push(format!("图标不在 metadata.all_files 中: {}", self.icon));
+        }
+        let mut invalid_modes: Vec<String> = self
+            .file_modes
+            .iter()
+            .filter(|(path, mode)| !self.has_file(path) || parse_file_mode(mode).is_none())
+            .map(|(path, mode)| format!("{path} ({mode})"))
+            .collect();
+        if !invalid_modes.is_empty() {
+            invalid_modes.sort();
+            problems.push(format!(
+                "file_modes 中的权限无效或文件不在 metadata.all_files 中: {}",
+                invalid_modes.join(", ")
+            ));
+        }
+
+        // 清单中的入口和图标还需实际存在
+        let listed = self
+            .entry
+            .paths()
+            .iter()
+            .map(String::as_str)
+            .chain(std::iter::once(self.icon.as_str()))
+            .filter(|path| !path.is_empty() && self.has_file(path));
+        for path in listed {
+            if !package_root.join(path).is_file() {
+                problems.push(format!("文件不存在: {path}"));
+            }
+        }
+
+        problems
+    }
+
+    /// 检查 pageos-pkgr 版本是否满足 `min_pkgr_version`
+    ///
+    /// # 参数
+    /// - `pkgr_version`: 当前 pageos-pkgr 的版本，调用方传入 `env!("CARGO_PKG_VERSION")`
+    ///
+    /// # 返回值
+    /// 未设置 `min_pkgr_version` 或版本满足要求时返回 Ok，否则返回提示升级的错误
+    pub fn check_pkgr_version(&self, pkgr_version: &str) -> Result<()> {
+        match &self.min_pkgr_version {
+            Some(required) if version::semver_ordering(pkgr_version, required).is_lt() => {
+                Err(anyhow!(
+                    "软件包 {} 需要 pageos-pkgr {} 或更高版本（当前为 {}），请升级 pageos-pkgr",
+                    self.id,
+                    required,
+                    pkgr_version
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// 计算元数据自身的哈希值
+    ///
+    /// 将除 `metadata_hash` 和 `signature` 外的所有字段序列化为键有序的规范 JSON 后计算 SHA256，
+    /// 因此结果与字段书写顺序和格式无关。
+    /// 空的 `dependencies` 不参与计算，引入该字段之前写入的哈希仍然有效
+    pub fn compute_metadata_hash(&self) -> Result<String> {
+        let mut canonical = serde_json::to_value(self)?;
+        if let Some(object) = canonical.as_object_mut() {
+            object.remove("metadata_hash");
+            object.remove("signature");
+            if self.dependencies.is_empty() {
+                object.remove("dependencies");
+            }
+        }
+        Ok(crypto::bytes_hash(serde_json::to_string(&canonical)?.as_bytes()))
+    }
+
+    /// 验证元数据自身的哈希值
+    ///
+    /// 未携带 `metadata_hash` 时视为通过
+    pub fn verify_metadata_hash(&self) -> Result<bool> {
+        match &self.metadata_hash {
+            Some(expected) => Ok(self.compute_metadata_hash()?.eq_ignore_ascii_case(expected)),
+            None => Ok(true),
+        }
+    }
+
+    /// 用 Ed25519 私钥（32 字节种子）对元数据哈希签名，写入 `signature`
+    ///
+    /// 签名覆盖除 `metadata_hash` 和 `signature` 外的所有字段（包括文件清单中的哈希），
+    /// 之后修改元数据会使签名失效
+    pub fn sign(&mut self, secret_key: &[u8]) -> Result<()> {
+        let hash = self.compute_metadata_hash()?;
+        let signature = crypto::sign(hash.as_bytes(), secret_key)?;
+        self.signature = Some(crypto::hex_encode(&signature));
+        Ok(())
+    }
+
+    /// 用受信任的公钥检查签名（`None` 表示没有签名），没有签名或验证失败时返回错误
+    ///
+    /// 安装和 `repo verify` 共用，保证两处对签名的判断一致
+    pub fn check_signature(&self, signature: Option<&str>, trusted_key: &str) -> Result<()> {
+        let signature = signature.ok_or_else(|| anyhow!("元数据没有签名"))?;
+        match self.verify_signature(signature, trusted_key) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(anyhow!("元数据签名验证失败")),
+            Err(e) => Err(e.context("元数据签名验证失败")),
+        }
+    }
+
+    /// 用十六进制的 Ed25519 公钥验证十六进制签名是否与当前元数据相符
+    pub fn verify_signature(&self, signature: &str, public_key: &str) -> Result<bool> {
+        let hash = self.compute_metadata_hash()?;
+        crypto::verify_signature(
+            hash.as_bytes(),
+            &crypto::hex_decode(signature)?,
+            &crypto::hex_decode(public_key)?,
+        )
     }
 }
 
 
+/// 检查软件包ID是否只包含允许的字符（`[a-z0-9._-]`，以字母或数字开头）
+fn is_valid_id(id: &str) -> bool {
+    id.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'))
+}
+
 impl FileManifest {
     /// 创建一个新的文件清单项
     pub fn new(path: String, hash: String) -> Self {
@@ -143,13 +513,204 @@ mod tests {
 
         metadata.add_file(path.clone(), hash.clone());
         assert!(metadata.has_file(&path));
-        assert_eq!(metadata.get_file_hash(&path), Some(&hash));
+        assert_eq!(metadata.get_file_hash(&path), Some(hash.as_str()));
 
         let removed_hash = metadata.remove_file(&path);
         assert_eq!(removed_hash, Some(hash));
         assert!(!metadata.has_file(&path));
     }
 
+    #[test]
+    fn test_validate() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        std::fs::write(temp_dir.path().join("index.html"), "<html></html>")?;
+
+        let mut metadata = PackageMetadata::new();
+        metadata.id = "org.pageos.demo-app".to_string();
+        metadata.name = "Demo".to_string();
+        metadata.version = "1.0.0".to_string();
+        metadata.entry = "index.html".into();
+        metadata.add_file("index.html".to_string(), "abc123".to_string());
+        metadata.validate(temp_dir.path())?;
+
+        // 大写ID、空版本、图标未列入清单都会被一并报告
+        metadata.id = "Demo".to_string();
+        metadata.version = String::new();
+        metadata.icon = "icon.png".to_string();
+        let message = metadata.validate(temp_dir.path()).unwrap_err().to_string();
+        assert!(message.contains("id 无效: Demo"));
+        assert!(message.contains("version 不能为空"));
+        assert!(message.contains("图标不在 metadata.all_files 中: icon.png"));
+
+        // 清单中有但磁盘上不存在的图标
+        metadata.id = "demo".to_string();
+        metadata.version = "1.0.0".to_string();
+        metadata.add_file("icon.png".to_string(), "def456".to_string());
+        let message = metadata.validate(temp_dir.path()).unwrap_err().to_string();
+        assert!(message.contains("文件不存在: icon.png"));
+        assert!(!message.contains("id 无效"));
+
+        assert!(!is_valid_id("../escape"));
+        assert!(!is_valid_id(".hidden"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_pkgr_version() {
+        let mut metadata = PackageMetadata::new();
+        assert!(metadata.check_pkgr_version("1.0.0").is_ok());
+
+        metadata.min_pkgr_version = Some("2.1.0".to_string());
+        assert!(metadata.check_pkgr_version("2.1.0").is_ok());
+        assert!(metadata.check_pkgr_version("2.10.0").is_ok());
+        let error = metadata.check_pkgr_version("2.0.4").unwrap_err();
+        assert!(error.to_string().contains("请升级 pageos-pkgr"));
+    }
+
+    #[test]
+    fn test_file_entry_sizes() {
+        assert_eq!(format_file_entry("abc123", 42), "abc123:42");
+        assert_eq!(parse_file_entry("abc123:42"), ("abc123", Some(42)));
+        assert_eq!(parse_file_entry("abc123"), ("abc123", None));
+
+        let mut metadata = PackageMetadata::new();
+        metadata.add_file("index.html".to_string(), format_file_entry("abc123", 40));
+        metadata.add_file("app.js".to_string(), format_file_entry("def456", 2));
+        assert_eq!(metadata.get_file_hash("index.html"), Some("abc123"));
+        assert_eq!(metadata.total_size(), Some(42));
+
+        // 仅记录哈希的旧清单项大小未知
+        metadata.add_file("legacy.css".to_string(), "789abc".to_string());
+        assert_eq!(metadata.get_file_hash("legacy.css"), Some("789abc"));
+        assert_eq!(metadata.total_size(), None);
+    }
+
+    #[test]
+    fn test_file_mode_mask() {
+        let mut metadata = PackageMetadata::new();
+        metadata.add_file("run.sh".to_string(), "abc123".to_string());
+        metadata.set_file_mode("run.sh", Some(0o4777));
+        assert_eq!(metadata.get_file_mode("run.sh"), Some(0o755));
+        metadata.set_file_mode("run.sh", Some(0o700));
+        assert_eq!(metadata.get_file_mode("run.sh"), Some(0o700));
+    }
+
+    #[test]
+    fn test_metadata_hash() -> Result<()> {
+        let mut metadata = PackageMetadata::new();
+        metadata.id = "test.package".to_string();
+        metadata.version = "1.0.0".to_string();
+        metadata.add_file("index.html".to_string(), "abc123".to_string());
+
+        // 未携带哈希时视为通过
+        assert!(metadata.verify_metadata_hash()?);
+
+        // 嵌入哈希后验证通过，且哈希不受自身字段影响
+        let hash = metadata.compute_metadata_hash()?;
+        metadata.metadata_hash = Some(hash.clone());
+        assert_eq!(metadata.compute_metadata_hash()?, hash);
+        assert!(metadata.verify_metadata_hash()?);
+
+        // 篡改文件清单后验证失败
+        metadata.add_file("evil.js".to_string(), "def456".to_string());
+        assert!(!metadata.verify_metadata_hash()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dependencies() -> Result<()> {
+        let mut metadata = PackageMetadata::new();
+        metadata.add_dependency("shared-ui".to_string(), "^1.0.0".to_string())?;
+        metadata.add_dependency("icons".to_string(), "*".to_string())?;
+        metadata.add_dependency("shared-ui".to_string(), "^1.2.0".to_string())?;
+        assert_eq!(metadata.dependencies.len(), 2);
+        assert_eq!(metadata.dependencies[0].version_req, "^1.2.0");
+
+        // 不合法的ID或无法解析的约束不会被加入
+        assert!(
+            metadata
+                .add_dependency("../evil".to_string(), "*".to_string())
+                .is_err()
+        );
+        assert!(
+            metadata
+                .add_dependency("icons".to_string(), "^latest".to_string())
+                .is_err()
+        );
+        assert_eq!(metadata.dependencies[1].version_req, "*");
+
+        let json = serde_json::to_string(&metadata)?;
+        let loaded: PackageMetadata = serde_json::from_str(&json)?;
+        assert_eq!(loaded.dependencies, metadata.dependencies);
+
+        assert_eq!(
+            metadata
+                .remove_dependency("icons")
+                .map(|dependency| dependency.id),
+            Some("icons".to_string())
+        );
+        assert!(metadata.remove_dependency("icons").is_none());
+
+        // 不含该字段的旧元数据视为没有依赖，且原有的元数据哈希保持不变
+        let mut legacy = serde_json::to_value(PackageMetadata::new())?;
+        legacy.as_object_mut().unwrap().remove("dependencies");
+        let legacy_hash = crypto::bytes_hash(serde_json::to_string(&legacy)?.as_bytes());
+        let legacy: PackageMetadata = serde_json::from_value(legacy)?;
+        assert!(legacy.dependencies.is_empty());
+        assert_eq!(legacy.compute_metadata_hash()?, legacy_hash);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_shapes() -> Result<()> {
+        // 字符串形式视为单个入口，并按原形式序列化
+        let single: Entry = serde_json::from_str(r#""index.html""#)?;
+        assert_eq!(single.paths(), ["index.html"]);
+        assert_eq!(serde_json::to_string(&single)?, r#""index.html""#);
+
+        let multiple: Entry = serde_json::from_str(r#"["index.html","admin.html"]"#)?;
+        assert_eq!(multiple.paths(), ["index.html", "admin.html"]);
+        assert_eq!(
+            serde_json::to_string(&multiple)?,
+            r#"["index.html","admin.html"]"#
+        );
+
+        // 检查入口是否都在文件清单中
+        let mut metadata = PackageMetadata::new();
+        metadata.entry = multiple;
+        metadata.add_file("index.html".to_string(), "hash".to_string());
+        assert_eq!(metadata.missing_entries(), vec!["admin.html"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_install_hook_serialization() -> Result<()> {
+        let json = r#"{
+            "type": "command",
+            "command": "update-desktop-database"
+        }"#;
+        let hook: PostInstallHook = serde_json::from_str(json)?;
+        assert_eq!(
+            hook,
+            PostInstallHook::Command {
+                command: "update-desktop-database".to_string(),
+                args: Vec::new(),
+            }
+        );
+
+        let hook = PostInstallHook::DesktopEntry {
+            file: "app.desktop".to_string(),
+        };
+        let value = serde_json::to_value(&hook)?;
+        assert_eq!(value["type"], "desktop_entry");
+        assert_eq!(value["file"], "app.desktop");
+
+        Ok(())
+    }
+
     #[test]
     fn test_version_history_operations() {
         let mut history = VersionHistory::new();
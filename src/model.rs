@@ -0,0 +1,53 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! 索引数据模型：index.json 的结构，由仓库管理（repo）、索引服务（index）和打包工具（pack）共用
+
+use serde::{Deserialize, Serialize};
+
+/// 仓库索引结构
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct RepositoryIndex {
+    /// 已安装的包列表
+    pub packages: Vec<PackageInfo>,
+    /// 软件源中的包列表
+    pub source: Vec<PackageInfo>,
+}
+
+/// 包信息
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PackageInfo {
+    /// 应用唯一标识
+    pub id: String,
+    /// 应用名称
+    pub name: String,
+    /// 图标路径
+    pub icon: String,
+    /// 作者
+    pub author: String,
+    /// 最新版本号
+    pub latest_version: String,
+    /// 应用描述
+    pub description: String,
+    /// 位置信息（本地路径或URL）
+    pub location: String,
+    /// 可用版本列表（可选），安装 `latest` 时取其中语义化版本最高者
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub available_versions: Vec<String>,
+    /// 最新版本所有文件的总大小（字节），文件清单未记录大小时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// 最新版本元数据的 Ed25519 签名（十六进制），未签名时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// 最新版本的软件包归档文件名（与 metadata.json 位于同一目录），未发布归档时为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<String>,
+    /// 安装时间（RFC 3339），本地添加的软件包为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_at: Option<String>,
+    /// 安装来源的软件源ID，本地添加的软件包为空
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_from: Option<String>,
+}
@@ -2,14 +2,623 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use tokio::io::AsyncWriteExt;
+use crate::config::{DEFAULT_MAX_RETRIES, DEFAULT_RETRY_BASE_DELAY_MS, NetworkConfig, SourceConfig};
+use crate::crypto::{self, HashAlgorithm};
+use crate::fsxg;
+use crate::metadata::{PackageMetadata, parse_file_entry};
+use crate::path::{check_destination_paths, is_safe_relative};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// HTTP 客户端
+///
+/// 由按网络配置构建的共享客户端和各软件源的 TLS 策略组成，发起请求时按 URL 选择实际使用的客户端。
+/// 通过同一客户端（及其克隆）发起的请求复用连接池：服务器支持 HTTP/2 时通过 ALPN 协商，
+/// 并发请求在同一连接上多路复用，而不是各自建立新连接。克隆的开销很小
+#[derive(Clone)]
+pub struct Client {
+    /// 没有匹配的软件源 TLS 策略时使用的客户端
+    shared: reqwest::Client,
+    /// 各软件源的 TLS 策略
+    sources: Arc<[SourceTls]>,
+    /// 离线模式：拒绝发出任何请求
+    offline: bool,
+    /// 通过该客户端发起的请求使用的重试策略
+    retry: RetryPolicy,
+}
+
+impl Client {
+    /// 按网络配置和软件源配置创建客户端
+    ///
+    /// 启用的源配置了 `min_tls_version` 或 `pin` 时，为其单独构建客户端，
+    /// 之后以源 URL（或任一镜像 URL）开头的请求都使用该客户端并校验证书固定；
+    /// 其它请求使用共享客户端和平台默认的 TLS 设置。
+    /// 经由该客户端的下载和索引获取按 `retry` 重试（见 [`with_retry`]）
+    pub fn new(
+        network: &NetworkConfig,
+        sources: &[SourceConfig],
+        retry: RetryPolicy,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut source_tls = Vec::new();
+        for source in sources.iter().filter(|source| source.enabled) {
+            source_tls.extend(
+                SourceTls::build(source, network)
+                    .map_err(|e| format!("源 {} 的 TLS 配置无效: {e}", source.id))?,
+            );
+        }
+        Ok(Self {
+            shared: build_client(network)?,
+            sources: source_tls.into(),
+            offline: false,
+            retry,
+        })
+    }
+
+    /// 设置离线模式，离线时所有请求在发出前即返回错误
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+    }
+
+    /// 是否处于离线模式
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// 创建客户端时指定的重试策略
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// 选择请求使用的客户端：匹配最长源 URL 前缀的 TLS 策略，没有匹配时使用共享客户端
+    fn route(&self, url: &str) -> (&reqwest::Client, Option<&CertPin>) {
+        let matched = self
+            .sources
+            .iter()
+            .filter(|source_tls| url.starts_with(&source_tls.prefix))
+            .max_by_key(|source_tls| source_tls.prefix.len());
+        match matched {
+            Some(source_tls) => (&source_tls.client, source_tls.pin.as_ref()),
+            None => (&self.shared, None),
+        }
+    }
+
+    /// 发起 GET 请求
+    ///
+    /// 按 URL 选择客户端，`customize` 可在发送前调整请求（如设置超时、请求头）；
+    /// 源配置了证书固定时，在读取响应体之前校验服务器证书，不匹配则丢弃响应并返回错误。
+    /// 离线模式下不发出请求，直接返回错误
+    async fn get(
+        &self,
+        url: &str,
+        customize: impl FnOnce(reqwest::RequestBuilder) -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+        if self.offline {
+            return Err(format!("离线模式已启用，无法访问: {url}").into());
+        }
+        let (client, pin) = self.route(url);
+        log::trace!("GET {url}");
+        let response = customize(client.get(url)).send().await?;
+        if let Some(pin) = pin {
+            pin.verify(url, &response)?;
+        }
+        Ok(response)
+    }
+}
+
+/// 按网络配置构建 HTTP 客户端
+fn build_client(config: &NetworkConfig) -> reqwest::Result<reqwest::Client> {
+    client_builder(config).build()
+}
+
+/// 按网络配置创建 HTTP 客户端构建器
+///
+/// 超时为空闲超时（建立连接或两次收到数据之间的最长等待时间），不限制整个请求的总时长，
+/// 持续传输数据的大文件下载不会因耗时较长而被中断
+fn client_builder(config: &NetworkConfig) -> reqwest::ClientBuilder {
+    let idle_timeout = Duration::from_secs(config.http_timeout_secs);
+    let mut builder = reqwest::Client::builder()
+        .user_agent(&config.user_agent)
+        .connect_timeout(idle_timeout)
+        .read_timeout(idle_timeout)
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host);
+    if config.tcp_keepalive > 0 {
+        builder = builder.tcp_keepalive(Duration::from_secs(config.tcp_keepalive));
+    }
+    if config.http1_only {
+        builder = builder.http1_only();
+    } else {
+        // 按带宽时延自动调整 HTTP/2 流控窗口，提升多路复用时的吞吐
+        builder = builder.http2_adaptive_window(true);
+    }
+    builder
+}
+
+/// 证书固定
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CertPin {
+    /// 服务器证书（DER 编码）的 SHA256 哈希值
+    Certificate(String),
+    /// 服务器公钥（SubjectPublicKeyInfo，DER 编码）的 SHA256 哈希值
+    PublicKey(String),
+}
+
+impl CertPin {
+    /// 解析 `cert-sha256:<十六进制>` 或 `spki-sha256:<十六进制>` 格式的证书固定
+    ///
+    /// 十六进制部分不区分大小写，允许使用 `openssl x509 -fingerprint` 输出中的冒号分隔
+    pub fn parse(pin: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let (kind, hash) = pin
+            .split_once(':')
+            .ok_or_else(|| format!("无效的证书固定: {pin}"))?;
+        let hash = hash.replace(':', "").to_ascii_lowercase();
+        if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("无效的证书固定哈希（应为 64 位十六进制 SHA256）: {pin}").into());
+        }
+        match kind {
+            "cert-sha256" => Ok(Self::Certificate(hash)),
+            "spki-sha256" => Ok(Self::PublicKey(hash)),
+            _ => Err(
+                format!("不支持的证书固定类型 {kind}（应为 cert-sha256 或 spki-sha256）").into(),
+            ),
+        }
+    }
+
+    /// 校验响应所在连接的服务器证书
+    fn verify(
+        &self,
+        url: &str,
+        response: &reqwest::Response,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let certificate = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(|info| info.peer_certificate())
+            .ok_or_else(|| format!("{url} 未建立 TLS 连接，无法校验证书固定"))?;
+
+        let (kind, expected, data) = match self {
+            Self::Certificate(hash) => ("证书", hash, Some(certificate.to_vec())),
+            Self::PublicKey(hash) => ("公钥", hash, certificate_spki(certificate)),
+        };
+        let data = data.ok_or_else(|| format!("无法解析 {url} 的服务器证书"))?;
+        let actual = crypto::bytes_hash(&data);
+        if actual != *expected {
+            return Err(format!(
+                "{url} 的服务器{kind}与固定的哈希不匹配，已中止连接 (预期: {expected}, 实际: {actual})"
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// 从 DER 编码的 X.509 证书中取出 SubjectPublicKeyInfo（DER 编码，含标签和长度）
+fn certificate_spki(der: &[u8]) -> Option<Vec<u8>> {
+    let certificate = rustls_pki_types::CertificateDer::from(der);
+    let certificate = webpki::EndEntityCert::try_from(&certificate).ok()?;
+    Some(certificate.subject_public_key_info().as_ref().to_vec())
+}
+
+/// 解析最低 TLS 版本（`1.0`、`1.1`、`1.2` 或 `1.3`）
+fn parse_tls_version(version: &str) -> Result<reqwest::tls::Version, Box<dyn std::error::Error>> {
+    match version {
+        "1.0" => Ok(reqwest::tls::Version::TLS_1_0),
+        "1.1" => Ok(reqwest::tls::Version::TLS_1_1),
+        "1.2" => Ok(reqwest::tls::Version::TLS_1_2),
+        "1.3" => Ok(reqwest::tls::Version::TLS_1_3),
+        _ => Err(format!("无效的 TLS 版本: {version}（应为 1.0、1.1、1.2 或 1.3）").into()),
+    }
+}
+
+/// 软件源的 TLS 策略
+struct SourceTls {
+    /// 源 URL 前缀（以 / 结尾）
+    prefix: String,
+    /// 按源的 TLS 设置构建的客户端
+    client: reqwest::Client,
+    /// 证书固定
+    pin: Option<CertPin>,
+}
+
+impl SourceTls {
+    /// 按源的 TLS 设置为其主 URL 和各镜像 URL 构建 TLS 策略，源未配置 `min_tls_version` 和 `pin` 时为空
+    fn build(
+        source: &SourceConfig,
+        network: &NetworkConfig,
+    ) -> Result<Vec<Self>, Box<dyn std::error::Error>> {
+        if source.min_tls_version.is_none() && source.pin.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let pin = source.pin.as_deref().map(CertPin::parse).transpose()?;
+        let mut builder = client_builder(network).tls_info(pin.is_some());
+        if let Some(version) = &source.min_tls_version {
+            builder = builder.min_tls_version(parse_tls_version(version)?);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| format!("无法构建 HTTP 客户端: {e}"))?;
+
+        Ok(source
+            .urls()
+            .map(|url| Self {
+                prefix: format!("{}/", url.trim_end_matches('/')),
+                client: client.clone(),
+                pin: pin.clone(),
+            })
+            .collect())
+    }
+}
+
+/// 熔断器默认阈值（连续失败次数）
+pub const DEFAULT_BREAKER_THRESHOLD: u32 = 3;
+
+/// 按软件源划分的熔断器
+///
+/// 一次运行中某个源连续失败达到阈值后即熔断，之后对该源的请求直接失败而不再访问网络，
+/// 避免批量操作对不可用的源反复等待。熔断状态只保存在内存中，下次运行时自动重置。
+pub struct CircuitBreaker {
+    /// 熔断阈值
+    threshold: u32,
+    /// 各源的连续失败次数
+    failures: Mutex<HashMap<String, u32>>,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(DEFAULT_BREAKER_THRESHOLD)
+    }
+}
+
+impl CircuitBreaker {
+    /// 创建熔断器
+    pub fn new(threshold: u32) -> Self {
+        Self {
+            threshold,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 指定源是否已熔断
+    pub fn is_tripped(&self, key: &str) -> bool {
+        self.failures
+            .lock()
+            .unwrap()
+            .get(key)
+            .is_some_and(|&count| count >= self.threshold)
+    }
+
+    /// 通过熔断器执行网络操作
+    ///
+    /// # 参数
+    ///
+    /// * `key` - 熔断器键（软件源ID）
+    /// * `operation` - 要执行的网络操作
+    ///
+    /// # 返回值
+    ///
+    /// 源已熔断时直接返回错误，不执行操作；否则返回操作的结果并记录成功或失败
+    pub async fn call<T, F>(
+        &self,
+        key: &str,
+        operation: impl FnOnce() -> F,
+    ) -> Result<T, Box<dyn std::error::Error>>
+    where
+        F: Future<Output = Result<T, Box<dyn std::error::Error>>>,
+    {
+        if self.is_tripped(key) {
+            return Err(format!("软件源 {key} 已熔断，跳过请求").into());
+        }
+
+        let result = operation().await;
+
+        let mut failures = self.failures.lock().unwrap();
+        match &result {
+            Ok(_) => {
+                failures.remove(key);
+            }
+            Err(_) => {
+                let count = failures.entry(key.to_string()).or_insert(0);
+                *count += 1;
+                // 仅在刚熔断时提示一次
+                if *count == self.threshold {
+                    log::warn!(
+                        "软件源 {} 连续 {} 次请求失败，本次运行中将跳过该源",
+                        key,
+                        count
+                    );
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// HTTP 响应状态不是成功状态
+#[derive(Debug)]
+pub struct HttpStatusError {
+    /// 响应状态码
+    pub status: reqwest::StatusCode,
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP请求失败: {}", self.status)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// 索引哈希文件的后缀（`index.json.sha256`，格式同 `sha256sum` 的输出）
+pub const INDEX_HASH_SUFFIX: &str = ".sha256";
+
+/// 索引签名文件的后缀（`index.json.sig`，内容为十六进制的 Ed25519 签名）
+pub const INDEX_SIGNATURE_SUFFIX: &str = ".sig";
+
+/// 索引完整性校验失败
+///
+/// 与下载失败区分：索引已成功获取，但内容与源发布的哈希或签名不符
+#[derive(Debug)]
+pub enum IndexVerifyError {
+    /// 源未发布 index.json.sha256
+    MissingHash,
+    /// index.json 的哈希与 index.json.sha256 不符
+    HashMismatch {
+        /// index.json.sha256 中的哈希
+        expected: String,
+        /// 实际获取的 index.json 的哈希
+        actual: String,
+    },
+    /// 源配置了 `trusted_key`，但未发布 index.json.sig
+    MissingSignature,
+    /// index.json.sig 无法用源的 `trusted_key` 验证
+    BadSignature,
+}
+
+impl std::fmt::Display for IndexVerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingHash => write!(
+                f,
+                "源未发布 index.json{INDEX_HASH_SUFFIX}，无法校验索引（可为该源设置 verify_index = false）"
+            ),
+            Self::HashMismatch { expected, actual } => {
+                write!(f, "索引哈希不匹配: 期望 {expected}，实际 {actual}")
+            }
+            Self::MissingSignature => write!(
+                f,
+                "源未发布 index.json{INDEX_SIGNATURE_SUFFIX}，无法用 trusted_key 验证索引"
+            ),
+            Self::BadSignature => write!(
+                f,
+                "索引签名无效: index.json{INDEX_SIGNATURE_SUFFIX} 与 trusted_key 不符"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IndexVerifyError {}
+
+/// 重试策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// 最大重试次数（不含首次请求）
+    pub max_retries: u32,
+    /// 首次重试前的等待时间，之后每次加倍
+    pub base_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            base_delay: Duration::from_millis(DEFAULT_RETRY_BASE_DELAY_MS),
+        }
+    }
+}
+
+/// 判断错误是否值得重试
+///
+/// 连接失败、超时、传输中断等网络错误以及 5xx/429 响应可重试；
+/// 其余 4xx 响应、本地文件错误和响应内容解析错误不重试
+fn is_retryable(error: &(dyn std::error::Error + 'static)) -> bool {
+    let retryable_status =
+        |status: reqwest::StatusCode| status.is_server_error() || status.as_u16() == 429;
+    if let Some(error) = error.downcast_ref::<HttpStatusError>() {
+        return retryable_status(error.status);
+    }
+    if let Some(error) = error.downcast_ref::<reqwest::Error>() {
+        return match error.status() {
+            Some(status) => retryable_status(status),
+            None => {
+                error.is_connect() || error.is_timeout() || error.is_request() || error.is_body()
+            }
+        };
+    }
+    false
+}
+
+/// 第 `attempt` 次重试（从 0 开始）前的等待时间
+///
+/// 以 `base_delay * 2^attempt` 为上限，在其一半到全部之间随机取值，避免多个客户端同时重试
+fn backoff_delay(policy: RetryPolicy, attempt: u32) -> Duration {
+    let delay = policy.base_delay.saturating_mul(1 << attempt.min(16));
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    delay / 2 + delay.mul_f64(random as f64 / u64::MAX as f64) / 2
+}
+
+/// 按重试策略执行网络操作
+///
+/// # 参数
+///
+/// * `policy` - 重试策略
+/// * `operation` - 要执行的网络操作，每次重试重新调用
+///
+/// # 返回值
+///
+/// 返回首次成功的结果；遇到不可重试的错误或重试次数用尽时返回最后一次的错误
+///
+/// # 功能特性
+///
+/// * 仅在网络错误和 5xx/429 响应时重试，其余 4xx 响应立即失败
+/// * 指数退避并加入随机抖动
+pub async fn with_retry<T, F>(
+    policy: RetryPolicy,
+    mut operation: impl FnMut() -> F,
+) -> Result<T, Box<dyn std::error::Error>>
+where
+    F: Future<Output = Result<T, Box<dyn std::error::Error>>>,
+{
+    let mut attempt = 0;
+    loop {
+        // 错误在等待前释放，使返回的 Future 可以跨线程执行
+        let delay = match operation().await {
+            Err(e) if attempt < policy.max_retries && is_retryable(e.as_ref()) => {
+                let delay = backoff_delay(policy, attempt);
+                attempt += 1;
+                log::warn!(
+                    "{}，{:.1} 秒后重试 ({}/{})",
+                    e,
+                    delay.as_secs_f64(),
+                    attempt,
+                    policy.max_retries
+                );
+                delay
+            }
+            result => return result,
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// 依次尝试多个地址执行网络操作
+///
+/// # 参数
+///
+/// * `urls` - 按顺序尝试的地址（如软件源的主 URL 及各镜像 URL 下的相同路径）
+/// * `operation` - 要执行的网络操作，参数为本次尝试的地址
+///
+/// # 返回值
+///
+/// 返回首个成功的结果；全部失败时返回最后一个地址的错误
+pub async fn with_fallback<T, F>(
+    urls: &[String],
+    mut operation: impl FnMut(String) -> F,
+) -> Result<T, Box<dyn std::error::Error>>
+where
+    F: Future<Output = Result<T, Box<dyn std::error::Error>>>,
+{
+    let Some((last, rest)) = urls.split_last() else {
+        return Err("没有可用的地址".into());
+    };
+    for url in rest {
+        match operation(url.clone()).await {
+            Ok(value) => return Ok(value),
+            Err(e) => log::warn!("{url} 不可用: {e}，尝试下一个镜像",),
+        }
+    }
+    operation(last.clone()).await
+}
+
+/// 下载进度回调
+///
+/// 同一个实现可以在多个并发下载之间共享，用于汇总总体进度
+pub trait DownloadProgress: Send + Sync {
+    /// 开始接收响应体，`size` 为本次响应的内容长度（未知时为 `None`）
+    fn begin(&self, _size: Option<u64>) {}
+
+    /// 收到 `bytes` 字节数据
+    fn advance(&self, bytes: u64);
+
+    /// 本次响应接收结束（无论成功与否）
+    fn finish(&self) {}
+}
+
+/// 在标准错误输出单个文件的下载百分比，结束时换行
+#[derive(Default)]
+pub struct ConsoleProgress {
+    total: AtomicU64,
+    downloaded: AtomicU64,
+    shown: AtomicBool,
+}
+
+impl DownloadProgress for ConsoleProgress {
+    fn begin(&self, size: Option<u64>) {
+        self.total.store(size.unwrap_or(0), Ordering::Relaxed);
+        self.downloaded.store(0, Ordering::Relaxed);
+    }
+
+    fn advance(&self, bytes: u64) {
+        let downloaded = self.downloaded.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        let total = self.total.load(Ordering::Relaxed);
+        if let Some(percent) = (downloaded * 100).checked_div(total) {
+            eprint!("\r下载进度: {}%", percent.min(100));
+            self.shown.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn finish(&self) {
+        if self.shown.swap(false, Ordering::Relaxed) {
+            eprintln!();
+        }
+    }
+}
+
+/// 本地地址对应的文件路径
+///
+/// `file://` 开头的地址、绝对路径和以 `./`、`../` 开头的相对路径（相对于当前目录）为本地地址（本地软件源），
+/// 直接通过文件系统读取；`file://` 地址中的百分号编码（如 `%20`）会被解码。
+/// 其它地址以及无法转换为本地路径的 `file://` 地址（如带有主机名）返回 `None`
+pub fn local_path(location: &str) -> Option<std::path::PathBuf> {
+    if location.starts_with("file://") {
+        return reqwest::Url::parse(location).ok()?.to_file_path().ok();
+    }
+    let path = std::path::Path::new(location);
+    let relative = [".", ".."].contains(&location)
+        || location.starts_with("./")
+        || location.starts_with("../");
+    (path.is_absolute() || relative).then(|| path.to_path_buf())
+}
+
+/// 从本地软件源复制文件到本地路径，并向 `progress` 报告进度
+fn copy_local(
+    source: &std::path::Path,
+    path: &str,
+    progress: &dyn DownloadProgress,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let size = std::fs::metadata(source)
+        .map_err(|e| format!("无法读取本地文件 {}: {e}", source.display()))?
+        .len();
+    if let Some(parent_dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(parent_dir)?;
+    }
+
+    progress.begin(Some(size));
+    let result = std::fs::copy(source, path);
+    if result.is_ok() {
+        progress.advance(size);
+    }
+    progress.finish();
+    result.map_err(|e| format!("无法复制本地文件 {}: {e}", source.display()))?;
+    Ok(())
+}
 
 /// 从指定URL下载文件到本地路径
 ///
 /// # 参数
 ///
+/// * `client` - HTTP 客户端（见 [`Client`]）
 /// * `url` - 要下载的文件的URL
 /// * `path` - 本地保存文件的路径
+/// * `progress` - 下载进度回调（如 [`ConsoleProgress`]）
 ///
 /// # 返回值
 ///
@@ -18,70 +627,361 @@ use tokio::io::AsyncWriteExt;
 /// # 功能特性
 ///
 /// * 支持 HTTP/HTTPS 下载
-/// * 显示下载进度
-/// * 处理网络异常（超时、连接失败等）
-/// * 流式下载，节省内存
-pub async fn download_file(url: &str, path: &str) -> Result<(), Box<dyn std::error::Error>> {
-    // 创建 HTTP 客户端
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
-
-    // 发起 GET 请求
-    let response = client.get(url).send().await?;
-
-    // 检查响应状态
-    if !response.status().is_success() {
-        return Err(format!("HTTP请求失败: {}", response.status()).into());
-    }
-
-    // 获取文件总大小用于进度显示
-    let total_size = response.content_length().unwrap_or(0);
-    let mut downloaded: u64 = 0;
+/// * 每收到一块数据报告一次进度
+/// * 处理网络异常（连接或读取空闲超时、连接失败等）
+/// * 流式下载：基于 [`download_to_writer`]，每收到一块数据即写入文件，不在内存中缓存完整响应
+/// * 网络错误和 5xx/429 响应按重试策略重新下载（见 [`with_retry`]），每次重试重新写入整个文件
+/// * 本地地址（见 [`local_path`]）直接读取文件
+/// * 下载失败时删除已创建的本地文件
+pub async fn download_file(
+    client: &Client,
+    url: &str,
+    path: &str,
+    progress: &dyn DownloadProgress,
+) -> Result<(), Box<dyn std::error::Error>> {
+    with_retry(client.retry_policy(), || {
+        download_to(client, url, path, progress)
+    })
+    .await
+}
 
+/// 创建本地文件并下载到其中，失败时删除该文件
+async fn download_to(
+    client: &Client,
+    url: &str,
+    path: &str,
+    progress: &dyn DownloadProgress,
+) -> Result<(), Box<dyn std::error::Error>> {
     // 确保目标目录存在
     let parent_dir = std::path::Path::new(path)
         .parent()
         .ok_or("无法获取父目录")?;
     tokio::fs::create_dir_all(parent_dir).await?;
 
-    // 创建本地文件
     let mut file = tokio::fs::File::create(path).await?;
+    let result = download_to_writer(client, url, &mut file, progress).await;
+    drop(file);
+    if result.is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+    result.map(|_| ())
+}
 
-    // 流式写入文件
-    let bytes = response.bytes().await?;
-    let bytes_len = bytes.len() as u64;
-    file.write_all(&bytes).await?;
+/// 从指定URL流式下载到任意写入器
+///
+/// # 参数
+///
+/// * `client` - HTTP 客户端（见 [`Client`]）
+/// * `url` - 要下载的文件的URL
+/// * `writer` - 接收响应体的写入器（如文件、边写边计算哈希的写入器）
+/// * `progress` - 下载进度回调（如 [`ConsoleProgress`]）
+///
+/// # 返回值
+///
+/// 返回 `Result<u64, Box<dyn std::error::Error>>`，成功时返回写入的字节数
+///
+/// # 功能特性
+///
+/// * 每收到一块数据即写入 `writer` 并报告一次进度，完成后刷新写入器
+/// * 本地地址（见 [`local_path`]）直接读取文件
+/// * 不重试：失败时 `writer` 中可能已有部分内容，需要重试的调用方应自行准备新的写入器（见 [`download_file`]）
+pub async fn download_to_writer(
+    client: &Client,
+    url: &str,
+    mut writer: impl AsyncWrite + Unpin,
+    progress: &dyn DownloadProgress,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    if let Some(source) = local_path(url) {
+        let mut file = tokio::fs::File::open(&source)
+            .await
+            .map_err(|e| format!("无法读取本地文件 {}: {e}", source.display()))?;
+        progress.begin(Some(file.metadata().await?.len()));
+        let result = tokio::io::copy(&mut file, &mut writer).await;
+        if let Ok(copied) = result {
+            progress.advance(copied);
+        }
+        progress.finish();
+        let copied = result?;
+        writer.flush().await?;
+        return Ok(copied);
+    }
 
-    // 更新下载进度
-    downloaded += bytes_len;
+    let response = send_get(client, url).await?;
+    write_with_progress(response, writer, 0, progress).await
+}
 
-    // 显示进度
-    if total_size > 0 {
-        let progress = (downloaded as f64 / total_size as f64 * 100.0) as u8;
-        eprint!("\r下载进度: {progress}%");
+/// 将响应体写入写入器，并把每块数据的字节数报告给 `progress`
+///
+/// `existing` 为续传时本地已有的字节数，计入总大小并在开始接收前报告
+async fn write_with_progress(
+    response: reqwest::Response,
+    writer: impl AsyncWrite + Unpin,
+    existing: u64,
+    progress: &dyn DownloadProgress,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    progress.begin(response.content_length().map(|size| size + existing));
+    if existing > 0 {
+        progress.advance(existing);
     }
+    let mut reported = 0;
+    let result = write_response(response, writer, |downloaded, _| {
+        progress.advance(downloaded - reported);
+        reported = downloaded;
+    })
+    .await;
+    progress.finish();
+    result
+}
 
-    // 确保所有数据都写入磁盘
-    file.flush().await?;
+/// 可续传地下载文件到本地路径（用于多个文件并发下载，由调用方汇总各文件的进度）
+///
+/// # 参数
+///
+/// * `client` - HTTP 客户端（见 [`Client`]）
+/// * `url` - 要下载的文件的URL
+/// * `path` - 本地保存文件的路径，已存在的内容视为上次中断的部分下载
+/// * `progress` - 下载进度回调；续传时已有的内容在开始接收前一并计入
+///
+/// # 返回值
+///
+/// 返回 `Result<bool, Box<dyn std::error::Error>>`，在已有内容之后续传时为 `true`，完整下载时为 `false`
+///
+/// # 功能特性
+///
+/// * 本地文件非空时携带 `Range: bytes=<已有长度>-`，服务器返回 206 时追加到已有内容之后
+/// * 服务器不支持范围请求（返回 200）时覆盖重写整个文件
+/// * 服务器返回 416（已有内容不短于远程文件）时重新完整下载
+/// * 不校验哈希：续传得到的文件可能因已有内容损坏而不正确，调用方应在校验失败时删除后重新下载
+pub async fn download_file_resumable(
+    client: &Client,
+    url: &str,
+    path: &str,
+    progress: &dyn DownloadProgress,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    // 本地源无需续传，直接完整复制
+    if let Some(source) = local_path(url) {
+        copy_local(&source, path, progress)?;
+        return Ok(false);
+    }
 
-    // 换行结束进度显示
-    if total_size > 0 {
-        eprintln!();
+    let existing = tokio::fs::metadata(path)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    if existing == 0 {
+        download_to(client, url, path, progress).await?;
+        return Ok(false);
     }
 
-    // 换行结束进度显示
-    if total_size > 0 {
-        eprintln!();
+    let response = client
+        .get(url, |request| {
+            request.header(reqwest::header::RANGE, format!("bytes={existing}-"))
+        })
+        .await?;
+    match response.status() {
+        reqwest::StatusCode::PARTIAL_CONTENT => {
+            if content_range_start(&response) != Some(existing) {
+                return Err(format!("服务器返回的范围与请求不符: {url}").into());
+            }
+            let mut file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(path)
+                .await?;
+            write_with_progress(response, &mut file, existing, progress).await?;
+            Ok(true)
+        }
+        reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+            download_to(client, url, path, progress).await?;
+            Ok(false)
+        }
+        status if status.is_success() => {
+            let mut file = tokio::fs::File::create(path).await?;
+            write_with_progress(response, &mut file, 0, progress).await?;
+            Ok(false)
+        }
+        status => Err(HttpStatusError { status }.into()),
     }
+}
 
-    Ok(())
+/// 解析 206 响应 `Content-Range: bytes <start>-<end>/<total>` 中的起始位置
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .parse()
+        .ok()
+}
+
+/// 条件下载文件
+///
+/// # 参数
+///
+/// * `client` - HTTP 客户端（见 [`Client`]）
+/// * `url` - 要下载的文件的URL
+/// * `path` - 本地保存路径
+/// * `expected_hash` - 期望的 SHA256 哈希值（可选），下载到新内容时校验
+///
+/// # 返回值
+///
+/// 返回 `Result<bool, Box<dyn std::error::Error>>`，下载了新内容时为 `true`，服务器返回 304 而跳过时为 `false`
+///
+/// # 功能特性
+///
+/// * 本地文件已存在时携带 `If-Modified-Since`（取本地文件的修改时间），未修改的文件不再重复下载
+/// * 先写入同目录下的临时文件，校验通过后再替换目标文件；
+///   中断或校验失败的下载不会留下修改时间较新的残缺文件，避免之后被误判为未修改
+pub async fn download_file_if_modified(
+    client: &Client,
+    url: &str,
+    path: &str,
+    expected_hash: Option<&str>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let partial_path = format!("{path}.part");
+
+    if let Some(source) = local_path(url) {
+        // 本地源：本地文件已与清单一致时跳过
+        if let Some(expected_hash) = expected_hash
+            && std::path::Path::new(path).is_file()
+            && crypto::hash_eq(&crypto::file_hash_like(path, expected_hash)?, expected_hash)
+        {
+            return Ok(false);
+        }
+        std::fs::copy(&source, &partial_path)
+            .map_err(|e| format!("无法复制本地文件 {}: {e}", source.display()))?;
+    } else {
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified());
+        let response = client
+            .get(url, |request| match modified {
+                Ok(modified) => request.header(
+                    reqwest::header::IF_MODIFIED_SINCE,
+                    httpdate::fmt_http_date(modified),
+                ),
+                Err(_) => request,
+            })
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(false);
+        }
+        if !response.status().is_success() {
+            return Err(HttpStatusError {
+                status: response.status(),
+            }
+            .into());
+        }
+
+        let mut file = tokio::fs::File::create(&partial_path).await?;
+        let result = write_response(response, &mut file, |_, _| {}).await;
+        drop(file);
+        if let Err(e) = result {
+            let _ = std::fs::remove_file(&partial_path);
+            return Err(e);
+        }
+    }
+
+    if let Some(expected_hash) = expected_hash {
+        let actual_hash = crypto::file_hash_like(&partial_path, expected_hash)?;
+        if !crypto::hash_eq(&actual_hash, expected_hash) {
+            let _ = std::fs::remove_file(&partial_path);
+            return Err(format!(
+                "文件哈希不匹配: {url} (预期: {expected_hash}, 实际: {actual_hash})"
+            )
+            .into());
+        }
+    }
+
+    std::fs::rename(&partial_path, path)?;
+    Ok(true)
+}
+
+/// 将时间格式化为 RFC 3339 UTC 时间，如 `1994-11-06T08:49:37Z`
+pub fn rfc3339(time: std::time::SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// 流式下载并计算 SHA256 哈希值
+///
+/// # 参数
+///
+/// * `client` - HTTP 客户端（见 [`Client`]）
+/// * `url` - 要下载的文件的URL
+/// * `algorithm` - 哈希算法
+///
+/// # 返回值
+///
+/// 返回 `Result<(String, u64), Box<dyn std::error::Error>>`，成功时返回（带算法前缀的十六进制哈希值, 字节数），SHA256 不带前缀
+///
+/// # 功能特性
+///
+/// * 数据边下载边计算哈希后即丢弃，不写入磁盘也不在内存中缓存完整响应
+pub async fn download_hash(
+    client: &Client,
+    url: &str,
+    algorithm: HashAlgorithm,
+) -> Result<(String, u64), Box<dyn std::error::Error>> {
+    let mut response = send_get(client, url).await?;
+    let mut hasher = crypto::Hasher::new(algorithm);
+    let mut size: u64 = 0;
+
+    while let Some(chunk) = response.chunk().await? {
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+    }
+
+    Ok((algorithm.format(&hasher.finalize()), size))
+}
+
+/// 发起 GET 请求并检查响应状态
+async fn send_get(
+    client: &Client,
+    url: &str,
+) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    // 发起 GET 请求
+    let response = client.get(url, |request| request).await?;
+
+    // 检查响应状态
+    if !response.status().is_success() {
+        return Err(HttpStatusError {
+            status: response.status(),
+        }
+        .into());
+    }
+
+    Ok(response)
+}
+
+/// 将响应体按块写入写入器
+async fn write_response(
+    mut response: reqwest::Response,
+    mut writer: impl AsyncWrite + Unpin,
+    mut progress: impl FnMut(u64, u64),
+) -> Result<u64, Box<dyn std::error::Error>> {
+    // 获取文件总大小用于进度显示
+    let total_size = response.content_length().unwrap_or(0);
+    let mut downloaded: u64 = 0;
+
+    // 流式写入
+    while let Some(chunk) = response.chunk().await? {
+        writer.write_all(&chunk).await?;
+        downloaded += chunk.len() as u64;
+        progress(downloaded, total_size);
+    }
+
+    // 确保所有数据都写入
+    writer.flush().await?;
+
+    Ok(downloaded)
 }
 
 /// 从指定URL获取索引数据
 ///
 /// # 参数
 ///
+/// * `client` - HTTP 客户端（见 [`Client`]）
 /// * `url` - 索引文件的URL
 ///
 /// # 返回值
@@ -93,123 +993,980 @@ pub async fn download_file(url: &str, path: &str) -> Result<(), Box<dyn std::err
 /// * 支持 HTTP/HTTPS 请求
 /// * 处理网络异常（超时、连接失败等）
 /// * 返回解析后的 JSON 数据
-pub async fn fetch_index(url: &str) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
-    // 创建 HTTP 客户端
-    let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(30))
-        .build()?;
+/// * 网络错误和 5xx/429 响应按重试策略重新请求（见 [`with_retry`]）
+/// * 本地地址（见 [`local_path`]）直接读取文件
+pub async fn fetch_index(
+    client: &Client,
+    url: &str,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_str(&fetch_text(client, url).await?)?)
+}
 
+/// 索引的缓存验证信息（HTTP 响应中的 `ETag` 和 `Last-Modified`），用于下次获取时发送条件请求
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexValidators {
+    /// 返回这些验证信息的索引地址，只对同一地址发送条件请求
+    pub url: String,
+    /// `ETag` 响应头，下次请求时作为 `If-None-Match` 发送
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// `Last-Modified` 响应头，下次请求时作为 `If-Modified-Since` 发送
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// 条件获取索引的结果
+#[derive(Debug)]
+pub enum IndexFetch {
+    /// 获取到索引内容，`validators` 为服务器返回的验证信息（本地源或服务器未返回时为 `None`）
+    Fresh {
+        index: serde_json::Value,
+        validators: Option<IndexValidators>,
+    },
+    /// 服务器返回 304，索引自上次获取以来未改变
+    NotModified,
+}
+
+/// 获取软件源的索引，源启用了索引校验（见 [`SourceConfig::verifies_index`]）时先校验再解析
+///
+/// # 参数
+///
+/// * `client` - HTTP 客户端（见 [`Client`]）
+/// * `url` - 索引文件的URL（主 URL 或镜像 URL 下的 index.json）
+/// * `source` - 索引所属的软件源
+///
+/// # 返回值
+///
+/// 返回 `Result<serde_json::Value, Box<dyn std::error::Error>>`，成功时返回解析后的JSON值；
+/// 校验失败时返回 [`IndexVerifyError`]，获取失败时返回其它错误
+///
+/// # 功能特性
+///
+/// * 从同一地址获取 index.json.sha256，哈希与 index.json 的原始内容不符时拒绝使用
+/// * 源配置了 `trusted_key` 时要求发布 index.json.sig，并验证其为该哈希的有效签名；
+///   签名缺失视为校验失败，避免删除签名文件即可把源降级为未签名
+pub async fn fetch_source_index(
+    client: &Client,
+    url: &str,
+    source: &SourceConfig,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    match fetch_source_index_if_modified(client, url, source, None).await? {
+        IndexFetch::Fresh { index, .. } => Ok(index),
+        IndexFetch::NotModified => Err("服务器在无条件请求时返回了 304".into()),
+    }
+}
+
+/// 按上次的验证信息条件获取软件源的索引
+///
+/// `validators` 的地址与 `url` 相同时发送 `If-None-Match` / `If-Modified-Since`，
+/// 服务器返回 304 时不再下载和校验，返回 [`IndexFetch::NotModified`]；
+/// 否则同 [`fetch_source_index`]，并一同返回响应中的验证信息供下次使用
+pub async fn fetch_source_index_if_modified(
+    client: &Client,
+    url: &str,
+    source: &SourceConfig,
+    validators: Option<&IndexValidators>,
+) -> Result<IndexFetch, Box<dyn std::error::Error>> {
+    let Some((body, validators)) = fetch_text_if_modified(client, url, validators).await? else {
+        return Ok(IndexFetch::NotModified);
+    };
+    verify_source_index(client, url, source, &body).await?;
+
+    Ok(IndexFetch::Fresh {
+        index: serde_json::from_str(&body)?,
+        validators,
+    })
+}
+
+/// 按软件源的配置校验已获取的索引原始内容
+///
+/// 源未启用索引校验（见 [`SourceConfig::verifies_index`]）时直接通过；
+/// 否则从 `url` 旁获取 index.json.sha256（及配置了 `trusted_key` 时的 index.json.sig）进行核对，
+/// 规则同 [`fetch_source_index`]，校验失败时返回 [`IndexVerifyError`]
+pub async fn verify_source_index(
+    client: &Client,
+    url: &str,
+    source: &SourceConfig,
+    body: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !source.verifies_index() {
+        return Ok(());
+    }
+
+    let Some(expected) = fetch_optional_text(client, &format!("{url}{INDEX_HASH_SUFFIX}")).await?
+    else {
+        return Err(IndexVerifyError::MissingHash.into());
+    };
+    let expected = expected.split_whitespace().next().unwrap_or_default();
+    let actual = crypto::bytes_hash(body.as_bytes());
+    if !crypto::hash_eq(&actual, expected) {
+        return Err(IndexVerifyError::HashMismatch {
+            expected: expected.to_string(),
+            actual,
+        }
+        .into());
+    }
+
+    if let Some(trusted_key) = &source.trusted_key {
+        let Some(signature) =
+            fetch_optional_text(client, &format!("{url}{INDEX_SIGNATURE_SUFFIX}")).await?
+        else {
+            return Err(IndexVerifyError::MissingSignature.into());
+        };
+        let valid = crypto::verify_signature(
+            actual.as_bytes(),
+            &crypto::hex_decode(signature.trim())?,
+            &crypto::hex_decode(trusted_key)?,
+        )?;
+        if !valid {
+            return Err(IndexVerifyError::BadSignature.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// 条件获取文本内容，未改变（304）时返回 `None`
+///
+/// 本地地址直接读取文件，不使用验证信息
+async fn fetch_text_if_modified(
+    client: &Client,
+    url: &str,
+    validators: Option<&IndexValidators>,
+) -> Result<Option<(String, Option<IndexValidators>)>, Box<dyn std::error::Error>> {
+    if local_path(url).is_some() {
+        return Ok(Some((fetch_text(client, url).await?, None)));
+    }
+    let validators = validators.filter(|validators| validators.url == url);
+    with_retry(client.retry_policy(), || {
+        fetch_text_if_modified_once(client, url, validators)
+    })
+    .await
+}
+
+/// 条件获取文本内容（单次请求，不重试）
+async fn fetch_text_if_modified_once(
+    client: &Client,
+    url: &str,
+    validators: Option<&IndexValidators>,
+) -> Result<Option<(String, Option<IndexValidators>)>, Box<dyn std::error::Error>> {
+    let response = client
+        .get(url, |mut request| {
+            if let Some(validators) = validators {
+                if let Some(etag) = &validators.etag {
+                    request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &validators.last_modified {
+                    request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            request
+        })
+        .await?;
+
+    if validators.is_some() && response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(HttpStatusError {
+            status: response.status(),
+        }
+        .into());
+    }
+
+    let header = |name: reqwest::header::HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag = header(reqwest::header::ETAG);
+    let last_modified = header(reqwest::header::LAST_MODIFIED);
+    let fresh = (etag.is_some() || last_modified.is_some()).then(|| IndexValidators {
+        url: url.to_string(),
+        etag,
+        last_modified,
+    });
+    Ok(Some((response.text().await?, fresh)))
+}
+
+/// 获取文本内容：本地地址直接读取文件，远程地址按重试策略请求
+async fn fetch_text(client: &Client, url: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if let Some(path) = local_path(url) {
+        return Ok(std::fs::read_to_string(&path)
+            .map_err(|e| format!("无法读取 {}: {e}", path.display()))?);
+    }
+    with_retry(client.retry_policy(), || fetch_text_once(client, url)).await
+}
+
+/// 获取可选的文本内容，本地文件不存在或服务器返回 404 时返回 `None`
+async fn fetch_optional_text(
+    client: &Client,
+    url: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    if local_path(url).is_some_and(|path| !path.exists()) {
+        return Ok(None);
+    }
+    match fetch_text(client, url).await {
+        Ok(text) => Ok(Some(text)),
+        Err(e)
+            if e.downcast_ref::<HttpStatusError>()
+                .is_some_and(|e| e.status == reqwest::StatusCode::NOT_FOUND) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// 获取文本内容（单次请求，不重试）
+async fn fetch_text_once(client: &Client, url: &str) -> Result<String, Box<dyn std::error::Error>> {
     // 发起 GET 请求
-    let response = client.get(url).send().await?;
+    let response = client.get(url, |request| request).await?;
 
     // 检查响应状态
     if !response.status().is_success() {
-        return Err(format!("HTTP请求失败: {}", response.status()).into());
+        return Err(HttpStatusError {
+            status: response.status(),
+        }
+        .into());
     }
 
     // 读取响应体
+    Ok(response.text().await?)
+}
+
+/// 探测结果
+#[derive(Debug)]
+pub struct ProbeResult {
+    /// HTTP 状态码
+    pub status: u16,
+    /// 往返延迟（发出请求到收到响应头）
+    pub latency: std::time::Duration,
+    /// 响应体
+    pub body: String,
+}
+
+/// 探测指定URL的可用性
+///
+/// # 参数
+///
+/// * `client` - HTTP 客户端（见 [`Client`]）
+/// * `url` - 要探测的URL
+/// * `timeout` - 超时时间
+///
+/// # 返回值
+///
+/// 返回 `Result<ProbeResult, Box<dyn std::error::Error>>`，收到任意 HTTP 响应（包括非 2xx）时返回 Ok，
+/// 无法连接或超时时返回错误
+///
+/// # 功能特性
+///
+/// * 记录往返延迟
+/// * 不因非成功状态码而报错，便于上层报告状态
+pub async fn probe(
+    client: &Client,
+    url: &str,
+    timeout: std::time::Duration,
+) -> Result<ProbeResult, Box<dyn std::error::Error>> {
+    // 发起 GET 请求并计时（单独设置超时，覆盖客户端的默认值）
+    let started = std::time::Instant::now();
+    let response = client.get(url, |request| request.timeout(timeout)).await?;
+    let latency = started.elapsed();
+
+    let status = response.status().as_u16();
     let body = response.text().await?;
 
-    // 解析JSON
-    let index: serde_json::Value = serde_json::from_str(&body)?;
+    Ok(ProbeResult {
+        status,
+        latency,
+        body,
+    })
+}
 
-    Ok(index)
+/// 镜像同步选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MirrorOptions {
+    /// 演练：只获取索引和元数据，不下载软件包文件，也不写入任何内容
+    pub dry_run: bool,
 }
 
 /// 执行镜像同步，完全同步源的内容到本地
 ///
 /// # 参数
 ///
-/// * `source_url` - 源的基URL
+/// * `client` - HTTP 客户端（见 [`Client`]）
+/// * `source_url` - 源的基URL（主 URL 或镜像 URL）
+/// * `source` - 软件源配置（是否启用、是否强制使用HTTPS、是否校验索引）
 /// * `target_dir` - 本地目标目录
-/// * `enabled` - 源是否启用
-/// * `require_https` - 是否强制使用HTTPS
+/// * `options` - 同步选项（见 [`MirrorOptions`]）
 ///
 /// # 返回值
 ///
-/// 返回 `Result<(), Box<dyn std::error::Error>>`，成功时返回 Ok(())，失败时返回错误
+/// 返回 `Result<Vec<String>, Box<dyn std::error::Error>>`，成功时返回写入的软件包文件路径列表（不含索引和版本历史），失败时返回错误；
+/// 演练时返回将要写入的文件（本地缺失或哈希不符的文件，以及内容有变化的 metadata.json）
 ///
 /// # 功能特性
 ///
-/// * 完全同步源的内容，保持与源一致
-/// * 处理文件的添加、更新和删除
-/// * 确保数据完整性
-/// * 处理网络异常
+/// * 镜像源索引 packages 部分中每个软件包的所有可用版本，按 `packages/<id>/<version>/` 布局写入目标目录
+/// * 源启用了索引校验时，按 [`fetch_source_index`] 校验源索引后再使用
+/// * 逐个获取并校验各版本的 metadata.json（自校验哈希、ID 和版本号），按清单校验每个文件的哈希
+/// * 生成目标目录下的 index.json 及 index.json.sha256，`location` 改写为相对的 `./packages/<id>/<version>`，并写入各软件包的 versions.txt；
+///   同步完成后目标目录可以直接作为新软件源的 URL 使用
+/// * 未修改的文件由服务器返回 304 跳过
 pub async fn mirror_sync(
+    client: &Client,
     source_url: &str,
+    source: &SourceConfig,
     target_dir: &str,
-    enabled: bool,
-    require_https: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
+    options: &MirrorOptions,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let dry_run = options.dry_run;
+
     // 检查源是否启用
-    if !enabled {
-        return Ok(());
+    if !source.enabled {
+        return Ok(Vec::new());
     }
 
     // 验证URL协议
-    if require_https && !source_url.starts_with("https://") {
+    if source.require_https && !source_url.starts_with("https://") {
         return Err("源配置要求使用HTTPS，但提供的URL不是HTTPS".into());
     }
 
     // 创建目标目录
-    std::fs::create_dir_all(target_dir)?;
+    let target_dir = std::path::Path::new(target_dir);
+    if !dry_run {
+        std::fs::create_dir_all(target_dir)?;
+    }
 
     // 获取源索引
-    let index_url = format!("{}/index.json", source_url.trim_end_matches('/'));
-    let index = fetch_index(&index_url).await?;
-
-    // 同步源索引中的所有文件
-    if let Some(source) = index["source"].as_array() {
-        for pkg in source {
-            if let Some(location) = pkg["location"].as_str() {
-                // 确保位置以/结尾
-                let location = if location.ends_with('/') {
-                    location.to_string()
-                } else {
-                    format!("{location}/")
-                };
+    let source_url = source_url.trim_end_matches('/');
+    let index = fetch_source_index(client, &format!("{source_url}/index.json"), source).await?;
 
-                // 获取包的文件列表
-                let files_url = format!("{location}metadata.json");
-                let files_index = fetch_index(&files_url).await?;
+    // 记录写入的文件和镜像索引条目
+    let mut written_files = Vec::new();
+    let mut mirrored_packages = Vec::new();
 
-                // 同步包中的所有文件
-                if let Some(files) = files_index["all_files"].as_object() {
-                    for (file_path, _hash) in files {
-                        let file_url = format!("{location}{file_path}");
-                        let local_path = format!("{target_dir}/{file_path}");
+    for package in index["packages"].as_array().into_iter().flatten() {
+        let (Some(id), Some(latest_version), Some(location)) = (
+            package["id"].as_str(),
+            package["latest_version"].as_str(),
+            package["location"].as_str(),
+        ) else {
+            return Err(format!("源索引中的软件包条目不完整: {package}").into());
+        };
 
-                        // 确保本地目录存在
-                        if let Some(parent) = std::path::Path::new(&local_path).parent() {
-                            std::fs::create_dir_all(parent)?;
-                        }
+        // 相对位置相对于源根目录解析；location 指向最新版本目录，其它版本位于同级目录
+        let location = match location.strip_prefix("./") {
+            Some(relative) => format!("{source_url}/{relative}"),
+            None => location.to_string(),
+        };
+        let location = location.trim_end_matches('/');
+        let parent_location = location.rsplit_once('/').map_or("", |(parent, _)| parent);
+
+        let mut versions: Vec<&str> = package["available_versions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|version| version.as_str())
+            .collect();
+        if versions.is_empty() {
+            versions.push(latest_version);
+        }
 
-                        // 下载文件
-                        download_file(&file_url, &local_path).await?;
+        let packages_dir = target_dir.join("packages");
+        if !is_safe_relative(&packages_dir, id) {
+            return Err(format!("源索引中的软件包ID不合法: {id}").into());
+        }
+        let package_dir = packages_dir.join(id);
+        for version in &versions {
+            if !is_safe_relative(&package_dir, version) {
+                return Err(format!("源索引中 {id} 的版本号不合法: {version}").into());
+            }
+            let version_location = if *version == latest_version {
+                location.to_string()
+            } else {
+                format!("{parent_location}/{version}")
+            };
+            let version_dir = package_dir.join(version);
+
+            // 获取并校验元数据
+            let metadata_url = format!("{version_location}/metadata.json");
+            let metadata_value = fetch_index(client, &metadata_url).await?;
+            let metadata: PackageMetadata = serde_json::from_value(metadata_value.clone())?;
+            if !metadata.verify_metadata_hash()? {
+                return Err(format!("元数据自校验失败: {metadata_url}").into());
+            }
+            if metadata.id != id || metadata.version != *version {
+                return Err(format!(
+                    "元数据 {}:{} 与索引条目 {id}:{version} 不一致: {metadata_url}",
+                    metadata.id, metadata.version
+                )
+                .into());
+            }
+
+            // 写入任何文件之前拒绝越出版本目录的路径（路径穿越）和已存在的符号链接
+            check_destination_paths(&version_dir, metadata.all_files.keys())
+                .map_err(|e| format!("软件包 {id}:{version}: {e}"))?;
+
+            // 同步包中的所有文件（未修改的文件由服务器返回 304 跳过，新内容按清单校验哈希）
+            for (file_path, entry) in &metadata.all_files {
+                let (expected_hash, _) = parse_file_entry(entry);
+                let local_path = version_dir.join(file_path);
+                let local_path = local_path.to_str().ok_or("路径包含无效的 UTF-8 字符")?;
+
+                // 演练时按本地文件的哈希判断是否需要下载
+                if dry_run {
+                    let up_to_date = std::path::Path::new(local_path).is_file()
+                        && crypto::hash_eq(
+                            &crypto::file_hash_like(local_path, expected_hash)?,
+                            expected_hash,
+                        );
+                    if !up_to_date {
+                        written_files.push(local_path.to_string());
                     }
+                    continue;
                 }
+
+                // 确保本地目录存在
+                if let Some(parent) = std::path::Path::new(local_path).parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                // 下载文件
+                let file_url = format!("{version_location}/{file_path}");
+                download_file_if_modified(client, &file_url, local_path, Some(expected_hash))
+                    .await?;
+                written_files.push(local_path.to_string());
+            }
+
+            // 写入校验过的元数据
+            let metadata_path = version_dir.join("metadata.json");
+            let metadata_content = serde_json::to_string_pretty(&metadata_value)?;
+            if dry_run {
+                if std::fs::read_to_string(&metadata_path).ok().as_ref() != Some(&metadata_content)
+                {
+                    written_files.push(metadata_path.to_string_lossy().to_string());
+                }
+                continue;
+            }
+            fsxg::atomic_write(&metadata_path, metadata_content)?;
+            written_files.push(metadata_path.to_string_lossy().to_string());
+        }
+
+        if dry_run {
+            continue;
+        }
+
+        // 写入版本历史（保留目标目录中已有的版本）
+        let history_path = package_dir.join("versions.txt");
+        let mut history: Vec<String> = std::fs::read_to_string(&history_path)
+            .map(|content| content.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        for version in versions {
+            if !history.iter().any(|existing| existing == version) {
+                history.push(version.to_string());
             }
         }
+        fsxg::atomic_write(&history_path, history.join("\n"))?;
+
+        // 改写为相对位置
+        let mut mirrored = package.clone();
+        mirrored["location"] = format!("./packages/{id}/{latest_version}").into();
+        mirrored_packages.push(mirrored);
     }
 
-    Ok(())
+    if dry_run {
+        return Ok(written_files);
+    }
+
+    // 生成镜像索引
+    let mirror_index = serde_json::json!({
+        "packages": mirrored_packages,
+        "source": [],
+    });
+    let mirror_index = serde_json::to_string_pretty(&mirror_index)?;
+    fsxg::atomic_write(target_dir.join("index.json"), &mirror_index)?;
+    fsxg::atomic_write(
+        target_dir.join(format!("index.json{INDEX_HASH_SUFFIX}")),
+        format!(
+            "{}  index.json\n",
+            crypto::bytes_hash(mirror_index.as_bytes())
+        ),
+    )?;
+
+    Ok(written_files)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::testing::test_source;
     use std::fs;
+    use std::path::PathBuf;
     use tempfile::TempDir;
 
+    fn test_client() -> Client {
+        Client::new(&NetworkConfig::default(), &[], RetryPolicy::default()).unwrap()
+    }
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(
+            local_path("file:///srv/my%20repo/index.json"),
+            Some(PathBuf::from("/srv/my repo/index.json"))
+        );
+        assert_eq!(local_path("/srv/repo"), Some(PathBuf::from("/srv/repo")));
+        assert_eq!(local_path("./repo"), Some(PathBuf::from("./repo")));
+        assert_eq!(
+            local_path("../repo/index.json"),
+            Some(PathBuf::from("../repo/index.json"))
+        );
+        // 带主机名的 file:// 地址和远程地址不是本地地址
+        assert_eq!(local_path("file://host/repo"), None);
+        assert_eq!(local_path("https://example.com/repo"), None);
+        assert_eq!(local_path("repo"), None);
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        let at = |secs| std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        assert_eq!(rfc3339(at(0)), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(at(784111777)), "1994-11-06T08:49:37Z");
+        assert_eq!(rfc3339(at(951782400)), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn test_cert_pin_parse() {
+        let hash = "ab".repeat(32);
+        assert_eq!(
+            CertPin::parse(&format!("cert-sha256:{}", hash.to_uppercase())).unwrap(),
+            CertPin::Certificate(hash.clone())
+        );
+
+        // 兼容 openssl 指纹输出中的冒号分隔
+        let fingerprint = vec!["AB"; 32].join(":");
+        assert_eq!(
+            CertPin::parse(&format!("spki-sha256:{fingerprint}")).unwrap(),
+            CertPin::PublicKey(hash.clone())
+        );
+
+        assert!(CertPin::parse(&hash).is_err());
+        assert!(CertPin::parse("cert-sha256:abcd").is_err());
+        assert!(CertPin::parse(&format!("md5:{hash}")).is_err());
+    }
+
+    /// 自签名测试证书（DER 编码，P-256 公钥，CN=pinned.example.com）
+    const TEST_CERTIFICATE: &str = concat!(
+        "3082019130820137a00302010202145913e7fca3891d38e71368770ae8c8622eef50ed30",
+        "0a06082a8648ce3d040302301d311b301906035504030c1270696e6e65642e6578616d70",
+        "6c652e636f6d3020170d3236313031363139323030355a180f3231323630393232313932",
+        "3030355a301d311b301906035504030c1270696e6e65642e6578616d706c652e636f6d30",
+        "59301306072a8648ce3d020106082a8648ce3d030107034200041ca8fb9e7b85793a17fa",
+        "3fb6455cf684a56e01548f68c16898bf7204af2d411dceb74b7e74ff3124f0aefce79cce",
+        "5aac235f3781f2628e74971cc8c17565bb3da3533051301d0603551d0e04160414ca697a",
+        "f2ee9fd10587eb5d79b653c80476711882301f0603551d23041830168014ca697af2ee9f",
+        "d10587eb5d79b653c80476711882300f0603551d130101ff040530030101ff300a06082a",
+        "8648ce3d0403020348003045022100aa1702fb40e01a7a9bfd5ed3b22bf4d84a7c0412b7",
+        "5787b5dd00f5c95f3961a2022009be5297a448b6401cd33d736de53663f68d4fc8b60aa2",
+        "1959dddda5369b1e7a",
+    );
+
+    #[test]
+    fn test_certificate_spki() {
+        let certificate = crypto::hex_decode(TEST_CERTIFICATE).unwrap();
+
+        // 与 `openssl x509 -pubkey | openssl pkey -pubin -outform DER | sha256sum` 的结果一致
+        let spki = certificate_spki(&certificate).unwrap();
+        assert_eq!(
+            crypto::bytes_hash(&spki),
+            "375ab3edcbd0fdda5a34ba24bf807b39f314db25a4f14be1ee99aebc227fb00b"
+        );
+        assert_eq!(certificate_spki(&certificate[..10]), None);
+    }
+
+    #[test]
+    fn test_client_source_tls() {
+        let mut source = SourceConfig {
+            id: "pinned".to_string(),
+            name: "Pinned".to_string(),
+            url: "https://pinned.example.com/repo".to_string(),
+            mirrors: vec!["https://mirror.example.com/pinned/".to_string()],
+            min_tls_version: Some("1.2".to_string()),
+            pin: Some(format!("spki-sha256:{}", "0".repeat(64))),
+            ..Default::default()
+        };
+        let client = Client::new(
+            &NetworkConfig::default(),
+            &[source.clone()],
+            RetryPolicy::default(),
+        )
+        .unwrap();
+
+        // 仅源 URL（及镜像 URL）下的请求使用源的 TLS 策略
+        let pin = Some(CertPin::PublicKey("0".repeat(64)));
+        let (_, matched) = client.route("https://pinned.example.com/repo/index.json");
+        assert_eq!(matched, pin.as_ref());
+        let (_, matched) = client.route("https://mirror.example.com/pinned/index.json");
+        assert_eq!(matched, pin.as_ref());
+        let (_, matched) = client.route("https://pinned.example.com/repo2/index.json");
+        assert_eq!(matched, None);
+
+        // 不同客户端的策略互不影响
+        let other = test_client();
+        assert_eq!(
+            other.route("https://pinned.example.com/repo/index.json").1,
+            None
+        );
+
+        // 禁用的源不构建策略，启用的源配置无效时报错
+        source.min_tls_version = Some("2.0".to_string());
+        assert!(
+            Client::new(
+                &NetworkConfig::default(),
+                &[source.clone()],
+                RetryPolicy::default()
+            )
+            .is_err()
+        );
+        source.enabled = false;
+        assert!(Client::new(&NetworkConfig::default(), &[source], RetryPolicy::default()).is_ok());
+    }
+
+    #[test]
+    fn test_build_client() {
+        // 默认启用 HTTP/2 协商，也可以强制 HTTP/1.1 并关闭 keep-alive
+        assert!(build_client(&NetworkConfig::default()).is_ok());
+        let config = NetworkConfig {
+            http1_only: true,
+            tcp_keepalive: 0,
+            ..NetworkConfig::default()
+        };
+        assert!(build_client(&config).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_client_user_agent() -> Result<(), Box<dyn std::error::Error>> {
+        use tokio::io::AsyncReadExt;
+
+        // 服务器记录收到的请求头
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            let mut buffer = vec![0; 4096];
+            let read = stream.read(&mut buffer).await?;
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+                .await?;
+            std::io::Result::Ok(String::from_utf8_lossy(&buffer[..read]).to_lowercase())
+        });
+
+        let config = NetworkConfig {
+            user_agent: "mirror-probe/1.0".to_string(),
+            ..NetworkConfig::default()
+        };
+        build_client(&config)?
+            .get(format!("http://{address}/"))
+            .send()
+            .await?;
+        assert!(server.await??.contains("user-agent: mirror-probe/1.0"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let breaker = CircuitBreaker::new(2);
+        let mut attempts = 0;
+
+        for _ in 0..4 {
+            let result: Result<(), _> = breaker
+                .call("dead", || {
+                    attempts += 1;
+                    async { Err("连接失败".into()) }
+                })
+                .await;
+            assert!(result.is_err());
+        }
+
+        // 熔断后不再发起请求，其它源不受影响
+        assert_eq!(attempts, 2);
+        assert!(breaker.is_tripped("dead"));
+        assert!(!breaker.is_tripped("alive"));
+        let result = breaker.call("alive", || async { Ok(1) }).await;
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    /// 在本机回环地址上依次以给定状态码响应（用完后重复最后一个），返回 URL 和已收到的请求数
+    async fn serve_statuses(
+        statuses: Vec<u16>,
+    ) -> Result<(String, std::sync::Arc<std::sync::atomic::AtomicUsize>), Box<dyn std::error::Error>>
+    {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::AsyncReadExt;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let hits = std::sync::Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0; 4096];
+                let _ = stream.read(&mut buffer).await;
+                let hit = counter.fetch_add(1, Ordering::SeqCst);
+                let status = statuses[hit.min(statuses.len() - 1)];
+                let body = if status == 200 { r#"{"ok":true}"# } else { "" };
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        Ok((format!("http://{address}/index.json"), hits))
+    }
+
+    #[tokio::test]
+    async fn test_client_reuses_connections() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::AsyncReadExt;
+
+        // 保持连接的服务器，分别统计建立的连接数和收到的请求数
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}/index.json", listener.local_addr()?);
+        let connections = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let (connection_counter, request_counter) = (connections.clone(), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                connection_counter.fetch_add(1, Ordering::SeqCst);
+                let request_counter = request_counter.clone();
+                tokio::spawn(async move {
+                    let mut buffer = vec![0; 4096];
+                    while matches!(stream.read(&mut buffer).await, Ok(n) if n > 0) {
+                        request_counter.fetch_add(1, Ordering::SeqCst);
+                        let body = r#"{"ok":true}"#;
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+                            body.len()
+                        );
+                        if stream.write_all(response.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        // 同一客户端及其克隆发起的请求复用同一连接
+        let client = test_client();
+        for client in [client.clone(), client.clone(), client] {
+            assert_eq!(fetch_index(&client, &url).await?["ok"], true);
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_with_retry() -> Result<(), Box<dyn std::error::Error>> {
+        let client = test_client();
+        use std::sync::atomic::Ordering;
+
+        let policy = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+        };
+
+        // 前两次 503 后成功
+        let (url, hits) = serve_statuses(vec![503, 503, 200]).await?;
+        let body = with_retry(policy, || fetch_text_once(&client, &url)).await?;
+        let index: serde_json::Value = serde_json::from_str(&body)?;
+        assert_eq!(index["ok"], true);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // 4xx 不重试
+        let (url, hits) = serve_statuses(vec![404, 200]).await?;
+        assert!(
+            with_retry(policy, || fetch_text_once(&client, &url))
+                .await
+                .is_err()
+        );
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // 重试次数用尽后返回最后一次的错误
+        let (url, hits) = serve_statuses(vec![429]).await?;
+        let error = with_retry(policy, || fetch_text_once(&client, &url))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("429"));
+        assert_eq!(hits.load(Ordering::SeqCst), 4);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_to_writer() -> Result<(), Box<dyn std::error::Error>> {
+        let client = test_client();
+
+        // 远程响应和本地文件都可以写入任意写入器
+        let (url, _) = serve_statuses(vec![200]).await?;
+        let mut body = Vec::new();
+        let size =
+            download_to_writer(&client, &url, &mut body, &ConsoleProgress::default()).await?;
+        assert_eq!((body.as_slice(), size), (&br#"{"ok":true}"#[..], 11));
+
+        let temp_dir = TempDir::new()?;
+        let local = temp_dir.path().join("local.txt");
+        fs::write(&local, "local")?;
+        let mut body = Vec::new();
+        download_to_writer(
+            &client,
+            local.to_str().unwrap(),
+            &mut body,
+            &ConsoleProgress::default(),
+        )
+        .await?;
+        assert_eq!(body, b"local");
+
+        // download_file 在其上写入文件，失败时不留下文件
+        let path = temp_dir.path().join("out/index.json");
+        let path_str = path.to_str().unwrap();
+        download_file(&client, &url, path_str, &ConsoleProgress::default()).await?;
+        assert_eq!(fs::read_to_string(&path)?, r#"{"ok":true}"#);
+        let (missing, _) = serve_statuses(vec![404]).await?;
+        assert!(
+            download_file(&client, &missing, path_str, &ConsoleProgress::default())
+                .await
+                .is_err()
+        );
+        assert!(!path.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_client_offline() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::atomic::Ordering;
+
+        let (url, hits) = serve_statuses(vec![200]).await?;
+        let source_url = url.trim_end_matches("/index.json");
+        let mut client = test_client();
+        client.set_offline(true);
+
+        // 离线时所有请求（包括镜像同步）都在发出前失败，克隆的客户端同样离线
+        let error = fetch_text_once(&client.clone(), &url).await.unwrap_err();
+        assert!(error.to_string().contains("离线模式已启用"));
+        let target = TempDir::new()?;
+        let error = mirror_sync(
+            &client,
+            source_url,
+            &test_source(source_url),
+            target.path().to_str().unwrap(),
+            &MirrorOptions::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("离线模式已启用"));
+        assert_eq!(hits.load(Ordering::SeqCst), 0);
+
+        client.set_offline(false);
+        fetch_text_once(&client, &url).await?;
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_source_index_not_modified() -> Result<(), Box<dyn std::error::Error>> {
+        let client = test_client();
+        use tokio::io::AsyncReadExt;
+
+        // 请求带有匹配的 If-None-Match 时返回 304，否则返回索引和验证信息
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let requests = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0; 4096];
+                let read = stream.read(&mut buffer).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..read]).to_lowercase();
+                let response = if request.contains("if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    let body = r#"{"packages":[]}"#;
+                    format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Tue, 01 Sep 2026 00:00:00 GMT\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                };
+                received.lock().unwrap().push(request);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        let url = format!("http://{address}/index.json");
+        let source = SourceConfig {
+            id: "etag".to_string(),
+            name: "ETag".to_string(),
+            ..test_source(format!("http://{address}"))
+        };
+
+        let IndexFetch::Fresh { index, validators } =
+            fetch_source_index_if_modified(&client, &url, &source, None).await?
+        else {
+            panic!("首次获取应返回索引内容");
+        };
+        assert_eq!(index["packages"], serde_json::json!([]));
+        let validators = validators.expect("应记录验证信息");
+        assert_eq!(validators.url, url);
+        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(
+            validators.last_modified.as_deref(),
+            Some("Tue, 01 Sep 2026 00:00:00 GMT")
+        );
+
+        // 带上验证信息时服务器返回 304
+        let fetched =
+            fetch_source_index_if_modified(&client, &url, &source, Some(&validators)).await?;
+        assert!(matches!(fetched, IndexFetch::NotModified));
+        assert!(
+            requests.lock().unwrap()[1]
+                .contains("if-modified-since: tue, 01 sep 2026 00:00:00 gmt")
+        );
+
+        // 验证信息来自其它地址（如镜像）时不发送条件请求
+        let other = IndexValidators {
+            url: format!("http://{address}/mirror/index.json"),
+            ..validators
+        };
+        let fetched = fetch_source_index_if_modified(&client, &url, &source, Some(&other)).await?;
+        assert!(matches!(fetched, IndexFetch::Fresh { .. }));
+        assert!(!requests.lock().unwrap()[2].contains("if-none-match"));
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_download_file_success() -> Result<(), Box<dyn std::error::Error>> {
+        let client = test_client();
         // 创建临时目录
         let temp_dir = TempDir::new()?;
         let file_path = temp_dir.path().join("test_download.txt");
         let file_path_str = file_path.to_str().unwrap();
 
         // 下载一个已知的小文件进行测试
-        download_file("https://httpbin.org/bytes/1024", file_path_str).await?;
+        download_file(
+            &client,
+            "https://httpbin.org/bytes/1024",
+            file_path_str,
+            &ConsoleProgress::default(),
+        )
+        .await?;
 
         // 验证文件存在且大小正确
         assert!(file_path.exists());
@@ -221,13 +1978,27 @@ mod tests {
 
     #[tokio::test]
     async fn test_download_file_invalid_url() {
-        let result = download_file("https://not-exsist.example.com/file.txt", "test.txt").await;
+        let client = test_client();
+        let result = download_file(
+            &client,
+            "https://not-exsist.example.com/file.txt",
+            "test.txt",
+            &ConsoleProgress::default(),
+        )
+        .await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_download_file_to_invalid_path() {
-        let result = download_file("https://httpbin.org/bytes/10", "/invalid/path/test.txt").await;
+        let client = test_client();
+        let result = download_file(
+            &client,
+            "https://httpbin.org/bytes/10",
+            "/invalid/path/test.txt",
+            &ConsoleProgress::default(),
+        )
+        .await;
         assert!(result.is_err());
     }
 }
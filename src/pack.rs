@@ -0,0 +1,203 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! 软件包打包：生成可分发的 tar.zst 归档，或适合静态托管的目录结构
+
+use crate::app;
+use crate::archive;
+use crate::crypto;
+use crate::fsxg;
+use crate::metadata::PackageMetadata;
+use crate::model::{PackageInfo, RepositoryIndex};
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 生成的归档
+#[derive(Debug)]
+pub struct PackedBundle {
+    /// 归档路径
+    pub path: PathBuf,
+    /// 归档的 SHA256 哈希
+    pub hash: String,
+    /// 归档大小（字节）
+    pub size: u64,
+}
+
+/// 生成的静态托管目录结构
+#[derive(Debug)]
+pub struct PackedDirectory {
+    /// 版本目录（`<output>/packages/<id>/<version>`）
+    pub version_dir: PathBuf,
+    /// 索引片段（`<output>/<id>-<version>.index.json`），内容为只含该软件包的 index.json
+    pub fragment: PathBuf,
+}
+
+/// 检查软件包并读取元数据
+///
+/// 运行与 `app check` 相同的检查（元数据校验、重新计算 `all_files` 中每个文件的哈希），
+/// 存在错误时拒绝打包。返回 metadata.json 的原始内容和解析后的元数据
+fn load_checked(package_path: &Path) -> Result<(Vec<u8>, PackageMetadata)> {
+    let result = app::check(package_path)?;
+    if result.error_count() > 0 {
+        bail!(
+            "软件包检查未通过（{} 个错误），请使用 app check 查看详情",
+            result.error_count()
+        );
+    }
+
+    let metadata_path = package_path.join("metadata.json");
+    let content = fs::read(&metadata_path)
+        .with_context(|| format!("无法读取元数据文件: {}", metadata_path.display()))?;
+    let metadata = serde_json::from_slice(&content).with_context(|| "无法解析元数据 JSON")?;
+    Ok((content, metadata))
+}
+
+/// 将软件包打包为 `<output_dir>/<id>-<version>.tar.zst`
+///
+/// 归档的第一个条目为 metadata.json，其后为 `all_files` 中的所有文件，
+/// 可直接用于 `repo add`，加入仓库后供安装时整体下载
+pub fn pack_bundle(package_path: &Path, output_dir: &Path) -> Result<PackedBundle> {
+    let (metadata_content, metadata) = load_checked(package_path)?;
+
+    let path = output_dir.join(archive::bundle_file_name(&metadata.id, &metadata.version));
+    archive::create_bundle(&path, &metadata_content, &metadata, package_path)?;
+
+    let hash = crypto::file_hash(&path)
+        .with_context(|| format!("无法计算文件哈希: {}", path.display()))?;
+    let size = fs::metadata(&path)?.len();
+    Ok(PackedBundle { path, hash, size })
+}
+
+/// 将软件包输出为适合静态托管的目录结构
+///
+/// 文件复制到 `<output_dir>/packages/<id>/<version>/`（按 `file_modes` 设置权限），
+/// metadata.json 写入与 `repo add` 相同的元数据自身哈希；
+/// 同时写入索引片段 `<output_dir>/<id>-<version>.index.json`，其 packages 部分可合并到托管目录的 index.json 中。
+/// 目标版本目录已存在时报错
+pub fn pack_directory(package_path: &Path, output_dir: &Path) -> Result<PackedDirectory> {
+    let (_, mut metadata) = load_checked(package_path)?;
+
+    let version_dir = output_dir
+        .join("packages")
+        .join(&metadata.id)
+        .join(&metadata.version);
+    if version_dir.exists() {
+        bail!("目标目录已存在: {}", version_dir.display());
+    }
+
+    // 按路径顺序复制文件
+    let files: BTreeMap<&String, PathBuf> = metadata
+        .all_files
+        .keys()
+        .map(|file| (file, version_dir.join(file)))
+        .collect();
+    for (file, dest_path) in &files {
+        if let Some(parent) = dest_path.parent() {
+            fsxg::create_directory(parent)?;
+        }
+        fs::copy(package_path.join(file), dest_path)
+            .with_context(|| format!("无法复制文件: {file}"))?;
+        if let Some(mode) = metadata.get_file_mode(file) {
+            fsxg::set_file_mode(dest_path, mode)?;
+        }
+    }
+
+    metadata.metadata_hash = Some(metadata.compute_metadata_hash()?);
+    fsxg::atomic_write(
+        version_dir.join("metadata.json"),
+        serde_json::to_string_pretty(&metadata)?,
+    )?;
+
+    // 索引片段：location 使用托管目录中的相对路径
+    let fragment = output_dir.join(format!("{}-{}.index.json", metadata.id, metadata.version));
+    let index = RepositoryIndex {
+        packages: vec![PackageInfo {
+            id: metadata.id.clone(),
+            name: metadata.name.clone(),
+            icon: metadata.icon.clone(),
+            author: metadata.author.clone(),
+            latest_version: metadata.version.clone(),
+            description: metadata.description.clone(),
+            location: format!("./packages/{}/{}", metadata.id, metadata.version),
+            available_versions: vec![metadata.version.clone()],
+            size: metadata.total_size(),
+            signature: metadata.signature.clone(),
+            bundle: None,
+            installed_at: None,
+            installed_from: None,
+        }],
+        source: Vec::new(),
+    };
+    fsxg::atomic_write(&fragment, serde_json::to_string_pretty(&index)?)?;
+
+    Ok(PackedDirectory {
+        version_dir,
+        fragment,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serde_utils::load_json;
+    use tempfile::TempDir;
+
+    fn sample_package(dir: &Path) -> Result<PathBuf> {
+        let package_path = dir.join("packed-app");
+        app::init(&package_path)?;
+        fsxg::create_directory(package_path.join("js"))?;
+        fs::write(package_path.join("index.html"), "packed-app")?;
+        fs::write(package_path.join("js/app.js"), "console.log(1)")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        app::testing::add_file(package_path.join("js"), package_path.clone())?;
+        Ok(package_path)
+    }
+
+    #[test]
+    fn test_pack_bundle() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = sample_package(temp_dir.path())?;
+
+        let packed = pack_bundle(&package_path, temp_dir.path())?;
+        assert_eq!(
+            packed.path,
+            temp_dir.path().join("packed-app-0.0.0.tar.zst")
+        );
+        assert_eq!(packed.hash, crypto::bytes_hash(&fs::read(&packed.path)?));
+        assert_eq!(packed.size, fs::metadata(&packed.path)?.len());
+
+        // 文件与清单不一致时拒绝打包
+        fs::write(package_path.join("js/app.js"), "changed")?;
+        assert!(pack_bundle(&package_path, temp_dir.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pack_directory() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_path = sample_package(temp_dir.path())?;
+        let output = temp_dir.path().join("site");
+
+        let packed = pack_directory(&package_path, &output)?;
+        assert_eq!(packed.version_dir, output.join("packages/packed-app/0.0.0"));
+        assert_eq!(
+            fs::read_to_string(packed.version_dir.join("js/app.js"))?,
+            "console.log(1)"
+        );
+        let metadata: PackageMetadata = load_json(&packed.version_dir.join("metadata.json"))?;
+        assert!(metadata.verify_metadata_hash()?);
+
+        let index: RepositoryIndex = load_json(&packed.fragment)?;
+        assert_eq!(index.packages.len(), 1);
+        assert_eq!(index.packages[0].location, "./packages/packed-app/0.0.0");
+
+        // 不覆盖已存在的版本目录
+        assert!(pack_directory(&package_path, &output).is_err());
+
+        Ok(())
+    }
+}
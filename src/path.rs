@@ -2,9 +2,22 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use anyhow::{Result, anyhow};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Windows 传统路径长度上限（MAX_PATH，含结尾空字符）
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Windows 文件名中不允许出现的字符
+const WINDOWS_ILLEGAL_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// Windows 保留的设备名（不区分大小写，带扩展名同样保留）
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
 /// 展开路径中的特殊符号
 /// 目前支持 `~` 符号展开为用户的主目录
 pub fn expand_path<P: AsRef<Path>>(path: P) -> PathBuf {
@@ -95,6 +108,229 @@ pub fn normalize_path<P: AsRef<Path>>(path: P) -> PathBuf {
     result
 }
 
+/// 检查文件名在 Windows 文件系统上是否合法
+///
+/// - 不含 `<>:"|?*\` 及控制字符
+/// - 不是保留设备名（如 `CON`、`nul.txt`）
+/// - 不以空格或 `.` 结尾
+pub fn is_windows_compatible_name(name: &str) -> bool {
+    if name.is_empty() || name.ends_with(' ') || name.ends_with('.') {
+        return false;
+    }
+
+    if name
+        .chars()
+        .any(|c| c.is_control() || WINDOWS_ILLEGAL_CHARS.contains(&c))
+    {
+        return false;
+    }
+
+    let stem = name.split('.').next().unwrap_or(name);
+    !WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+/// 从指定目录向上查找仓库
+///
+/// 返回第一个同时包含 `config.toml` 和 `index.json` 的目录（包括起始目录本身），未找到时返回 `None`
+pub fn discover_repo(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join("config.toml").is_file() && dir.join("index.json").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// 将路径转换为 UTF-8 字符串
+///
+/// 路径包含无效的 UTF-8 字符时返回指明该路径的错误，而不是 panic 或有损转换
+pub fn path_to_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!("路径包含无效的 UTF-8 字符: {}", path.display()))
+}
+
+/// 将相对路径转换为清单中使用的 `/` 分隔字符串
+pub fn to_manifest_path(relative_path: &Path) -> Result<String> {
+    Ok(path_to_str(relative_path)?.replace('\\', "/"))
+}
+
+/// 判断清单中的相对路径 `candidate`（`/` 分隔）拼接到 `base` 后是否仍位于 `base` 之内
+///
+/// 拒绝空路径、绝对路径（包括 `/`、`\` 开头和带盘符前缀的路径）、含 `..` 的路径，
+/// 以及规范化后越出 `base` 的路径。来自远程元数据的路径写入前都应经过此检查，防止路径穿越
+pub fn is_safe_relative(base: &Path, candidate: &str) -> bool {
+    let candidate_path = Path::new(candidate);
+    if candidate.is_empty()
+        || candidate.starts_with(['/', '\\'])
+        || candidate_path.has_root()
+        || candidate_path
+            .components()
+            .any(|component| matches!(component, std::path::Component::Prefix(_)))
+        || candidate.split(['/', '\\']).any(|part| part == "..")
+    {
+        return false;
+    }
+    let base = normalize_path(base);
+    let path = normalize_path(base.join(candidate));
+    path != base && path.starts_with(&base)
+}
+
+/// 解析 `path` 中的符号链接，确认最终目标仍位于 `root` 之内
+///
+/// 用于读取包目录中的文件：指向包目录之外的符号链接会把任意文件打包进软件包
+pub fn ensure_within(root: &Path, path: &Path) -> Result<()> {
+    let root =
+        fs::canonicalize(root).map_err(|e| anyhow!("无法解析路径 {}: {}", root.display(), e))?;
+    let target =
+        fs::canonicalize(path).map_err(|e| anyhow!("无法解析路径 {}: {}", path.display(), e))?;
+    if !target.starts_with(&root) {
+        return Err(anyhow!(
+            "符号链接 {} 指向包目录之外: {}",
+            path.display(),
+            target.display()
+        ));
+    }
+    Ok(())
+}
+
+/// 安装前检查目标路径
+///
+/// 在写入任何文件之前检查所有目标路径，避免安装中途出现难以理解的 I/O 错误：
+/// - 路径须位于目标目录之内（见 [`is_safe_relative`]）
+/// - 目标目录中已存在的路径（包括中间目录）不能是符号链接，避免写入被重定向到目录之外
+/// - 文件名不能包含空字符
+/// - 在 Windows 上，文件名须符合 Windows 文件系统规则，且最长路径不超过 MAX_PATH
+///
+/// # 参数
+/// - `dest_dir`: 包的目标目录
+/// - `relative_paths`: 相对于目标目录的文件路径（使用 `/` 分隔）
+pub fn check_destination_paths<'a, I>(dest_dir: &Path, relative_paths: I) -> Result<()>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let dest_dir = std::path::absolute(dest_dir).unwrap_or_else(|_| dest_dir.to_path_buf());
+    let mut longest: Option<(usize, PathBuf)> = None;
+
+    for relative_path in relative_paths {
+        if !is_safe_relative(&dest_dir, relative_path) {
+            return Err(anyhow!("文件路径越出软件包目录: {}", relative_path));
+        }
+        let mut existing = dest_dir.clone();
+        for name in relative_path.split('/') {
+            existing.push(name);
+            match fs::symlink_metadata(&existing) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    return Err(anyhow!(
+                        "目标路径是符号链接，拒绝写入: {}",
+                        existing.display()
+                    ));
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        for name in relative_path.split('/') {
+            if name.contains('\0') {
+                return Err(anyhow!("文件名包含空字符: {}", relative_path));
+            }
+            if cfg!(windows) && !name.is_empty() && !is_windows_compatible_name(name) {
+                return Err(anyhow!(
+                    "文件名 {} 在 Windows 文件系统上不合法: {}",
+                    name,
+                    relative_path
+                ));
+            }
+        }
+
+        let dest_path = dest_dir.join(relative_path);
+        let length = dest_path.to_string_lossy().encode_utf16().count();
+        if longest.as_ref().is_none_or(|(max, _)| length > *max) {
+            longest = Some((length, dest_path));
+        }
+    }
+
+    if cfg!(windows)
+        && let Some((length, path)) = longest
+        && length >= WINDOWS_MAX_PATH
+    {
+        return Err(anyhow!(
+            "目标路径过长（{} 个字符，上限 {}）: {}；请使用更短的仓库路径，或在系统中启用长路径支持",
+            length,
+            WINDOWS_MAX_PATH - 1,
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// 获取配置文件路径
+///
+/// # 返回值
+///
+/// 返回 `PathBuf`，表示配置文件的路径
+///
+/// # 功能特性
+///
+/// * 遵循 XDG 基础目录规范
+/// * 在 Linux 系统上返回 `$XDG_CONFIG_HOME/pageos-pkgr/config.toml` 或默认的 `~/.config/pageos-pkgr/config.toml`
+/// * 确保路径格式正确
+pub fn get_config_path() -> PathBuf {
+    // 尝试从环境变量获取 XDG_CONFIG_HOME
+    if let Ok(xdg_config_home) = std::env::var("XDG_CONFIG_HOME") {
+        if !xdg_config_home.is_empty() {
+            return PathBuf::from(xdg_config_home)
+                .join("pageos-pkgr")
+                .join("config.toml");
+        }
+    }
+
+    // 如果 XDG_CONFIG_HOME 未设置或为空，使用默认的 ~/.config
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.config"))
+        .join("pageos-pkgr")
+        .join("config.toml")
+}
+
+/// 获取密钥目录路径
+///
+/// # 返回值
+///
+/// 返回 `PathBuf`，表示存放签名密钥的目录路径
+///
+/// # 功能特性
+///
+/// * 与配置文件位于同一目录，遵循 XDG 基础目录规范
+/// * 在 Linux 系统上返回 `$XDG_CONFIG_HOME/pageos-pkgr/keys` 或默认的 `~/.config/pageos-pkgr/keys`
+pub fn get_keys_dir() -> PathBuf {
+    get_config_path().with_file_name("keys")
+}
+
+/// 获取缓存目录路径
+///
+/// # 返回值
+///
+/// 返回 `PathBuf`，表示缓存目录的路径
+///
+/// # 功能特性
+///
+/// * 遵循 XDG 基础目录规范
+/// * 在 Linux 系统上返回 `$XDG_CACHE_HOME/pageos-pkgr` 或默认的 `~/.cache/pageos-pkgr`
+/// * 确保目录存在
+pub fn get_cache_dir() -> PathBuf {
+    // 尝试从环境变量获取 XDG_CACHE_HOME
+    if let Ok(xdg_cache_home) = std::env::var("XDG_CACHE_HOME") {
+        if !xdg_cache_home.is_empty() {
+            return PathBuf::from(xdg_cache_home).join("pageos-pkgr");
+        }
+    }
+
+    // 如果 XDG_CACHE_HOME 未设置或为空，使用默认的 ~/.cache
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("~/.cache"))
+        .join("pageos-pkgr")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,6 +381,97 @@ mod tests {
         let _ = std::fs::remove_dir_all(&temp_dir);
     }
 
+    #[test]
+    fn test_is_windows_compatible_name() {
+        assert!(is_windows_compatible_name("index.html"));
+        assert!(is_windows_compatible_name("console.js"));
+        assert!(!is_windows_compatible_name("a:b.txt"));
+        assert!(!is_windows_compatible_name("what?.txt"));
+        assert!(!is_windows_compatible_name("CON"));
+        assert!(!is_windows_compatible_name("nul.txt"));
+        assert!(!is_windows_compatible_name("trailing."));
+        assert!(!is_windows_compatible_name("trailing "));
+    }
+
+    #[test]
+    fn test_discover_repo() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        let nested = repo_path.join("packages/test-app/1.0.0");
+        fs::create_dir_all(&nested)?;
+
+        // 缺少 index.json 时不视为仓库
+        fs::write(repo_path.join("config.toml"), "")?;
+        assert_eq!(discover_repo(&nested), None);
+
+        fs::write(repo_path.join("index.json"), "{}")?;
+        assert_eq!(discover_repo(&nested), Some(repo_path.clone()));
+        assert_eq!(discover_repo(&repo_path), Some(repo_path));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_destination_paths() {
+        let files = vec!["index.html".to_string(), "assets/app.js".to_string()];
+        assert!(check_destination_paths(Path::new("/repo/packages/app/1.0.0"), &files).is_ok());
+
+        let files = vec!["bad\0name".to_string()];
+        assert!(check_destination_paths(Path::new("/repo"), &files).is_err());
+
+        let files = vec!["../../../../etc/cron.d/x".to_string()];
+        assert!(check_destination_paths(Path::new("/repo/packages/app/1.0.0"), &files).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_are_rejected() -> Result<()> {
+        use std::os::unix::fs::symlink;
+
+        let temp_dir = tempfile::TempDir::new()?;
+        let package_dir = temp_dir.path().join("package");
+        let outside = temp_dir.path().join("outside");
+        fs::create_dir_all(package_dir.join("assets"))?;
+        fs::create_dir_all(&outside)?;
+        fs::write(package_dir.join("index.html"), "hello")?;
+        fs::write(outside.join("secret.txt"), "secret")?;
+        symlink(
+            package_dir.join("index.html"),
+            package_dir.join("inside-link"),
+        )?;
+        symlink(outside.join("secret.txt"), package_dir.join("outside-link"))?;
+        symlink(&outside, package_dir.join("outside-dir"))?;
+
+        // 读取：目标须位于包目录之内
+        assert!(ensure_within(&package_dir, &package_dir.join("inside-link")).is_ok());
+        assert!(ensure_within(&package_dir, &package_dir.join("outside-link")).is_err());
+
+        // 写入：已存在的符号链接（包括中间目录）不能作为目标
+        let files = vec!["assets/app.js".to_string(), "new/file.txt".to_string()];
+        assert!(check_destination_paths(&package_dir, &files).is_ok());
+        let files = vec!["inside-link".to_string()];
+        assert!(check_destination_paths(&package_dir, &files).is_err());
+        let files = vec!["outside-dir/cron".to_string()];
+        assert!(check_destination_paths(&package_dir, &files).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_safe_relative() {
+        let base = Path::new("/repo/packages/app/1.0.0");
+        assert!(is_safe_relative(base, "index.html"));
+        assert!(is_safe_relative(base, "assets/./app.js"));
+
+        assert!(!is_safe_relative(base, ""));
+        assert!(!is_safe_relative(base, "/etc/passwd"));
+        assert!(!is_safe_relative(base, "\\windows\\system32"));
+        assert!(!is_safe_relative(base, "../1.0.1/index.html"));
+        assert!(!is_safe_relative(base, "assets/../../escape"));
+        assert!(!is_safe_relative(base, "assets\\..\\..\\escape"));
+        assert!(!is_safe_relative(base, "."));
+    }
+
     #[test]
     fn test_normalize_path_simple() {
         let normalized = normalize_path("/a/b/c");
@@ -187,56 +514,3 @@ mod tests {
         assert_eq!(normalized, Path::new("/a/b/"));
     }
 }
-
-/// 获取配置文件路径
-///
-/// # 返回值
-///
-/// 返回 `PathBuf`，表示配置文件的路径
-///
-/// # 功能特性
-///
-/// * 遵循 XDG 基础目录规范
-/// * 在 Linux 系统上返回 `$XDG_CONFIG_HOME/pageos-pkgr/config.toml` 或默认的 `~/.config/pageos-pkgr/config.toml`
-/// * 确保路径格式正确
-pub fn get_config_path() -> PathBuf {
-    // 尝试从环境变量获取 XDG_CONFIG_HOME
-    if let Ok(xdg_config_home) = std::env::var("XDG_CONFIG_HOME") {
-        if !xdg_config_home.is_empty() {
-            return PathBuf::from(xdg_config_home)
-                .join("pageos-pkgr")
-                .join("config.toml");
-        }
-    }
-
-    // 如果 XDG_CONFIG_HOME 未设置或为空，使用默认的 ~/.config
-    dirs::config_dir()
-        .unwrap_or_else(|| PathBuf::from("~/.config"))
-        .join("pageos-pkgr")
-        .join("config.toml")
-}
-
-/// 获取缓存目录路径
-///
-/// # 返回值
-///
-/// 返回 `PathBuf`，表示缓存目录的路径
-///
-/// # 功能特性
-///
-/// * 遵循 XDG 基础目录规范
-/// * 在 Linux 系统上返回 `$XDG_CACHE_HOME/pageos-pkgr` 或默认的 `~/.cache/pageos-pkgr`
-/// * 确保目录存在
-pub fn get_cache_dir() -> PathBuf {
-    // 尝试从环境变量获取 XDG_CACHE_HOME
-    if let Ok(xdg_cache_home) = std::env::var("XDG_CACHE_HOME") {
-        if !xdg_cache_home.is_empty() {
-            return PathBuf::from(xdg_cache_home).join("pageos-pkgr");
-        }
-    }
-
-    // 如果 XDG_CACHE_HOME 未设置或为空，使用默认的 ~/.cache
-    dirs::cache_dir()
-        .unwrap_or_else(|| PathBuf::from("~/.cache"))
-        .join("pageos-pkgr")
-}
@@ -2,18 +2,33 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::config::{ConfigManager, RepositoryConfig};
+use crate::app;
+use crate::archive::{self, ArchiveSource};
+use crate::config::{ConfigManager, RepositoryConfig, SourceConfig};
 use crate::fsxg;
-use crate::metadata::PackageMetadata;
+use crate::lockfile::{LockedPackage, Lockfile};
+use crate::model::{PackageInfo, RepositoryIndex};
+use crate::metadata::{
+    Dependency, PackageMetadata, PostInstallHook, format_file_entry, parse_file_entry,
+};
 use crate::net;
-use crate::path::{expand_path, get_cache_dir};
+use crate::path::{
+    check_destination_paths, ensure_within, expand_path, get_cache_dir, is_safe_relative,
+    path_to_str, to_manifest_path,
+};
 use crate::serde_utils::{load_json, save_json};
+use crate::style;
 use crate::transaction::Transaction;
-use crate::crypto;
+use crate::version;
+use crate::crypto::{self, HashAlgorithm};
 use anyhow::{Context, Result, anyhow};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 /// 仓库管理状态
 pub struct RepoManager {
@@ -21,36 +36,601 @@ pub struct RepoManager {
     repo_path: PathBuf,
     /// 仓库配置
     config: RepositoryConfig,
-    /// 事务管理器
-    _transaction: Option<Transaction>,
+    /// 按软件源划分的熔断器（仅在本次运行中有效，由并发下载任务共享）
+    breaker: Arc<net::CircuitBreaker>,
+    /// HTTP 客户端（按打开仓库时的网络配置和各软件源的 TLS 设置构建）
+    client: net::Client,
+    /// 仓库锁（.pkgr.lock），RepoManager 被丢弃时释放
+    lock: fs::File,
+    /// 打开仓库时获取的锁模式
+    lock_mode: LockMode,
+    /// 仓库被其他进程占用时是否等待（打开仓库和临时升级写锁时均适用）
+    wait: bool,
 }
 
-/// 仓库索引结构
-#[derive(Serialize, Deserialize, Debug)]
-pub struct RepositoryIndex {
-    /// 已安装的包列表
-    pub packages: Vec<PackageInfo>,
-    /// 软件源中的包列表
-    pub source: Vec<PackageInfo>,
+/// 仓库锁文件名（RepoManager 存续期间一直持有）
+const LOCK_FILE: &str = ".pkgr.lock";
+
+/// 每个软件源上次同步时的索引快照（`snapshots/<source-id>/last_sync_index.json`）
+const SYNC_SNAPSHOT_FILE: &str = "last_sync_index.json";
+
+/// 仓库锁模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// 共享锁，用于只读操作，可与其他共享锁同时持有
+    Shared,
+    /// 独占锁，用于修改仓库的操作
+    Exclusive,
 }
 
-/// 包信息
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct PackageInfo {
+/// 软件源健康检查的超时时间
+const HEALTH_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// 软件源健康状态
+#[derive(Serialize, Debug)]
+pub struct SourceHealth {
+    /// 软件源ID
+    pub id: String,
+    /// 软件源 URL
+    pub url: String,
+    /// 是否可访问
+    pub reachable: bool,
+    /// HTTP 状态码（本地源或无法连接时为空）
+    pub status: Option<u16>,
+    /// 往返延迟（毫秒）
+    pub latency_ms: Option<u64>,
+    /// 索引是否可解析
+    pub index_valid: bool,
+    /// 索引是否通过校验（index.json.sha256 及配置了 `trusted_key` 时的签名）；源未启用索引校验或未获取到索引时为空
+    pub index_verified: Option<bool>,
+    /// 错误信息
+    pub error: Option<String>,
+}
+
+/// 软件源目录校验结果
+#[derive(Serialize, Debug)]
+pub struct SourceVerification {
+    /// 软件源ID
+    pub source: String,
+    /// 各软件包的校验结果
+    #[serde(flatten)]
+    pub summary: VerificationSummary,
+}
+
+/// 一组软件包的校验结果（软件源目录或已安装的软件包）
+#[derive(Serialize, Debug, Default)]
+pub struct VerificationSummary {
+    /// 各软件包版本的校验结果
+    pub packages: Vec<PackageVerification>,
+    /// 通过的软件包数
+    pub passed: usize,
+    /// 失败的软件包数
+    pub failed: usize,
+}
+
+impl VerificationSummary {
+    /// 记入一个软件包的校验结果，没有错误即为通过
+    fn push(&mut self, mut result: PackageVerification) {
+        result.passed = result.errors.is_empty();
+        if result.passed {
+            self.passed += 1;
+        } else {
+            self.failed += 1;
+        }
+        self.packages.push(result);
+    }
+}
+
+/// 单个软件包的校验结果
+#[derive(Serialize, Debug)]
+pub struct PackageVerification {
+    /// 应用唯一标识
+    pub id: String,
+    /// 版本号
+    pub version: String,
+    /// 是否通过
+    pub passed: bool,
+    /// 校验通过的文件数
+    pub verified_files: usize,
+    /// 错误信息
+    pub errors: Vec<String>,
+}
+
+impl PackageVerification {
+    fn new(id: &str, version: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            version: version.to_string(),
+            passed: false,
+            verified_files: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// 校验元数据的自校验哈希，以及软件源配置了受信任公钥时的签名
+    fn check_metadata(
+        &mut self,
+        metadata: &PackageMetadata,
+        trusted_key: Option<&str>,
+    ) -> Result<()> {
+        if !metadata.verify_metadata_hash()? {
+            self.errors.push("元数据自校验失败".to_string());
+        }
+        if let Some(trusted_key) = trusted_key
+            && let Err(e) = metadata.check_signature(metadata.signature.as_deref(), trusted_key)
+        {
+            self.errors.push(e.to_string());
+        }
+        Ok(())
+    }
+
+    /// 比较单个文件实际的（哈希, 大小）与清单记录，`expected_size` 为 `None` 时不比较大小
+    fn check_file(
+        &mut self,
+        file_path: &str,
+        expected_hash: &str,
+        expected_size: Option<u64>,
+        actual: Result<(String, u64)>,
+    ) {
+        match actual {
+            Ok((actual_hash, _)) if !crypto::hash_eq(&actual_hash, expected_hash) => {
+                self.errors.push(format!("文件哈希不匹配: {file_path}"));
+            }
+            Ok((_, actual_size)) if expected_size.is_some_and(|size| size != actual_size) => {
+                self.errors.push(format!("文件大小不匹配: {file_path}"));
+            }
+            Ok(_) => self.verified_files += 1,
+            Err(e) => self.errors.push(format!("{file_path}: {e}")),
+        }
+    }
+}
+
+/// 软件包列表的筛选方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFilter {
+    /// 已安装的软件包（索引的 packages 部分）
+    Installed,
+    /// 软件源中尚未安装的软件包
+    Available,
+    /// 全部软件包
+    All,
+}
+
+/// 软件包列表中的条目
+#[derive(Serialize, Debug)]
+pub struct ListedPackage {
     /// 应用唯一标识
     pub id: String,
     /// 应用名称
     pub name: String,
-    /// 图标路径
-    pub icon: String,
-    /// 作者
-    pub author: String,
-    /// 最新版本号
-    pub latest_version: String,
-    /// 应用描述
+    /// 已安装的最高版本，未安装时为空
+    pub installed_version: Option<String>,
+    /// 软件源中的最新版本，软件源中没有时为空
+    pub available_version: Option<String>,
+    /// 描述
     pub description: String,
-    /// 位置信息（本地路径或URL）
-    pub location: String,
+    /// 安装时间（RFC 3339），未安装或本地添加时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed_at: Option<String>,
+    /// 安装来源的软件源ID，未安装或本地添加时为空
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed_from: Option<String>,
+}
+
+/// 已安装软件包的文件列表
+#[derive(Serialize, Debug)]
+pub struct InstalledFiles {
+    /// 应用唯一标识
+    pub id: String,
+    /// 版本号
+    pub version: String,
+    /// 文件列表，按路径排序
+    pub files: Vec<InstalledFile>,
+}
+
+/// 已安装的文件
+#[derive(Serialize, Debug)]
+pub struct InstalledFile {
+    /// 相对于版本目录的路径
+    pub path: String,
+    /// SHA256 哈希值
+    pub hash: String,
+    /// 文件大小（字节），清单未记录大小时为空
+    pub size: Option<u64>,
+}
+
+/// 已安装软件包与锁文件的偏差
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LockDrift {
+    /// 锁定的软件包未安装
+    MissingPackage { id: String, version: String },
+    /// 未安装锁定的版本
+    VersionMismatch {
+        id: String,
+        locked: String,
+        /// 已安装的版本
+        installed: Vec<String>,
+    },
+    /// 文件缺失或哈希与锁定值不一致
+    HashMismatch {
+        id: String,
+        version: String,
+        path: String,
+        locked: String,
+        /// 实际哈希值，文件缺失时为空
+        actual: Option<String>,
+    },
+}
+
+/// 同步选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncOptions {
+    /// 镜像同步模式
+    pub mirror: bool,
+    /// 强制切换同步模式并清理遗留的镜像文件
+    pub force: bool,
+    /// 只列出将要下载和修改的内容，不实际执行
+    pub dry_run: bool,
+}
+
+/// 安装选项
+#[derive(Debug, Clone, Default)]
+pub struct InstallOptions {
+    /// 跳过安装后钩子
+    pub no_hooks: bool,
+    /// 校验和覆盖（相对路径 -> 预期哈希），优先于元数据中的哈希
+    pub checksum_overrides: BTreeMap<String, String>,
+    /// 安装成功后写入锁定信息的锁文件（`--save`）
+    pub lockfile: Option<PathBuf>,
+    /// 请求的版本为约束时，多个满足约束的版本中的选择偏好
+    pub prefer: version::Preference,
+}
+
+/// 安装记录文件名（位于软件包版本目录中）
+const INSTALL_RECORD_FILE: &str = ".pkgr-install.json";
+
+/// 安装记录
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct InstallRecord {
+    /// 安装来源的软件源ID
+    pub source: String,
+    /// 安装时使用的校验和覆盖（相对路径 -> 预期哈希）
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub checksum_overrides: BTreeMap<String, String>,
+    /// 安装时间（RFC 3339），旧版本写入的记录中没有
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_at: Option<String>,
+}
+
+/// 读取版本目录中的安装记录，本地添加的版本没有安装记录
+fn load_install_record(version_dir: &Path) -> Result<Option<InstallRecord>> {
+    let record_path = version_dir.join(INSTALL_RECORD_FILE);
+    if record_path.exists() {
+        Ok(Some(load_json(&record_path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// 同步模式
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    /// 镜像同步（写入实际文件）
+    Mirror,
+    /// 增量同步（仅更新索引）
+    Incremental,
+}
+
+/// 单个软件源的同步状态
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SourceSyncState {
+    /// 上次同步使用的模式
+    pub mode: SyncMode,
+    /// 镜像同步写入的文件列表（用于切换模式时清理）
+    #[serde(default)]
+    pub mirrored_files: Vec<String>,
+}
+
+/// 同步状态记录（sync.json）
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SyncState {
+    /// 软件源ID -> 同步状态
+    #[serde(default)]
+    pub sources: BTreeMap<String, SourceSyncState>,
+}
+
+/// 仓库操作对文件系统和索引的变更
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "action", content = "target", rename_all = "snake_case")]
+pub enum PlannedChange {
+    /// 删除目录（递归）
+    RemoveDir(PathBuf),
+    /// 删除文件
+    RemoveFile(PathBuf),
+    /// 下载文件到本地路径
+    Download(PathBuf),
+    /// 写入文件
+    WriteFile(PathBuf),
+    /// 修改仓库索引（index.json）
+    EditIndex(String),
+}
+
+impl std::fmt::Display for PlannedChange {
+    /// 每项变更一行，动作在前、目标在后，便于逐行比较
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RemoveDir(path) => write!(f, "删除目录 {}", path.display()),
+            Self::RemoveFile(path) => write!(f, "删除文件 {}", path.display()),
+            Self::Download(path) => write!(f, "下载文件 {}", path.display()),
+            Self::WriteFile(path) => write!(f, "写入文件 {}", path.display()),
+            Self::EditIndex(description) => write!(f, "修改索引 {description}"),
+        }
+    }
+}
+
+/// 变更记录
+///
+/// 记录操作执行（或演练时计划执行）的变更；演练（`--dry-run`）时只记录、不执行
+#[derive(Debug, Default)]
+pub struct ChangeSet {
+    dry_run: bool,
+    changes: Vec<PlannedChange>,
+}
+
+impl ChangeSet {
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            dry_run,
+            changes: Vec::new(),
+        }
+    }
+
+    /// 记录一项变更，返回是否应当实际执行（非演练）
+    fn record(&mut self, change: PlannedChange) -> bool {
+        self.changes.push(change);
+        !self.dry_run
+    }
+
+    /// 删除目录（存在时），演练时只记录
+    fn remove_dir(&mut self, path: &Path) -> Result<()> {
+        if path.exists() && self.record(PlannedChange::RemoveDir(path.to_path_buf())) {
+            fsxg::remove_directory(path)?;
+        }
+        Ok(())
+    }
+
+    /// 在事务中删除目录（存在时），演练时只记录
+    fn remove_dir_in(&mut self, transaction: &mut Transaction, path: &Path) -> Result<()> {
+        if path.exists() && self.record(PlannedChange::RemoveDir(path.to_path_buf())) {
+            transaction.safe_remove_dir(path)?;
+        }
+        Ok(())
+    }
+
+    /// 在事务中删除文件（存在时），演练时只记录
+    fn remove_file_in(&mut self, transaction: &mut Transaction, path: &Path) -> Result<()> {
+        if path.is_file() && self.record(PlannedChange::RemoveFile(path.to_path_buf())) {
+            transaction.safe_remove(path)?;
+        }
+        Ok(())
+    }
+
+    /// 记录的变更
+    pub fn changes(&self) -> &[PlannedChange] {
+        &self.changes
+    }
+}
+
+/// 仓库清理结果
+#[derive(Debug)]
+pub struct CleanReport {
+    /// 执行（或演练时计划执行）的变更
+    pub changes: ChangeSet,
+    /// 未记录在 versions.txt 中的版本目录（`<id>/<version>`，已保留）
+    pub orphans: Vec<String>,
+}
+
+/// 索引与磁盘之间的不一致（repo doctor 的检查结果）
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IndexProblem {
+    /// 索引中有该软件包，但磁盘上没有可用的版本目录
+    Dangling { id: String },
+    /// 磁盘上有该软件包，但索引中没有
+    Unindexed { id: String, version: String },
+    /// 索引记录的最新版本与磁盘上的不一致
+    Outdated {
+        id: String,
+        indexed: String,
+        actual: String,
+    },
+    /// versions.txt 记录的版本没有对应的目录（或目录中缺少 metadata.json）
+    MissingVersion { id: String, version: String },
+    /// 版本目录中的 metadata.json 无法读取，该版本视为不可用
+    BadMetadata {
+        id: String,
+        version: String,
+        error: String,
+    },
+}
+
+impl std::fmt::Display for IndexProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dangling { id } => write!(f, "{id}: 已记录在索引中，但磁盘上没有可用的版本"),
+            Self::Unindexed { id, version } => {
+                write!(f, "{id}: 已安装 {version}，但未记录在索引中")
+            }
+            Self::Outdated {
+                id,
+                indexed,
+                actual,
+            } => write!(f, "{id}: 索引记录为 {indexed}，与磁盘上的 {actual} 不一致"),
+            Self::MissingVersion { id, version } => {
+                write!(f, "{id}: versions.txt 记录了 {version}，但版本目录不存在")
+            }
+            Self::BadMetadata { id, version, error } => {
+                write!(f, "{id}: {version}/metadata.json 无法读取: {error}")
+            }
+        }
+    }
+}
+
+/// 升级结果
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UpgradeOutcome {
+    /// 已从 `from` 升级到 `to`
+    Upgraded { from: String, to: String },
+    /// 已是软件源中的最新版本
+    UpToDate { version: String },
+    /// 软件源中有更高的版本 `latest`，但超出了固定的版本 `pin`，未升级
+    Pinned {
+        version: String,
+        pin: String,
+        latest: String,
+    },
+}
+
+/// 批量升级中单个软件包的结果
+#[derive(Serialize, Debug)]
+pub struct UpgradeResult {
+    /// 软件包ID
+    pub id: String,
+    /// 升级结果，升级失败时为 `None`
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<UpgradeOutcome>,
+    /// 升级失败时的错误信息
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 仓库同步结果
+#[derive(Debug)]
+pub struct SyncReport {
+    /// 执行（或演练时计划执行）的变更
+    pub changes: ChangeSet,
+    /// 增量同步时 source 部分的变化（按包 ID 排序），镜像同步时为空
+    pub changelog: Vec<SourceChange>,
+}
+
+/// 增量同步中 source 部分单个软件包的变化
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SourceChange {
+    /// 远程新增的软件包
+    Added { id: String, version: String },
+    /// 最新版本发生变化的软件包
+    Updated {
+        id: String,
+        from: String,
+        to: String,
+    },
+    /// 远程已不再提供的软件包
+    Removed { id: String, version: String },
+}
+
+impl SourceChange {
+    /// 软件包ID
+    pub fn id(&self) -> &str {
+        match self {
+            Self::Added { id, .. } | Self::Updated { id, .. } | Self::Removed { id, .. } => id,
+        }
+    }
+}
+
+impl std::fmt::Display for SourceChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Added { id, version } => write!(f, "新增 {id}:{version}"),
+            Self::Updated { id, from, to } => write!(f, "更新 {id}: {from} -> {to}"),
+            Self::Removed { id, version } => write!(f, "移除 {id}:{version}"),
+        }
+    }
+}
+
+/// 将软件源索引 packages 部分中的相对位置（`./packages/...`）转换为该源下的绝对位置（本地源为文件系统路径）
+fn resolve_source_locations(
+    source: &SourceConfig,
+    mut packages: Vec<PackageInfo>,
+) -> Vec<PackageInfo> {
+    for package in &mut packages {
+        if let Some(package_path) = package.location.strip_prefix("./packages/") {
+            package.location = match net::local_path(&source.url) {
+                Some(root) => root
+                    .join("packages")
+                    .join(package_path)
+                    .to_string_lossy()
+                    .to_string(),
+                None => format!(
+                    "{}/packages/{}",
+                    source.url.trim_end_matches('/'),
+                    package_path
+                ),
+            };
+        }
+    }
+    packages
+}
+
+/// 判断 source 条目的位置是否位于该软件源之下（即由该源同步而来）
+fn source_owns(source: &SourceConfig, location: &str) -> bool {
+    match net::local_path(&source.url) {
+        Some(root) => Path::new(location).starts_with(root.join("packages")),
+        None => location.starts_with(&format!("{}/packages/", source.url.trim_end_matches('/'))),
+    }
+}
+
+/// 按包 ID 和最新版本比较本地与远程的 source 条目，结果按包 ID 排序
+fn diff_source(local: &[PackageInfo], remote: &[PackageInfo]) -> Vec<SourceChange> {
+    let local: BTreeMap<&str, &str> = local
+        .iter()
+        .map(|package| (package.id.as_str(), package.latest_version.as_str()))
+        .collect();
+    let remote: BTreeMap<&str, &str> = remote
+        .iter()
+        .map(|package| (package.id.as_str(), package.latest_version.as_str()))
+        .collect();
+
+    let mut changelog: Vec<SourceChange> = remote
+        .iter()
+        .filter_map(|(id, version)| match local.get(id) {
+            None => Some(SourceChange::Added {
+                id: id.to_string(),
+                version: version.to_string(),
+            }),
+            Some(from) if from != version => Some(SourceChange::Updated {
+                id: id.to_string(),
+                from: from.to_string(),
+                to: version.to_string(),
+            }),
+            Some(_) => None,
+        })
+        .collect();
+    changelog.extend(
+        local
+            .iter()
+            .filter(|(id, _)| !remote.contains_key(*id))
+            .map(|(id, version)| SourceChange::Removed {
+                id: id.to_string(),
+                version: version.to_string(),
+            }),
+    );
+    changelog.sort_by(|a, b| a.id().cmp(b.id()));
+    changelog
+}
+
+/// 变化摘要，如“新增 3 个，更新 2 个，移除 1 个”
+pub fn changelog_summary(changelog: &[SourceChange]) -> String {
+    let count = |kind: fn(&SourceChange) -> bool| changelog.iter().filter(|c| kind(c)).count();
+    format!(
+        "新增 {} 个，更新 {} 个，移除 {} 个",
+        count(|c| matches!(c, SourceChange::Added { .. })),
+        count(|c| matches!(c, SourceChange::Updated { .. })),
+        count(|c| matches!(c, SourceChange::Removed { .. })),
+    )
 }
 
 impl RepoManager {
@@ -62,22 +642,28 @@ impl RepoManager {
         // 创建仓库目录结构
         fsxg::create_directory(repo_path.join("packages"))
             .with_context(|| format!("无法创建 packages 目录: {}", repo_path.display()))?;
+        let lock = acquire_repo_lock(&repo_path, LockMode::Exclusive, false)?;
 
         // 初始化配置文件
         let config = RepositoryConfig::default();
         ConfigManager::new(&config_path).and_then(|cm| cm.save(&config))?;
 
         // 初始化索引文件
-        let index = RepositoryIndex {
+        let mut index = RepositoryIndex {
             packages: Vec::new(),
             source: Vec::new(),
         };
-        save_json(&index, &repo_path.join("index.json"))?;
+        save_index(&mut index, &repo_path.join("index.json"))?;
+        let client = build_client(&config)?;
 
         Ok(Self {
             repo_path,
             config,
-            _transaction: None,
+            breaker: Arc::default(),
+            client,
+            lock,
+            lock_mode: LockMode::Exclusive,
+            wait: false,
         })
     }
 
@@ -88,7 +674,22 @@ impl RepoManager {
     }
 
     /// 打开已有仓库
+    ///
+    /// packages/ 目录缺失时不报错，各操作将其视为空目录，并在需要写入时重新创建。
+    /// 以共享模式获取仓库锁，仓库被其他进程独占时立即报错
     pub fn open<P: AsRef<Path>>(repo_path: P) -> Result<Self> {
+        Self::open_with_lock(repo_path, LockMode::Shared, false)
+    }
+
+    /// 以指定模式获取仓库锁并打开已有仓库
+    ///
+    /// `wait` 为 false 时，仓库被其他进程占用则立即返回错误，否则阻塞等待锁释放；
+    /// 以共享模式打开的仓库之后临时升级为写锁时同样遵循该设置
+    pub fn open_with_lock<P: AsRef<Path>>(
+        repo_path: P,
+        mode: LockMode,
+        wait: bool,
+    ) -> Result<Self> {
         let repo_path = expand_path(repo_path);
         let config_path = repo_path.join("config.toml");
 
@@ -96,62 +697,263 @@ impl RepoManager {
         if !repo_path.exists() {
             return Err(anyhow!("仓库目录不存在: {}", repo_path.display()));
         }
+        let lock = acquire_repo_lock(&repo_path, mode, wait)?;
 
-        // 安全加载配置（仅在文件不存在时创建默认配置）
-        let config = ConfigManager::new(&config_path)?.load()?;
+        // 安全加载配置（仅在文件不存在时创建默认配置），叠加在系统配置和用户配置之上
+        let config = ConfigManager::new(&config_path)?.load_merged()?;
+        let client = build_client(&config)?;
 
         Ok(Self {
             repo_path,
             config,
-            _transaction: None,
+            breaker: Arc::default(),
+            client,
+            lock,
+            lock_mode: mode,
+            wait,
         })
     }
 
-    /// 清理仓库
-    pub fn clean(&mut self) -> Result<()> {
-        // 清空下载缓存
-        let cache_dir = get_cache_dir();
-        if cache_dir.exists() {
-            fsxg::remove_directory(&cache_dir)?;
+    /// 设置离线模式
+    ///
+    /// 离线时访问远程地址的操作（更新索引、同步、校验软件源等）立即报错而不发出请求；
+    /// 安装只在本地已安装对应版本的元数据且所有文件都已存在并通过校验时成功
+    pub fn set_offline(&mut self, offline: bool) {
+        self.client.set_offline(offline);
+    }
+
+    /// 是否处于离线模式
+    pub fn is_offline(&self) -> bool {
+        self.client.is_offline()
+    }
+
+    /// 离线模式下拒绝访问远程地址
+    fn ensure_online(&self, location: &str) -> Result<()> {
+        if self.is_offline() && is_remote(location) {
+            return Err(anyhow!("离线模式已启用，无法访问: {}", location));
         }
+        Ok(())
+    }
 
-        // 清理旧版本（保留最新两个版本）
-        for package_dir in fs::read_dir(self.repo_path.join("packages"))? {
-            let package_dir = package_dir?.path();
-            if package_dir.is_dir() {
-                clean_old_versions(&package_dir)?;
+    /// 获取源中软件包指定版本的元数据
+    ///
+    /// 离线模式下源位于远程时改为读取本地已安装该版本的 metadata.json（同样校验元数据自身的哈希）
+    async fn source_metadata(
+        &self,
+        package: &PackageInfo,
+        version: &str,
+    ) -> Result<PackageMetadata> {
+        if self.is_offline() && is_remote(&version_location(package, version)) {
+            let metadata_path = self
+                .repo_path
+                .join("packages")
+                .join(&package.id)
+                .join(version)
+                .join("metadata.json");
+            if !metadata_path.is_file() {
+                return Err(anyhow!(
+                    "离线模式已启用，本地没有 {}:{} 的元数据",
+                    package.id,
+                    version
+                ));
+            }
+            let metadata: PackageMetadata = load_json(&metadata_path)?;
+            if !metadata.verify_metadata_hash()? {
+                return Err(anyhow!(
+                    "元数据自校验失败，metadata.json 可能已被篡改: {}",
+                    metadata_path.display()
+                ));
             }
+            return Ok(metadata);
         }
+        let metadata_url = join_location(&version_location(package, version), "metadata.json");
+        fetch_source_metadata(&self.client, &self.mirror_urls(&metadata_url)).await
+    }
 
-        // 清空source索引
-        let mut index: RepositoryIndex = load_json(&self.repo_path.join("index.json"))?;
-        index.source.clear();
-        save_json(&index, &self.repo_path.join("index.json"))?;
+    /// 将软件源下的地址展开为依次尝试的地址列表（原地址及各镜像中的相同路径），
+    /// 不属于任何已启用软件源的地址原样返回
+    fn mirror_urls(&self, url: &str) -> Vec<String> {
+        self.config
+            .source
+            .iter()
+            .filter(|source| source.enabled)
+            .map(|source| source.mirror_urls(url))
+            .find(|urls| urls.len() > 1)
+            .unwrap_or_else(|| vec![url.to_string()])
+    }
 
-        Ok(())
+    /// 获取仓库写锁，返回的守卫被丢弃时恢复打开仓库时的锁模式
+    ///
+    /// 以独占模式打开的仓库已持有写锁，不再加锁；以共享模式打开时先释放共享锁，再获取独占锁：
+    /// 打开仓库时未指定等待（`--wait`）则其他进程仍持有锁时立即报错，并恢复原有的共享锁，否则阻塞等待。
+    /// 升级不是原子的，释放与重新加锁之间其他写入者可能修改仓库，
+    /// 调用方应在加锁后重新读取要修改的文件（如 [`Self::with_index_mut`]），而不是沿用加锁前读到的内容
+    fn write_lock(&self) -> Result<WriteLock<'_>> {
+        if self.lock_mode == LockMode::Exclusive {
+            return Ok(WriteLock { lock: None });
+        }
+        let lock_path = self.repo_path.join(LOCK_FILE);
+        self.lock
+            .unlock()
+            .with_context(|| format!("无法锁定仓库: {}", lock_path.display()))?;
+        let result = if self.wait {
+            self.lock.lock().map_err(fs::TryLockError::Error)
+        } else {
+            self.lock.try_lock()
+        };
+        if let Err(e) = result {
+            // 未能升级时恢复共享锁；其他进程已持有独占锁时无法恢复，不阻塞等待
+            if let Err(e) = self.lock.try_lock_shared() {
+                log::warn!("无法恢复仓库共享锁: {e}");
+            }
+            return Err(lock_error(&self.repo_path, e));
+        }
+        Ok(WriteLock {
+            lock: Some(&self.lock),
+        })
     }
 
-    /// 更新索引的 source 部分
+    /// 在仓库锁保护下修改索引
     ///
-    /// 遍历所有启用的软件源，从每个源获取索引，并合并到本地索引的 source 部分。
-    /// 合并策略：对于同一个包 ID，后处理的源会覆盖先处理的源。
-    pub async fn update_source_index(&mut self) -> Result<()> {
-        // 获取索引文件路径
+    /// 加锁后重新读取 index.json（不存在时视为空索引），应用修改并保存，
+    /// 避免并发写入者在读取与保存之间的修改被覆盖。所有索引修改都应通过此方法进行。
+    pub fn with_index_mut<R>(&self, f: impl FnOnce(&mut RepositoryIndex) -> R) -> Result<R> {
+        let _lock = self.write_lock()?;
         let index_path = self.repo_path.join("index.json");
 
-        // 加载现有索引（如果存在）
-        let mut local_index: RepositoryIndex = if index_path.exists() {
+        let mut index: RepositoryIndex = if index_path.exists() {
             load_json(&index_path)?
         } else {
-            RepositoryIndex {
-                packages: Vec::new(),
-                source: Vec::new(),
-            }
+            RepositoryIndex::default()
         };
+        let result = f(&mut index);
+        save_index(&mut index, &index_path)?;
+
+        Ok(result)
+    }
+
+    /// 用 Ed25519 私钥（32 字节种子）对当前索引签名
+    ///
+    /// 按 index.json 的当前内容重写 index.json.sha256，并写入该哈希的分离签名 index.json.sig。
+    /// 之后任何修改索引的操作都会删除签名，需要重新签名。
+    /// 返回对应的公钥（供软件源配置为 `trusted_key`）
+    pub fn sign_index(&self, secret_key: &[u8]) -> Result<Vec<u8>> {
+        let _lock = self.write_lock()?;
+        let index_path = self.repo_path.join("index.json");
+        let content = fs::read(&index_path)
+            .with_context(|| format!("无法读取索引: {}", index_path.display()))?;
+
+        let hash = write_index_hash(&index_path, &content)?;
+        let signature = crypto::sign(hash.as_bytes(), secret_key)?;
+        let signature_path = index_companion_path(&index_path, net::INDEX_SIGNATURE_SUFFIX);
+        fsxg::atomic_write(
+            &signature_path,
+            format!("{}\n", crypto::hex_encode(&signature)),
+        )
+        .with_context(|| format!("无法写入签名文件: {}", signature_path.display()))?;
+
+        crypto::public_key(secret_key)
+    }
+
+    /// 默认保留的版本数
+    pub const DEFAULT_KEEP_VERSIONS: usize = 2;
+
+    /// 清理仓库
+    ///
+    /// # 参数
+    /// - `keep_versions`: 每个软件包保留的最新版本数（按语义化版本优先级）
+    /// - `dry_run`: 只列出将要执行的变更，不实际执行
+    ///
+    /// # 返回值
+    /// 执行的变更，以及未记录在 versions.txt 中的版本目录（已保留，由调用方提示）
+    pub fn clean(&mut self, keep_versions: usize, dry_run: bool) -> Result<CleanReport> {
+        let mut changes = ChangeSet::new(dry_run);
+
+        // 清空下载缓存和配置的缓存目录，删除任何目录前先确认不会波及仓库数据
+        let cache_dirs = [get_cache_dir(), expand_path(&self.config.cache_dir)];
+        for cache_dir in &cache_dirs {
+            check_cache_dir(cache_dir, &self.repo_path)?;
+        }
+        for cache_dir in &cache_dirs {
+            changes.remove_dir(cache_dir)?;
+        }
+
+        let orphans = self.clean_packages(keep_versions, &mut changes)?;
+
+        // 清空source索引
+        if changes.record(PlannedChange::EditIndex("清空 source 部分".to_string())) {
+            self.with_index_mut(|index| index.source.clear())?;
+        }
+
+        Ok(CleanReport { changes, orphans })
+    }
+
+    /// 清理各软件包的旧版本并重新扫描 packages 部分，返回未记录在 versions.txt 中的版本目录
+    fn clean_packages(
+        &mut self,
+        keep_versions: usize,
+        changes: &mut ChangeSet,
+    ) -> Result<Vec<String>> {
+        // packages/ 不存在时视为空
+        let mut orphans = Vec::new();
+        let packages_dir = self.repo_path.join("packages");
+        if packages_dir.is_dir() {
+            let mut package_dirs: Vec<_> = fs::read_dir(&packages_dir)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<_>>()?;
+            package_dirs.sort();
+            for package_dir in package_dirs.iter().filter(|path| path.is_dir()) {
+                let package_id = package_dir
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let old = clean_old_versions(package_dir, keep_versions, None)?;
+                for version in &old.outdated {
+                    changes.remove_dir(&package_dir.join(version))?;
+                }
+                // 删除的版本同时从版本历史中移除
+                let history_path = package_dir.join("versions.txt");
+                if !old.outdated.is_empty()
+                    && changes.record(PlannedChange::WriteFile(history_path.clone()))
+                {
+                    let remaining: Vec<String> = read_version_history(&history_path)?
+                        .into_iter()
+                        .filter(|version| !old.outdated.contains(version))
+                        .collect();
+                    fs::write(&history_path, remaining.join("\n"))?;
+                }
+                for version in old.orphans {
+                    orphans.push(format!("{package_id}/{version}"));
+                }
+            }
+        }
+
+        // 删除旧版本后重新扫描 packages 部分，使索引中的可用版本与磁盘一致
+        if changes
+            .changes()
+            .iter()
+            .any(|change| matches!(change, PlannedChange::RemoveDir(path) if path.starts_with(&packages_dir)))
+            && changes.record(PlannedChange::EditIndex(
+                "重新扫描 packages/ 更新 packages 部分".to_string(),
+            ))
+        {
+            self.update_local_index()?;
+        }
+
+        Ok(orphans)
+    }
 
+    /// 更新索引的 source 部分
+    ///
+    /// 遍历所有启用的软件源，从每个源获取索引，并合并到本地索引的 source 部分。
+    /// 合并策略：对于同一个包 ID，后处理的源会覆盖先处理的源。
+    ///
+    /// 远程源的索引经 [`Self::fetch_source_index_cached`] 获取，未改变时不再下载和解析
+    pub async fn update_source_index(&mut self) -> Result<()> {
         // 创建一个 HashMap 来合并包（包ID -> PackageInfo）
         use std::collections::HashMap;
         let mut merged_source = HashMap::new();
+        let mut snapshots = Vec::new();
 
         // 遍历所有启用的软件源
         for source in &self.config.source {
@@ -161,70 +963,250 @@ impl RepoManager {
 
             // 构建索引 URL
             let index_url = format!("{}/index.json", source.url.trim_end_matches('/'));
+            self.ensure_online(&index_url)?;
 
-            // 获取索引（返回的是 serde_json::Value）
-            let source_index_value = net::fetch_index(&index_url)
-                .await
-                .map_err(|e| anyhow::anyhow!("从源 {} 获取索引失败: {}", source.id, e))?;
-
-            // 尝试将 Value 转换为 RepositoryIndex
-            let source_index: RepositoryIndex = serde_json::from_value(source_index_value)
-                .map_err(|e| anyhow::anyhow!("解析源 {} 的索引失败: {}", source.id, e))?;
-
-            // 将源索引中的包合并到 HashMap，并将相对路径转换为绝对路径
-            for mut package in source_index.packages {
-                if package.location.starts_with("./packages/") {
-                    let package_path = &package.location["./packages/".len()..];
-                    package.location = format!(
-                        "{}/packages/{}",
-                        source.url.trim_end_matches('/'),
-                        package_path
-                    );
-                }
+            // 获取索引，主 URL 不可用时依次尝试各镜像
+            log::info!("获取软件源 {} 的索引: {}", source.id, index_url);
+            let index = self
+                .fetch_source_index_cached(source, &index_url, true)
+                .await?;
+            let packages = serde_json::from_value::<RepositoryIndex>(index)
+                .map_err(|e| anyhow::anyhow!("解析源 {} 的索引失败: {}", source.id, e))?
+                .packages;
+            snapshots.push((source.id.clone(), packages.clone()));
+
+            // 将源索引中的包合并到 HashMap
+            for package in resolve_source_locations(source, packages) {
                 merged_source.insert(package.id.clone(), package);
             }
         }
 
         // 将 HashMap 中的值转换为 Vec，作为新的 source 部分
-        local_index.source = merged_source.into_values().collect();
+        let source = merged_source.into_values().collect();
+        self.with_index_mut(|index| index.source = source)?;
 
-        // 保存更新后的索引
-        save_json(&local_index, &index_path)?;
+        for (source_id, packages) in snapshots {
+            self.save_sync_snapshot(&source_id, packages)?;
+        }
 
         Ok(())
     }
 
-    /// 添加包到仓库
-    pub fn add_package<P: AsRef<Path>>(&mut self, package_path: P) -> Result<()> {
-        let package_path = expand_path(package_path);
-        let metadata_path = package_path.join("metadata.json");
-        let metadata: PackageMetadata = load_json(&metadata_path)?;
+    /// 软件源索引缓存的目录，位于缓存目录中，按源 ID 区分：
+    /// `validators.json` 为验证信息（`ETag` / `Last-Modified`），`index.json` 为对应的索引内容
+    fn index_cache_dir(&self, source_id: &str) -> PathBuf {
+        expand_path(&self.config.cache_dir)
+            .join("index-validators")
+            .join(source_id)
+    }
 
-        // 创建包目标目录
-        let package_dir = self
-            .repo_path
-            .join("packages")
-            .join(&metadata.id)
-            .join(&metadata.version);
+    /// 获取软件源的索引，按缓存的验证信息发送条件请求
+    ///
+    /// 验证信息和对应的索引内容都已缓存时发送 `If-None-Match` / `If-Modified-Since`，
+    /// 源返回 304 时直接使用缓存的索引，不再下载和校验。`save` 为真时缓存新获取的索引及其验证信息，
+    /// 源未返回验证信息时删除旧的缓存
+    async fn fetch_source_index_cached(
+        &self,
+        source: &SourceConfig,
+        index_url: &str,
+        save: bool,
+    ) -> Result<serde_json::Value> {
+        let cache_dir = self.index_cache_dir(&source.id);
+        let validators_path = cache_dir.join("validators.json");
+        let index_path = cache_dir.join("index.json");
+        let validators = if index_path.exists() {
+            load_json::<net::IndexValidators>(&validators_path).ok()
+        } else {
+            None
+        };
 
-        fsxg::create_directory(&package_dir)?;
+        match self
+            .fetch_source_index_if_modified(source, index_url, validators.as_ref())
+            .await?
+        {
+            net::IndexFetch::NotModified => {
+                log::info!("软件源 {} 的索引未改变，使用缓存的索引", source.id);
+                load_json(&index_path)
+                    .with_context(|| format!("无法读取软件源 {} 缓存的索引", source.id))
+            }
+            net::IndexFetch::Fresh { index, validators } => {
+                if save {
+                    match validators {
+                        Some(validators) => {
+                            fsxg::create_directory(&cache_dir)?;
+                            save_json(&index, &index_path)?;
+                            save_json(&validators, &validators_path)?;
+                        }
+                        None if cache_dir.exists() => fsxg::remove_directory(&cache_dir)?,
+                        None => {}
+                    }
+                }
+                Ok(index)
+            }
+        }
+    }
 
-        // 确保 metadata.all_files 至少包含一项
-        if metadata.all_files.is_empty() {
-            return Err(anyhow!("metadata.all_files 必须至少包含一项"));
+    /// 软件源同步快照的路径
+    fn sync_snapshot_path(&self, source_id: &str) -> PathBuf {
+        self.repo_path
+            .join("snapshots")
+            .join(source_id)
+            .join(SYNC_SNAPSHOT_FILE)
+    }
+
+    /// 保存软件源本次同步时索引中的软件包，供 `changelog` 对比
+    fn save_sync_snapshot(&self, source_id: &str, mut packages: Vec<PackageInfo>) -> Result<()> {
+        let path = self.sync_snapshot_path(source_id);
+        if let Some(parent) = path.parent() {
+            fsxg::create_directory(parent)?;
         }
+        packages.sort_by(|a, b| a.id.cmp(&b.id));
+        let snapshot = RepositoryIndex {
+            packages,
+            source: Vec::new(),
+        };
+        save_json(&snapshot, &path)
+    }
 
-        // 确保 metadata.all_files 列表中的文件的 SHA256 值验证成功
-        for (file_path, expected_hash) in &metadata.all_files {
-            let src_path = package_path.join(file_path);
-            if !src_path.exists() {
-                return Err(anyhow!("文件不存在: {}", src_path.display()));
+    /// 对比软件源当前的索引与上次同步时的快照
+    ///
+    /// 返回自上次 `repo update` 或 `repo sync` 以来该源新增、更新和移除的软件包（按包 ID 排序）；
+    /// 尚未同步过（没有快照）时所有软件包都视为新增
+    pub async fn changelog(&self, source_id: &str) -> Result<Vec<SourceChange>> {
+        let source = self
+            .config
+            .source
+            .iter()
+            .find(|s| s.id == source_id)
+            .ok_or_else(|| anyhow!("未找到软件源: {}", source_id))?;
+        let index_url = format!("{}/index.json", source.url.trim_end_matches('/'));
+        self.ensure_online(&index_url)?;
+
+        let current: RepositoryIndex =
+            serde_json::from_value(self.fetch_source_index(source, &index_url).await?)
+                .map_err(|e| anyhow!("解析源 {} 的索引失败: {}", source.id, e))?;
+
+        let snapshot_path = self.sync_snapshot_path(source_id);
+        let snapshot = if snapshot_path.exists() {
+            load_json::<RepositoryIndex>(&snapshot_path)?.packages
+        } else {
+            Vec::new()
+        };
+        Ok(diff_source(&snapshot, &current.packages))
+    }
+
+    /// 通过熔断器获取软件源的索引，主 URL 不可用时依次尝试各镜像
+    ///
+    /// 源启用了索引校验时按 [`net::fetch_source_index`] 校验，
+    /// 校验失败与获取失败分别报告，便于区分索引被篡改和网络问题
+    async fn fetch_source_index(
+        &self,
+        source: &SourceConfig,
+        index_url: &str,
+    ) -> Result<serde_json::Value> {
+        match self
+            .fetch_source_index_if_modified(source, index_url, None)
+            .await?
+        {
+            net::IndexFetch::Fresh { index, .. } => Ok(index),
+            net::IndexFetch::NotModified => {
+                Err(anyhow!("源 {} 在无条件请求时返回了 304", source.id))
+            }
+        }
+    }
+
+    /// 同 [`Self::fetch_source_index`]，按 `validators` 发送条件请求，见 [`net::fetch_source_index_if_modified`]
+    async fn fetch_source_index_if_modified(
+        &self,
+        source: &SourceConfig,
+        index_url: &str,
+        validators: Option<&net::IndexValidators>,
+    ) -> Result<net::IndexFetch> {
+        let index_urls = source.mirror_urls(index_url);
+        self.breaker
+            .call(&source.id, || {
+                net::with_fallback(&index_urls, |url| async move {
+                    net::fetch_source_index_if_modified(&self.client, &url, source, validators)
+                        .await
+                })
+            })
+            .await
+            .map_err(|e| match e.downcast_ref::<net::IndexVerifyError>() {
+                Some(e) => anyhow!("源 {} 的索引校验失败: {}", source.id, e),
+                None => anyhow!("从源 {} 获取索引失败: {}", source.id, e),
+            })
+    }
+
+    /// 添加包到仓库
+    ///
+    /// `package_path` 可以是软件包目录，也可以是 `app pack` 生成的 `.tar.zst` 归档：
+    /// 归档先解压到临时目录并按其中的 metadata.json 校验，加入后归档本身也保存到版本目录，供安装时整体下载。
+    ///
+    /// 仓库中已存在同一版本时报错；`force` 为 `true` 时新版本先完整写入暂存目录，再整体替换该版本目录，
+    /// 不会留下新清单中已不存在的旧文件，写入失败时旧版本保持原样
+    pub fn add_package<P: AsRef<Path>>(&mut self, package_path: P, force: bool) -> Result<()> {
+        let package_path = expand_path(package_path);
+        if !package_path.is_file() {
+            return self.add_package_dir(&package_path, None, force);
+        }
+
+        let staging = tempfile::TempDir::new().with_context(|| "无法创建暂存目录")?;
+        archive::extract_bundle(&package_path, staging.path())
+            .with_context(|| format!("无法解压软件包归档: {}", package_path.display()))?;
+        self.add_package_dir(staging.path(), Some(&package_path), force)
+    }
+
+    /// 从软件包目录添加包，`bundle` 为该目录解压自的归档
+    fn add_package_dir(
+        &mut self,
+        package_path: &Path,
+        bundle: Option<&Path>,
+        force: bool,
+    ) -> Result<()> {
+        let metadata_path = package_path.join("metadata.json");
+        let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+        metadata.check_pkgr_version(env!("CARGO_PKG_VERSION"))?;
+        log::info!("添加 {}:{} 到仓库", metadata.id, metadata.version);
+
+        // 创建包目标目录
+        let package_dir = self
+            .repo_path
+            .join("packages")
+            .join(&metadata.id)
+            .join(&metadata.version);
+        if package_dir.exists() && !force {
+            return Err(anyhow!(
+                "仓库中已存在版本 {}:{}，使用 --force 覆盖",
+                metadata.id,
+                metadata.version
+            ));
+        }
+
+        // 校验元数据（必填字段、ID字符集、入口和图标在文件清单中）
+        metadata.validate(package_path)?;
+
+        // 写入前检查目标路径（文件名合法性、路径长度）
+        check_destination_paths(&package_dir, metadata.all_files.keys())?;
+
+        // 确保 metadata.all_files 至少包含一项
+        if metadata.all_files.is_empty() {
+            return Err(anyhow!("metadata.all_files 必须至少包含一项"));
+        }
+
+        // 确保 metadata.all_files 列表中的文件的 SHA256 值验证成功
+        for (file_path, entry) in &metadata.all_files {
+            let (expected_hash, _) = parse_file_entry(entry);
+            let src_path = package_path.join(file_path);
+            if !src_path.exists() {
+                return Err(anyhow!("文件不存在: {}", src_path.display()));
             }
             if src_path.is_dir() {
                 return Err(anyhow!("路径是目录，不是文件: {}", src_path.display()));
             }
-            let actual_hash = crypto::file_hash(src_path.to_str().unwrap())?;
-            if actual_hash != *expected_hash {
+            // 符号链接按目标内容复制，目标须位于软件包目录之内
+            ensure_within(package_path, &src_path)?;
+            let actual_hash = crypto::file_hash_like(&src_path, expected_hash)?;
+            if !crypto::hash_eq(&actual_hash, expected_hash) {
                 return Err(anyhow!(
                     "文件哈希不匹配: {} (预期: {}, 实际: {})",
                     file_path,
@@ -234,512 +1216,5857 @@ impl RepoManager {
             }
         }
 
+        // 在仓库内的暂存目录中写入完整的版本目录，全部写入后再替换已有的版本目录
+        let staging = tempfile::TempDir::new_in(&self.repo_path)
+            .with_context(|| format!("无法创建暂存目录: {}", self.repo_path.display()))?;
+        let staged_dir = staging.path().join("version");
+        fsxg::create_directory(&staged_dir)?;
+
         // 复制所有文件
         for file_path in metadata.all_files.keys() {
             let src_path = package_path.join(file_path);
-            let dest_path = package_dir.join(file_path);
+            let dest_path = staged_dir.join(file_path);
 
             if let Some(parent) = dest_path.parent() {
                 fsxg::create_directory(parent)?;
             }
 
-            fs::copy(src_path, dest_path)?;
+            // fs::copy 保留源文件的权限，清单中记录了权限时以记录为准
+            fs::copy(src_path, &dest_path)?;
+            if let Some(mode) = metadata.get_file_mode(file_path) {
+                fsxg::set_file_mode(&dest_path, mode)?;
+            }
+        }
+
+        // 写入带有自校验哈希的 metadata.json 文件
+        metadata.metadata_hash = Some(metadata.compute_metadata_hash()?);
+        fsxg::atomic_write(
+            staged_dir.join("metadata.json"),
+            serde_json::to_string_pretty(&metadata)?,
+        )?;
+
+        // 保存归档；从目录添加时不保存，同一版本此前发布的归档随旧目录一起移除
+        if let Some(bundle) = bundle {
+            let bundle_path =
+                staged_dir.join(archive::bundle_file_name(&metadata.id, &metadata.version));
+            fs::copy(bundle, &bundle_path)
+                .with_context(|| format!("无法保存软件包归档: {}", bundle_path.display()))?;
         }
 
-        // 复制 metadata.json 文件
-        let src_metadata_path = package_path.join("metadata.json");
-        let dest_metadata_path = package_dir.join("metadata.json");
-        fs::copy(src_metadata_path, dest_metadata_path)?;
+        // 旧版本目录先移入暂存目录（随其一起删除），新目录移入失败时移回
+        if let Some(parent) = package_dir.parent() {
+            fsxg::create_directory(parent)?;
+        }
+        let replaced = staging.path().join("replaced");
+        let had_old = package_dir.exists();
+        if had_old {
+            fs::rename(&package_dir, &replaced)
+                .with_context(|| format!("无法替换版本目录: {}", package_dir.display()))?;
+        }
+        if let Err(e) = fs::rename(&staged_dir, &package_dir) {
+            if had_old {
+                fs::rename(&replaced, &package_dir)?;
+            }
+            return Err(e).with_context(|| format!("无法移动版本目录: {}", package_dir.display()));
+        }
 
         // 更新版本历史
         update_version_history(&metadata.id, &metadata.version, &self.repo_path)?;
 
         // 更新索引
-        update_package_index(&metadata, &package_dir, &self.repo_path.join("index.json"))?;
+        let bundle = published_bundle(&package_dir, &metadata);
+        let versions = self.version_history(&metadata.id)?;
+        self.with_index_mut(|index| {
+            update_package_index(index, &metadata, versions, bundle, None)
+        })?;
 
         Ok(())
     }
 
-    /// 安装软件包
-    pub async fn install_package(
+    /// 更新仓库中已有版本的单个文件
+    ///
+    /// 从软件包目录复制指定文件到 `packages/{id}/{version}/`，并同步更新存储的
+    /// metadata.json 中的哈希与索引。文件与元数据的替换在事务中进行，失败时回滚。
+    /// 目标版本必须已存在于仓库中。
+    pub fn update_package_file<P: AsRef<Path>>(
         &mut self,
-        package_spec: &str,
-        version: Option<&str>,
+        package_path: P,
+        relative_path: &str,
     ) -> Result<()> {
-        // 解析 package_spec，支持三种格式：
-        // 1. package_id (使用默认源和最新版本)
-        // 2. source:package_id (使用指定源和最新版本)
-        // 3. source:package_id:version (使用指定源和版本)
-        let parts: Vec<&str> = package_spec.split(':').collect();
-
-        let (source_id, package_id, final_version) = match parts.len() {
-            1 => {
-                // 只提供了包ID，使用默认源
-                let default_source = self
-                    .config
-                    .source
-                    .first()
-                    .map(|s| s.id.as_str())
-                    .unwrap_or("default");
-                (default_source, parts[0], version.unwrap_or("latest"))
-            }
-            2 => {
-                // 提供了 source:package_id
-                (parts[0], parts[1], version.unwrap_or("latest"))
-            }
-            3 => {
-                // 提供了完整的 source:package_id:version
-                // 覆盖传入的 version 参数
-                (parts[0], parts[1], parts[2])
-            }
-            _ => {
-                return Err(anyhow!("错误: 请使用 source:package:version 格式"));
-            }
-        };
-
-        // 查找软件源配置
-        let source = self
-            .config
-            .source
-            .iter()
-            .find(|s| s.id == source_id)
-            .ok_or_else(|| anyhow!("未找到软件源: {}", source_id))?;
-
-        // 根据源和版本获取包元数据 URL 【费案】实现获取不同源不同版本的元数据
-        // let metadata_url = format!(
-        //     "{}{}/{}/metadata.json",
-        //     source.url, package_id, final_version
-        // );
-
-        // 从索引中获取软件包的 location 值
-        let index_path = self.repo_path.join("index.json");
-        let index: RepositoryIndex = load_json(&index_path)?;
-
-        // 在源索引中查找包
-        let package_info = index
-            .source
-            .iter()
-            .find(|p| p.id == package_id)
-            .ok_or_else(|| anyhow!("未在索引中找到包: {}", package_id))?;
-
-        // 构建元数据 URL
-        let metadata_url = format!(
-            "{}/metadata.json",
-            package_info.location.trim_end_matches('/')
-        );
+        let package_path = expand_path(package_path);
+        let source_metadata: PackageMetadata = load_json(&package_path.join("metadata.json"))?;
 
-        // 下载元数据
-        let metadata_path = get_cache_dir().join("metadata.json");
-        let metadata_str = metadata_path
-            .to_str()
-            .ok_or_else(|| anyhow!("无效的缓存路径"))?;
-        net::download_file(&metadata_url, metadata_str)
-            .await
-            .map_err(|e| anyhow!("下载失败: {}", e))?;
-        let metadata_content = fs::read(&metadata_path)?;
-        let metadata: PackageMetadata = serde_json::from_slice(&metadata_content)?;
+        // 规范化相对路径，拒绝越出包目录的路径
+        let relative_path = relative_path.replace('\\', "/");
+        if Path::new(&relative_path).is_absolute()
+            || relative_path
+                .split('/')
+                .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(anyhow!("无效的文件相对路径: {}", relative_path));
+        }
 
-        // 创建包目录
+        // 确保目标版本已存在
         let package_dir = self
             .repo_path
             .join("packages")
-            .join(&metadata.id)
-            .join(&metadata.version);
+            .join(&source_metadata.id)
+            .join(&source_metadata.version);
+        let stored_metadata_path = package_dir.join("metadata.json");
+        if !stored_metadata_path.exists() {
+            return Err(anyhow!(
+                "仓库中不存在版本 {}:{}，请先使用 repo add 添加",
+                source_metadata.id,
+                source_metadata.version
+            ));
+        }
 
-        fsxg::create_directory(&package_dir)?;
+        log::warn!(
+            "正在修改已发布的版本 {}:{}，通常建议发布新版本代替",
+            source_metadata.id,
+            source_metadata.version
+        );
 
-        // 下载并验证所有文件
-        for (file_path, expected_hash) in &metadata.all_files {
-            let file_url = format!(
-                "{}packages/{}/{}/{}",
-                source.url, package_id, metadata.version, file_path
-            );
+        let src_path = package_path.join(&relative_path);
+        if !src_path.is_file() {
+            return Err(anyhow!("文件不存在: {}", src_path.display()));
+        }
+        let content =
+            fs::read(&src_path).with_context(|| format!("无法读取文件: {}", src_path.display()))?;
 
-            let dest_path = package_dir.join(file_path);
-            if let Some(parent) = dest_path.parent() {
-                fsxg::create_directory(parent)?;
+        // 更新存储的元数据
+        let mut metadata: PackageMetadata = load_json(&stored_metadata_path)?;
+        metadata.add_file(
+            relative_path.clone(),
+            format_file_entry(&crypto::bytes_hash(&content), content.len() as u64),
+        );
+        if metadata.signature.take().is_some() {
+            log::warn!("文件清单已改变，已移除原有签名，请重新签名",);
+        }
+        metadata.metadata_hash = Some(metadata.compute_metadata_hash()?);
+        let metadata_json = serde_json::to_string_pretty(&metadata)?;
+
+        // 在事务中替换文件和元数据
+        let mut transaction = Transaction::begin();
+        let result = (|| {
+            stage_file(
+                &mut transaction,
+                &package_dir.join(&relative_path),
+                &content,
+            )?;
+            stage_file(
+                &mut transaction,
+                &stored_metadata_path,
+                metadata_json.as_bytes(),
+            )?;
+            // 已发布的归档不再与元数据一致，删除后安装时回退到逐个下载文件
+            let bundle_path =
+                package_dir.join(archive::bundle_file_name(&metadata.id, &metadata.version));
+            if bundle_path.is_file() {
+                transaction.safe_remove(&bundle_path)?;
+                log::warn!("已删除与新内容不一致的软件包归档，请重新运行 app pack 并 repo add",);
             }
+            let record = load_install_record(&package_dir)?;
+            let versions = self.version_history(&metadata.id)?;
+            self.with_index_mut(|index| {
+                update_package_index(index, &metadata, versions, None, record.as_ref())
+            })
+        })();
 
-            // 添加日志调试
-            eprintln!("下载文件: {}", &file_url);
-            eprintln!("目标路径: {:?}", &dest_path);
+        match result {
+            Ok(()) => transaction.commit(),
+            Err(e) => {
+                transaction.rollback()?;
+                Err(e)
+            }
+        }
+    }
 
-            let dest_str = dest_path
-                .to_str()
-                .ok_or_else(|| anyhow!("无效的文件路径"))?;
-            net::download_file(&file_url, dest_str)
-                .await
-                .map_err(|e| anyhow!("下载失败: {}", e))?;
+    /// 下载软件包归档并安装其中的文件
+    ///
+    /// 归档解压到仓库内的暂存目录，先按归档自带的 metadata.json 校验，再按 `downloads` 中
+    /// 实际用于校验的哈希（含校验和覆盖）逐个校验；全部通过后才移入目标路径，失败时不修改目标目录
+    ///
+    /// # 返回值
+    ///
+    /// 返回各文件实际用于校验的哈希
+    async fn install_bundle(
+        &self,
+        source: &SourceConfig,
+        bundle_url: &str,
+        downloads: &[FileDownload],
+    ) -> Result<BTreeMap<String, String>> {
+        let staging = tempfile::TempDir::new_in(&self.repo_path)
+            .with_context(|| format!("无法创建暂存目录: {}", self.repo_path.display()))?;
+        let archive_path = staging.path().join("bundle.tar.zst");
+        let archive_str = path_to_str(&archive_path)?;
+        let bundle_urls = source.mirror_urls(bundle_url);
+        log::info!("下载软件包归档: {}", bundle_urls[0]);
+        self.breaker
+            .call(&source.id, || {
+                net::with_fallback(&bundle_urls, |url| async move {
+                    net::download_file(
+                        &self.client,
+                        &url,
+                        archive_str,
+                        &net::ConsoleProgress::default(),
+                    )
+                    .await
+                })
+            })
+            .await
+            .map_err(|e| anyhow!("下载失败: {}", e))?;
+
+        let extracted = staging.path().join("files");
+        archive::extract_bundle(&archive_path, &extracted)?;
 
-            // 验证文件哈希
-            let actual_hash = crypto::file_hash(dest_str)?;
-            if &actual_hash != expected_hash {
+        let mut verified_files = BTreeMap::new();
+        for download in downloads {
+            let staged_path = extracted.join(&download.file_path);
+            let actual_hash = crypto::file_hash_like(&staged_path, &download.expected_hash)
+                .with_context(|| format!("归档中缺少文件: {}", download.file_path))?;
+            if !crypto::hash_eq(&actual_hash, &download.expected_hash) {
                 return Err(anyhow!(
                     "文件哈希不匹配: {} (预期: {}, 实际: {})",
-                    file_path,
-                    expected_hash,
+                    download.file_path,
+                    download.expected_hash,
                     actual_hash
                 ));
             }
+            verified_files.insert(download.file_path.clone(), actual_hash);
+        }
+        for download in downloads {
+            fs::rename(extracted.join(&download.file_path), &download.dest_path)
+                .with_context(|| format!("无法移动文件: {}", download.dest_path.display()))?;
         }
 
-        // 复制 metadata.json 文件
-        let src_metadata_path = metadata_path; // 缓存目录中的 metadata.json
-        let dest_metadata_path = package_dir.join("metadata.json");
-        fs::copy(src_metadata_path, dest_metadata_path)?;
-
-        // 更新版本历史
-        update_version_history(&metadata.id, &metadata.version, &self.repo_path)?;
-
-        // 更新索引
-        update_package_index(&metadata, &package_dir, &self.repo_path.join("index.json"))?;
-
-        Ok(())
+        Ok(verified_files)
     }
 
-    /// 卸载软件包
-    pub fn remove_package(&mut self, package_id: &str, version: Option<&str>) -> Result<()> {
-        let package_dir = self.repo_path.join("packages").join(package_id);
-
-        if let Some(version) = version {
-            // 移除特定版本
-            let version_dir = package_dir.join(version);
-            if version_dir.exists() {
-                fsxg::remove_directory(&version_dir)?;
-            }
-        } else {
-            // 移除整个包
-            if package_dir.exists() {
-                fsxg::remove_directory(&package_dir)?;
-            }
+    /// 展开安装规格中软件包ID部分的通配符
+    ///
+    /// 软件包ID包含 `*` 或 `?` 时，在本地索引的 source 部分中查找所有匹配的软件包，
+    /// 返回将ID替换为各匹配项后的规格（按ID排序）；不含通配符时原样返回。
+    /// 规格带有 `source:` 前缀时只匹配由该软件源同步而来的软件包
+    pub fn expand_package_spec(&self, package_spec: &str) -> Result<Vec<String>> {
+        let parts: Vec<&str> = package_spec.split(':').collect();
+        let id_position = if parts.len() == 1 { 0 } else { 1 };
+        let pattern = parts[id_position];
+        if !pattern.contains(['*', '?']) {
+            return Ok(vec![package_spec.to_string()]);
         }
 
-        // 更新版本历史
-        if let Some(version) = version {
-            remove_version_from_history(package_id, version, &self.repo_path)?;
-        } else {
-            remove_package_history(package_id, &self.repo_path)?;
-        }
+        let source = match id_position {
+            0 => None,
+            _ => Some(
+                self.config
+                    .source
+                    .iter()
+                    .find(|s| s.id == parts[0])
+                    .ok_or_else(|| anyhow!("未找到软件源: {}", parts[0]))?,
+            ),
+        };
 
-        // 更新索引
-        remove_package_from_index(package_id, version, &self.repo_path.join("index.json"))?;
+        let index: RepositoryIndex = load_json(&self.repo_path.join("index.json"))?;
+        let mut package_ids: Vec<&str> = index
+            .source
+            .iter()
+            .filter(|package| source.is_none_or(|source| source_owns(source, &package.location)))
+            .map(|package| package.id.as_str())
+            .filter(|id| app::glob_match(pattern, id))
+            .collect();
+        package_ids.sort_unstable();
+        package_ids.dedup();
+        if package_ids.is_empty() {
+            return Err(anyhow!("源索引中没有与 {} 匹配的软件包", pattern));
+        }
 
-        Ok(())
+        Ok(package_ids
+            .into_iter()
+            .map(|id| {
+                let mut spec = parts.clone();
+                spec[id_position] = id;
+                spec.join(":")
+            })
+            .collect())
     }
 
-    /// 升级软件包
-    pub async fn upgrade_package(&mut self, package_id: &str) -> Result<()> {
-        // 获取当前安装的最新版本
-        let history_path = self
-            .repo_path
-            .join("packages")
-            .join(package_id)
-            .join("versions.txt");
-
-        let versions = read_version_history(&history_path)?;
-        let current_version = versions
-            .last()
-            .ok_or_else(|| anyhow!("没有安装的版本: {}", package_id))?
-            .clone();
-
-        // 从索引中获取软件源中的最新版本和源信息
+    /// 按ID、名称或描述搜索索引中的软件包（不区分大小写的子串匹配）
+    ///
+    /// 依次搜索已安装的包（packages）和软件源中的包（source），
+    /// 软件源中与已匹配的已安装包ID相同的条目不重复返回；`installed_only` 为 true 时只搜索已安装的包
+    pub fn search_packages(&self, query: &str, installed_only: bool) -> Result<Vec<PackageInfo>> {
         let index_path = self.repo_path.join("index.json");
-        let index: RepositoryIndex = load_json(&index_path)?;
+        let index: RepositoryIndex = if index_path.exists() {
+            load_json(&index_path)?
+        } else {
+            RepositoryIndex::default()
+        };
 
-        let remote_pkg = index
-            .source
-            .iter()
-            .find(|p| p.id == package_id)
-            .ok_or_else(|| anyhow!("未在软件源中找到包: {}", package_id))?;
+        let query = query.to_lowercase();
+        let matches = |package: &PackageInfo| {
+            [&package.id, &package.name, &package.description]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&query))
+        };
 
-        let latest_version = remote_pkg.latest_version.clone();
+        let mut results: Vec<PackageInfo> = index.packages.into_iter().filter(matches).collect();
+        if !installed_only {
+            let source_matches: Vec<PackageInfo> = index
+                .source
+                .into_iter()
+                .filter(|package| {
+                    matches(package) && !results.iter().any(|found| found.id == package.id)
+                })
+                .collect();
+            results.extend(source_matches);
+        }
+        Ok(results)
+    }
 
-        // 查找软件源 ID（使用第一个包含该包的启用源）
-        let source_id = self
-            .config
-            .source
-            .iter()
-            .find(|s| s.enabled && index.source.iter().any(|p| p.id == package_id))
-            .map(|s| s.id.clone())
-            .ok_or_else(|| anyhow!("没有找到包含 {} 的启用源", package_id))?;
+    /// 列出索引中的软件包
+    ///
+    /// 合并索引的 packages 和 source 部分，同一ID只列出一次并同时给出已安装版本和软件源中的最新版本，
+    /// 按 `filter` 筛选后按ID排序
+    pub fn list(&self, filter: ListFilter) -> Result<Vec<ListedPackage>> {
+        let index_path = self.repo_path.join("index.json");
+        let index: RepositoryIndex = if index_path.exists() {
+            load_json(&index_path)?
+        } else {
+            RepositoryIndex::default()
+        };
 
-        // 比较版本
-        if latest_version != current_version {
-            // 安装新版本
-            self.install_package(
-                &format!("{source_id}:{package_id}"),
-                Some(&latest_version),
-            )
-            .await?;
+        let mut listed: BTreeMap<String, ListedPackage> = BTreeMap::new();
+        for package in index.packages {
+            listed.insert(
+                package.id.clone(),
+                ListedPackage {
+                    id: package.id,
+                    name: package.name,
+                    installed_version: Some(package.latest_version),
+                    available_version: None,
+                    description: package.description,
+                    installed_at: package.installed_at,
+                    installed_from: package.installed_from,
+                },
+            );
+        }
+        for package in index.source {
+            listed
+                .entry(package.id.clone())
+                .or_insert_with(|| ListedPackage {
+                    id: package.id,
+                    name: package.name,
+                    installed_version: None,
+                    available_version: None,
+                    description: package.description,
+                    installed_at: None,
+                    installed_from: None,
+                })
+                .available_version = Some(package.latest_version);
         }
 
-        Ok(())
+        Ok(listed
+            .into_values()
+            .filter(|package| match filter {
+                ListFilter::Installed => package.installed_version.is_some(),
+                ListFilter::Available => package.installed_version.is_none(),
+                ListFilter::All => true,
+            })
+            .collect())
     }
 
-    /// 同步仓库
-    pub async fn sync_repository(&mut self, source_id: &str, mirror: bool) -> Result<()> {
-        // 获取软件源配置
-        let source = self
-            .config
+    /// 获取软件包的完整元数据
+    ///
+    /// 已安装的软件包读取已安装最高版本的 metadata.json；
+    /// 仅存在于软件源中的软件包从索引中的 location 获取最新版本的 metadata.json，并校验元数据自身的哈希
+    pub async fn package_info(&self, package_id: &str) -> Result<PackageMetadata> {
+        let package_dir = self.repo_path.join("packages").join(package_id);
+        let versions = read_version_history(&package_dir.join("versions.txt"))?;
+        if let Some(version) = version::get_latest_semver(&versions) {
+            return load_json(&package_dir.join(version).join("metadata.json"));
+        }
+
+        let index_path = self.repo_path.join("index.json");
+        let index: RepositoryIndex = if index_path.exists() {
+            load_json(&index_path)?
+        } else {
+            RepositoryIndex::default()
+        };
+        let package = index
             .source
             .iter()
-            .find(|s| s.id == source_id)
-            .ok_or_else(|| anyhow!("未找到软件源: {}", source_id))?;
+            .find(|package| package.id == package_id)
+            .ok_or_else(|| anyhow!("软件包 {} 既未安装，也不在软件源索引中", package_id))?;
+        self.source_metadata(package, &package.latest_version).await
+    }
 
-        if mirror {
-            // 镜像同步
-            net::mirror_sync(
-                &source.url,
-                &self.repo_path.join("packages").to_string_lossy(),
-                source.enabled,
-                source.require_https,
-            )
-            .await
-            .map_err(|e| anyhow!("镜像同步失败: {}", e))?;
+    /// 获取已安装软件包在索引中的信息（含安装时间和来源），未安装时返回 `None`
+    pub fn installed_package(&self, package_id: &str) -> Result<Option<PackageInfo>> {
+        let index_path = self.repo_path.join("index.json");
+        let index: RepositoryIndex = if index_path.exists() {
+            load_json(&index_path)?
         } else {
-            // 增量同步 (简化实现)
-            let index_url = format!("{}/index.json", source.url.trim_end_matches('/'));
-            let remote_index = net::fetch_index(&index_url)
-                .await
-                .map_err(|e| anyhow!("获取索引失败: {}", e))?;
+            RepositoryIndex::default()
+        };
+        Ok(index
+            .packages
+            .into_iter()
+            .find(|package| package.id == package_id))
+    }
+
+    /// 安装软件包
+    ///
+    /// 先解析请求软件包的传递依赖（见 [`Self::resolve_dependencies`]），按拓扑顺序安装其中
+    /// 尚未安装对应版本的依赖，最后安装请求的软件包。依赖安装不使用校验和覆盖（覆盖只针对请求的软件包）。
+    /// 配置了 `auto_prune_keep` 时，安装成功后清理该软件包（不含依赖）的旧版本
+    pub async fn install_package(
+        &mut self,
+        package_spec: &str,
+        version: Option<&str>,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        let (source_id, package_id, requested) = self.parse_install_spec(package_spec, version)?;
+        let index: RepositoryIndex = load_json(&self.repo_path.join("index.json"))?;
+        let package_info = find_source_package(&index, package_id)?;
+        let install_version = resolve_version(package_info, requested, options.prefer)?;
+        let exact_spec = format!("{source_id}:{package_id}:{install_version}");
 
-            // 更新本地索引
-            let mut local_index: RepositoryIndex = load_json(&self.repo_path.join("index.json"))?;
-            local_index.source = serde_json::from_value(remote_index["source"].clone())?;
-            save_json(&local_index, &self.repo_path.join("index.json"))?;
+        let plan = self.resolve_dependencies(&exact_spec).await?;
+        let dependency_options = InstallOptions {
+            checksum_overrides: BTreeMap::new(),
+            ..options.clone()
+        };
+        for dependency_spec in &plan[..plan.len() - 1] {
+            let (_, dependency_id, dependency_version) =
+                self.parse_install_spec(dependency_spec, None)?;
+            let history_path = self
+                .repo_path
+                .join("packages")
+                .join(dependency_id)
+                .join("versions.txt");
+            if read_version_history(&history_path)?
+                .iter()
+                .any(|installed| installed == dependency_version)
+            {
+                continue;
+            }
+            log::info!("安装依赖: {dependency_id}:{dependency_version}");
+            self.install_single_package(dependency_spec, &dependency_options)
+                .await
+                .with_context(|| format!("安装 {package_id} 的依赖 {dependency_id} 失败"))?;
         }
 
+        self.install_single_package(&exact_spec, options).await?;
+
+        // 安装已经成功，清理旧版本失败只给出警告
+        if let Some(keep_versions) = self.config.auto_prune_keep {
+            let lockfile_path = options
+                .lockfile
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(crate::lockfile::DEFAULT_LOCKFILE));
+            if let Err(e) =
+                self.prune_versions(package_id, keep_versions, &install_version, &lockfile_path)
+            {
+                log::warn!("清理 {package_id} 的旧版本失败: {e:#}");
+            }
+        }
         Ok(())
     }
 
-    /// 更新本地索引
+    /// 只保留软件包最新的 `keep_versions` 个版本，`installed` 版本始终保留
     ///
-    /// 扫描 packages/ 目录下的所有已安装包，并更新 index.json 文件中的 packages 部分
-    pub fn update_local_index(&mut self) -> Result<()> {
-        // 获取索引文件路径
-        let index_path = self.repo_path.join("index.json");
+    /// 以下版本同样保留：锁文件 `lockfile_path` 中锁定的版本；
+    /// 其它已安装软件包依赖该软件包、且约束只能由该版本满足时（保留满足约束的最高版本）。
+    /// 删除的版本同时从版本历史和索引中移除，单个版本删除失败时给出警告并继续
+    fn prune_versions(
+        &mut self,
+        package_id: &str,
+        keep_versions: usize,
+        installed: &str,
+        lockfile_path: &Path,
+    ) -> Result<()> {
+        let package_dir = self.repo_path.join("packages").join(package_id);
+        let candidates = clean_old_versions(&package_dir, keep_versions, Some(installed))?.outdated;
+        if candidates.is_empty() {
+            return Ok(());
+        }
 
-        // 加载现有索引
-        let mut index: RepositoryIndex = if index_path.exists() {
-            load_json(&index_path)?
+        let locked = Lockfile::load(lockfile_path)?
+            .packages
+            .remove(package_id)
+            .map(|package| package.version);
+        let requirements = self.dependent_requirements(package_id)?;
+        let mut remaining: Vec<String> = self
+            .version_history(package_id)?
+            .into_iter()
+            .filter(|version| !candidates.contains(version))
+            .collect();
+
+        // 从新到旧处理，依赖约束由仍保留的最高版本满足
+        for version in candidates.iter().rev() {
+            if locked.as_deref() == Some(version) {
+                log::info!("保留锁文件中锁定的版本: {package_id}:{version}");
+                remaining.push(version.clone());
+                continue;
+            }
+            if let Some((dependent, version_req)) = requirements.iter().find(|(_, version_req)| {
+                version::satisfies(version, version_req)
+                    && !remaining
+                        .iter()
+                        .any(|kept| version::satisfies(kept, version_req))
+            }) {
+                log::info!("保留 {package_id}:{version}（{dependent} 依赖 {version_req}）");
+                remaining.push(version.clone());
+                continue;
+            }
+
+            log::info!("清理旧版本: {package_id}:{version}");
+            if let Err(e) = self.remove_package(package_id, Some(version), false) {
+                log::warn!("清理旧版本 {package_id}:{version} 失败: {e:#}");
+            }
+        }
+        Ok(())
+    }
+
+    /// 其它已安装软件包（所有已安装版本）对 `package_id` 的版本约束，返回（依赖方 `id:version`, 约束）
+    fn dependent_requirements(&self, package_id: &str) -> Result<Vec<(String, String)>> {
+        let mut requirements = Vec::new();
+        let packages_dir = self.repo_path.join("packages");
+        if !packages_dir.is_dir() {
+            return Ok(requirements);
+        }
+        for entry in fs::read_dir(&packages_dir)? {
+            let entry = entry?;
+            let dependent = entry.file_name().to_string_lossy().to_string();
+            if dependent == package_id || !entry.path().is_dir() {
+                continue;
+            }
+            for version in read_version_history(&entry.path().join("versions.txt"))? {
+                let metadata_path = entry.path().join(&version).join("metadata.json");
+                if !metadata_path.is_file() {
+                    continue;
+                }
+                let metadata: PackageMetadata = load_json(&metadata_path)?;
+                for dependency in metadata.dependencies {
+                    if dependency.id == package_id {
+                        requirements
+                            .push((format!("{dependent}:{version}"), dependency.version_req));
+                    }
+                }
+            }
+        }
+        Ok(requirements)
+    }
+
+    /// 解析安装规格，返回（软件源ID, 软件包ID, 请求的版本）
+    ///
+    /// 支持三种格式：
+    /// 1. package_id (使用第一个软件源，版本为 `version` 参数或 latest)
+    /// 2. source:package_id (使用指定源，版本为 `version` 参数或 latest)
+    /// 3. source:package_id:version (使用指定源和版本，忽略 `version` 参数)
+    fn parse_install_spec<'a>(
+        &self,
+        package_spec: &'a str,
+        version: Option<&'a str>,
+    ) -> Result<(String, &'a str, &'a str)> {
+        let parts: Vec<&str> = package_spec.split(':').collect();
+        match parts.len() {
+            1 => {
+                // 只提供了包ID，使用默认源
+                let default_source = self
+                    .config
+                    .source
+                    .first()
+                    .map_or("default", |s| s.id.as_str());
+                Ok((
+                    default_source.to_string(),
+                    parts[0],
+                    version.unwrap_or("latest"),
+                ))
+            }
+            2 => Ok((parts[0].to_string(), parts[1], version.unwrap_or("latest"))),
+            3 => Ok((parts[0].to_string(), parts[1], parts[2])),
+            _ => Err(anyhow!("错误: 请使用 source:package:version 格式")),
+        }
+    }
+
+    /// 解析软件包的传递依赖
+    ///
+    /// 从源索引出发深度优先遍历依赖图（读取各依赖选定版本的 metadata.json）。
+    /// 每个依赖选择满足所有请求方约束的最高版本，被多个软件包依赖时只出现一次；
+    /// 遍历中途选定的版本与最终收集到的约束不符时，固定为重新计算的版本后重新遍历。
+    /// 存在循环依赖时返回列出循环路径的错误。
+    ///
+    /// 返回按拓扑顺序排列的安装规格（`source:package_id:version`），依赖在前，请求的软件包在最后
+    pub async fn resolve_dependencies(&self, package_spec: &str) -> Result<Vec<String>> {
+        /// 遍历中的软件包及其尚未处理的依赖
+        struct Frame {
+            id: String,
+            version: String,
+            dependencies: Vec<Dependency>,
+            next: usize,
+        }
+
+        let (source_id, package_id, requested) = self.parse_install_spec(package_spec, None)?;
+        let index: RepositoryIndex = load_json(&self.repo_path.join("index.json"))?;
+        let root = find_source_package(&index, package_id)?;
+        let root_version = resolve_version(root, requested, version::Preference::Newest)?;
+
+        let mut dependencies_cache: BTreeMap<(String, String), Vec<Dependency>> = BTreeMap::new();
+        let mut pinned: BTreeMap<String, String> = BTreeMap::new();
+        for _ in 0..MAX_RESOLVE_ROUNDS {
+            let mut constraints: BTreeMap<String, Vec<version::Constraint>> = BTreeMap::new();
+            let mut chosen: BTreeMap<String, String> = BTreeMap::new();
+            let mut order = Vec::new();
+            let mut stack: Vec<Frame> = Vec::new();
+
+            let mut next_package = Some((root, root_version.clone()));
+            loop {
+                if let Some((package, version)) = next_package.take() {
+                    let key = (package.id.clone(), version.clone());
+                    if !dependencies_cache.contains_key(&key) {
+                        let metadata = self.source_metadata(package, &version).await?;
+                        dependencies_cache.insert(key.clone(), metadata.dependencies);
+                    }
+                    stack.push(Frame {
+                        id: package.id.clone(),
+                        version,
+                        dependencies: dependencies_cache[&key].clone(),
+                        next: 0,
+                    });
+                }
+
+                let Some(frame) = stack.last_mut() else {
+                    break;
+                };
+                let Some(dependency) = frame.dependencies.get(frame.next).cloned() else {
+                    let frame = stack.pop().expect("栈顶元素存在");
+                    order.push(format!("{source_id}:{}:{}", frame.id, frame.version));
+                    continue;
+                };
+                frame.next += 1;
+                let requester = format!("{}:{}", frame.id, frame.version);
+
+                if let Some(position) = stack.iter().position(|frame| frame.id == dependency.id) {
+                    let cycle: Vec<&str> = stack[position..]
+                        .iter()
+                        .map(|frame| frame.id.as_str())
+                        .chain([dependency.id.as_str()])
+                        .collect();
+                    return Err(anyhow!("检测到循环依赖: {}", cycle.join(" -> ")));
+                }
+                constraints
+                    .entry(dependency.id.clone())
+                    .or_default()
+                    .push(version::Constraint {
+                        requester: requester.clone(),
+                        requirement: dependency.version_req.clone(),
+                    });
+                if chosen.contains_key(&dependency.id) {
+                    // 已经处理过的依赖不再重复遍历，最终版本在遍历结束后统一检查
+                    continue;
+                }
+
+                let package = find_source_package(&index, &dependency.id)
+                    .with_context(|| format!("{requester} 的依赖无法解析"))?;
+                let version = match pinned.get(&dependency.id) {
+                    Some(version) => version.clone(),
+                    None => version::resolve_constraints(
+                        &dependency.id,
+                        &constraints[&dependency.id],
+                        candidate_versions(package),
+                    )?,
+                };
+                chosen.insert(dependency.id.clone(), version.clone());
+                next_package = Some((package, version));
+            }
+
+            // 检查每个依赖选定的版本是否为满足全部约束的版本
+            let mut changed = false;
+            for (dependency_id, dependency_constraints) in &constraints {
+                let package = find_source_package(&index, dependency_id)?;
+                let best = version::resolve_constraints(
+                    dependency_id,
+                    dependency_constraints,
+                    candidate_versions(package),
+                )?;
+                if chosen[dependency_id] != best {
+                    pinned.insert(dependency_id.clone(), best);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return Ok(order);
+            }
+        }
+
+        Err(anyhow!(
+            "软件包 {} 的依赖版本无法收敛，请明确指定依赖的版本",
+            package_id
+        ))
+    }
+
+    /// 安装单个软件包（不处理依赖）
+    ///
+    /// 所有写入（包括新建的目录）都记录在事务中，任何一步失败时回滚
+    async fn install_single_package(
+        &mut self,
+        package_spec: &str,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        let (source_id, package_id, final_version) = self.parse_install_spec(package_spec, None)?;
+
+        // 查找软件源配置
+        let source = self
+            .config
+            .source
+            .iter()
+            .find(|s| s.id == source_id)
+            .ok_or_else(|| anyhow!("未找到软件源: {}", source_id))?;
+
+        // 从索引中获取软件包的 location 值
+        let index_path = self.repo_path.join("index.json");
+        let index: RepositoryIndex = load_json(&index_path)?;
+        let package_info = find_source_package(&index, package_id)?;
+
+        // 解析要安装的版本
+        let install_version = resolve_version(package_info, final_version, options.prefer)?;
+
+        let mut transaction = Transaction::begin();
+        let result = self
+            .install_version(
+                &mut transaction,
+                source,
+                package_info,
+                &install_version,
+                options,
+            )
+            .await;
+
+        match result {
+            Ok(()) => transaction.commit(),
+            Err(e) => {
+                transaction.rollback()?;
+                Err(e)
+            }
+        }
+    }
+
+    /// 在事务中下载并安装软件包的指定版本
+    ///
+    /// 写入文件前先记录到事务中，由调用方决定提交或回滚
+    async fn install_version(
+        &self,
+        transaction: &mut Transaction,
+        source: &SourceConfig,
+        package_info: &PackageInfo,
+        install_version: &str,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        log::info!(
+            "安装 {}:{}（软件源: {}）",
+            package_info.id,
+            install_version,
+            source.id
+        );
+
+        // 软件包ID和版本号来自远程索引，拼接为本地目录前确认不会越出 packages/
+        let packages_dir = self.repo_path.join("packages");
+        if !is_safe_relative(&packages_dir, &package_info.id) {
+            return Err(anyhow!("源索引中的软件包ID不合法: {}", package_info.id));
+        }
+        if !is_safe_relative(&packages_dir.join(&package_info.id), install_version) {
+            return Err(anyhow!("源索引中的版本号不合法: {}", install_version));
+        }
+
+        let metadata_url = join_location(
+            &version_location(package_info, install_version),
+            "metadata.json",
+        );
+
+        // 下载元数据（离线时读取本地已安装该版本的元数据）
+        let metadata_path = if self.is_offline() {
+            let installed = self
+                .repo_path
+                .join("packages")
+                .join(&package_info.id)
+                .join(install_version)
+                .join("metadata.json");
+            if !installed.is_file() {
+                return Err(anyhow!(
+                    "离线模式已启用，本地没有 {}:{} 的元数据",
+                    package_info.id,
+                    install_version
+                ));
+            }
+            installed
         } else {
-            RepositoryIndex {
-                packages: Vec::new(),
-                source: Vec::new(),
+            let cached = get_cache_dir().join("metadata.json");
+            let metadata_str = path_to_str(&cached)?;
+            let metadata_urls = source.mirror_urls(&metadata_url);
+            self.breaker
+                .call(&source.id, || {
+                    net::with_fallback(&metadata_urls, |url| async move {
+                        net::download_file(
+                            &self.client,
+                            &url,
+                            metadata_str,
+                            &net::ConsoleProgress::default(),
+                        )
+                        .await
+                    })
+                })
+                .await
+                .map_err(|e| anyhow!("下载失败: {}", e))?;
+            cached
+        };
+        let metadata_content = fs::read(&metadata_path)?;
+        let metadata: PackageMetadata = serde_json::from_slice(&metadata_content)?;
+
+        // 在信任 all_files 之前校验元数据自身的哈希
+        if !metadata.verify_metadata_hash()? {
+            return Err(anyhow!(
+                "元数据自校验失败，metadata.json 可能已被篡改: {}",
+                metadata_url
+            ));
+        }
+        if metadata.id != package_info.id {
+            return Err(anyhow!(
+                "元数据中的软件包ID {} 与源索引中的 {} 不一致: {}",
+                metadata.id,
+                package_info.id,
+                metadata_url
+            ));
+        }
+        if metadata.version != install_version {
+            return Err(anyhow!(
+                "元数据版本 {} 与请求的版本 {} 不一致: {}",
+                metadata.version,
+                install_version,
+                metadata_url
+            ));
+        }
+        metadata.check_pkgr_version(env!("CARGO_PKG_VERSION"))?;
+
+        // 软件源配置了受信任公钥时验证元数据签名（元数据未携带签名时使用源索引中最新版本的签名）
+        if let Some(trusted_key) = &source.trusted_key {
+            let signature = metadata.signature.as_deref().or(package_info
+                .signature
+                .as_deref()
+                .filter(|_| package_info.latest_version == metadata.version));
+            if let Err(e) = metadata.check_signature(signature, trusted_key) {
+                return Err(anyhow!(
+                    "软件源 {} 要求签名，{}:{} {e}: {}",
+                    source.id,
+                    metadata.id,
+                    metadata.version,
+                    metadata_url
+                ));
+            }
+        }
+
+        // 创建包目录
+        let package_dir = self
+            .repo_path
+            .join("packages")
+            .join(&metadata.id)
+            .join(&metadata.version);
+
+        // 下载前检查目标路径（文件名合法性、路径长度）
+        check_destination_paths(&package_dir, metadata.all_files.keys())?;
+
+        // 校验和覆盖只能针对清单中存在的文件，避免拼写错误被静默忽略
+        for file_path in options.checksum_overrides.keys() {
+            if !metadata.all_files.contains_key(file_path) {
+                return Err(anyhow!("校验和覆盖中的文件不在软件包清单中: {}", file_path));
+            }
+        }
+
+        transaction.safe_create_dir(&package_dir)?;
+
+        // 确定每个文件的下载地址、目标路径和用于校验的哈希（校验和覆盖优先于清单）
+        let mut downloads = Vec::new();
+        for (file_path, entry) in &metadata.all_files {
+            let (manifest_hash, size) = parse_file_entry(entry);
+            let expected_hash = match options.checksum_overrides.get(file_path) {
+                Some(override_hash) => {
+                    log::warn!(
+                        "使用校验和覆盖验证 {} (清单: {}, 覆盖: {})",
+                        file_path,
+                        manifest_hash,
+                        override_hash
+                    );
+                    override_hash.clone()
+                }
+                None => manifest_hash.to_string(),
+            };
+            let file_url = join_location(
+                &version_location(package_info, &metadata.version),
+                file_path,
+            );
+
+            let dest_path = package_dir.join(file_path);
+            if let Some(parent) = dest_path.parent() {
+                transaction.safe_create_dir(parent)?;
+            }
+            transaction.safe_modify(&dest_path)?;
+
+            downloads.push(FileDownload {
+                file_path: file_path.clone(),
+                urls: source.mirror_urls(&file_url),
+                dest_path,
+                expected_hash,
+                size,
+            });
+        }
+
+        // 软件源为该版本发布了归档时整体下载并解压，归档不可用时回退到逐个下载文件
+        let bundle = package_info
+            .bundle
+            .as_ref()
+            .filter(|_| !self.is_offline() && package_info.latest_version == metadata.version);
+        let bundled_files = match bundle {
+            Some(bundle) => {
+                let bundle_url =
+                    join_location(&version_location(package_info, &metadata.version), bundle);
+                match self.install_bundle(source, &bundle_url, &downloads).await {
+                    Ok(verified_files) => Some(verified_files),
+                    Err(e) => {
+                        log::warn!("软件包归档不可用，改为逐个下载文件: {:#}", e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        // 并发下载并逐个验证所有文件，记录实际用于校验的哈希
+        let verified_files = match bundled_files {
+            Some(verified_files) => verified_files,
+            None => {
+                download_files(
+                    downloads,
+                    &self.client,
+                    &self.breaker,
+                    &source.id,
+                    self.config.max_concurrent_downloads(),
+                )
+                .await?
+            }
+        };
+
+        // 下载的文件使用默认权限，按清单恢复可执行文件等的权限
+        for file_path in metadata.all_files.keys() {
+            if let Some(mode) = metadata.get_file_mode(file_path) {
+                fsxg::set_file_mode(package_dir.join(file_path), mode)?;
+            }
+        }
+
+        // 写入已校验的 metadata.json 文件（缓存目录中下载的原始内容）
+        let stored_metadata_path = package_dir.join("metadata.json");
+        transaction.safe_modify(&stored_metadata_path)?;
+        fsxg::atomic_write(&stored_metadata_path, &metadata_content)?;
+
+        // 写入安装记录
+        let record = InstallRecord {
+            source: source.id.clone(),
+            checksum_overrides: options.checksum_overrides.clone(),
+            installed_at: Some(net::rfc3339(std::time::SystemTime::now())),
+        };
+        let record_path = package_dir.join(INSTALL_RECORD_FILE);
+        transaction.safe_modify(&record_path)?;
+        save_json(&record, &record_path)?;
+
+        // 更新版本历史
+        let history_path = self
+            .repo_path
+            .join("packages")
+            .join(&metadata.id)
+            .join("versions.txt");
+        transaction.safe_modify(&history_path)?;
+        update_version_history(&metadata.id, &metadata.version, &self.repo_path)?;
+
+        // 更新索引
+        safe_modify_index(transaction, &self.repo_path.join("index.json"))?;
+        let versions = self.version_history(&metadata.id)?;
+        self.with_index_mut(|index| {
+            update_package_index(index, &metadata, versions, None, Some(&record))
+        })?;
+
+        // 执行安装后钩子
+        if !metadata.post_install.is_empty() {
+            if options.no_hooks {
+                log::warn!(
+                    "已跳过 {} 个安装后钩子（--no-hooks）",
+                    metadata.post_install.len()
+                );
+            } else {
+                let applications_dir = dirs::data_dir()
+                    .ok_or_else(|| anyhow!("无法确定用户数据目录"))?
+                    .join("applications");
+                run_post_install_hooks(
+                    transaction,
+                    &metadata.post_install,
+                    &package_dir,
+                    source.allow_hooks,
+                    &applications_dir,
+                )
+                .with_context(|| format!("软件包 {} 的安装后钩子执行失败", metadata.id))?;
+            }
+        }
+
+        // 记录到锁文件
+        if let Some(lockfile_path) = &options.lockfile {
+            let mut lockfile = Lockfile::load(lockfile_path)?;
+            lockfile.insert(
+                &metadata.id,
+                LockedPackage {
+                    version: metadata.version.clone(),
+                    source: source.id.clone(),
+                    files: verified_files,
+                },
+            );
+            transaction.safe_modify(lockfile_path)?;
+            lockfile.save(lockfile_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// 卸载软件包
+    ///
+    /// 删除目录、改写版本历史和索引在同一事务中进行，任一步失败时全部回滚。
+    /// `dry_run` 时只返回将要执行的变更，不实际执行
+    pub fn remove_package(
+        &mut self,
+        package_id: &str,
+        version: Option<&str>,
+        dry_run: bool,
+    ) -> Result<ChangeSet> {
+        let mut changes = ChangeSet::new(dry_run);
+        let mut transaction = Transaction::begin();
+        let result = self.remove_package_in(&mut transaction, &mut changes, package_id, version);
+
+        match result {
+            Ok(()) => transaction.commit()?,
+            Err(e) => {
+                transaction.rollback()?;
+                return Err(e);
+            }
+        }
+        Ok(changes)
+    }
+
+    /// 在事务中卸载软件包，由调用方决定提交或回滚
+    fn remove_package_in(
+        &self,
+        transaction: &mut Transaction,
+        changes: &mut ChangeSet,
+        package_id: &str,
+        version: Option<&str>,
+    ) -> Result<()> {
+        let package_dir = self.repo_path.join("packages").join(package_id);
+
+        // 移除特定版本并更新版本历史，或移除整个包（包括版本历史）
+        if let Some(version) = version {
+            changes.remove_dir_in(transaction, &package_dir.join(version))?;
+            let history_path = package_dir.join("versions.txt");
+            if history_path.exists()
+                && changes.record(PlannedChange::WriteFile(history_path.clone()))
+            {
+                transaction.safe_modify(&history_path)?;
+                remove_version_from_history(package_id, version, &self.repo_path)?;
+            }
+        } else {
+            // 版本历史随包目录一起移除
+            changes.remove_dir_in(transaction, &package_dir)?;
+        }
+
+        // 更新索引
+        let target = match version {
+            Some(version) => format!("{package_id}:{version}"),
+            None => package_id.to_string(),
+        };
+        if changes.record(PlannedChange::EditIndex(format!("移除 {target}"))) {
+            safe_modify_index(transaction, &self.repo_path.join("index.json"))?;
+            self.with_index_mut(|index| remove_package_from_index(index, package_id, version))?;
+        }
+
+        Ok(())
+    }
+
+    /// 查询已安装软件包各版本的大小
+    ///
+    /// 按 versions.txt 中的顺序返回（版本号, 总大小），大小取自各版本 metadata.json
+    /// 中记录的文件大小，无需遍历文件系统；清单未记录大小的版本为 `None`
+    pub fn package_sizes(&self, package_id: &str) -> Result<Vec<(String, Option<u64>)>> {
+        let package_dir = self.repo_path.join("packages").join(package_id);
+        let versions = read_version_history(&package_dir.join("versions.txt"))?;
+        if versions.is_empty() {
+            return Err(anyhow!("未安装软件包: {}", package_id));
+        }
+
+        versions
+            .into_iter()
+            .map(|version| {
+                let metadata: PackageMetadata =
+                    load_json(&package_dir.join(&version).join("metadata.json"))?;
+                Ok((version, metadata.total_size()))
+            })
+            .collect()
+    }
+
+    /// 列出已安装软件包的文件
+    ///
+    /// 读取对应版本 metadata.json 中的文件清单；未指定版本时使用已安装的最高版本
+    pub fn package_files(&self, package_id: &str, version: Option<&str>) -> Result<InstalledFiles> {
+        let package_dir = self.repo_path.join("packages").join(package_id);
+        let versions = read_version_history(&package_dir.join("versions.txt"))?;
+        let version = match version {
+            Some(version) if versions.iter().any(|v| v == version) => version,
+            Some(version) => return Err(anyhow!("未安装版本 {}:{}", package_id, version)),
+            None => version::get_latest_semver(&versions)
+                .ok_or_else(|| anyhow!("未安装软件包: {}", package_id))?,
+        };
+
+        let metadata: PackageMetadata =
+            load_json(&package_dir.join(version).join("metadata.json"))?;
+        let mut files: Vec<InstalledFile> = metadata
+            .all_files
+            .iter()
+            .map(|(path, entry)| {
+                let (hash, size) = parse_file_entry(entry);
+                InstalledFile {
+                    path: path.clone(),
+                    hash: hash.to_string(),
+                    size,
+                }
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+
+        Ok(InstalledFiles {
+            id: package_id.to_string(),
+            version: version.to_string(),
+            files,
+        })
+    }
+
+    /// 对照锁文件检查已安装的软件包
+    ///
+    /// 逐个检查锁定的软件包是否已安装、是否安装了锁定的版本，并重新计算磁盘上文件的哈希
+    /// 与锁定值比对；返回发现的全部偏差，为空表示与锁文件一致
+    pub fn verify_lockfile(&self, lockfile: &Lockfile) -> Result<Vec<LockDrift>> {
+        let mut drifts = Vec::new();
+        for (package_id, locked) in &lockfile.packages {
+            let package_dir = self.repo_path.join("packages").join(package_id);
+            let versions = read_version_history(&package_dir.join("versions.txt"))?;
+            if versions.is_empty() {
+                drifts.push(LockDrift::MissingPackage {
+                    id: package_id.clone(),
+                    version: locked.version.clone(),
+                });
+                continue;
+            }
+            if !versions.contains(&locked.version) {
+                drifts.push(LockDrift::VersionMismatch {
+                    id: package_id.clone(),
+                    locked: locked.version.clone(),
+                    installed: versions,
+                });
+                continue;
+            }
+
+            let version_dir = package_dir.join(&locked.version);
+            for (file_path, locked_hash) in &locked.files {
+                let file = version_dir.join(file_path);
+                let actual = if file.is_file() {
+                    Some(crypto::file_hash_like(&file, locked_hash)?)
+                } else {
+                    None
+                };
+                if !actual
+                    .as_deref()
+                    .is_some_and(|actual| crypto::hash_eq(actual, locked_hash))
+                {
+                    drifts.push(LockDrift::HashMismatch {
+                        id: package_id.clone(),
+                        version: locked.version.clone(),
+                        path: file_path.clone(),
+                        locked: locked_hash.clone(),
+                        actual,
+                    });
+                }
+            }
+        }
+        Ok(drifts)
+    }
+
+    /// 校验所有已安装的软件包
+    ///
+    /// 逐个检查 versions.txt 中记录的每个版本：重新计算 `all_files` 中各文件的哈希和大小，
+    /// 并在安装来源的软件源配置了 `trusted_key` 时验证元数据签名。
+    /// 通过 `repo add` 添加的软件包没有安装记录，只校验文件
+    pub fn verify_installed(&self) -> Result<VerificationSummary> {
+        let mut verification = VerificationSummary::default();
+
+        let packages_dir = self.repo_path.join("packages");
+        let mut package_ids = Vec::new();
+        if packages_dir.is_dir() {
+            for entry in fs::read_dir(&packages_dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    package_ids.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+        package_ids.sort();
+
+        for package_id in package_ids {
+            let package_dir = packages_dir.join(&package_id);
+            for version in read_version_history(&package_dir.join("versions.txt"))? {
+                let mut result = PackageVerification::new(&package_id, &version);
+                self.verify_installed_version(&package_dir.join(&version), &mut result)?;
+                verification.push(result);
+            }
+        }
+
+        Ok(verification)
+    }
+
+    /// 校验单个已安装版本，发现的问题记入 `result.errors`
+    fn verify_installed_version(
+        &self,
+        version_dir: &Path,
+        result: &mut PackageVerification,
+    ) -> Result<()> {
+        let metadata: PackageMetadata = match load_json(&version_dir.join("metadata.json")) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                result.errors.push(format!("无法读取元数据: {e}"));
+                return Ok(());
+            }
+        };
+        let record = load_install_record(version_dir)?;
+
+        // 安装来源的软件源配置了受信任公钥时同时验证签名
+        let trusted_key = record.as_ref().and_then(|record| {
+            self.config
+                .source
+                .iter()
+                .find(|source| source.id == record.source)
+                .and_then(|source| source.trusted_key.as_deref())
+        });
+        result.check_metadata(&metadata, trusted_key)?;
+
+        let mut files: Vec<_> = metadata.all_files.iter().collect();
+        files.sort();
+        for (file_path, entry) in files {
+            // 安装时使用了校验和覆盖的文件按覆盖值校验，不再比较清单中的大小
+            let (expected_hash, expected_size) = match record
+                .as_ref()
+                .and_then(|record| record.checksum_overrides.get(file_path))
+            {
+                Some(override_hash) => (override_hash.as_str(), None),
+                None => parse_file_entry(entry),
+            };
+
+            let file = version_dir.join(file_path);
+            if !file.is_file() {
+                result.errors.push(format!("文件缺失: {file_path}"));
+                continue;
+            }
+            let actual = local_file_hash(&file, expected_hash);
+            result.check_file(file_path, expected_hash, expected_size, actual);
+        }
+
+        Ok(())
+    }
+
+    /// 升级软件包
+    ///
+    /// 软件包在配置中固定了版本时，只升级到满足固定版本（或约束）的最高版本，不会降级
+    pub async fn upgrade_package(&mut self, package_id: &str) -> Result<UpgradeOutcome> {
+        // 获取当前安装的最高版本
+        let history_path = self
+            .repo_path
+            .join("packages")
+            .join(package_id)
+            .join("versions.txt");
+
+        // 版本历史中有无法按语义化版本解析的版本时，按清单顺序取最后安装的版本
+        let versions = read_version_history(&history_path)?;
+        let current_version = if versions.iter().all(|v| version::parse(v).is_ok()) {
+            version::get_latest_semver(&versions)
+        } else {
+            version::get_latest(&versions)
+        }
+        .ok_or_else(|| anyhow!("没有安装的版本: {}", package_id))?
+        .to_string();
+
+        // 从索引中获取软件源中的最新版本和源信息
+        let index_path = self.repo_path.join("index.json");
+        let index: RepositoryIndex = load_json(&index_path)?;
+
+        let remote_pkg = index
+            .source
+            .iter()
+            .find(|p| p.id == package_id)
+            .ok_or_else(|| anyhow!("未在软件源中找到包: {}", package_id))?;
+
+        let latest_version = resolve_version(remote_pkg, "latest", version::Preference::Newest)?;
+
+        // 查找软件源 ID（使用第一个包含该包的启用源）
+        let source_id = self
+            .config
+            .source
+            .iter()
+            .find(|s| s.enabled && index.source.iter().any(|p| p.id == package_id))
+            .map(|s| s.id.clone())
+            .ok_or_else(|| anyhow!("没有找到包含 {} 的启用源", package_id))?;
+
+        // 固定了版本时以固定的版本（或满足约束的最高版本）为升级目标
+        let pin = self.config.pinned.get(package_id).cloned();
+        let target_version = match &pin {
+            Some(pin) => resolve_version(remote_pkg, pin, version::Preference::Newest)?,
+            None => latest_version.clone(),
+        };
+
+        // 仅在软件源中有更高版本时安装；版本号无法按 SemVer 解析时按版本清单中的位置比较，
+        // 清单为本地版本历史之后接上软件源中尚未安装的版本（均按从旧到新排列）
+        let history: Vec<String> = versions
+            .iter()
+            .chain(
+                candidate_versions(remote_pkg)
+                    .iter()
+                    .filter(|version| !versions.contains(version)),
+            )
+            .cloned()
+            .collect();
+        let is_newer = |candidate: &str| match version::compare_semver(candidate, &current_version)
+        {
+            Some(ordering) => ordering.is_gt(),
+            None => version::compare(candidate, &current_version, &history) > 0,
+        };
+        if is_newer(&target_version) {
+            // 安装新版本
+            self.install_package(
+                &format!("{source_id}:{package_id}"),
+                Some(&target_version),
+                &InstallOptions::default(),
+            )
+            .await?;
+            return Ok(UpgradeOutcome::Upgraded {
+                from: current_version,
+                to: target_version,
+            });
+        }
+
+        match pin {
+            Some(pin) if is_newer(&latest_version) => Ok(UpgradeOutcome::Pinned {
+                version: current_version,
+                pin,
+                latest: latest_version,
+            }),
+            _ => Ok(UpgradeOutcome::UpToDate {
+                version: current_version,
+            }),
+        }
+    }
+
+    /// 升级所有已安装的软件包
+    ///
+    /// 按包 ID 顺序逐个检查 packages 部分中同样出现在软件源索引里的软件包（遵循固定版本），
+    /// 有更高版本时升级；单个软件包升级失败时记录错误并继续处理其余软件包。
+    /// 不在任何软件源中的软件包（如通过 `repo add` 加入的）无从升级，不包含在结果中
+    pub async fn upgrade_all(&mut self) -> Result<Vec<UpgradeResult>> {
+        let index: RepositoryIndex = load_json(&self.repo_path.join("index.json"))?;
+        let mut package_ids: Vec<String> = index
+            .packages
+            .iter()
+            .filter(|package| index.source.iter().any(|p| p.id == package.id))
+            .map(|package| package.id.clone())
+            .collect();
+        package_ids.sort();
+        package_ids.dedup();
+
+        let mut results = Vec::new();
+        for id in package_ids {
+            let (outcome, error) = match self.upgrade_package(&id).await {
+                Ok(outcome) => (Some(outcome), None),
+                Err(e) => (None, Some(format!("{e:#}"))),
+            };
+            results.push(UpgradeResult { id, outcome, error });
+        }
+        Ok(results)
+    }
+
+    /// 将软件包固定到指定版本或版本约束（写入仓库配置的 `pinned`），之后升级时不超出该版本
+    pub fn pin_package(&mut self, package_id: &str, version: &str) -> Result<()> {
+        ConfigManager::new(self.repo_path.join("config.toml"))?.pin_package(package_id, version)?;
+        self.config
+            .pinned
+            .insert(package_id.to_string(), version.to_string());
+        Ok(())
+    }
+
+    /// 取消固定软件包的版本
+    pub fn unpin_package(&mut self, package_id: &str) -> Result<()> {
+        ConfigManager::new(self.repo_path.join("config.toml"))?.unpin_package(package_id)?;
+        self.config.pinned.remove(package_id);
+        Ok(())
+    }
+
+    /// 同步仓库
+    ///
+    /// 每次同步后在 sync.json 中记录该源的同步模式。
+    /// 若该源上次以镜像模式同步、本次切换为增量模式，镜像写入的文件将不再与索引对应，
+    /// 此时需要 `options.force` 才会清理这些遗留文件并继续，否则返回错误。
+    /// `options.dry_run` 时仍会获取远程索引和元数据，但只返回将要执行的变更，不写入任何内容。
+    ///
+    /// 增量同步按包 ID 和最新版本比较远程与本地的 source 部分，返回新增、更新和移除的软件包；
+    /// 内容没有任何变化时不改写索引。
+    pub async fn sync_repository(
+        &mut self,
+        source_id: &str,
+        options: &SyncOptions,
+    ) -> Result<SyncReport> {
+        let SyncOptions {
+            mirror,
+            force,
+            dry_run,
+        } = *options;
+        let mut changes = ChangeSet::new(dry_run);
+        let mut changelog = Vec::new();
+        let mut snapshot = None;
+
+        // 获取软件源配置
+        let source = self
+            .config
+            .source
+            .iter()
+            .find(|s| s.id == source_id)
+            .ok_or_else(|| anyhow!("未找到软件源: {}", source_id))?;
+        self.ensure_online(&source.url)?;
+
+        // 加载同步状态
+        let state_path = self.repo_path.join("sync.json");
+        let mut state: SyncState = if state_path.exists() {
+            load_json(&state_path)?
+        } else {
+            SyncState::default()
+        };
+
+        let mode = if mirror {
+            SyncMode::Mirror
+        } else {
+            SyncMode::Incremental
+        };
+
+        // 检查模式切换是否会遗留镜像文件
+        if let Some(previous) = state.sources.get(source_id)
+            && previous.mode == SyncMode::Mirror
+            && mode == SyncMode::Incremental
+            && !previous.mirrored_files.is_empty()
+        {
+            if !force {
+                return Err(anyhow!(
+                    "软件源 {} 上次以镜像模式同步，切换为增量模式将遗留 {} 个镜像文件；使用 --force 清理这些文件并继续",
+                    source_id,
+                    previous.mirrored_files.len()
+                ));
+            }
+
+            // 清理遗留的镜像文件，以及随之失效的版本
+            let mut transaction = Transaction::begin();
+            match self.remove_mirrored_files(
+                &mut transaction,
+                &mut changes,
+                &previous.mirrored_files,
+            ) {
+                Ok(()) => transaction.commit()?,
+                Err(e) => {
+                    transaction.rollback()?;
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut mirrored_files = Vec::new();
+
+        if mirror {
+            // 镜像同步到仓库根目录：镜像生成的 index.json 会覆盖本地索引，
+            // 完成后恢复原有的 source 部分，并重新扫描 packages/（包括本地已有的软件包）
+            let source_urls: Vec<String> = source.urls().map(String::from).collect();
+            let target_dir = self.repo_path.to_string_lossy();
+            let client = &self.client;
+            let sync = |url: String| {
+                let target_dir = &target_dir;
+                async move {
+                    net::mirror_sync(
+                        client,
+                        &url,
+                        source,
+                        target_dir,
+                        &net::MirrorOptions { dry_run },
+                    )
+                    .await
+                }
+            };
+            if dry_run {
+                let planned = net::with_fallback(&source_urls, sync)
+                    .await
+                    .map_err(|e| anyhow!("镜像同步失败: {}", e))?;
+                for file in planned {
+                    changes.record(PlannedChange::Download(PathBuf::from(file)));
+                }
+                changes.record(PlannedChange::EditIndex(
+                    "重新扫描 packages/ 更新 packages 部分".to_string(),
+                ));
+            } else {
+                let previous_source = self.with_index_mut(|index| index.source.clone())?;
+                mirrored_files = net::with_fallback(&source_urls, sync)
+                    .await
+                    .map_err(|e| anyhow!("镜像同步失败: {}", e))?;
+                for file in &mirrored_files {
+                    changes.record(PlannedChange::Download(PathBuf::from(file)));
+                }
+                // 镜像索引的 packages 部分即源索引中的软件包
+                snapshot = Some(self.with_index_mut(|index| {
+                    index.source = previous_source;
+                    index.packages.clone()
+                })?);
+                self.update_local_index()?;
+            }
+        } else {
+            // 增量同步：与本地 source 部分逐个比较
+            let index_url = format!("{}/index.json", source.url.trim_end_matches('/'));
+            let remote_index = self
+                .fetch_source_index_cached(source, &index_url, !dry_run)
+                .await?;
+            // 与 `repo update` 和 `repo changelog` 相同，取源索引的 packages 部分（源自身发布的软件包）
+            let packages: Vec<PackageInfo> =
+                serde_json::from_value::<RepositoryIndex>(remote_index)
+                    .map_err(|e| anyhow!("解析源 {} 的索引失败: {}", source.id, e))?
+                    .packages;
+            let mut remote = resolve_source_locations(source, packages.clone());
+            remote.sort_by(|a, b| a.id.cmp(&b.id));
+            snapshot = Some(packages);
+
+            // 只与本地 source 部分中属于该源的条目比较，其它源同步来的条目保持不变
+            let index_path = self.repo_path.join("index.json");
+            let mut local = if index_path.exists() {
+                load_json::<RepositoryIndex>(&index_path)?.source
+            } else {
+                Vec::new()
+            };
+            local.retain(|package| source_owns(source, &package.location));
+            local.sort_by(|a, b| a.id.cmp(&b.id));
+            changelog = diff_source(&local, &remote);
+
+            // 版本未变但其它字段（如位置、描述）有更新时同样写入
+            if serde_json::to_value(&local)? != serde_json::to_value(&remote)? {
+                let edit = format!("source 部分{}", changelog_summary(&changelog));
+                if changes.record(PlannedChange::EditIndex(edit)) {
+                    self.with_index_mut(|index| {
+                        let remote_ids: HashSet<&str> =
+                            remote.iter().map(|package| package.id.as_str()).collect();
+                        index.source.retain(|package| {
+                            !source_owns(source, &package.location)
+                                && !remote_ids.contains(package.id.as_str())
+                        });
+                        index.source.extend(remote);
+                        index.source.sort_by(|a, b| a.id.cmp(&b.id));
+                    })?;
+                }
+            }
+        }
+
+        // 记录本次同步模式
+        if changes.record(PlannedChange::WriteFile(state_path.clone())) {
+            state.sources.insert(
+                source_id.to_string(),
+                SourceSyncState {
+                    mode,
+                    mirrored_files,
+                },
+            );
+            save_json(&state, &state_path)?;
+        }
+
+        // 保存本次同步时源索引的快照
+        if let Some(packages) = snapshot
+            && changes.record(PlannedChange::WriteFile(self.sync_snapshot_path(source_id)))
+        {
+            self.save_sync_snapshot(source_id, packages)?;
+        }
+
+        Ok(SyncReport { changes, changelog })
+    }
+
+    /// 在事务中清理镜像同步写入的文件
+    ///
+    /// 镜像文件清理后不再有其它文件的版本目录随之删除，并从 versions.txt 和索引的 packages 部分中移除；
+    /// 软件包已没有任何版本时删除整个软件包目录和索引条目。
+    /// 版本目录中还有镜像以外的文件时保留该版本
+    fn remove_mirrored_files(
+        &self,
+        transaction: &mut Transaction,
+        changes: &mut ChangeSet,
+        files: &[String],
+    ) -> Result<()> {
+        let packages_dir = self.repo_path.join("packages");
+        let files: HashSet<PathBuf> = files.iter().map(PathBuf::from).collect();
+
+        // 镜像文件位于 packages/<id>/<version>/ 下
+        let mut versions = BTreeMap::<String, Vec<String>>::new();
+        for file in &files {
+            changes
+                .remove_file_in(transaction, file)
+                .with_context(|| format!("无法清理镜像文件: {}", file.display()))?;
+            let Ok(relative) = file.strip_prefix(&packages_dir) else {
+                continue;
+            };
+            let mut components = relative.components();
+            if let (Some(id), Some(version), Some(_)) =
+                (components.next(), components.next(), components.next())
+            {
+                let id = id.as_os_str().to_string_lossy().to_string();
+                let version = version.as_os_str().to_string_lossy().to_string();
+                let entry = versions.entry(id).or_default();
+                if !entry.contains(&version) {
+                    entry.push(version);
+                }
+            }
+        }
+
+        let mut pruned = Vec::new();
+        for (package_id, versions) in versions {
+            let package_dir = packages_dir.join(&package_id);
+            let history_path = package_dir.join("versions.txt");
+            let mut history = read_version_history(&history_path)?;
+            for version in versions {
+                let version_dir = package_dir.join(&version);
+                let has_other_files = walkdir::WalkDir::new(&version_dir)
+                    .into_iter()
+                    .filter_map(|entry| entry.ok())
+                    .any(|entry| entry.file_type().is_file() && !files.contains(entry.path()));
+                if has_other_files {
+                    continue;
+                }
+
+                changes.remove_dir_in(transaction, &version_dir)?;
+                if history.contains(&version)
+                    && changes.record(PlannedChange::WriteFile(history_path.clone()))
+                {
+                    transaction.safe_modify(&history_path)?;
+                    remove_version_from_history(&package_id, &version, &self.repo_path)?;
+                }
+                history.retain(|existing| existing != &version);
+            }
+
+            // 软件包已没有任何版本
+            if history.is_empty() {
+                changes.remove_dir_in(transaction, &package_dir)?;
+            }
+            pruned.push((package_id, history));
+        }
+
+        if !pruned.is_empty()
+            && changes.record(PlannedChange::EditIndex(
+                "移除随镜像文件清理的版本".to_string(),
+            ))
+        {
+            safe_modify_index(transaction, &self.repo_path.join("index.json"))?;
+            self.with_index_mut(|index| {
+                for (package_id, history) in &pruned {
+                    if history.is_empty() {
+                        index.packages.retain(|package| &package.id != package_id);
+                    } else if let Some(package) = index
+                        .packages
+                        .iter_mut()
+                        .find(|package| &package.id == package_id)
+                    {
+                        package
+                            .available_versions
+                            .retain(|version| history.contains(version));
+                        if let Some(latest) = version::get_latest_semver(history) {
+                            package.latest_version = latest.to_string();
+                        }
+                    }
+                }
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// 获取生效的仓库配置（已填充默认值）
+    pub fn config(&self) -> &RepositoryConfig {
+        &self.config
+    }
+
+    /// 检查所有启用的软件源
+    ///
+    /// 对每个启用的源以较短的超时获取索引，报告可达性、HTTP 状态、往返延迟、索引能否解析，
+    /// 以及源启用了索引校验时能否通过与 `repo update` 相同的校验（见 [`net::verify_source_index`]）。
+    /// 单个源失败不会中断检查。
+    pub async fn check_sources(&self) -> Vec<SourceHealth> {
+        let mut results = Vec::new();
+
+        for source in self.config.source.iter().filter(|s| s.enabled) {
+            let mut health = SourceHealth {
+                id: source.id.clone(),
+                url: source.url.clone(),
+                reachable: false,
+                status: None,
+                latency_ms: None,
+                index_valid: false,
+                index_verified: None,
+                error: None,
+            };
+
+            let index_url = format!("{}/index.json", source.url.trim_end_matches('/'));
+            let body = if let Err(e) = self.ensure_online(&index_url) {
+                Err(e.to_string())
+            } else if let Some(index_path) = net::local_path(&index_url) {
+                // 本地源：直接读取索引文件
+                let started = std::time::Instant::now();
+                let body = fs::read_to_string(index_path);
+                health.latency_ms = Some(started.elapsed().as_millis() as u64);
+                body.map_err(|e| e.to_string())
+            } else {
+                match net::probe(&self.client, &index_url, HEALTH_CHECK_TIMEOUT).await {
+                    Ok(probe) => {
+                        health.status = Some(probe.status);
+                        health.latency_ms = Some(probe.latency.as_millis() as u64);
+                        if (200..300).contains(&probe.status) {
+                            Ok(probe.body)
+                        } else {
+                            Err(format!("HTTP请求失败: {}", probe.status))
+                        }
+                    }
+                    Err(e) => Err(e.to_string()),
+                }
+            };
+
+            match body {
+                Ok(body) => {
+                    health.reachable = true;
+                    match serde_json::from_str::<RepositoryIndex>(&body) {
+                        Ok(_) => health.index_valid = true,
+                        Err(e) => health.error = Some(format!("索引解析失败: {e}")),
+                    }
+                    if source.verifies_index() {
+                        let verified =
+                            net::verify_source_index(&self.client, &index_url, source, &body)
+                                .await
+                                .map_err(|e| format!("索引校验失败: {e}"));
+                        health.index_verified = Some(verified.is_ok());
+                        if let Err(e) = verified {
+                            health.error.get_or_insert(e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    health.reachable = health.status.is_some();
+                    health.error = Some(e);
+                }
+            }
+
+            results.push(health);
+        }
+
+        results
+    }
+
+    /// 校验软件源发布的全部软件包
+    ///
+    /// 只读审计：获取源索引，对其中每个软件包获取 metadata.json，并逐一下载清单中的文件核对哈希与大小。
+    /// 文件内容边下载边计算哈希后即丢弃，不在本地保存任何内容；单个软件包失败不会中断校验。
+    pub async fn verify_source(&self, source_id: &str) -> Result<SourceVerification> {
+        let source = self
+            .config
+            .source
+            .iter()
+            .find(|s| s.id == source_id)
+            .ok_or_else(|| anyhow!("未找到软件源: {}", source_id))?;
+        let source_url = source.url.trim_end_matches('/');
+        self.ensure_online(source_url)?;
+
+        let index: RepositoryIndex = serde_json::from_value(
+            fetch_json(&self.client, &format!("{source_url}/index.json")).await?,
+        )
+        .with_context(|| format!("解析源 {} 的索引失败", source.id))?;
+
+        let mut verification = SourceVerification {
+            source: source.id.clone(),
+            summary: VerificationSummary::default(),
+        };
+
+        for package in &index.packages {
+            let mut result = PackageVerification::new(&package.id, &package.latest_version);
+
+            // location 为 ./ 开头的相对路径时相对于源的根目录
+            let location = match package.location.strip_prefix("./") {
+                Some(relative) => format!("{source_url}/{relative}"),
+                None => package.location.clone(),
+            };
+            let location = location.trim_end_matches('/');
+
+            match fetch_json(&self.client, &format!("{location}/metadata.json"))
+                .await
+                .and_then(|value| Ok(serde_json::from_value::<PackageMetadata>(value)?))
+            {
+                Ok(metadata) => {
+                    result.check_metadata(&metadata, None)?;
+                    if metadata.version != package.latest_version {
+                        result.errors.push(format!(
+                            "元数据版本 {} 与索引中的版本 {} 不一致",
+                            metadata.version, package.latest_version
+                        ));
+                    }
+
+                    let mut files: Vec<_> = metadata.all_files.iter().collect();
+                    files.sort();
+                    for (file_path, entry) in files {
+                        let (expected_hash, expected_size) = parse_file_entry(entry);
+                        let actual = hash_location(
+                            &self.client,
+                            &format!("{location}/{file_path}"),
+                            expected_hash,
+                        )
+                        .await;
+                        result.check_file(file_path, expected_hash, expected_size, actual);
+                    }
+                }
+                Err(e) => result.errors.push(format!("无法获取元数据: {e}")),
+            }
+            verification.summary.push(result);
+        }
+
+        Ok(verification)
+    }
+
+    /// 读取软件包的版本历史（versions.txt），不存在时为空
+    fn version_history(&self, package_id: &str) -> Result<Vec<String>> {
+        read_version_history(
+            &self
+                .repo_path
+                .join("packages")
+                .join(package_id)
+                .join("versions.txt"),
+        )
+    }
+
+    /// 更新本地索引
+    ///
+    /// 扫描 packages/ 目录下的所有已安装包，并更新 index.json 文件中的 packages 部分
+    pub fn update_local_index(&mut self) -> Result<()> {
+        // 重新扫描 packages 部分
+        let packages = self.scan_packages()?;
+        self.with_index_mut(|index| index.packages = packages)?;
+
+        Ok(())
+    }
+
+    /// 从磁盘重建索引
+    ///
+    /// 用于 index.json 丢失或损坏后的恢复：
+    /// 1. 为缺少 versions.txt 的软件包根据版本目录重建版本历史
+    /// 2. 扫描 packages/ 重建 packages 部分（现有索引无法解析时，source 部分置空）
+    /// 3. 若 `with_source` 为真，重新从软件源获取 source 部分
+    ///
+    /// # 返回值
+    ///
+    /// 返回重建了 versions.txt 的软件包ID列表
+    pub async fn reindex(&mut self, with_source: bool) -> Result<Vec<String>> {
+        let index_path = self.repo_path.join("index.json");
+
+        // 重建缺失的版本历史
+        let mut rebuilt = Vec::new();
+        let packages_dir = self.repo_path.join("packages");
+        if packages_dir.is_dir() {
+            for entry in fs::read_dir(&packages_dir)? {
+                let entry = entry?;
+                if !entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let package_dir = entry.path();
+                let history_path = package_dir.join("versions.txt");
+                if history_path.exists() {
+                    continue;
+                }
+
+                let mut versions = Vec::new();
+                for version_entry in fs::read_dir(&package_dir)? {
+                    let version_entry = version_entry?;
+                    if version_entry.file_type()?.is_dir()
+                        && version_entry.path().join("metadata.json").is_file()
+                    {
+                        versions
+                            .push(path_to_str(Path::new(&version_entry.file_name()))?.to_string());
+                    }
+                }
+                if versions.is_empty() {
+                    continue;
+                }
+                versions.sort_by(|a, b| version::semver_ordering(a, b));
+                fs::write(&history_path, versions.join("\n"))?;
+                rebuilt.push(path_to_str(Path::new(&entry.file_name()))?.to_string());
+            }
+        }
+        rebuilt.sort();
+
+        // 索引可能已损坏，不经 with_index_mut 读取，直接在锁内覆盖写入
+        let lock = self.write_lock()?;
+
+        // 保留现有索引中仍可解析的 source 部分
+        let source = match load_json::<RepositoryIndex>(&index_path) {
+            Ok(index) if !with_source => index.source,
+            _ => Vec::new(),
+        };
+        let mut index = RepositoryIndex {
+            packages: self.scan_packages()?,
+            source,
+        };
+        save_index(&mut index, &index_path)?;
+        drop(lock);
+
+        // 重新获取 source 部分
+        if with_source {
+            self.update_source_index().await?;
+        }
+
+        Ok(rebuilt)
+    }
+
+    /// 检查索引与磁盘是否一致
+    ///
+    /// 对照 packages/ 目录和各软件包的 versions.txt 检查索引的 packages 部分：
+    /// 索引中有但磁盘上没有的软件包、磁盘上有但索引中没有的软件包、与磁盘上最新版本不一致的条目，
+    /// 以及 versions.txt 中没有对应目录的版本。元数据无法读取的版本视为不可用，不会中止检查。
+    ///
+    /// `fix` 为真时从 versions.txt 中移除没有对应目录的版本，并按磁盘上实际可用的版本重新生成
+    /// packages 部分（不影响 source 部分）；检查和修复期间持有仓库写锁，修复依据的是加锁后读取的状态
+    ///
+    /// # 返回值
+    ///
+    /// 返回发现的问题（修复时为修复前发现的问题）
+    pub fn doctor(&self, fix: bool) -> Result<Vec<IndexProblem>> {
+        // 修复时在检查前加锁，修复期间不再经 with_index_mut 重复加锁
+        let _lock = if fix { Some(self.write_lock()?) } else { None };
+        let mut problems = Vec::new();
+        let mut actual = Vec::new();
+        let mut pruned_histories = Vec::new();
+
+        let packages_dir = self.repo_path.join("packages");
+        if packages_dir.is_dir() {
+            let mut entries = fs::read_dir(&packages_dir)?.collect::<std::io::Result<Vec<_>>>()?;
+            entries.sort_by_key(|entry| entry.file_name());
+            for entry in entries {
+                if !entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let package_dir = entry.path();
+                let package_id = entry.file_name().to_string_lossy().to_string();
+                let history_path = package_dir.join("versions.txt");
+                let history = read_version_history(&history_path)?;
+
+                // 只保留有目录和元数据的版本
+                let mut versions = Vec::new();
+                for version in &history {
+                    let metadata_path = package_dir.join(version).join("metadata.json");
+                    if metadata_path.is_file() {
+                        versions.push(version.clone());
+                    } else {
+                        problems.push(IndexProblem::MissingVersion {
+                            id: package_id.clone(),
+                            version: version.clone(),
+                        });
+                    }
+                }
+                if versions.len() != history.len() {
+                    pruned_histories.push((history_path, versions.clone()));
+                }
+
+                // 从最高版本开始，取第一个元数据可读的版本
+                let mut candidates = versions.clone();
+                candidates.sort_by(|a, b| version::semver_ordering(b, a));
+                for version in candidates {
+                    let version_dir = package_dir.join(&version);
+                    match load_json::<PackageMetadata>(&version_dir.join("metadata.json")) {
+                        Ok(metadata) => {
+                            actual.push(installed_package_info(&metadata, &version_dir, versions));
+                            break;
+                        }
+                        Err(e) => problems.push(IndexProblem::BadMetadata {
+                            id: package_id.clone(),
+                            version,
+                            error: format!("{e:#}"),
+                        }),
+                    }
+                }
+            }
+        }
+
+        // 对照索引的 packages 部分
+        let index_path = self.repo_path.join("index.json");
+        let mut index: RepositoryIndex = if index_path.exists() {
+            load_json(&index_path).context("无法解析 index.json，请使用 repo reindex 重建索引")?
+        } else {
+            RepositoryIndex::default()
+        };
+        for indexed in &index.packages {
+            if !actual.iter().any(|package| package.id == indexed.id) {
+                problems.push(IndexProblem::Dangling {
+                    id: indexed.id.clone(),
+                });
+            }
+        }
+        for package in &actual {
+            match index
+                .packages
+                .iter()
+                .find(|indexed| indexed.id == package.id)
+            {
+                None => problems.push(IndexProblem::Unindexed {
+                    id: package.id.clone(),
+                    version: package.latest_version.clone(),
+                }),
+                Some(indexed) if indexed.latest_version != package.latest_version => {
+                    problems.push(IndexProblem::Outdated {
+                        id: package.id.clone(),
+                        indexed: indexed.latest_version.clone(),
+                        actual: package.latest_version.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+
+        if fix && !problems.is_empty() {
+            for (history_path, versions) in pruned_histories {
+                if versions.is_empty() {
+                    fs::remove_file(&history_path)?;
+                } else {
+                    fs::write(&history_path, versions.join("\n"))?;
+                }
+            }
+            index.packages = actual;
+            save_index(&mut index, &index_path)?;
+        }
+
+        Ok(problems)
+    }
+
+    /// 导出仓库为 tar.gz 归档
+    ///
+    /// 归档包含 packages/ 下的所有文件、index.json 以及顶层的 `MANIFEST.json`（每个文件的哈希和大小）。
+    /// 默认不导出索引的 source 部分，`include_source` 为真时一并导出。
+    /// 打开仓库时获取的锁（共享锁即可）使导出期间没有其他写入者，条目顺序和元数据固定，相同内容产生相同的归档。
+    ///
+    /// # 返回值
+    ///
+    /// 返回归档中的文件数（不含清单）
+    pub fn export(&self, output: &Path, include_source: bool) -> Result<usize> {
+        let mut entries = BTreeMap::new();
+
+        // 收集 packages/ 下的文件
+        let packages_dir = self.repo_path.join("packages");
+        if packages_dir.is_dir() {
+            for file in fsxg::get_directory_files(&packages_dir, true)? {
+                let relative_path = file
+                    .strip_prefix(&self.repo_path)
+                    .with_context(|| "无法计算相对于仓库的路径")?;
+                entries.insert(to_manifest_path(relative_path)?, ArchiveSource::File(file));
+            }
+        }
+
+        // 导出索引
+        let index_path = self.repo_path.join("index.json");
+        let mut index: RepositoryIndex = if index_path.exists() {
+            load_json(&index_path)?
+        } else {
+            RepositoryIndex::default()
+        };
+        if !include_source {
+            index.source.clear();
+        }
+        index.packages.sort_by(|a, b| a.id.cmp(&b.id));
+        index.source.sort_by(|a, b| a.id.cmp(&b.id));
+        entries.insert(
+            "index.json".to_string(),
+            ArchiveSource::Bytes(serde_json::to_vec_pretty(&index)?),
+        );
+
+        let manifest = archive::create(output, &entries)?;
+        Ok(manifest.files.len())
+    }
+
+    /// 从 tar.gz 归档导入软件包
+    ///
+    /// 先将归档流式解压到仓库内的暂存目录并逐个按 `MANIFEST.json` 校验，
+    /// 遇到第一个不匹配的文件即中止并丢弃暂存目录，不修改仓库。
+    /// 校验通过后移入仓库中尚不存在的版本、合并版本历史并更新本地索引；
+    /// 归档包含 source 部分时替换本地索引的 source 部分。
+    ///
+    /// # 返回值
+    ///
+    /// 返回导入的 `软件包ID:版本` 列表（已存在的版本会被跳过）
+    pub fn import(&mut self, archive_path: &Path) -> Result<Vec<String>> {
+        let staging = tempfile::TempDir::new_in(&self.repo_path)
+            .with_context(|| format!("无法创建暂存目录: {}", self.repo_path.display()))?;
+        archive::extract_verified(archive_path, staging.path())?;
+
+        let mut imported = Vec::new();
+        let staged_packages = staging.path().join("packages");
+        if staged_packages.is_dir() {
+            for entry in fs::read_dir(&staged_packages)? {
+                let entry = entry?;
+                if !entry.file_type()?.is_dir() {
+                    continue;
+                }
+                let package_id = path_to_str(Path::new(&entry.file_name()))?.to_string();
+                let target_dir = self.repo_path.join("packages").join(&package_id);
+
+                // 按归档中的版本历史顺序导入
+                let versions = read_version_history(&entry.path().join("versions.txt"))?;
+                for version in versions {
+                    let staged_version = entry.path().join(&version);
+                    let target_version = target_dir.join(&version);
+                    if !staged_version.is_dir() || target_version.exists() {
+                        continue;
+                    }
+                    fsxg::create_directory(&target_dir)?;
+                    fs::rename(&staged_version, &target_version).with_context(|| {
+                        format!("无法移动软件包目录: {}", target_version.display())
+                    })?;
+                    update_version_history(&package_id, &version, &self.repo_path)?;
+                    imported.push(format!("{package_id}:{version}"));
+                }
+            }
+        }
+
+        // 合并索引
+        let staged_index: RepositoryIndex = load_json(&staging.path().join("index.json"))?;
+        if !staged_index.source.is_empty() {
+            self.with_index_mut(|index| index.source = staged_index.source)?;
+        }
+        self.update_local_index()?;
+
+        Ok(imported)
+    }
+
+    /// 扫描 packages/ 目录，读取每个软件包最新版本的元数据
+    fn scan_packages(&self) -> Result<Vec<PackageInfo>> {
+        let mut packages = Vec::new();
+
+        // 扫描 packages/ 目录
+        let packages_dir = self.repo_path.join("packages");
+        if packages_dir.exists() && packages_dir.is_dir() {
+            for entry in fs::read_dir(packages_dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    let package_dir = entry.path();
+                    let package_name = entry.file_name().to_string_lossy().to_string();
+
+                    // 获取最高版本的目录
+                    let versions = read_version_history(&package_dir.join("versions.txt"))?;
+                    if let Some(latest_version) = version::get_latest_semver(&versions) {
+                        let version_dir = package_dir.join(latest_version);
+                        if version_dir.exists() && version_dir.is_dir() {
+                            // 读取 metadata.json
+                            let metadata_path = version_dir.join("metadata.json");
+                            if metadata_path.exists() {
+                                let metadata: PackageMetadata = load_json(&metadata_path)?;
+
+                                // 添加到索引
+                                packages.push(installed_package_info(
+                                    &metadata,
+                                    &version_dir,
+                                    versions.clone(),
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(packages)
+    }
+}
+
+/// 根据已安装版本的元数据创建索引 packages 部分的条目
+fn installed_package_info(
+    metadata: &PackageMetadata,
+    version_dir: &Path,
+    available_versions: Vec<String>,
+) -> PackageInfo {
+    let record = load_install_record(version_dir).ok().flatten();
+    PackageInfo {
+        id: metadata.id.clone(),
+        name: metadata.name.clone(),
+        icon: metadata.icon.clone(),
+        author: metadata.author.clone(),
+        latest_version: metadata.version.clone(),
+        description: metadata.description.clone(),
+        location: version_dir.to_string_lossy().to_string(),
+        available_versions,
+        size: metadata.total_size(),
+        signature: metadata.signature.clone(),
+        bundle: published_bundle(version_dir, metadata),
+        installed_at: record
+            .as_ref()
+            .and_then(|record| record.installed_at.clone()),
+        installed_from: record.map(|record| record.source),
+    }
+}
+
+/// 版本目录中已发布的软件包归档文件名，没有归档时返回 `None`
+fn published_bundle(version_dir: &Path, metadata: &PackageMetadata) -> Option<String> {
+    Some(archive::bundle_file_name(&metadata.id, &metadata.version))
+        .filter(|bundle| version_dir.join(bundle).is_file())
+}
+
+/// 读取 JSON 文件（本地路径）或通过网络获取（HTTP/HTTPS URL）
+async fn fetch_json(client: &net::Client, location: &str) -> Result<serde_json::Value> {
+    if is_remote(location) {
+        net::fetch_index(client, location)
+            .await
+            .map_err(|e| anyhow!("获取 {} 失败: {}", location, e))
+    } else {
+        let path = net::local_path(location).unwrap_or_else(|| PathBuf::from(location));
+        load_json(&path).with_context(|| format!("无法读取 {location}"))
+    }
+}
+
+/// 按期望哈希值的算法计算本地文件或远程文件的哈希值与大小，远程文件流式下载后丢弃
+async fn hash_location(
+    client: &net::Client,
+    location: &str,
+    expected_hash: &str,
+) -> Result<(String, u64)> {
+    if is_remote(location) {
+        let (algorithm, _) = HashAlgorithm::split(expected_hash)?;
+        net::download_hash(client, location, algorithm)
+            .await
+            .map_err(|e| anyhow!("下载失败: {}", e))
+    } else {
+        local_file_hash(
+            &net::local_path(location).unwrap_or_else(|| PathBuf::from(location)),
+            expected_hash,
+        )
+    }
+}
+
+/// 按期望哈希值的算法计算本地文件的哈希值与大小
+fn local_file_hash(path: &Path, expected_hash: &str) -> Result<(String, u64)> {
+    let size = fs::metadata(path)
+        .with_context(|| format!("无法读取文件: {}", path.display()))?
+        .len();
+    Ok((crypto::file_hash_like(path, expected_hash)?, size))
+}
+
+/// 安装时待下载的单个文件
+struct FileDownload {
+    /// 清单中的相对路径
+    file_path: String,
+    /// 下载地址（主 URL 在前，其后为各镜像中的相同路径）
+    urls: Vec<String>,
+    /// 本地目标路径
+    dest_path: PathBuf,
+    /// 用于校验的哈希值（可带算法前缀）
+    expected_hash: String,
+    /// 清单中记录的文件大小（未记录时为空）
+    size: Option<u64>,
+}
+
+/// 安装时所有文件的总体下载进度
+///
+/// 总大小取自文件清单中记录的大小，未记录大小的文件在收到响应时按内容长度计入；
+/// 仅在标准错误为终端时输出，多个文件并发下载时显示同一个百分比
+struct InstallProgress {
+    /// 总字节数
+    total: AtomicU64,
+    /// 已下载的字节数
+    downloaded: AtomicU64,
+    /// 上次输出的百分比，尚未输出时为 `u64::MAX`
+    shown: AtomicU64,
+    /// 是否输出进度
+    enabled: bool,
+}
+
+impl InstallProgress {
+    fn new(total: u64) -> Self {
+        Self {
+            total: AtomicU64::new(total),
+            downloaded: AtomicU64::new(0),
+            shown: AtomicU64::new(u64::MAX),
+            enabled: std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// 百分比变化时刷新进度行（续传或重试可能重复计入部分数据，百分比不超过 100）
+    fn report(&self) {
+        let total = self.total.load(Ordering::Relaxed);
+        if !self.enabled || total == 0 {
+            return;
+        }
+        let downloaded = self.downloaded.load(Ordering::Relaxed).min(total);
+        let percent = downloaded * 100 / total;
+        if self.shown.swap(percent, Ordering::Relaxed) != percent {
+            eprint!(
+                "\r下载进度: {percent}% ({} / {})",
+                style::format_bytes(downloaded),
+                style::format_bytes(total)
+            );
+        }
+    }
+
+    /// 结束进度显示
+    fn finish(&self) {
+        if self.shown.load(Ordering::Relaxed) != u64::MAX {
+            eprintln!();
+        }
+    }
+}
+
+/// 单个文件的下载进度，汇总到 [`InstallProgress`]
+struct FileProgress {
+    overall: Arc<InstallProgress>,
+    /// 文件大小已计入总大小（清单中记录了大小，或已按内容长度计入过）
+    counted: AtomicBool,
+}
+
+impl net::DownloadProgress for FileProgress {
+    fn begin(&self, size: Option<u64>) {
+        if let Some(size) = size
+            && !self.counted.swap(true, Ordering::Relaxed)
+        {
+            self.overall.total.fetch_add(size, Ordering::Relaxed);
+        }
+    }
+
+    fn advance(&self, bytes: u64) {
+        self.overall.downloaded.fetch_add(bytes, Ordering::Relaxed);
+        self.overall.report();
+    }
+}
+
+/// 以不超过 `limit` 的并发数下载文件，每个文件下载完成后立即校验哈希
+///
+/// 返回各文件实际用于校验的哈希；任一文件下载失败或哈希不匹配时，
+/// 取消其余尚未完成的下载并返回该文件的错误。`client` 处于离线模式时不发出请求，
+/// 只校验目标路径上已有的文件，缺少文件时报错
+async fn download_files(
+    downloads: Vec<FileDownload>,
+    client: &net::Client,
+    breaker: &Arc<net::CircuitBreaker>,
+    source_id: &str,
+    limit: usize,
+) -> Result<BTreeMap<String, String>> {
+    let progress = Arc::new(InstallProgress::new(
+        downloads.iter().filter_map(|download| download.size).sum(),
+    ));
+    let result = run_downloads(downloads, client, breaker, source_id, limit, &progress).await;
+    progress.finish();
+    result
+}
+
+/// 执行 [`download_files`] 的下载任务，各文件的进度汇总到 `progress`
+async fn run_downloads(
+    downloads: Vec<FileDownload>,
+    client: &net::Client,
+    breaker: &Arc<net::CircuitBreaker>,
+    source_id: &str,
+    limit: usize,
+    progress: &Arc<InstallProgress>,
+) -> Result<BTreeMap<String, String>> {
+    let mut pending = downloads.into_iter();
+    let mut tasks = tokio::task::JoinSet::new();
+    let mut verified_files = BTreeMap::new();
+
+    loop {
+        // 补足正在进行的下载任务
+        while tasks.len() < limit {
+            let Some(download) = pending.next() else {
+                break;
+            };
+            let client = client.clone();
+            let breaker = Arc::clone(breaker);
+            let source_id = source_id.to_string();
+            let file_progress = FileProgress {
+                overall: Arc::clone(progress),
+                counted: AtomicBool::new(download.size.is_some()),
+            };
+            tasks.spawn(async move {
+                let dest_str = path_to_str(&download.dest_path)?;
+                let client = &client;
+                let file_progress = &file_progress;
+                let fetch = || async {
+                    breaker
+                        .call(&source_id, || {
+                            net::with_fallback(&download.urls, |url| async move {
+                                // 重试时在上次中断的位置续传
+                                net::with_retry(client.retry_policy(), || {
+                                    net::download_file_resumable(
+                                        client,
+                                        &url,
+                                        dest_str,
+                                        file_progress,
+                                    )
+                                })
+                                .await
+                            })
+                        })
+                        .await
+                        .map_err(|e| anyhow!("下载失败: {}: {}", download.file_path, e))
+                };
+
+                let resumed = if client.is_offline() {
+                    if !download.dest_path.is_file() {
+                        return Err(anyhow!(
+                            "离线模式已启用，本地缺少文件: {}",
+                            download.file_path
+                        ));
+                    }
+                    false
+                } else {
+                    log::debug!(
+                        "下载文件: {} -> {}",
+                        download.urls[0],
+                        download.dest_path.display()
+                    );
+                    fetch().await?
+                };
+                let mut actual_hash = crypto::file_hash_like(dest_str, &download.expected_hash)?;
+                if resumed && !crypto::hash_eq(&actual_hash, &download.expected_hash) {
+                    // 续传前已有的部分内容损坏，删除后从头下载
+                    log::warn!("续传的文件 {} 校验失败，重新完整下载", download.file_path);
+                    fs::remove_file(&download.dest_path)?;
+                    fetch().await?;
+                    actual_hash = crypto::file_hash_like(dest_str, &download.expected_hash)?;
+                }
+                if !crypto::hash_eq(&actual_hash, &download.expected_hash) {
+                    return Err(anyhow!(
+                        "文件哈希不匹配: {} (预期: {}, 实际: {})",
+                        download.file_path,
+                        download.expected_hash,
+                        actual_hash
+                    ));
+                }
+                Ok((download.file_path, actual_hash))
+            });
+        }
+
+        let Some(joined) = tasks.join_next().await else {
+            break;
+        };
+        match joined.map_err(|e| anyhow!("下载任务异常终止: {}", e))? {
+            Ok((file_path, actual_hash)) => {
+                verified_files.insert(file_path, actual_hash);
+            }
+            Err(e) => {
+                let cancelled = tasks.len() + pending.len();
+                tasks.abort_all();
+                // 等待被取消的任务结束，避免调用方回滚时仍有任务在写入文件
+                while tasks.join_next().await.is_some() {}
+                if cancelled > 0 {
+                    log::warn!("已取消其余 {} 个文件的下载", cancelled);
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(verified_files)
+}
+
+/// 是否为远程地址
+fn is_remote(location: &str) -> bool {
+    location.starts_with("http://") || location.starts_with("https://")
+}
+
+/// 按仓库配置构建 HTTP 客户端（网络设置、各软件源的 TLS 设置和重试策略）
+fn build_client(config: &RepositoryConfig) -> Result<net::Client> {
+    let retry = net::RetryPolicy {
+        max_retries: config.max_retries(),
+        base_delay: config.retry_base_delay(),
+    };
+    net::Client::new(&config.network, &config.source, retry)
+        .map_err(|e| anyhow!("无法初始化 HTTP 客户端: {}", e))
+}
+
+/// 由 [`RepoManager::write_lock`] 临时升级的仓库写锁，被丢弃时降回共享锁
+struct WriteLock<'a> {
+    /// 需要降级的锁文件（仓库以独占模式打开时为 `None`）
+    lock: Option<&'a fs::File>,
+}
+
+impl Drop for WriteLock<'_> {
+    fn drop(&mut self) {
+        if let Some(lock) = self.lock
+            && let Err(e) = lock.unlock().and_then(|()| lock.lock_shared())
+        {
+            log::warn!("无法恢复仓库共享锁: {e}");
+        }
+    }
+}
+
+/// 获取仓库锁
+///
+/// 在 .pkgr.lock 上加共享锁或独占锁；`wait` 为 false 时锁已被占用则立即报错而不是阻塞
+fn acquire_repo_lock(repo_path: &Path, mode: LockMode, wait: bool) -> Result<fs::File> {
+    let lock_path = repo_path.join(LOCK_FILE);
+    let file = open_lock_file(&lock_path)?;
+    let result = match (mode, wait) {
+        (LockMode::Shared, true) => file.lock_shared().map_err(fs::TryLockError::Error),
+        (LockMode::Exclusive, true) => file.lock().map_err(fs::TryLockError::Error),
+        (LockMode::Shared, false) => file.try_lock_shared(),
+        (LockMode::Exclusive, false) => file.try_lock(),
+    };
+    result.map(|()| file).map_err(|e| lock_error(repo_path, e))
+}
+
+/// 加锁失败时的错误：锁被其他进程占用时提示可使用 `--wait`
+fn lock_error(repo_path: &Path, error: fs::TryLockError) -> anyhow::Error {
+    match error {
+        fs::TryLockError::WouldBlock => anyhow!(
+            "仓库正被另一个进程使用: {}（可使用 --wait 等待其完成）",
+            repo_path.display()
+        ),
+        fs::TryLockError::Error(e) => anyhow::Error::new(e).context(format!(
+            "无法锁定仓库: {}",
+            repo_path.join(LOCK_FILE).display()
+        )),
+    }
+}
+
+/// 打开（必要时创建）锁文件，不截断已有内容
+fn open_lock_file(lock_path: &Path) -> Result<fs::File> {
+    fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(lock_path)
+        .with_context(|| format!("无法打开仓库锁文件: {}", lock_path.display()))
+}
+
+/// 保存索引
+///
+/// 写入前按包 ID 对 packages 和 source 排序，保证相同内容产生相同的 index.json。
+/// 同时写入 index.json.sha256 供客户端校验；原有的 index.json.sig 不再与新内容相符，一并删除
+fn save_index(index: &mut RepositoryIndex, index_path: &Path) -> Result<()> {
+    index.packages.sort_by(|a, b| a.id.cmp(&b.id));
+    index.source.sort_by(|a, b| a.id.cmp(&b.id));
+    let content = serde_json::to_string_pretty(index)?;
+    fsxg::atomic_write(index_path, &content)?;
+    write_index_hash(index_path, content.as_bytes())?;
+
+    let signature_path = index_companion_path(index_path, net::INDEX_SIGNATURE_SUFFIX);
+    if signature_path.exists() {
+        fs::remove_file(&signature_path)
+            .with_context(|| format!("无法删除过期的索引签名: {}", signature_path.display()))?;
+    }
+    Ok(())
+}
+
+/// 在事务中记录即将改写的索引及其附属文件（哈希、签名）
+fn safe_modify_index(transaction: &mut Transaction, index_path: &Path) -> Result<()> {
+    transaction.safe_modify(index_path)?;
+    transaction.safe_modify(&index_companion_path(index_path, net::INDEX_HASH_SUFFIX))?;
+    transaction.safe_modify(&index_companion_path(
+        index_path,
+        net::INDEX_SIGNATURE_SUFFIX,
+    ))
+}
+
+/// 索引的附属文件路径（如 `index.json.sha256`）
+fn index_companion_path(index_path: &Path, suffix: &str) -> PathBuf {
+    let mut path = index_path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// 按 `sha256sum` 的输出格式写入索引哈希文件，返回哈希值
+fn write_index_hash(index_path: &Path, content: &[u8]) -> Result<String> {
+    let hash = crypto::bytes_hash(content);
+    let file_name = index_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    fsxg::atomic_write(
+        index_companion_path(index_path, net::INDEX_HASH_SUFFIX),
+        format!("{hash}  {file_name}\n"),
+    )?;
+    Ok(hash)
+}
+
+/// 执行安装后钩子
+///
+/// 每个钩子执行前都会输出将要进行的操作。
+/// 命令类钩子仅在 `allow_commands` 为真（即软件源启用了 `allow_hooks`）时执行，否则跳过并警告。
+/// 桌面条目经 `transaction` 写入，安装失败回滚时一并撤销（命令的副作用无法撤销）。
+fn run_post_install_hooks(
+    transaction: &mut Transaction,
+    hooks: &[PostInstallHook],
+    package_dir: &Path,
+    allow_commands: bool,
+    applications_dir: &Path,
+) -> Result<()> {
+    for hook in hooks {
+        match hook {
+            PostInstallHook::Command { command, args } => {
+                let command_line = std::iter::once(command.as_str())
+                    .chain(args.iter().map(|a| a.as_str()))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                if !allow_commands {
+                    log::warn!("软件源未启用 allow_hooks，已跳过命令钩子: {}", command_line);
+                    continue;
+                }
+
+                log::info!("运行安装后钩子: {command_line}");
+                let status = std::process::Command::new(command)
+                    .args(args)
+                    .current_dir(package_dir)
+                    .status()
+                    .with_context(|| format!("无法执行命令: {command_line}"))?;
+                if !status.success() {
+                    return Err(anyhow!("命令 {} 执行失败: {}", command_line, status));
+                }
+            }
+            PostInstallHook::DesktopEntry { file } => {
+                // 路径来自元数据，拒绝绝对路径和越出包目录的路径
+                if !is_safe_relative(package_dir, file) {
+                    return Err(anyhow!("无效的桌面条目路径: {}", file));
+                }
+                let src_path = package_dir.join(file);
+                let file_name = Path::new(file)
+                    .file_name()
+                    .ok_or_else(|| anyhow!("无效的桌面条目路径: {}", file))?;
+                if !src_path.is_file() {
+                    return Err(anyhow!("桌面条目文件不存在: {}", src_path.display()));
+                }
+
+                let dest_path = applications_dir.join(file_name);
+                log::info!("安装桌面条目: {}", dest_path.display());
+                let content = fs::read(&src_path)
+                    .with_context(|| format!("无法读取桌面条目: {}", src_path.display()))?;
+                stage_file(transaction, &dest_path, &content)
+                    .with_context(|| format!("无法安装桌面条目: {}", dest_path.display()))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// 在事务中用新内容替换（或创建）文件
+///
+/// 先将内容写入同目录下的临时文件，再移动到目标位置，回滚时可恢复原文件
+fn stage_file(transaction: &mut Transaction, dest_path: &Path, content: &[u8]) -> Result<()> {
+    let file_name = dest_path
+        .file_name()
+        .ok_or_else(|| anyhow!("无效的文件路径: {}", dest_path.display()))?;
+    let staged_path =
+        dest_path.with_file_name(format!(".{}.pkgr-new", file_name.to_string_lossy()));
+
+    // 清理上次中断遗留的临时文件
+    if staged_path.exists() {
+        fs::remove_file(&staged_path)?;
+    }
+
+    transaction.safe_create(&staged_path, content)?;
+    transaction.safe_move(&staged_path, dest_path)
+}
+
+/// 确认缓存目录可以安全清空
+///
+/// 缓存目录等于或包含仓库目录、或其中存在 packages/ 目录时拒绝清理，
+/// 避免 `cache_dir` 配置错误时删除仓库数据
+fn check_cache_dir(cache_dir: &Path, repo_path: &Path) -> Result<()> {
+    if !cache_dir.exists() {
+        return Ok(());
+    }
+
+    let cache_dir = fs::canonicalize(cache_dir)
+        .with_context(|| format!("无法解析缓存目录: {}", cache_dir.display()))?;
+    let repo_path = fs::canonicalize(repo_path)
+        .with_context(|| format!("无法解析仓库目录: {}", repo_path.display()))?;
+    if repo_path.starts_with(&cache_dir) {
+        return Err(anyhow!(
+            "拒绝清理缓存目录 {}: 该目录等于或包含仓库目录 {}，请检查 config.toml 中的 cache_dir",
+            cache_dir.display(),
+            repo_path.display()
+        ));
+    }
+
+    if let Some(packages_dir) = walkdir::WalkDir::new(&cache_dir)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .find(|entry| entry.file_type().is_dir() && entry.file_name() == "packages")
+    {
+        return Err(anyhow!(
+            "拒绝清理缓存目录 {}: 其中包含 packages 目录 {}，可能存放着仓库数据，请检查 config.toml 中的 cache_dir",
+            cache_dir.display(),
+            packages_dir.path().display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// 清理旧版本，保留最新的 `keep_versions` 个版本
+///
+/// 新旧与索引、`repo doctor` 一致，按语义化版本优先级比较（[`version::semver_ordering`]），
+/// 而不是 versions.txt 中的加入顺序（先加入 2.0.0 再加入修复版 1.5.0 时，2.0.0 仍是最新）；
+/// 只计入版本历史中目录仍存在的版本。
+/// 不在历史中的版本目录无从判断新旧，予以保留并列为孤立版本。
+/// `preserve` 指定的版本无论新旧都会保留，并占用一个保留名额。
+/// 只查询，不删除任何内容
+fn clean_old_versions(
+    package_dir: &Path,
+    keep_versions: usize,
+    preserve: Option<&str>,
+) -> Result<OldVersions> {
+    let history = read_version_history(&package_dir.join("versions.txt"))?;
+    let existing: Vec<&String> = history
+        .iter()
+        .filter(|version| !version.is_empty() && package_dir.join(version).is_dir())
+        .collect();
+
+    // 保留的版本占用一个名额，其余名额留给最新的版本
+    let preserved = existing
+        .iter()
+        .any(|version| Some(version.as_str()) == preserve);
+    let mut existing: Vec<&String> = existing
+        .into_iter()
+        .filter(|version| Some(version.as_str()) != preserve)
+        .collect();
+    existing.sort_by(|a, b| version::semver_ordering(a, b));
+    let keep_versions = keep_versions.saturating_sub(usize::from(preserved));
+    let outdated = existing[..existing.len().saturating_sub(keep_versions)]
+        .iter()
+        .map(|version| version.to_string())
+        .collect();
+
+    let mut orphans = Vec::new();
+    for entry in fs::read_dir(package_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.path().is_dir() && !history.contains(&name) {
+            orphans.push(name);
+        }
+    }
+    orphans.sort();
+
+    Ok(OldVersions { outdated, orphans })
+}
+
+/// 软件包目录中的旧版本（[`clean_old_versions`] 的查询结果）
+struct OldVersions {
+    /// 超出保留数量的版本，从旧到新排列
+    outdated: Vec<String>,
+    /// 未记录在 versions.txt 中的版本目录，按名称排序
+    orphans: Vec<String>,
+}
+
+/// 更新版本历史
+fn update_version_history(package_id: &str, version: &str, repo_path: &Path) -> Result<()> {
+    let history_path = repo_path
+        .join("packages")
+        .join(package_id)
+        .join("versions.txt");
+
+    let mut versions = if history_path.exists() {
+        fs::read_to_string(&history_path)?
+            .lines()
+            .map(|s| s.to_string())
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // 添加新版本（如果不存在）
+    if !versions.contains(&version.to_string()) {
+        versions.push(version.to_string());
+        fs::write(&history_path, versions.join("\n"))?;
+    }
+
+    Ok(())
+}
+
+/// 依赖解析的最大遍历轮数
+const MAX_RESOLVE_ROUNDS: usize = 8;
+
+/// 在源索引中查找软件包
+fn find_source_package<'a>(
+    index: &'a RepositoryIndex,
+    package_id: &str,
+) -> Result<&'a PackageInfo> {
+    index
+        .source
+        .iter()
+        .find(|p| p.id == package_id)
+        .ok_or_else(|| anyhow!("未在索引中找到包: {}", package_id))
+}
+
+/// 软件包的可选版本（索引未列出可用版本时只有最新版本）
+fn candidate_versions(package: &PackageInfo) -> &[String] {
+    if package.available_versions.is_empty() {
+        std::slice::from_ref(&package.latest_version)
+    } else {
+        package.available_versions.as_slice()
+    }
+}
+
+/// 软件包指定版本所在的位置（location 指向最新版本目录，其它版本位于同级目录）
+fn version_location(package: &PackageInfo, version: &str) -> String {
+    let location = package.location.trim_end_matches('/');
+    if version == package.latest_version {
+        location.to_string()
+    } else {
+        match location.rsplit_once('/') {
+            Some((parent, _)) => format!("{parent}/{version}"),
+            None => version.to_string(),
+        }
+    }
+}
+
+/// 将版本目录的位置与其中的相对路径拼接，两者之间只保留一个 `/`
+fn join_location(location: &str, file_path: &str) -> String {
+    format!(
+        "{}/{}",
+        location.trim_end_matches('/'),
+        file_path.trim_start_matches('/')
+    )
+}
+
+/// 获取源中软件包指定版本的 metadata.json，并校验元数据自身的哈希
+///
+/// `metadata_urls` 为依次尝试的地址（原地址及各镜像中的相同路径）
+async fn fetch_source_metadata(
+    client: &net::Client,
+    metadata_urls: &[String],
+) -> Result<PackageMetadata> {
+    let value = net::with_fallback(metadata_urls, |url| async move {
+        fetch_json(client, &url).await.map_err(Into::into)
+    })
+    .await
+    .map_err(|e| anyhow!("{e}"))?;
+    let metadata: PackageMetadata = serde_json::from_value(value)?;
+    if !metadata.verify_metadata_hash()? {
+        return Err(anyhow!(
+            "元数据自校验失败，metadata.json 可能已被篡改: {}",
+            metadata_urls[0]
+        ));
+    }
+    Ok(metadata)
+}
+
+/// 解析要安装的版本
+///
+/// `latest` 在索引提供 `available_versions` 时取其中语义化版本最高者，
+/// 仅在未提供时使用索引中的 `latest_version`；
+/// 约束（如 `^1.2.0`）按 `preference` 从 `available_versions`（未提供时为 `latest_version`）
+/// 中选出满足约束的最高或最低版本，没有满足的版本时报错；其它值原样返回
+fn resolve_version(
+    package: &PackageInfo,
+    requested: &str,
+    preference: version::Preference,
+) -> Result<String> {
+    if requested == "latest" {
+        return Ok(version::get_latest_semver(&package.available_versions)
+            .map(str::to_string)
+            .unwrap_or_else(|| package.latest_version.clone()));
+    }
+    if !version::is_requirement(requested) {
+        return Ok(requested.to_string());
+    }
+
+    let candidates = candidate_versions(package);
+    version::select_satisfying(candidates, requested, preference)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            anyhow!(
+                "{} 没有满足 {} 的版本（可用版本: {}）",
+                package.id,
+                requested,
+                candidates.join(", ")
+            )
+        })
+}
+
+/// 更新包索引
+///
+/// `available_versions` 为该软件包 versions.txt 中的全部版本，
+/// `installed` 为安装记录，本地添加的软件包为 `None`
+fn update_package_index(
+    index: &mut RepositoryIndex,
+    metadata: &PackageMetadata,
+    available_versions: Vec<String>,
+    bundle: Option<String>,
+    installed: Option<&InstallRecord>,
+) {
+    // 创建包信息
+    let package_info = PackageInfo {
+        id: metadata.id.clone(),
+        name: metadata.name.clone(),
+        icon: metadata.icon.clone(),
+        author: metadata.author.clone(),
+        latest_version: metadata.version.clone(),
+        description: metadata.description.clone(),
+        location: format!("./packages/{}/{}", metadata.id, metadata.version),
+        available_versions,
+        size: metadata.total_size(),
+        signature: metadata.signature.clone(),
+        bundle,
+        installed_at: installed.and_then(|record| record.installed_at.clone()),
+        installed_from: installed.map(|record| record.source.clone()),
+    };
+
+    // 更新或添加包信息
+    if let Some(pos) = index.packages.iter().position(|p| p.id == metadata.id) {
+        index.packages[pos] = package_info;
+    } else {
+        index.packages.push(package_info);
+    }
+}
+
+/// 从索引中移除包
+fn remove_package_from_index(index: &mut RepositoryIndex, package_id: &str, version: Option<&str>) {
+    if let Some(version) = version {
+        // 移除特定版本（从版本历史中移除，但保留包记录）
+        if let Some(package) = index.packages.iter_mut().find(|p| p.id == package_id) {
+            package.available_versions.retain(|v| v != version);
+            // 更新最新版本为剩余版本中的最新版
+            let history_path = Path::new(&package.location)
+                .parent()
+                .unwrap()
+                .join("versions.txt");
+
+            if let Ok(versions) = read_version_history(&history_path)
+                && let Some(latest) = version::get_latest_semver(&versions)
+            {
+                package.latest_version = latest.to_string();
+            }
+        }
+    } else {
+        // 移除整个包
+        index.packages.retain(|p| p.id != package_id);
+    }
+
+}
+
+/// 读取版本历史
+fn read_version_history(path: &Path) -> Result<Vec<String>> {
+    if path.exists() {
+        Ok(fs::read_to_string(path)?
+            .lines()
+            .map(|s| s.to_string())
+            .collect())
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// 从版本历史中移除特定版本
+fn remove_version_from_history(package_id: &str, version: &str, repo_path: &Path) -> Result<()> {
+    let history_path = repo_path
+        .join("packages")
+        .join(package_id)
+        .join("versions.txt");
+
+    if history_path.exists() {
+        let mut versions: Vec<String> = fs::read_to_string(&history_path)?
+            .lines()
+            .map(|s| s.to_string())
+            .collect();
+
+        versions.retain(|v| v != version);
+
+        if versions.is_empty() {
+            fs::remove_file(&history_path)?;
+        } else {
+            fs::write(&history_path, versions.join("\n"))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::testing::test_source;
+    use crate::logging;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn test_client() -> net::Client {
+        net::Client::new(
+            &crate::config::NetworkConfig::default(),
+            &[],
+            net::RetryPolicy::default(),
+        )
+        .unwrap()
+    }
+
+    fn package_info(id: &str) -> PackageInfo {
+        PackageInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            icon: String::new(),
+            author: "Test Author".to_string(),
+            latest_version: "1.0.0".to_string(),
+            description: String::new(),
+            location: format!("./packages/{id}/1.0.0"),
+            available_versions: Vec::new(),
+            size: None,
+            signature: None,
+            bundle: None,
+            installed_at: None,
+            installed_from: None,
+        }
+    }
+
+    #[test]
+    fn test_save_index_is_deterministic() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let ids: Vec<String> = (0..32).map(|i| format!("test.package{i}")).collect();
+
+        // 两次经由 HashMap 合并（迭代顺序随机），写出的索引应完全一致
+        let mut outputs = Vec::new();
+        for run in 0..2 {
+            let merged: HashMap<String, PackageInfo> = ids
+                .iter()
+                .map(|id| (id.clone(), package_info(id)))
+                .collect();
+            let mut index = RepositoryIndex {
+                packages: merged.values().cloned().collect(),
+                source: merged.into_values().collect(),
+            };
+
+            let index_path = temp_dir.path().join(format!("index{run}.json"));
+            save_index(&mut index, &index_path)?;
+            outputs.push(fs::read(&index_path)?);
+        }
+
+        assert_eq!(outputs[0], outputs[1]);
+
+        let index: RepositoryIndex = load_json(&temp_dir.path().join("index0.json"))?;
+        assert!(index.source.windows(2).all(|w| w[0].id <= w[1].id));
+        assert!(index.packages.windows(2).all(|w| w[0].id <= w[1].id));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_expand_package_spec() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut repo_manager = RepoManager::init(temp_dir.path())?;
+        for id in ["pageos", "other"] {
+            repo_manager.config.source.push(SourceConfig {
+                id: id.to_string(),
+                ..test_source(format!("https://{id}.example.com"))
+            });
+        }
+        repo_manager.with_index_mut(|index| {
+            index.source = [
+                ("pageos", "pageos-settings"),
+                ("pageos", "pageos-files"),
+                ("other", "other-app"),
+                ("other", "pageos-mirror"),
+            ]
+            .into_iter()
+            .map(|(source, id)| PackageInfo {
+                location: format!("https://{source}.example.com/packages/{id}/1.0.0"),
+                ..package_info(id)
+            })
+            .collect();
+        })?;
+
+        assert_eq!(
+            repo_manager.expand_package_spec("pageos:pageos-*:latest")?,
+            [
+                "pageos:pageos-files:latest",
+                "pageos:pageos-settings:latest"
+            ]
+        );
+        assert_eq!(
+            repo_manager.expand_package_spec("other-ap?")?,
+            ["other-app"]
+        );
+        // 不含通配符时原样返回，不检查索引
+        assert_eq!(
+            repo_manager.expand_package_spec("pageos:missing-app")?,
+            ["pageos:missing-app"]
+        );
+        assert!(
+            repo_manager
+                .expand_package_spec("pageos:missing-*")
+                .is_err()
+        );
+        // 源前缀限定匹配范围，不带前缀时匹配所有源
+        assert_eq!(
+            repo_manager.expand_package_spec("other:pageos-*")?,
+            ["other:pageos-mirror"]
+        );
+        assert_eq!(
+            repo_manager.expand_package_spec("pageos-*")?,
+            ["pageos-files", "pageos-mirror", "pageos-settings"]
+        );
+        assert!(
+            repo_manager
+                .expand_package_spec("other:*-settings")
+                .is_err()
+        );
+        assert!(
+            repo_manager
+                .expand_package_spec("missing:pageos-*")
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_packages() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_manager = RepoManager::init(temp_dir.path())?;
+        repo_manager.with_index_mut(|index| {
+            let mut settings = package_info("pageos-settings");
+            settings.description = "System Settings panel".to_string();
+            index.packages = vec![settings.clone()];
+            let mut files = package_info("pageos-files");
+            files.name = "Files".to_string();
+            index.source = vec![settings, files, package_info("other-app")];
+        })?;
+
+        let ids = |query: &str, installed_only: bool| -> Result<Vec<String>> {
+            Ok(repo_manager
+                .search_packages(query, installed_only)?
+                .into_iter()
+                .map(|package| package.id)
+                .collect())
+        };
+
+        // 已安装的包在前，源中同ID的条目不重复
+        assert_eq!(ids("PAGEOS", false)?, ["pageos-settings", "pageos-files"]);
+        assert_eq!(ids("settings", true)?, ["pageos-settings"]);
+        assert_eq!(ids("files", false)?, ["pageos-files"]);
+        assert!(ids("files", true)?.is_empty());
+        assert!(ids("missing", false)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_manager = RepoManager::init(temp_dir.path())?;
+        repo_manager.with_index_mut(|index| {
+            index.packages = vec![package_info("settings"), package_info("local-only")];
+            let mut settings = package_info("settings");
+            settings.latest_version = "1.1.0".to_string();
+            index.source = vec![package_info("files"), settings];
+        })?;
+
+        let ids = |filter| -> Result<Vec<String>> {
+            Ok(repo_manager
+                .list(filter)?
+                .into_iter()
+                .map(|package| package.id)
+                .collect())
+        };
+        assert_eq!(ids(ListFilter::Installed)?, ["local-only", "settings"]);
+        assert_eq!(ids(ListFilter::Available)?, ["files"]);
+        assert_eq!(ids(ListFilter::All)?, ["files", "local-only", "settings"]);
+
+        // 已安装且软件源中也有的软件包同时给出两个版本
+        let all = repo_manager.list(ListFilter::All)?;
+        assert_eq!(all[2].installed_version.as_deref(), Some("1.0.0"));
+        assert_eq!(all[2].available_version.as_deref(), Some("1.1.0"));
+        assert_eq!(all[1].available_version, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_package_info() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
+
+        // 已安装的软件包读取本地元数据
+        let metadata = source_repo.package_info("test-app").await?;
+        assert_eq!(
+            (metadata.id.as_str(), metadata.all_files.len()),
+            ("test-app", 1)
+        );
+
+        // 仅在软件源中的软件包从 location 获取元数据
+        let repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager.with_index_mut(|index| {
+            let mut package = package_info("test-app");
+            package.latest_version = "0.0.0".to_string();
+            package.location = source_path
+                .join("packages/test-app/0.0.0")
+                .to_string_lossy()
+                .to_string();
+            index.source = vec![package];
+        })?;
+        assert_eq!(
+            repo_manager.package_info("test-app").await?.version,
+            "0.0.0"
+        );
+
+        let error = repo_manager.package_info("missing-app").await.unwrap_err();
+        assert!(error.to_string().contains("既未安装"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_latest_version() {
+        let resolve_version = |package: &PackageInfo, requested: &str| {
+            resolve_version(package, requested, version::Preference::Newest).unwrap()
+        };
+        let mut package = package_info("test-app");
+        assert_eq!(resolve_version(&package, "latest"), "1.0.0");
+        assert_eq!(resolve_version(&package, "0.9.0"), "0.9.0");
+
+        // 可用版本中存在比 latest_version 更高的版本
+        package.available_versions = vec![
+            "1.0.0".to_string(),
+            "1.10.0".to_string(),
+            "1.9.0".to_string(),
+            "2.0.0-rc.1".to_string(),
+        ];
+        assert_eq!(resolve_version(&package, "latest"), "2.0.0-rc.1");
+        package.available_versions.push("2.0.0".to_string());
+        assert_eq!(resolve_version(&package, "latest"), "2.0.0");
+    }
+
+    #[test]
+    fn test_file_locations() {
+        // 文件地址由 location 拼接而来，与 location 是否以 `/` 结尾无关
+        let mut package = package_info("test-app");
+        package.latest_version = "1.0.0".to_string();
+        for location in [
+            "https://example.com/mirror/test-app/1.0.0",
+            "https://example.com/mirror/test-app/1.0.0/",
+        ] {
+            package.location = location.to_string();
+            assert_eq!(
+                join_location(&version_location(&package, "1.0.0"), "js/app.js"),
+                "https://example.com/mirror/test-app/1.0.0/js/app.js"
+            );
+            assert_eq!(
+                join_location(&version_location(&package, "0.9.0"), "metadata.json"),
+                "https://example.com/mirror/test-app/0.9.0/metadata.json"
+            );
+        }
+        assert_eq!(
+            join_location("/srv/repo/", "/index.html"),
+            "/srv/repo/index.html"
+        );
+    }
+
+    #[test]
+    fn test_resolve_version_preference() {
+        let mut package = package_info("test-app");
+        package.available_versions = ["1.0.0", "1.2.0", "1.10.0", "2.0.0"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+
+        let newest = resolve_version(&package, "^1.0.0", version::Preference::Newest);
+        let lowest = resolve_version(&package, ">=1.1.0", version::Preference::Lowest);
+        assert_eq!(newest.unwrap(), "1.10.0");
+        assert_eq!(lowest.unwrap(), "1.2.0");
+        assert!(resolve_version(&package, "^3.0.0", version::Preference::Newest).is_err());
+
+        // 约束中的版本号可以省略低位，普通版本号按原样精确匹配
+        let partial = resolve_version(&package, ">=1.0,<2.0", version::Preference::Newest);
+        assert_eq!(partial.unwrap(), "1.10.0");
+        let exact = resolve_version(&package, "1.2.0", version::Preference::Newest);
+        assert_eq!(exact.unwrap(), "1.2.0");
+
+        // 未提供可用版本列表时只能选择 latest_version
+        package.available_versions.clear();
+        let fallback = resolve_version(&package, "*", version::Preference::Lowest);
+        assert_eq!(fallback.unwrap(), "1.0.0");
+    }
+
+    #[test]
+    fn test_open_without_packages_dir() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        RepoManager::init(&repo_path)?;
+        fs::remove_dir_all(repo_path.join("packages"))?;
+
+        let mut repo_manager = RepoManager::open(&repo_path)?;
+        repo_manager.update_local_index()?;
+
+        let index: RepositoryIndex = load_json(&repo_path.join("index.json"))?;
+        assert!(index.packages.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_install_hooks() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_dir = temp_dir.path().join("package");
+        let applications_dir = temp_dir.path().join("applications");
+        fsxg::create_directory(&package_dir)?;
+        fs::write(package_dir.join("app.desktop"), "[Desktop Entry]")?;
+
+        let hooks = vec![
+            PostInstallHook::Command {
+                command: "pageos-pkgr-nonexistent-command".to_string(),
+                args: Vec::new(),
+            },
+            PostInstallHook::DesktopEntry {
+                file: "app.desktop".to_string(),
+            },
+        ];
+
+        // 未允许命令钩子时跳过命令，仍执行声明式钩子
+        let mut transaction = Transaction::begin();
+        run_post_install_hooks(
+            &mut transaction,
+            &hooks,
+            &package_dir,
+            false,
+            &applications_dir,
+        )?;
+        transaction.commit()?;
+        assert!(applications_dir.join("app.desktop").exists());
+
+        // 允许命令钩子时执行命令（此处命令不存在，应报错）
+        let mut transaction = Transaction::begin();
+        assert!(
+            run_post_install_hooks(
+                &mut transaction,
+                &hooks,
+                &package_dir,
+                true,
+                &applications_dir
+            )
+            .is_err()
+        );
+        transaction.rollback()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_post_install_hooks_desktop_entry_path() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_dir = temp_dir.path().join("package");
+        let applications_dir = temp_dir.path().join("applications");
+        fsxg::create_directory(&package_dir)?;
+        let outside = temp_dir.path().join("outside.desktop");
+        fs::write(&outside, "[Desktop Entry]")?;
+
+        // 绝对路径和越出包目录的路径均被拒绝，不写入任何桌面条目
+        for file in [
+            path_to_str(&outside)?.to_string(),
+            "../outside.desktop".to_string(),
+        ] {
+            let hooks = vec![PostInstallHook::DesktopEntry { file }];
+            let mut transaction = Transaction::begin();
+            let err = run_post_install_hooks(
+                &mut transaction,
+                &hooks,
+                &package_dir,
+                false,
+                &applications_dir,
+            )
+            .unwrap_err();
+            assert!(err.to_string().contains("无效的桌面条目路径"));
+            transaction.rollback()?;
+        }
+        assert!(!applications_dir.join("outside.desktop").exists());
+
+        // 回滚时撤销已安装的桌面条目
+        fs::write(package_dir.join("app.desktop"), "[Desktop Entry]")?;
+        let hooks = vec![PostInstallHook::DesktopEntry {
+            file: "app.desktop".to_string(),
+        }];
+        let mut transaction = Transaction::begin();
+        run_post_install_hooks(
+            &mut transaction,
+            &hooks,
+            &package_dir,
+            false,
+            &applications_dir,
+        )?;
+        assert!(applications_dir.join("app.desktop").exists());
+        transaction.rollback()?;
+        assert!(!applications_dir.join("app.desktop").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_package_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+
+        // 创建并添加软件包
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "v1")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+
+        // 版本尚未添加时应拒绝
+        assert!(
+            repo_manager
+                .update_package_file(&package_path, "index.html")
+                .is_err()
+        );
+
+        repo_manager.add_package(&package_path, false)?;
+
+        // 修改文件后仅更新该文件
+        fs::write(package_path.join("index.html"), "v2")?;
+        repo_manager.update_package_file(&package_path, "index.html")?;
+
+        let version_dir = temp_dir.path().join("repo/packages/test-app/0.0.0");
+        assert_eq!(fs::read_to_string(version_dir.join("index.html"))?, "v2");
+        let metadata: PackageMetadata = load_json(&version_dir.join("metadata.json"))?;
+        assert_eq!(
+            metadata.get_file_hash("index.html"),
+            Some(crypto::bytes_hash(b"v2").as_str())
+        );
+        assert!(metadata.verify_metadata_hash()?);
+
+        // 拒绝越出包目录的路径
+        assert!(
+            repo_manager
+                .update_package_file(&package_path, "../escape.txt")
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_lockfile() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        repo_manager.add_package(&package_path, false)?;
+
+        let locked = |version: &str, hash: String| LockedPackage {
+            version: version.to_string(),
+            source: "default".to_string(),
+            files: BTreeMap::from([("index.html".to_string(), hash)]),
+        };
+        let mut lockfile = Lockfile::default();
+        lockfile.insert("test-app", locked("0.0.0", crypto::bytes_hash(b"hello")));
+        assert!(repo_manager.verify_lockfile(&lockfile)?.is_empty());
+
+        // 安装后被修改的文件
+        fs::write(
+            temp_dir
+                .path()
+                .join("repo/packages/test-app/0.0.0/index.html"),
+            "changed",
+        )?;
+        let drifts = repo_manager.verify_lockfile(&lockfile)?;
+        assert_eq!(
+            drifts,
+            vec![LockDrift::HashMismatch {
+                id: "test-app".to_string(),
+                version: "0.0.0".to_string(),
+                path: "index.html".to_string(),
+                locked: crypto::bytes_hash(b"hello"),
+                actual: Some(crypto::bytes_hash(b"changed")),
+            }]
+        );
+
+        // 版本不符与未安装的软件包
+        lockfile.insert("test-app", locked("1.0.0", String::new()));
+        lockfile.insert("missing-app", locked("1.0.0", String::new()));
+        let drifts = repo_manager.verify_lockfile(&lockfile)?;
+        assert_eq!(
+            drifts,
+            vec![
+                LockDrift::MissingPackage {
+                    id: "missing-app".to_string(),
+                    version: "1.0.0".to_string(),
+                },
+                LockDrift::VersionMismatch {
+                    id: "test-app".to_string(),
+                    locked: "1.0.0".to_string(),
+                    installed: vec!["0.0.0".to_string()],
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_package_sizes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        fs::write(package_path.join("app.js"), "console.log(1)")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        app::testing::add_file(package_path.join("app.js"), package_path.clone())?;
+        repo_manager.add_package(&package_path, false)?;
+
+        // 索引记录最新版本的总大小
+        let index: RepositoryIndex = load_json(&temp_dir.path().join("repo/index.json"))?;
+        assert_eq!(index.packages[0].size, Some(19));
+        assert_eq!(
+            repo_manager.package_sizes("test-app")?,
+            vec![("0.0.0".to_string(), Some(19))]
+        );
+
+        // 仅记录哈希的旧清单视为大小未知
+        let metadata_path = package_path.join("metadata.json");
+        let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+        metadata.version = "0.1.0".to_string();
+        metadata.add_file("app.js".to_string(), crypto::bytes_hash(b"console.log(1)"));
+        save_json(&metadata, &metadata_path)?;
+        repo_manager.add_package(&package_path, false)?;
+        assert_eq!(
+            repo_manager.package_sizes("test-app")?,
+            vec![("0.0.0".to_string(), Some(19)), ("0.1.0".to_string(), None)]
+        );
+
+        assert!(repo_manager.package_sizes("missing-app").is_err());
+
+        // 默认列出最高版本的文件
+        let installed = repo_manager.package_files("test-app", None)?;
+        assert_eq!(installed.version, "0.1.0");
+        let paths: Vec<&str> = installed.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["app.js", "index.html"]);
+        assert_eq!(installed.files[0].size, None);
+        assert_eq!(installed.files[1].size, Some(5));
+        assert_eq!(
+            repo_manager.package_files("test-app", Some("0.0.0"))?.files[0].size,
+            Some(14)
+        );
+        assert!(
+            repo_manager
+                .package_files("test-app", Some("9.9.9"))
+                .is_err()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_package_alternative_hashes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        fs::write(package_path.join("app.js"), "console.log(1)")?;
+
+        // 带算法前缀的清单条目按对应算法校验
+        let metadata_path = package_path.join("metadata.json");
+        let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+        let blake3 =
+            crypto::file_hash_with(package_path.join("index.html"), HashAlgorithm::Blake3)?;
+        let sha512 = crypto::file_hash_with(package_path.join("app.js"), HashAlgorithm::Sha512)?;
+        metadata.add_file("index.html".to_string(), format!("blake3:{blake3}:5"));
+        metadata.add_file("app.js".to_string(), format!("sha512:{sha512}"));
+        save_json(&metadata, &metadata_path)?;
+        repo_manager.add_package(&package_path, false)?;
+
+        let installed = repo_manager.package_files("test-app", None)?;
+        assert_eq!(installed.files[1].size, Some(5));
+
+        // 哈希不符时拒绝添加
+        metadata.version = "0.1.0".to_string();
+        metadata.add_file("index.html".to_string(), format!("blake3:{sha512}"));
+        save_json(&metadata, &metadata_path)?;
+        assert!(repo_manager.add_package(&package_path, false).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_package_force() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        let mut repo_manager = RepoManager::init(&repo_path)?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        fs::write(package_path.join("extra.txt"), "extra")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        app::testing::add_file(package_path.join("extra.txt"), package_path.clone())?;
+        let metadata_path = package_path.join("metadata.json");
+        let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+        metadata.version = "1.0.0".to_string();
+        save_json(&metadata, &metadata_path)?;
+        repo_manager.add_package(&package_path, false)?;
+
+        // 新清单中不再包含 extra.txt
+        app::remove_file(package_path.join("extra.txt"), package_path.clone())?;
+        fs::remove_file(package_path.join("extra.txt"))?;
+        let version_dir = repo_path.join("packages/test-app/1.0.0");
+
+        // 不带 force 时拒绝覆盖已存在的版本，原有文件不变
+        let error = repo_manager.add_package(&package_path, false).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("仓库中已存在版本 test-app:1.0.0")
+        );
+        assert!(version_dir.join("extra.txt").exists());
+
+        // 带 force 时整体替换版本目录，暂存目录不留在仓库中
+        let entries = |path: &Path| -> Result<std::collections::BTreeSet<std::ffi::OsString>> {
+            Ok(fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.file_name()))
+                .collect::<std::io::Result<_>>()?)
+        };
+        let before = entries(&repo_path)?;
+        repo_manager.add_package(&package_path, true)?;
+        assert!(!version_dir.join("extra.txt").exists());
+        assert!(version_dir.join("index.html").exists());
+        let stored: PackageMetadata = load_json(&version_dir.join("metadata.json"))?;
+        assert!(!stored.all_files.contains_key("extra.txt"));
+        assert_eq!(entries(&repo_path)?, before);
+        assert_eq!(entries(&repo_path.join("packages/test-app"))?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index_lists_available_versions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        let mut repo_manager = RepoManager::init(&repo_path)?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        let metadata_path = package_path.join("metadata.json");
+        for version in ["1.0.0", "1.1.0"] {
+            let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+            metadata.version = version.to_string();
+            save_json(&metadata, &metadata_path)?;
+            repo_manager.add_package(&package_path, false)?;
+        }
+
+        let available = |repo_path: &Path| -> Result<Vec<String>> {
+            let index: RepositoryIndex = load_json(&repo_path.join("index.json"))?;
+            Ok(index.packages[0].available_versions.clone())
+        };
+        assert_eq!(available(&repo_path)?, vec!["1.0.0", "1.1.0"]);
+
+        // 重建索引时同样从 versions.txt 读取
+        repo_manager.update_local_index()?;
+        assert_eq!(available(&repo_path)?, vec!["1.0.0", "1.1.0"]);
+
+        // 移除单个版本后不再列出
+        repo_manager.remove_package("test-app", Some("1.0.0"), false)?;
+        assert_eq!(available(&repo_path)?, vec!["1.1.0"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_old_versions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_dir = temp_dir.path().join("app");
+        for version in ["1.0.0", "2.0.0", "1.5.0"] {
+            fsxg::create_directory(package_dir.join(version))?;
+        }
+        // 修复版 1.5.0 在 2.0.0 之后加入
+        fs::write(package_dir.join("versions.txt"), "1.0.0\n2.0.0\n1.5.0")?;
+
+        assert!(
+            clean_old_versions(&package_dir, 3, None)?
+                .outdated
+                .is_empty()
+        );
+
+        // 按版本优先级 2.0.0 最新，不受加入顺序影响；只查询，不删除
+        assert_eq!(
+            clean_old_versions(&package_dir, 1, None)?.outdated,
+            ["1.0.0", "1.5.0"]
+        );
+        assert!(package_dir.join("1.0.0").is_dir());
+
+        // 保留的版本占用一个名额
+        assert_eq!(
+            clean_old_versions(&package_dir, 2, Some("1.0.0"))?.outdated,
+            ["1.5.0"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_old_versions_semver_order() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let package_dir = temp_dir.path().join("app");
+        let versions = ["1.2.0", "1.9.0", "1.10.0", "1.11.0"];
+        for version in versions.iter().chain(&["0.1.0-manual"]) {
+            fsxg::create_directory(package_dir.join(version))?;
+        }
+        fs::write(package_dir.join("versions.txt"), versions.join("\n"))?;
+
+        // 字典序下 1.10.0 和 1.11.0 会被当作最旧的版本
+        let old = clean_old_versions(&package_dir, 2, None)?;
+        assert_eq!(old.outdated, ["1.2.0", "1.9.0"]);
+
+        // 不在历史中的目录保留并报告
+        assert_eq!(old.orphans, vec!["0.1.0-manual".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_doctor() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        let mut repo = RepoManager::init(&repo_path)?;
+        let dir = temp_dir.path();
+        for package_id in ["app-a", "app-b", "app-c"] {
+            add_package_with_dependencies(&mut repo, dir, package_id, &[])?;
+        }
+        assert!(repo.doctor(false)?.is_empty());
+
+        // 制造不一致：删除 app-b 的目录、从索引中移除 app-c、在 app-a 的历史中记录不存在的版本
+        let packages_dir = repo_path.join("packages");
+        fs::remove_dir_all(packages_dir.join("app-b"))?;
+        repo.with_index_mut(|index| index.packages.retain(|p| p.id != "app-c"))?;
+        let history_path = packages_dir.join("app-a").join("versions.txt");
+        fs::write(&history_path, "0.0.0\n9.9.9")?;
+        repo.with_index_mut(|index| {
+            for package in &mut index.packages {
+                if package.id == "app-a" {
+                    package.latest_version = "9.9.9".to_string();
+                }
+            }
+        })?;
+
+        let problems = repo.doctor(false)?;
+        assert_eq!(
+            problems,
+            [
+                IndexProblem::MissingVersion {
+                    id: "app-a".to_string(),
+                    version: "9.9.9".to_string(),
+                },
+                IndexProblem::Dangling {
+                    id: "app-b".to_string(),
+                },
+                IndexProblem::Outdated {
+                    id: "app-a".to_string(),
+                    indexed: "9.9.9".to_string(),
+                    actual: "0.0.0".to_string(),
+                },
+                IndexProblem::Unindexed {
+                    id: "app-c".to_string(),
+                    version: "0.0.0".to_string(),
+                },
+            ]
+        );
+        // 不修复时不修改任何文件
+        assert_eq!(fs::read_to_string(&history_path)?, "0.0.0\n9.9.9");
+
+        // 以共享模式打开的仓库修复时升级为写锁，完成后恢复共享锁
+        drop(repo);
+        let repo = RepoManager::open(&repo_path)?;
+        assert_eq!(repo.doctor(true)?, problems);
+        RepoManager::open_with_lock(&repo_path, LockMode::Shared, false)?;
+        assert_eq!(fs::read_to_string(&history_path)?, "0.0.0");
+        assert!(repo.doctor(false)?.is_empty());
+        let listed: Vec<String> = repo
+            .list(ListFilter::Installed)?
+            .into_iter()
+            .map(|package| package.id)
+            .collect();
+        assert_eq!(listed, ["app-a", "app-c"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_package_dry_run() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        add_package_with_dependencies(&mut repo_manager, temp_dir.path(), "test-app", &[])?;
+        let package_dir = repo_manager.repo_path.join("packages/test-app");
+
+        // 演练不修改文件和索引
+        let changes = repo_manager.remove_package("test-app", Some("0.0.0"), true)?;
+        assert_eq!(
+            changes.changes(),
+            [
+                PlannedChange::RemoveDir(package_dir.join("0.0.0")),
+                PlannedChange::WriteFile(package_dir.join("versions.txt")),
+                PlannedChange::EditIndex("移除 test-app:0.0.0".to_string()),
+            ]
+        );
+        assert!(package_dir.join("0.0.0").is_dir());
+        assert_eq!(
+            repo_manager.with_index_mut(|index| index.packages.len())?,
+            1
+        );
+
+        repo_manager.remove_package("test-app", None, false)?;
+        assert!(!package_dir.exists());
+        assert_eq!(
+            repo_manager.with_index_mut(|index| index.packages.len())?,
+            0
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_packages_updates_index() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        let metadata_path = package_path.join("metadata.json");
+        for version in ["1.0.0", "2.0.0", "1.5.0"] {
+            let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+            metadata.version = version.to_string();
+            save_json(&metadata, &metadata_path)?;
+            repo_manager.add_package(&package_path, false)?;
+        }
+
+        // 演练只列出将删除的目录和改写的版本历史
+        let package_dir = repo_manager.repo_path.join("packages/test-app");
+        let mut changes = ChangeSet::new(true);
+        repo_manager.clean_packages(1, &mut changes)?;
+        assert_eq!(
+            changes.changes(),
+            [
+                PlannedChange::RemoveDir(package_dir.join("1.0.0")),
+                PlannedChange::RemoveDir(package_dir.join("1.5.0")),
+                PlannedChange::WriteFile(package_dir.join("versions.txt")),
+                PlannedChange::EditIndex("重新扫描 packages/ 更新 packages 部分".to_string()),
+            ]
+        );
+        assert!(package_dir.join("1.0.0").is_dir());
+
+        let mut changes = ChangeSet::new(false);
+        repo_manager.clean_packages(1, &mut changes)?;
+        assert!(!package_dir.join("1.0.0").exists());
+        assert!(!package_dir.join("1.5.0").exists());
+        let packages = repo_manager.with_index_mut(|index| index.packages.clone())?;
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0].latest_version, "2.0.0");
+        assert_eq!(packages[0].available_versions, ["2.0.0"]);
+        assert!(repo_manager.doctor(false)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clean_refuses_repo_cache_dir() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        let mut repo_manager = RepoManager::init(&repo_path)?;
+
+        // 缓存目录为仓库根目录
+        repo_manager.config.cache_dir = repo_path.to_string_lossy().to_string();
+        assert!(
+            repo_manager
+                .clean(RepoManager::DEFAULT_KEEP_VERSIONS, false)
+                .is_err()
+        );
+        assert!(repo_path.join("index.json").exists());
+
+        // 缓存目录为仓库的上级目录
+        repo_manager.config.cache_dir = temp_dir.path().to_string_lossy().to_string();
+        assert!(
+            repo_manager
+                .clean(RepoManager::DEFAULT_KEEP_VERSIONS, false)
+                .is_err()
+        );
+        assert!(repo_path.join("index.json").exists());
+
+        // 缓存目录中含有其它仓库的 packages/ 目录
+        let other = temp_dir.path().join("other");
+        fsxg::create_directory(other.join("packages"))?;
+        assert!(check_cache_dir(&other, &repo_path).is_err());
+        assert!(check_cache_dir(&temp_dir.path().join("cache"), &repo_path).is_ok());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_source_local() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        // 作为软件源的本地仓库
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
+
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+
+        let verification = repo_manager.verify_source("local").await?.summary;
+        assert_eq!((verification.passed, verification.failed), (1, 0));
+        assert_eq!(verification.packages[0].verified_files, 1);
+
+        // 篡改已发布的文件后校验失败，且不影响源中的内容
+        let published = source_path.join("packages/test-app/0.0.0/index.html");
+        fs::write(&published, "tampered")?;
+        let verification = repo_manager.verify_source("local").await?.summary;
+        assert_eq!((verification.passed, verification.failed), (0, 1));
+        assert!(verification.packages[0].errors[0].contains("index.html"));
+        assert_eq!(fs::read_to_string(&published)?, "tampered");
+
+        assert!(repo_manager.verify_source("missing").await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_sources_local() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+
+        // 一个有效的本地源和一个缺少索引的本地源
+        let good = temp_dir.path().join("good");
+        fsxg::create_directory(&good)?;
+        save_json(
+            &RepositoryIndex {
+                packages: Vec::new(),
+                source: Vec::new(),
+            },
+            &good.join("index.json"),
+        )?;
+        let missing = temp_dir.path().join("missing");
+
+        for (id, dir) in [("good", &good), ("missing", &missing)] {
+            repo_manager.config.source.push(SourceConfig {
+                id: id.to_string(),
+                name: id.to_string(),
+                ..test_source(dir.to_string_lossy())
+            });
+        }
+
+        let results = repo_manager.check_sources().await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].reachable && results[0].index_valid);
+        assert_eq!(results[0].index_verified, None);
+        assert!(results[0].error.is_none());
+        assert!(!results[1].reachable && !results[1].index_valid);
+        assert!(results[1].error.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_sources_verifies_index() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        add_package_with_dependencies(&mut source_repo, temp_dir.path(), "test-app", &[])?;
+        source_repo.update_local_index()?;
+
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager.config.source.push(SourceConfig {
+            verify_index: Some(true),
+            ..test_source(serve_dir(source_path.clone()).await?)
+        });
+
+        let results = repo_manager.check_sources().await;
+        assert!(results[0].reachable && results[0].index_valid);
+        assert_eq!(results[0].index_verified, Some(true));
+        assert!(results[0].error.is_none());
+
+        // 索引被篡改后仍可解析，但校验失败
+        let index_path = source_path.join("index.json");
+        let content = fs::read_to_string(&index_path)?;
+        fs::write(&index_path, format!("{content} "))?;
+        let results = repo_manager.check_sources().await;
+        assert!(results[0].reachable && results[0].index_valid);
+        assert_eq!(results[0].index_verified, Some(false));
+        assert!(
+            results[0]
+                .error
+                .as_deref()
+                .is_some_and(|error| error.contains("索引哈希不匹配"))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_local_source() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+
+        // 作为软件源的本地仓库（路径含空格，file:// 地址中为百分号编码）
+        let source_path = temp_dir.path().join("local source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        add_package_with_dependencies(&mut source_repo, temp_dir.path(), "test-app", &[])?;
+        let source_url = reqwest::Url::from_file_path(&source_path)
+            .map_err(|_| anyhow!("无效的路径"))?
+            .to_string();
+        assert!(source_url.contains("local%20source"));
+
+        // file:// 源：相对位置改写为源目录下的文件系统路径，安装时直接复制文件
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager.config.source.push(test_source(source_url));
+        repo_manager.update_source_index().await?;
+        let source = repo_manager.with_index_mut(|index| index.source.clone())?;
+        assert_eq!(
+            Path::new(&source[0].location),
+            source_path.join("packages/test-app/0.0.0")
+        );
+
+        repo_manager
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await?;
+        assert_eq!(
+            fs::read_to_string(
+                repo_manager
+                    .repo_path
+                    .join("packages/test-app/0.0.0/index.html")
+            )?,
+            "test-app"
+        );
+
+        // 绝对路径源的增量同步同样不经过网络，位置同样改写为源目录下的路径
+        repo_manager.config.source[0].url = source_path.to_string_lossy().to_string();
+        repo_manager.with_index_mut(|index| index.source.clear())?;
+        repo_manager
+            .sync_repository("local", &SyncOptions::default())
+            .await?;
+        let source = repo_manager.with_index_mut(|index| index.source.clone())?;
+        assert_eq!(source.len(), 1);
+        assert_eq!(source[0].id, "test-app");
+        assert_eq!(source[0].latest_version, "0.0.0");
+        assert_eq!(
+            fs::read_to_string(Path::new(&source[0].location).join("index.html"))?,
+            "test-app"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_incremental_changelog() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let source_repo = RepoManager::init(&source_path)?;
+        let package = |id: &str, version: &str| PackageInfo {
+            latest_version: version.to_string(),
+            ..package_info(id)
+        };
+        source_repo.with_index_mut(|index| {
+            index.packages = vec![package("app-a", "1.0.0"), package("app-b", "1.0.0")]
+        })?;
+
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+        let report = repo_manager
+            .sync_repository("local", &SyncOptions::default())
+            .await?;
+        assert_eq!(
+            changelog_summary(&report.changelog),
+            "新增 2 个，更新 0 个，移除 0 个"
+        );
+
+        // 其它源同步来的条目既不参与比较，也不会被覆盖
+        repo_manager.with_index_mut(|index| {
+            index.source.push(PackageInfo {
+                location: "https://other.example/packages/other-app/1.0.0".to_string(),
+                ..package("other-app", "1.0.0")
+            })
+        })?;
+        source_repo.with_index_mut(|index| {
+            index.packages = vec![package("app-a", "1.1.0"), package("app-c", "1.0.0")]
+        })?;
+        let report = repo_manager
+            .sync_repository(
+                "local",
+                &SyncOptions {
+                    dry_run: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        assert_eq!(
+            report.changelog,
+            vec![
+                SourceChange::Updated {
+                    id: "app-a".to_string(),
+                    from: "1.0.0".to_string(),
+                    to: "1.1.0".to_string(),
+                },
+                SourceChange::Removed {
+                    id: "app-b".to_string(),
+                    version: "1.0.0".to_string(),
+                },
+                SourceChange::Added {
+                    id: "app-c".to_string(),
+                    version: "1.0.0".to_string(),
+                },
+            ]
+        );
+        // 演练不修改索引
+        assert_eq!(repo_manager.with_index_mut(|index| index.source.len())?, 3);
+
+        repo_manager
+            .sync_repository("local", &SyncOptions::default())
+            .await?;
+        let ids = repo_manager.with_index_mut(|index| {
+            index
+                .source
+                .iter()
+                .map(|package| package.id.clone())
+                .collect::<Vec<_>>()
+        })?;
+        assert_eq!(ids, ["app-a", "app-c", "other-app"]);
+
+        // 没有变化时不改写索引
+        let report = repo_manager
+            .sync_repository("local", &SyncOptions::default())
+            .await?;
+        assert!(report.changelog.is_empty());
+        assert!(
+            !report
+                .changes
+                .changes()
+                .iter()
+                .any(|change| matches!(change, PlannedChange::EditIndex(_)))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_changelog_since_last_sync() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let source_repo = RepoManager::init(&source_path)?;
+        let package = |id: &str, version: &str| PackageInfo {
+            latest_version: version.to_string(),
+            ..package_info(id)
+        };
+        source_repo.with_index_mut(|index| {
+            index.packages = vec![package("app-a", "1.0.0"), package("app-b", "1.0.0")]
+        })?;
+
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+
+        // 尚未同步时全部视为新增
+        let changelog = repo_manager.changelog("local").await?;
+        assert_eq!(
+            changelog_summary(&changelog),
+            "新增 2 个，更新 0 个，移除 0 个"
+        );
+
+        repo_manager.update_source_index().await?;
+        assert!(repo_manager.changelog("local").await?.is_empty());
+
+        source_repo.with_index_mut(|index| {
+            index.packages = vec![package("app-a", "1.1.0"), package("app-c", "1.0.0")]
+        })?;
+        let changelog = repo_manager.changelog("local").await?;
+        assert_eq!(
+            changelog
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            [
+                "更新 app-a: 1.0.0 -> 1.1.0",
+                "移除 app-b:1.0.0",
+                "新增 app-c:1.0.0"
+            ]
+        );
+
+        // 同步同样更新快照
+        repo_manager
+            .sync_repository("local", &SyncOptions::default())
+            .await?;
+        assert!(repo_manager.changelog("local").await?.is_empty());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_file_modes_preserved() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = TempDir::new()?;
+        let mode = |path: PathBuf| -> Result<u32> {
+            Ok(fs::metadata(path)?.permissions().mode() & 0o7777)
+        };
+
+        // 软件包中带一个可执行脚本
+        let package_path = temp_dir.path().join("script-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "script-app")?;
+        let script_path = package_path.join("helper.sh");
+        fs::write(&script_path, "#!/bin/sh\n")?;
+        fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        app::testing::add_file(script_path.clone(), package_path.clone())?;
+        let metadata: PackageMetadata = load_json(&package_path.join("metadata.json"))?;
+        assert_eq!(metadata.get_file_mode("helper.sh"), Some(0o755));
+        assert_eq!(metadata.get_file_mode("index.html"), None);
+
+        // 清单中的 setuid 和全局可写位不会被应用
+        let mut metadata = metadata;
+        metadata
+            .file_modes
+            .insert("helper.sh".to_string(), "4777".to_string());
+        save_json(&metadata, &package_path.join("metadata.json"))?;
+
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        source_repo.add_package(&package_path, false)?;
+        let stored_dir = source_path.join("packages/script-app/0.0.0");
+        assert_eq!(mode(stored_dir.join("helper.sh"))?, 0o755);
+
+        // 从本地源安装：文件以默认权限写入后按清单恢复
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+        repo_manager.update_source_index().await?;
+        fs::set_permissions(
+            stored_dir.join("helper.sh"),
+            fs::Permissions::from_mode(0o644),
+        )?;
+        repo_manager
+            .install_package("local:script-app", None, &InstallOptions::default())
+            .await?;
+        let installed_dir = repo_manager.repo_path.join("packages/script-app/0.0.0");
+        assert_eq!(mode(installed_dir.join("helper.sh"))?, 0o755);
+        assert_eq!(mode(installed_dir.join("index.html"))? & 0o111, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reindex() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        let mut repo_manager = RepoManager::init(&repo_path)?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "v1")?;
+        app::testing::add_file(&package_path.join("index.html"), &package_path)?;
+        repo_manager.add_package(&package_path, false)?;
+
+        // 损坏索引并删除版本历史
+        fs::write(repo_path.join("index.json"), "{ not json")?;
+        fs::remove_file(repo_path.join("packages/test-app/versions.txt"))?;
+
+        let rebuilt = repo_manager.reindex(false).await?;
+        assert_eq!(rebuilt, vec!["test-app".to_string()]);
+        assert_eq!(
+            read_version_history(&repo_path.join("packages/test-app/versions.txt"))?,
+            vec!["0.0.0".to_string()]
+        );
+
+        let index: RepositoryIndex = load_json(&repo_path.join("index.json"))?;
+        assert_eq!(index.packages.len(), 1);
+        assert_eq!(index.packages[0].id, "test-app");
+        assert!(index.source.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_index_mut_concurrent() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        RepoManager::init(&repo_path)?;
+
+        // 多个写入者并发修改索引（等待彼此释放写锁），任何一次修改都不应丢失
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let repo_path = repo_path.clone();
+                std::thread::spawn(move || -> Result<()> {
+                    let repo_manager =
+                        RepoManager::open_with_lock(&repo_path, LockMode::Shared, true)?;
+                    repo_manager.with_index_mut(|index| {
+                        index.packages.push(package_info(&format!("app-{i}")))
+                    })
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        let index: RepositoryIndex = load_json(&repo_path.join("index.json"))?;
+        assert_eq!(index.packages.len(), 8);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repo_lock() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo_path = temp_dir.path().join("repo");
+        RepoManager::init(&repo_path)?;
+
+        // 共享锁可以同时持有，此时独占锁立即报错
+        let reader = RepoManager::open(&repo_path)?;
+        let other_reader = RepoManager::open_with_lock(&repo_path, LockMode::Shared, false)?;
+        let Err(err) = RepoManager::open_with_lock(&repo_path, LockMode::Exclusive, false) else {
+            panic!("共享锁持有期间不应获得独占锁");
+        };
+        assert!(err.to_string().contains("仓库正被另一个进程使用"));
+
+        // 其他进程持有共享锁时，未指定等待的升级立即报错并保留原有的共享锁
+        let err = reader
+            .with_index_mut(|index| index.packages.push(package_info("app")))
+            .unwrap_err();
+        assert!(err.to_string().contains("仓库正被另一个进程使用"));
+        drop(other_reader);
+        assert!(RepoManager::open_with_lock(&repo_path, LockMode::Exclusive, false).is_err());
+
+        // 以共享模式打开的仓库修改索引时临时升级为独占锁，完成后降回共享锁
+        reader.with_index_mut(|index| index.packages.push(package_info("app")))?;
+        let other_reader = RepoManager::open(&repo_path)?;
+        assert!(RepoManager::open_with_lock(&repo_path, LockMode::Exclusive, false).is_err());
+        drop(reader);
+
+        // 指定等待时升级阻塞到其他进程释放锁
+        let waiting_reader = RepoManager::open_with_lock(&repo_path, LockMode::Shared, true)?;
+        let upgrade = std::thread::spawn(move || {
+            waiting_reader.with_index_mut(|index| index.packages.push(package_info("other")))
+        });
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!upgrade.is_finished());
+        drop(other_reader);
+        upgrade.join().unwrap()?;
+
+        // 独占锁持有期间共享锁同样报错，等待模式则阻塞到锁被释放
+        let writer = RepoManager::open_with_lock(&repo_path, LockMode::Exclusive, false)?;
+        assert!(RepoManager::open(&repo_path).is_err());
+        let waiter = {
+            let repo_path = repo_path.clone();
+            std::thread::spawn(move || {
+                RepoManager::open_with_lock(&repo_path, LockMode::Shared, true).map(|_| ())
+            })
+        };
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert!(!waiter.is_finished());
+        drop(writer);
+        waiter.join().unwrap()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_import() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut source_repo = RepoManager::init(temp_dir.path().join("source"))?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "v1")?;
+        app::testing::add_file(&package_path.join("index.html"), &package_path)?;
+        source_repo.add_package(&package_path, false)?;
+
+        let archive = temp_dir.path().join("export.tar.gz");
+        assert!(source_repo.export(&archive, false)? > 0);
+
+        let target_path = temp_dir.path().join("target");
+        let mut target_repo = RepoManager::init(&target_path)?;
+        let imported = target_repo.import(&archive)?;
+        assert_eq!(imported, vec!["test-app:0.0.0".to_string()]);
+        assert_eq!(
+            fs::read_to_string(target_path.join("packages/test-app/0.0.0/index.html"))?,
+            "v1"
+        );
+        let index: RepositoryIndex = load_json(&target_path.join("index.json"))?;
+        assert_eq!(index.packages.len(), 1);
+
+        // 再次导入时跳过已存在的版本
+        assert!(target_repo.import(&archive)?.is_empty());
+
+        Ok(())
+    }
+
+    /// 在本机回环地址上以 HTTP 提供目录中的静态文件，返回根 URL
+    ///
+    /// 支持 `Range: bytes=<start>-` 形式的范围请求
+    async fn serve_dir(root: PathBuf) -> Result<String> {
+        serve_dir_with_etag(root, false).await
+    }
+
+    /// 同 [`serve_dir`]，`etag` 为真时返回以内容哈希为值的 `ETag`，
+    /// 并对带有匹配 `If-None-Match` 的请求返回 304
+    async fn serve_dir_with_etag(root: PathBuf, etag: bool) -> Result<String> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let root = root.clone();
+                tokio::spawn(async move {
+                    let mut buffer = vec![0; 8192];
+                    let read = stream.read(&mut buffer).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buffer[..read]);
+                    let path = request.split_whitespace().nth(1).unwrap_or("/");
+                    let range_start = request.lines().find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        if !name.eq_ignore_ascii_case("range") {
+                            return None;
+                        }
+                        value
+                            .trim()
+                            .strip_prefix("bytes=")?
+                            .strip_suffix('-')?
+                            .parse::<usize>()
+                            .ok()
+                    });
+                    let header_value = |header: &str| {
+                        request.lines().find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            name.eq_ignore_ascii_case(header)
+                                .then(|| value.trim().to_string())
+                        })
+                    };
+                    let (status, extra, body) = match (
+                        fs::read(root.join(path.trim_start_matches('/'))),
+                        range_start,
+                    ) {
+                        (Ok(body), None) if etag => {
+                            let tag = format!("\"{}\"", crypto::bytes_hash(&body));
+                            if header_value("if-none-match").as_ref() == Some(&tag) {
+                                ("304 Not Modified", String::new(), Vec::new())
+                            } else {
+                                ("200 OK", format!("ETag: {tag}\r\n"), body)
+                            }
+                        }
+                        (Ok(body), Some(start)) if start >= body.len() => {
+                            ("416 Range Not Satisfiable", String::new(), Vec::new())
+                        }
+                        (Ok(body), Some(start)) => (
+                            "206 Partial Content",
+                            format!(
+                                "Content-Range: bytes {start}-{}/{}\r\n",
+                                body.len() - 1,
+                                body.len()
+                            ),
+                            body[start..].to_vec(),
+                        ),
+                        (Ok(body), None) => ("200 OK", String::new(), body),
+                        (Err(_), _) => ("404 Not Found", String::new(), Vec::new()),
+                    };
+                    let header = format!(
+                        "HTTP/1.1 {status}\r\n{extra}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    );
+                    let _ = stream.write_all(header.as_bytes()).await;
+                    let _ = stream.write_all(&body).await;
+                });
+            }
+        });
+        Ok(format!("http://{address}/"))
+    }
+
+    #[tokio::test]
+    async fn test_run_downloads_retries() -> Result<()> {
+        use std::sync::atomic::AtomicUsize;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // 第一次请求返回 503，之后返回文件内容
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let address = listener.local_addr()?;
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0; 4096];
+                let _ = stream.read(&mut buffer).await;
+                let response = if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    "HTTP/1.1 503 X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello"
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let temp_dir = TempDir::new()?;
+        let dest_path = temp_dir.path().join("index.html");
+        let downloads = vec![FileDownload {
+            file_path: "index.html".to_string(),
+            urls: vec![format!("http://{address}/index.html")],
+            dest_path: dest_path.clone(),
+            expected_hash: crypto::bytes_hash(b"hello"),
+            size: Some(5),
+        }];
+        let verified = run_downloads(
+            downloads,
+            &test_client(),
+            &Arc::default(),
+            "test",
+            1,
+            &Arc::new(InstallProgress::new(5)),
+        )
+        .await?;
+
+        assert_eq!(fs::read_to_string(&dest_path)?, "hello");
+        assert!(verified.contains_key("index.html"));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_update_source_index_not_modified() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
+
+        let mut client_repo = RepoManager::init(temp_dir.path().join("client"))?;
+        client_repo.config.cache_dir = temp_dir.path().join("cache").to_string_lossy().to_string();
+        client_repo.config.source.push(test_source(
+            serve_dir_with_etag(source_path.clone(), true).await?,
+        ));
+        let source_entry = |repo: &RepoManager| -> Result<PackageInfo> {
+            let index: RepositoryIndex = load_json(&repo.repo_path.join("index.json"))?;
+            Ok(index.source[0].clone())
+        };
+
+        client_repo.update_source_index().await?;
+        let cache_dir = client_repo.index_cache_dir("local");
+        let validators: net::IndexValidators = load_json(&cache_dir.join("validators.json"))?;
+        assert!(validators.etag.is_some());
+
+        // 索引未改变时服务器返回 304，直接使用缓存的索引（缓存中的名称改动说明没有重新下载）
+        let cached_path = cache_dir.join("index.json");
+        let mut cached: RepositoryIndex = load_json(&cached_path)?;
+        cached.packages[0].name = "来自缓存".to_string();
+        save_json(&cached, &cached_path)?;
+        client_repo.update_source_index().await?;
+        assert_eq!(source_entry(&client_repo)?.name, "来自缓存");
+
+        // 同步时同样发送条件请求，不再依赖 update 写入的快照
+        let snapshot_path = client_repo.sync_snapshot_path("local");
+        fs::remove_file(&snapshot_path)?;
+        client_repo.with_index_mut(|index| index.source.clear())?;
+        client_repo
+            .sync_repository("local", &SyncOptions::default())
+            .await?;
+        let index: RepositoryIndex = load_json(&client_repo.repo_path.join("index.json"))?;
+        assert_eq!(index.source[0].name, "来自缓存");
+        assert!(snapshot_path.exists());
+
+        // 索引改变后重新下载
+        let metadata_path = package_path.join("metadata.json");
+        let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+        metadata.version = "0.1.0".to_string();
+        save_json(&metadata, &metadata_path)?;
+        source_repo.add_package(&package_path, false)?;
+        client_repo.update_source_index().await?;
+        let entry = source_entry(&client_repo)?;
+        assert_eq!(entry.latest_version, "0.1.0");
+        assert_ne!(entry.name, "来自缓存");
+
+        // 缓存的索引缺失时不发送条件请求
+        fs::remove_file(&cached_path)?;
+        client_repo.update_source_index().await?;
+        assert!(cached_path.exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reject_path_traversal() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
+
+        // 恶意源：清单中的路径越出软件包目录，元数据自身哈希照常更新
+        let metadata_path = source_path.join("packages/test-app/0.0.0/metadata.json");
+        let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+        metadata.add_file(
+            "../../../../escape.txt".to_string(),
+            crypto::bytes_hash(b"evil"),
+        );
+        metadata.metadata_hash = Some(metadata.compute_metadata_hash()?);
+        save_json(&metadata, &metadata_path)?;
+
+        let source_url = serve_dir(source_path.clone()).await?;
+        let source = SourceConfig {
+            id: "evil".to_string(),
+            name: "Evil".to_string(),
+            ..test_source(source_url.clone())
+        };
+
+        // 安装时拒绝
+        let mut client_repo = RepoManager::init(temp_dir.path().join("client"))?;
+        client_repo.config.source.push(source.clone());
+        client_repo.update_source_index().await?;
+        let error = client_repo
+            .install_package("evil:test-app", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("文件路径越出软件包目录"));
+
+        // 镜像同步时拒绝
+        let mirror_path = temp_dir.path().join("mirror");
+        let error = net::mirror_sync(
+            &test_client(),
+            &source_url,
+            &source,
+            &mirror_path.to_string_lossy(),
+            &net::MirrorOptions::default(),
+        )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("文件路径越出软件包目录"));
+
+        // 元数据中的软件包ID与源索引不一致（拼接目录时会越出 packages/）
+        metadata.remove_file("../../../../escape.txt");
+        metadata.id = "../../escape".to_string();
+        metadata.metadata_hash = Some(metadata.compute_metadata_hash()?);
+        save_json(&metadata, &metadata_path)?;
+        let error = client_repo
+            .install_package("evil:test-app", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("与源索引中的 test-app 不一致"));
+
+        // 源索引中的软件包ID越出 packages/
+        let index_path = source_path.join("index.json");
+        let mut index: RepositoryIndex = load_json(&index_path)?;
+        for package in index.packages.iter_mut().chain(index.source.iter_mut()) {
+            package.id = "../../escape".to_string();
+        }
+        save_json(&index, &index_path)?;
+        client_repo.update_source_index().await?;
+        let error = client_repo
+            .install_package("evil:../../escape", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("源索引中的软件包ID不合法"));
+
+        assert!(!temp_dir.path().join("escape.txt").exists());
+        assert!(!temp_dir.path().join("escape").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_mirror_dry_run() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        add_package_with_dependencies(&mut source_repo, temp_dir.path(), "test-app", &[])?;
+        source_repo.update_local_index()?;
+
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo
+            .config
+            .source
+            .push(test_source(serve_dir(source_path).await?));
+        let index_before = fs::read_to_string(client_path.join("index.json"))?;
+
+        let report = client_repo
+            .sync_repository(
+                "local",
+                &SyncOptions {
+                    mirror: true,
+                    dry_run: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let changes = report.changes.changes();
+        assert!(changes.contains(&PlannedChange::Download(
+            client_path.join("packages/test-app/0.0.0/index.html")
+        )));
+        assert!(changes.contains(&PlannedChange::EditIndex(
+            "重新扫描 packages/ 更新 packages 部分".to_string()
+        )));
+
+        // 演练不写入软件包、索引、同步状态和快照
+        assert!(!client_path.join("packages/test-app").exists());
+        assert_eq!(
+            fs::read_to_string(client_path.join("index.json"))?,
+            index_before
+        );
+        assert!(!client_path.join("sync.json").exists());
+        assert!(!client_repo.sync_snapshot_path("local").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_sync_force_mode_switch() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        add_package_with_dependencies(&mut source_repo, temp_dir.path(), "test-app", &[])?;
+        source_repo.update_local_index()?;
+
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo
+            .config
+            .source
+            .push(test_source(serve_dir(source_path).await?));
+        client_repo
+            .sync_repository(
+                "local",
+                &SyncOptions {
+                    mirror: true,
+                    ..Default::default()
+                },
+            )
+            .await?;
+        let mirrored_file = client_path.join("packages/test-app/0.0.0/index.html");
+        assert!(mirrored_file.exists());
+
+        // 镜像后切换为增量模式需要 --force，拒绝时不清理镜像文件
+        let err = client_repo
+            .sync_repository("local", &SyncOptions::default())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("--force"));
+        assert!(mirrored_file.exists());
+
+        // 演练只列出将要清理的镜像文件
+        let force = |dry_run: bool| SyncOptions {
+            force: true,
+            dry_run,
+            ..Default::default()
+        };
+        let report = client_repo.sync_repository("local", &force(true)).await?;
+        assert!(
+            report
+                .changes
+                .changes()
+                .contains(&PlannedChange::RemoveFile(mirrored_file.clone()))
+        );
+        assert!(mirrored_file.exists());
+
+        // --force 清理镜像文件并记录为增量模式，随之移除失效的版本，不留下不一致的索引
+        client_repo.sync_repository("local", &force(false)).await?;
+        assert!(!mirrored_file.exists());
+        assert!(!client_path.join("packages/test-app").exists());
+        assert!(client_repo.doctor(false)?.is_empty());
+        let state: SyncState = load_json(&client_path.join("sync.json"))?;
+        let source_state = &state.sources["local"];
+        assert_eq!(source_state.mode, SyncMode::Incremental);
+        assert!(source_state.mirrored_files.is_empty());
+
+        // 之后的增量同步不再需要 --force
+        client_repo
+            .sync_repository("local", &SyncOptions::default())
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mirror_sync_is_servable() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut source_repo = RepoManager::init(temp_dir.path().join("source"))?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
+
+        // 镜像源仓库
+        let source_url = serve_dir(temp_dir.path().join("source")).await?;
+        let mirror_path = temp_dir.path().join("mirror");
+        let source = SourceConfig {
+            id: "source".to_string(),
+            name: "Source".to_string(),
+            verify_index: Some(true),
+            ..test_source(source_url.clone())
+        };
+        let client = test_client();
+        let mirror = |dry_run: bool| {
+            let target_dir = mirror_path.to_string_lossy().to_string();
+            let (client, source_url, source) = (&client, &source_url, &source);
+            async move {
+                net::mirror_sync(
+                    client,
+                    source_url,
+                    source,
+                    &target_dir,
+                    &net::MirrorOptions { dry_run },
+                )
+                .await
+                .map_err(|e| anyhow!("{}", e))
+            }
+        };
+
+        // 演练只列出将写入的文件，不创建目标目录
+        let version_dir = mirror_path.join("packages/test-app/0.0.0");
+        let mut planned: Vec<PathBuf> =
+            mirror(true).await?.into_iter().map(PathBuf::from).collect();
+        planned.sort();
+        assert_eq!(
+            planned,
+            [
+                version_dir.join("index.html"),
+                version_dir.join("metadata.json")
+            ]
+        );
+        assert!(!mirror_path.exists());
+
+        mirror(false).await?;
+        // 同步后再次演练没有需要写入的文件
+        assert!(mirror(true).await?.is_empty());
+        let mirror_index: RepositoryIndex = load_json(&mirror_path.join("index.json"))?;
+        assert_eq!(mirror_index.packages.len(), 1);
+        assert_eq!(
+            mirror_index.packages[0].location,
+            "./packages/test-app/0.0.0"
+        );
+
+        // 将镜像目录作为新软件源并从中安装
+        let mirror_url = serve_dir(mirror_path).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(SourceConfig {
+            id: "mirror".to_string(),
+            name: "Mirror".to_string(),
+            verify_index: Some(true),
+            ..test_source(mirror_url)
+        });
+        client_repo.update_source_index().await?;
+        client_repo
+            .install_package("mirror:test-app", None, &InstallOptions::default())
+            .await?;
+        assert_eq!(
+            fs::read_to_string(client_path.join("packages/test-app/0.0.0/index.html"))?,
+            "hello"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_source_index() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
+        let public_key = source_repo.sign_index(&[1; 32])?;
+        assert!(source_path.join("index.json.sig").exists());
+
+        let source_url = serve_dir(source_path.clone()).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(SourceConfig {
+            trusted_key: Some(crypto::hex_encode(&public_key)),
+            verify_index: Some(true),
+            ..test_source(source_url)
+        });
+        client_repo.update_source_index().await?;
+        let index: RepositoryIndex = load_json(&client_path.join("index.json"))?;
+        assert_eq!(index.source.len(), 1);
+
+        // 签名与受信任公钥不符
+        source_repo.sign_index(&[2; 32])?;
+        let error = client_repo.update_source_index().await.unwrap_err();
+        assert!(error.to_string().contains("索引签名无效"));
+
+        // 修改索引后过期的签名被删除，配置了 trusted_key 时拒绝未签名的索引
+        source_repo.with_index_mut(|_| ())?;
+        assert!(!source_path.join("index.json.sig").exists());
+        let error = client_repo.update_source_index().await.unwrap_err();
+        assert!(error.to_string().contains("索引校验失败"));
+        assert!(error.to_string().contains("源未发布 index.json.sig"));
+
+        // 未配置 trusted_key 时只校验哈希
+        client_repo.config.source[0].trusted_key = None;
+        client_repo.update_source_index().await?;
+        client_repo.config.source[0].trusted_key = Some(crypto::hex_encode(&public_key));
+
+        // 索引内容被篡改
+        let index_path = source_path.join("index.json");
+        let content = fs::read_to_string(&index_path)?;
+        fs::write(&index_path, format!("{content} "))?;
+        let error = client_repo.update_source_index().await.unwrap_err();
+        assert!(error.to_string().contains("索引校验失败"));
+        assert!(error.to_string().contains("索引哈希不匹配"));
+
+        // 未发布哈希文件
+        fs::write(&index_path, content)?;
+        fs::remove_file(source_path.join("index.json.sha256"))?;
+        let error = client_repo.update_source_index().await.unwrap_err();
+        assert!(error.to_string().contains("源未发布 index.json.sha256"));
+
+        // 获取失败与校验失败分别报告
+        fs::remove_file(&index_path)?;
+        let error = client_repo.update_source_index().await.unwrap_err();
+        assert!(error.to_string().contains("获取索引失败"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_download_file_resumable() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let content: Vec<u8> = (0..10000).map(|i| (i % 251) as u8).collect();
+        fs::write(temp_dir.path().join("data.bin"), &content)?;
+        let url = format!(
+            "{}data.bin",
+            serve_dir(temp_dir.path().to_path_buf()).await?
+        );
+
+        let dest = temp_dir.path().join("download/data.bin");
+        let dest_str = dest.to_string_lossy();
+        let client = test_client();
+        let download = || async {
+            net::download_file_resumable(&client, &url, &dest_str, &net::ConsoleProgress::default())
+                .await
+                .map_err(|e| anyhow!("{}", e))
+        };
+
+        // 不存在时完整下载
+        assert!(!download().await?);
+        assert_eq!(fs::read(&dest)?, content);
+
+        // 中断的部分下载在已有内容之后续传，已有内容一并计入进度
+        fs::write(&dest, &content[..4000])?;
+        let progress = FileProgress {
+            overall: Arc::new(InstallProgress::new(0)),
+            counted: AtomicBool::new(false),
+        };
+        assert!(
+            net::download_file_resumable(&client, &url, &dest_str, &progress)
+                .await
+                .map_err(|e| anyhow!("{}", e))?
+        );
+        assert_eq!(fs::read(&dest)?, content);
+        let overall = &progress.overall;
+        assert_eq!(overall.total.load(Ordering::Relaxed), content.len() as u64);
+        assert_eq!(
+            overall.downloaded.load(Ordering::Relaxed),
+            content.len() as u64
+        );
+
+        // 已完整时服务器返回 416，重新完整下载
+        assert!(!download().await?);
+        assert_eq!(fs::read(&dest)?, content);
+
+        Ok(())
+    }
+
+    /// 在软件源仓库中添加带依赖的软件包（依赖为（软件包ID, 版本约束））
+    fn add_package_with_dependencies(
+        source_repo: &mut RepoManager,
+        dir: &Path,
+        package_id: &str,
+        dependencies: &[(&str, &str)],
+    ) -> Result<()> {
+        let package_path = dir.join(package_id);
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), package_id)?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        for (id, version_req) in dependencies {
+            app::add_dependency(&package_path, id, version_req)?;
+        }
+        source_repo.add_package(&package_path, false)
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_respects_pin() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        let metadata_path = package_path.join("metadata.json");
+        for version in ["1.0.0", "1.1.0", "2.0.0"] {
+            let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+            metadata.version = version.to_string();
+            save_json(&metadata, &metadata_path)?;
+            source_repo.add_package(&package_path, false)?;
+        }
+        source_repo.update_local_index()?;
+
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+        repo_manager.update_source_index().await?;
+        repo_manager
+            .install_package("local:test-app", Some("1.0.0"), &InstallOptions::default())
+            .await?;
+
+        // 只升级到满足固定约束的最高版本
+        repo_manager.pin_package("test-app", "^1.0.0")?;
+        assert_eq!(
+            repo_manager.upgrade_package("test-app").await?,
+            UpgradeOutcome::Upgraded {
+                from: "1.0.0".to_string(),
+                to: "1.1.0".to_string(),
+            }
+        );
+        assert_eq!(
+            repo_manager.upgrade_package("test-app").await?,
+            UpgradeOutcome::Pinned {
+                version: "1.1.0".to_string(),
+                pin: "^1.0.0".to_string(),
+                latest: "2.0.0".to_string(),
+            }
+        );
+
+        // 取消固定后升级到最新版本，固定状态保存在仓库配置中
+        repo_manager.unpin_package("test-app")?;
+        let config = ConfigManager::new(repo_manager.repo_path.join("config.toml"))?.load()?;
+        assert!(config.pinned.is_empty());
+        repo_manager.upgrade_package("test-app").await?;
+        assert_eq!(
+            repo_manager.upgrade_package("test-app").await?,
+            UpgradeOutcome::UpToDate {
+                version: "2.0.0".to_string(),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_non_semver_versions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        let metadata_path = package_path.join("metadata.json");
+        for version in ["nightly-1", "nightly-2"] {
+            let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+            metadata.version = version.to_string();
+            save_json(&metadata, &metadata_path)?;
+            source_repo.add_package(&package_path, false)?;
+        }
+        source_repo.update_local_index()?;
+
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+        repo_manager.update_source_index().await?;
+        repo_manager
+            .install_package(
+                "local:test-app",
+                Some("nightly-1"),
+                &InstallOptions::default(),
+            )
+            .await?;
+
+        // 无法按 SemVer 比较时按版本清单中的位置判断，软件源中后发布的版本更新
+        assert_eq!(
+            repo_manager.upgrade_package("test-app").await?,
+            UpgradeOutcome::Upgraded {
+                from: "nightly-1".to_string(),
+                to: "nightly-2".to_string(),
             }
-        };
+        );
+        assert_eq!(
+            repo_manager.upgrade_package("test-app").await?,
+            UpgradeOutcome::UpToDate {
+                version: "nightly-2".to_string(),
+            }
+        );
 
-        // 清空 packages 部分
-        index.packages.clear();
+        Ok(())
+    }
 
-        // 扫描 packages/ 目录
-        let packages_dir = self.repo_path.join("packages");
-        if packages_dir.exists() && packages_dir.is_dir() {
-            for entry in fs::read_dir(packages_dir)? {
-                let entry = entry?;
-                if entry.file_type()?.is_dir() {
-                    let package_dir = entry.path();
-                    let package_name = entry.file_name().to_string_lossy().to_string();
+    #[tokio::test]
+    async fn test_install_auto_prune() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
 
-                    // 获取最新版本的目录
-                    let versions = read_version_history(&package_dir.join("versions.txt"))?;
-                    if let Some(latest_version) = versions.last() {
-                        let version_dir = package_dir.join(latest_version);
-                        if version_dir.exists() && version_dir.is_dir() {
-                            // 读取 metadata.json
-                            let metadata_path = version_dir.join("metadata.json");
-                            if metadata_path.exists() {
-                                let metadata: PackageMetadata = load_json(&metadata_path)?;
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        let metadata_path = package_path.join("metadata.json");
+        for version in ["1.0.0", "1.1.0", "1.2.0"] {
+            let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+            metadata.version = version.to_string();
+            save_json(&metadata, &metadata_path)?;
+            source_repo.add_package(&package_path, false)?;
+        }
+        source_repo.update_local_index()?;
 
-                                // 创建包信息
-                                let package_info = PackageInfo {
-                                    id: metadata.id.clone(),
-                                    name: metadata.name.clone(),
-                                    icon: metadata.icon.clone(),
-                                    author: metadata.author.clone(),
-                                    latest_version: metadata.version.clone(),
-                                    description: metadata.description.clone(),
-                                    location: version_dir.to_string_lossy().to_string(),
-                                };
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+        repo_manager.update_source_index().await?;
+        let package_dir = repo_manager.repo_path.join("packages/test-app");
 
-                                // 添加到索引
-                                index.packages.push(package_info);
-                            }
-                        }
-                    }
+        // 未配置时不清理
+        for version in ["1.0.0", "1.1.0"] {
+            repo_manager
+                .install_package("local:test-app", Some(version), &InstallOptions::default())
+                .await?;
+        }
+        assert!(package_dir.join("1.0.0").is_dir());
+        assert!(package_dir.join("1.1.0").is_dir());
+
+        repo_manager.config.auto_prune_keep = Some(1);
+        repo_manager
+            .install_package("local:test-app", Some("1.2.0"), &InstallOptions::default())
+            .await?;
+        assert!(!package_dir.join("1.0.0").exists());
+        assert!(!package_dir.join("1.1.0").exists());
+        assert!(package_dir.join("1.2.0").is_dir());
+        assert_eq!(repo_manager.version_history("test-app")?, vec!["1.2.0"]);
+
+        // 刚安装的旧版本不会被清理
+        repo_manager
+            .install_package("local:test-app", Some("1.0.0"), &InstallOptions::default())
+            .await?;
+        assert!(package_dir.join("1.0.0").is_dir());
+        assert!(!package_dir.join("1.2.0").exists());
+        let installed = repo_manager.installed_package("test-app")?.unwrap();
+        assert_eq!(installed.available_versions, vec!["1.0.0"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prune_keeps_locked_and_required_versions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let source_path = dir.join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        add_package_with_dependencies(&mut source_repo, dir, "test-app", &[])?;
+        let metadata_path = dir.join("test-app/metadata.json");
+        for version in ["1.0.0", "1.1.0", "1.2.0", "1.3.0"] {
+            let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+            metadata.version = version.to_string();
+            save_json(&metadata, &metadata_path)?;
+            source_repo.add_package(dir.join("test-app"), false)?;
+        }
+        add_package_with_dependencies(&mut source_repo, dir, "dep-app", &[("test-app", "~1.0")])?;
+        source_repo.update_local_index()?;
+
+        let mut repo_manager = RepoManager::init(dir.join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+        repo_manager.update_source_index().await?;
+        // dep-app 的依赖只能由 1.0.0 满足
+        repo_manager
+            .install_package("local:dep-app", None, &InstallOptions::default())
+            .await?;
+        for version in ["1.1.0", "1.2.0", "1.3.0"] {
+            repo_manager
+                .install_package("local:test-app", Some(version), &InstallOptions::default())
+                .await?;
+        }
+
+        let lockfile_path = dir.join(crate::lockfile::DEFAULT_LOCKFILE);
+        let mut lockfile = Lockfile::default();
+        lockfile.insert(
+            "test-app",
+            LockedPackage {
+                version: "1.1.0".to_string(),
+                source: "local".to_string(),
+                files: BTreeMap::new(),
+            },
+        );
+        lockfile.save(&lockfile_path)?;
+
+        repo_manager.prune_versions("test-app", 1, "1.3.0", &lockfile_path)?;
+        assert_eq!(
+            repo_manager.version_history("test-app")?,
+            ["1.0.0", "1.1.0", "1.3.0"]
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_all() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        let dir = temp_dir.path();
+        add_package_with_dependencies(&mut source_repo, dir, "app-a", &[])?;
+        add_package_with_dependencies(&mut source_repo, dir, "app-b", &[])?;
+        add_package_with_dependencies(&mut source_repo, dir, "app-c", &[])?;
+
+        let mut repo_manager = RepoManager::init(temp_dir.path().join("repo"))?;
+        repo_manager
+            .config
+            .source
+            .push(test_source(source_path.to_string_lossy()));
+        repo_manager.update_source_index().await?;
+        for id in ["app-a", "app-b"] {
+            repo_manager
+                .install_package(&format!("local:{id}"), None, &InstallOptions::default())
+                .await?;
+        }
+        // 不在软件源中的本地软件包不参与升级
+        add_package_with_dependencies(&mut repo_manager, &dir.join("local"), "app-local", &[])?;
+
+        // app-a 发布新版本；app-b 的索引指向不存在的版本，升级失败但不影响其余软件包
+        let metadata_path = dir.join("app-a/metadata.json");
+        let mut metadata: PackageMetadata = load_json(&metadata_path)?;
+        metadata.version = "0.1.0".to_string();
+        save_json(&metadata, &metadata_path)?;
+        source_repo.add_package(dir.join("app-a"), false)?;
+        repo_manager.update_source_index().await?;
+        repo_manager.with_index_mut(|index| {
+            for package in &mut index.source {
+                if package.id == "app-b" {
+                    package.latest_version = "9.0.0".to_string();
+                    package.available_versions.push("9.0.0".to_string());
                 }
             }
+        })?;
+
+        let results = repo_manager.upgrade_all().await?;
+        let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["app-a", "app-b"]);
+        assert_eq!(
+            results[0].outcome,
+            Some(UpgradeOutcome::Upgraded {
+                from: "0.0.0".to_string(),
+                to: "0.1.0".to_string(),
+            })
+        );
+        assert!(results[1].outcome.is_none() && results[1].error.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_install_resolves_dependencies() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+
+        // 菱形依赖：a -> b, a -> c, b -> d, c -> d
+        let dir = temp_dir.path();
+        add_package_with_dependencies(&mut source_repo, dir, "pkg-d", &[])?;
+        add_package_with_dependencies(&mut source_repo, dir, "pkg-b", &[("pkg-d", "*")])?;
+        add_package_with_dependencies(&mut source_repo, dir, "pkg-c", &[("pkg-d", ">=0.0.0")])?;
+        add_package_with_dependencies(
+            &mut source_repo,
+            dir,
+            "pkg-a",
+            &[("pkg-b", "*"), ("pkg-c", "*")],
+        )?;
+        // 循环依赖：e -> f -> e
+        add_package_with_dependencies(&mut source_repo, dir, "pkg-e", &[("pkg-f", "*")])?;
+        add_package_with_dependencies(&mut source_repo, dir, "pkg-f", &[("pkg-e", "*")])?;
+
+        let source_url = serve_dir(source_path).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(test_source(source_url));
+        client_repo.update_source_index().await?;
+
+        // 依赖在前，共同依赖只出现一次
+        assert_eq!(
+            client_repo.resolve_dependencies("local:pkg-a").await?,
+            [
+                "local:pkg-d:0.0.0",
+                "local:pkg-b:0.0.0",
+                "local:pkg-c:0.0.0",
+                "local:pkg-a:0.0.0"
+            ]
+        );
+
+        client_repo
+            .install_package("local:pkg-a", None, &InstallOptions::default())
+            .await?;
+        for package_id in ["pkg-a", "pkg-b", "pkg-c", "pkg-d"] {
+            assert_eq!(
+                read_version_history(
+                    &client_path.join(format!("packages/{package_id}/versions.txt"))
+                )?,
+                ["0.0.0"]
+            );
         }
 
-        // 保存更新后的索引
-        save_json(&index, &index_path)?;
+        let error = client_repo
+            .resolve_dependencies("local:pkg-e")
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("pkg-e -> pkg-f -> pkg-e"));
+        assert!(
+            client_repo
+                .install_package("local:pkg-e", None, &InstallOptions::default())
+                .await
+                .is_err()
+        );
+        assert!(!client_path.join("packages/pkg-e").exists());
 
         Ok(())
     }
-}
 
-/// 清理旧版本 (保留最新的2个版本)
-fn clean_old_versions(package_dir: &Path) -> Result<()> {
-    let mut versions: Vec<String> = fs::read_dir(package_dir)?
-        .filter_map(|entry| entry.ok().and_then(|e| e.file_name().into_string().ok()))
-        .collect();
+    #[tokio::test]
+    async fn test_install_concurrent_downloads() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        for i in 0..5 {
+            let file = package_path.join(format!("assets/{i}.js"));
+            fsxg::create_directory(file.parent().unwrap())?;
+            fs::write(&file, format!("console.log({i})"))?;
+        }
+        app::testing::add_file(package_path.join("assets"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
 
-    versions.sort();
+        let source_url = serve_dir(source_path.clone()).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.max_concurrent_downloads = Some(2);
+        client_repo.config.source.push(test_source(source_url));
+        client_repo.update_source_index().await?;
 
-    // 保留最新两个版本
-    if versions.len() > 2 {
-        for version in versions.iter().take(versions.len() - 2) {
-            let version_dir = package_dir.join(version);
-            if version_dir.is_dir() {
-                fsxg::remove_directory(&version_dir)?;
-            }
+        let lockfile_path = temp_dir.path().join(crate::lockfile::DEFAULT_LOCKFILE);
+        let options = InstallOptions {
+            lockfile: Some(lockfile_path.clone()),
+            ..InstallOptions::default()
+        };
+        client_repo
+            .install_package("local:test-app", None, &options)
+            .await?;
+        for i in 0..5 {
+            assert_eq!(
+                fs::read_to_string(
+                    client_path.join(format!("packages/test-app/0.0.0/assets/{i}.js"))
+                )?,
+                format!("console.log({i})")
+            );
         }
+        // 已有的部分内容损坏时续传后校验失败，删除后重新完整下载
+        let installed = client_path.join("packages/test-app/0.0.0/assets/1.js");
+        fs::write(&installed, "XXXXXXX")?;
+        client_repo
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await?;
+        assert_eq!(fs::read_to_string(&installed)?, "console.log(1)");
+
+        assert_eq!(
+            Lockfile::load(&lockfile_path)?.packages["test-app"]
+                .files
+                .len(),
+            6
+        );
+
+        // 任一文件哈希不匹配时整个安装失败
+        fs::write(
+            source_path.join("packages/test-app/0.0.0/assets/3.js"),
+            "tampered",
+        )?;
+        let error = client_repo
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("文件哈希不匹配: assets/3.js"));
+
+        Ok(())
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_install_rollback() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
 
-/// 更新版本历史
-fn update_version_history(package_id: &str, version: &str, repo_path: &Path) -> Result<()> {
-    let history_path = repo_path
-        .join("packages")
-        .join(package_id)
-        .join("versions.txt");
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        fs::write(package_path.join("app.js"), "console.log(1)")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        app::testing::add_file(package_path.join("app.js"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
 
-    let mut versions = if history_path.exists() {
-        fs::read_to_string(&history_path)?
-            .lines()
-            .map(|s| s.to_string())
-            .collect()
-    } else {
-        Vec::new()
-    };
+        let source_url = serve_dir(source_path.clone()).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(test_source(source_url));
+        client_repo.update_source_index().await?;
 
-    // 添加新版本（如果不存在）
-    if !versions.contains(&version.to_string()) {
-        versions.push(version.to_string());
-        fs::write(&history_path, versions.join("\n"))?;
+        let source_file = source_path.join("packages/test-app/0.0.0/app.js");
+        let installed_file = client_path.join("packages/test-app/0.0.0/app.js");
+        let installed_packages = |repo_path: &Path| -> Result<usize> {
+            let index: RepositoryIndex = load_json(&repo_path.join("index.json"))?;
+            Ok(index.packages.len())
+        };
+
+        // 首次安装时哈希不匹配：新建的目录被删除，索引不变
+        fs::write(&source_file, "tampered")?;
+        let error = client_repo
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("文件哈希不匹配: app.js"));
+        assert!(!client_path.join("packages/test-app").exists());
+        assert_eq!(installed_packages(&client_path)?, 0);
+
+        // 重新安装已安装的版本失败时恢复原有文件和版本历史
+        fs::write(&source_file, "console.log(1)")?;
+        client_repo
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await?;
+        fs::write(&source_file, "tampered")?;
+        assert!(
+            client_repo
+                .install_package("local:test-app", None, &InstallOptions::default())
+                .await
+                .is_err()
+        );
+        assert_eq!(fs::read_to_string(&installed_file)?, "console.log(1)");
+        assert_eq!(
+            fs::read_to_string(client_path.join("packages/test-app/versions.txt"))?,
+            "0.0.0"
+        );
+        assert_eq!(installed_packages(&client_path)?, 1);
+
+        Ok(())
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_install_checksum_override() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
 
-/// 更新包索引
-fn update_package_index(
-    metadata: &PackageMetadata,
-    package_dir: &Path,
-    index_path: &Path,
-) -> Result<()> {
-    let mut index: RepositoryIndex = if index_path.exists() {
-        load_json(index_path)?
-    } else {
-        RepositoryIndex {
-            packages: Vec::new(),
-            source: Vec::new(),
-        }
-    };
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        fs::write(package_path.join("app.js"), "console.log(1)")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        app::testing::add_file(package_path.join("app.js"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
 
-    // 创建包信息
-    let package_info = PackageInfo {
-        id: metadata.id.clone(),
-        name: metadata.name.clone(),
-        icon: metadata.icon.clone(),
-        author: metadata.author.clone(),
-        latest_version: metadata.version.clone(),
-        description: metadata.description.clone(),
-        location: format!("./packages/{}/{}", metadata.id, metadata.version),
-    };
+        let source_url = serve_dir(source_path.clone()).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(test_source(source_url));
+        client_repo.update_source_index().await?;
 
-    // 更新或添加包信息
-    if let Some(pos) = index.packages.iter().position(|p| p.id == metadata.id) {
-        index.packages[pos] = package_info;
-    } else {
-        index.packages.push(package_info);
+        // 源中的文件与清单不一致，未覆盖时安装失败
+        let source_file = source_path.join("packages/test-app/0.0.0/app.js");
+        fs::write(&source_file, "console.log(2)")?;
+        assert!(
+            client_repo
+                .install_package("local:test-app", None, &InstallOptions::default())
+                .await
+                .is_err()
+        );
+
+        // 覆盖中的文件不在清单中时在下载前报错
+        let overrides = |file_path: &str, hash: &str| InstallOptions {
+            checksum_overrides: BTreeMap::from([(file_path.to_string(), hash.to_string())]),
+            ..InstallOptions::default()
+        };
+        let patched_hash = HashAlgorithm::Sha256.format(&crypto::file_hash_with(
+            &source_file,
+            HashAlgorithm::Sha256,
+        )?);
+        let error = client_repo
+            .install_package(
+                "local:test-app",
+                None,
+                &overrides("missing.js", &patched_hash),
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("校验和覆盖中的文件不在软件包清单中: missing.js")
+        );
+        assert!(!client_path.join("packages/test-app").exists());
+
+        // 按覆盖值校验通过，覆盖记入安装记录
+        client_repo
+            .install_package("local:test-app", None, &overrides("app.js", &patched_hash))
+            .await?;
+        let version_dir = client_path.join("packages/test-app/0.0.0");
+        assert_eq!(
+            fs::read_to_string(version_dir.join("app.js"))?,
+            "console.log(2)"
+        );
+        let record = load_install_record(&version_dir)?.unwrap();
+        assert_eq!(record.checksum_overrides["app.js"], patched_hash);
+
+        // 之后校验已安装的软件包时同样使用覆盖值
+        let summary = client_repo.verify_installed()?;
+        assert_eq!((summary.passed, summary.failed), (1, 0));
+
+        Ok(())
     }
 
-    save_json(&index, index_path)?;
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_install_offline() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
 
-/// 从索引中移除包
-fn remove_package_from_index(
-    package_id: &str,
-    version: Option<&str>,
-    index_path: &Path,
-) -> Result<()> {
-    let mut index: RepositoryIndex = load_json(index_path)?;
+        let package_path = temp_dir.path().join("test-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "hello")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
 
-    if let Some(_version) = version {
-        // 移除特定版本（从版本历史中移除，但保留包记录）
-        if let Some(package) = index.packages.iter_mut().find(|p| p.id == package_id) {
-            // 更新最新版本为剩余版本中的最新版
-            let history_path = Path::new(&package.location)
-                .parent()
-                .unwrap()
-                .join("versions.txt");
+        let source_url = serve_dir(source_path.clone()).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(test_source(source_url));
+        client_repo.update_source_index().await?;
+        client_repo
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await?;
 
-            if let Ok(versions) = read_version_history(&history_path) {
-                if let Some(latest) = versions.last() {
-                    package.latest_version = latest.clone();
-                }
+        // 索引记录安装来源和时间，重建索引后仍保留；本地添加的软件包没有这些信息
+        let installed = client_repo.installed_package("test-app")?.unwrap();
+        assert_eq!(installed.installed_from.as_deref(), Some("local"));
+        assert!(installed.installed_at.is_some_and(|at| at.ends_with('Z')));
+        client_repo.update_local_index()?;
+        let listed = client_repo.list(ListFilter::Installed)?;
+        assert_eq!(listed[0].installed_from.as_deref(), Some("local"));
+        let added = source_repo.installed_package("test-app")?.unwrap();
+        assert!(added.installed_at.is_none() && added.installed_from.is_none());
+
+        client_repo.set_offline(true);
+        let error = client_repo.update_source_index().await.unwrap_err();
+        assert!(error.to_string().contains("离线模式已启用"));
+
+        // 源中的文件已被篡改，离线时不访问网络，只校验本地已有的文件
+        fs::write(
+            source_path.join("packages/test-app/0.0.0/index.html"),
+            "tampered",
+        )?;
+        client_repo
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await?;
+
+        // 本地缺少文件时离线安装失败
+        fs::remove_file(client_path.join("packages/test-app/0.0.0/index.html"))?;
+        let error = client_repo
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains("离线模式已启用，本地缺少文件: index.html")
+        );
+
+        // 未安装的版本没有本地元数据
+        client_repo.remove_package("test-app", None, false)?;
+        let error = client_repo
+            .install_package("local:test-app", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("离线模式已启用"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_install_verifies_signature() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+
+        for package_id in ["signed-app", "unsigned-app"] {
+            let package_path = temp_dir.path().join(package_id);
+            app::init(&package_path)?;
+            fs::write(package_path.join("index.html"), package_id)?;
+            app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+            if package_id == "signed-app" {
+                app::sign(&package_path, &[1; 32])?;
             }
+            source_repo.add_package(&package_path, false)?;
         }
-    } else {
-        // 移除整个包
-        index.packages.retain(|p| p.id != package_id);
+
+        let source_url = serve_dir(source_path.clone()).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(SourceConfig {
+            trusted_key: Some(crypto::hex_encode(&crypto::public_key(&[1; 32])?)),
+            ..test_source(source_url)
+        });
+        client_repo.update_source_index().await?;
+
+        client_repo
+            .install_package("local:signed-app", None, &InstallOptions::default())
+            .await?;
+        let error = client_repo
+            .install_package("local:unsigned-app", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("没有签名"));
+
+        // 由其他密钥签名的软件包被拒绝
+        client_repo.config.source[0].trusted_key =
+            Some(crypto::hex_encode(&crypto::public_key(&[2; 32])?));
+        let error = client_repo
+            .install_package("local:signed-app", None, &InstallOptions::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("签名验证失败"));
+
+        Ok(())
     }
 
-    save_json(&index, index_path)?;
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_install_from_mirror() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        let package_path = temp_dir.path().join("mirrored-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "mirrored-app")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        source_repo.add_package(&package_path, false)?;
 
-/// 读取版本历史
-fn read_version_history(path: &Path) -> Result<Vec<String>> {
-    if path.exists() {
-        Ok(fs::read_to_string(path)?
-            .lines()
-            .map(|s| s.to_string())
-            .collect())
-    } else {
-        Ok(Vec::new())
+        // 主 URL 对所有请求返回 404，镜像提供完整内容
+        let empty_path = temp_dir.path().join("empty");
+        fs::create_dir_all(&empty_path)?;
+        let primary_url = serve_dir(empty_path).await?;
+        let mirror_url = serve_dir(source_path.clone()).await?;
+
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(SourceConfig {
+            mirrors: vec![mirror_url],
+            ..test_source(primary_url)
+        });
+        client_repo.update_source_index().await?;
+        client_repo
+            .install_package("local:mirrored-app", None, &InstallOptions::default())
+            .await?;
+
+        let version = client_repo.package_info("mirrored-app").await?.version;
+        assert_eq!(
+            fs::read_to_string(
+                client_path
+                    .join("packages/mirrored-app")
+                    .join(version)
+                    .join("index.html")
+            )?,
+            "mirrored-app"
+        );
+
+        Ok(())
     }
-}
 
-/// 从版本历史中移除特定版本
-fn remove_version_from_history(package_id: &str, version: &str, repo_path: &Path) -> Result<()> {
-    let history_path = repo_path
-        .join("packages")
-        .join(package_id)
-        .join("versions.txt");
+    #[tokio::test]
+    async fn test_install_from_bundle() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
 
-    if history_path.exists() {
-        let mut versions: Vec<String> = fs::read_to_string(&history_path)?
-            .lines()
-            .map(|s| s.to_string())
-            .collect();
+        // 两个软件包都以归档形式加入软件源
+        for package_id in ["bundled-app", "fallback-app"] {
+            let package_path = temp_dir.path().join(package_id);
+            app::init(&package_path)?;
+            fs::write(package_path.join("index.html"), package_id)?;
+            app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+            let bundle = crate::pack::pack_bundle(&package_path, temp_dir.path())?;
+            source_repo.add_package(&bundle.path, false)?;
+        }
+        let listed = source_repo.with_index_mut(|index| index.packages.clone())?;
+        assert!(listed.iter().all(|package| package.bundle.is_some()));
 
-        versions.retain(|v| v != version);
+        // bundled-app 只保留归档和元数据，只能通过归档安装；fallback-app 删除归档，回退到逐个下载
+        let bundled_dir = source_path.join("packages/bundled-app/0.0.0");
+        fs::remove_file(bundled_dir.join("index.html"))?;
+        fs::remove_file(
+            source_path.join("packages/fallback-app/0.0.0/fallback-app-0.0.0.tar.zst"),
+        )?;
 
-        if versions.is_empty() {
-            fs::remove_file(&history_path)?;
-        } else {
-            fs::write(&history_path, versions.join("\n"))?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo
+            .config
+            .source
+            .push(test_source(serve_dir(source_path.clone()).await?));
+        client_repo.update_source_index().await?;
+        for package_id in ["bundled-app", "fallback-app"] {
+            // 未加 -v 时不输出归档地址和逐个下载的文件，回退到逐个下载时照常警告
+            let _lock = logging::testing::LOCK.lock().await;
+            logging::init(0);
+            logging::testing::start_capture();
+            let installed = client_repo
+                .install_package(
+                    &format!("local:{package_id}"),
+                    None,
+                    &InstallOptions::default(),
+                )
+                .await;
+            let output = logging::testing::finish_capture();
+            installed?;
+            // 归档地址为信息级别，逐个下载的文件为调试级别
+            assert!(!output.contains("信息:"), "{output}");
+            assert!(!output.contains("调试:"), "{output}");
+            assert_eq!(
+                output.contains("软件包归档不可用"),
+                package_id == "fallback-app",
+                "{output}"
+            );
+
+            let installed_dir = client_path.join("packages").join(package_id).join("0.0.0");
+            assert_eq!(
+                fs::read_to_string(installed_dir.join("index.html"))?,
+                package_id
+            );
+            assert!(
+                !installed_dir
+                    .join(format!("{package_id}-0.0.0.tar.zst"))
+                    .exists()
+            );
         }
+
+        Ok(())
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn test_verify_installed() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let source_path = temp_dir.path().join("source");
+        let mut source_repo = RepoManager::init(&source_path)?;
+        let package_path = temp_dir.path().join("signed-app");
+        app::init(&package_path)?;
+        fs::write(package_path.join("index.html"), "signed-app")?;
+        app::testing::add_file(package_path.join("index.html"), package_path.clone())?;
+        app::sign(&package_path, &[1; 32])?;
+        source_repo.add_package(&package_path, false)?;
 
-/// 移除整个包的历史记录
-fn remove_package_history(package_id: &str, repo_path: &Path) -> Result<()> {
-    let history_path = repo_path
-        .join("packages")
-        .join(package_id)
-        .join("versions.txt");
+        let source_url = serve_dir(source_path.clone()).await?;
+        let client_path = temp_dir.path().join("client");
+        let mut client_repo = RepoManager::init(&client_path)?;
+        client_repo.config.source.push(SourceConfig {
+            trusted_key: Some(crypto::hex_encode(&crypto::public_key(&[1; 32])?)),
+            ..test_source(source_url)
+        });
+        client_repo.update_source_index().await?;
+        client_repo
+            .install_package("local:signed-app", None, &InstallOptions::default())
+            .await?;
 
-    if history_path.exists() {
-        fs::remove_file(history_path)?;
-    }
+        let verification = client_repo.verify_installed()?;
+        assert_eq!((verification.passed, verification.failed), (1, 0));
+        assert_eq!(verification.packages[0].verified_files, 1);
 
-    Ok(())
+        // 软件源改用其他公钥后签名不再有效
+        client_repo.config.source[0].trusted_key =
+            Some(crypto::hex_encode(&crypto::public_key(&[2; 32])?));
+        let verification = client_repo.verify_installed()?;
+        assert_eq!(verification.failed, 1);
+        assert_eq!(verification.packages[0].errors, ["元数据签名验证失败"]);
+
+        // 文件被篡改
+        client_repo.config.source[0].trusted_key = None;
+        let version = client_repo.package_info("signed-app").await?.version;
+        fs::write(
+            client_path
+                .join("packages/signed-app")
+                .join(version)
+                .join("index.html"),
+            "tampered",
+        )?;
+        let verification = client_repo.verify_installed()?;
+        assert_eq!(verification.failed, 1);
+        assert_eq!(
+            verification.packages[0].errors,
+            ["文件哈希不匹配: index.html"]
+        );
+
+        Ok(())
+    }
 }
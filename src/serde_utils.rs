@@ -2,8 +2,10 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use crate::fsxg;
 use anyhow::Result;
 use std::fs;
+use std::io::Write;
 use std::path::Path;
 
 /// 从文件加载 TOML 配置
@@ -14,10 +16,11 @@ pub fn load_toml<T: for<'de> serde::Deserialize<'de>>(path: &Path) -> Result<T>
 }
 
 /// 保存 TOML 配置到文件
+///
+/// 原子写入（见 `fsxg::atomic_write`），写入中断时原文件保持不变
 pub fn save_toml<T: serde::Serialize>(value: &T, path: &Path) -> Result<()> {
     let content = toml::to_string_pretty(value)?;
-    fs::write(path, content)?;
-    Ok(())
+    fsxg::atomic_write(path, content)
 }
 
 /// 从文件加载 JSON 配置
@@ -28,8 +31,41 @@ pub fn load_json<T: for<'de> serde::Deserialize<'de>>(path: &Path) -> Result<T>
 }
 
 /// 保存 JSON 配置到文件
+///
+/// 原子写入（见 `fsxg::atomic_write_with`），边序列化边写入临时文件，写入中断时原文件保持不变
 pub fn save_json<T: serde::Serialize>(value: &T, path: &Path) -> Result<()> {
-    let content = serde_json::to_string_pretty(value)?;
-    fs::write(path, content)?;
-    Ok(())
+    fsxg::atomic_write_with(path, |file| {
+        let mut writer = std::io::BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, value)?;
+        writer.flush()?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_save_json_keeps_original_on_failure() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("index.json");
+
+        let original = BTreeMap::from([("version", 1)]);
+        save_json(&original, &path)?;
+        save_json(&BTreeMap::from([("version", 2)]), &path)?;
+        assert_eq!(load_json::<BTreeMap<String, i32>>(&path)?["version"], 2);
+
+        // 序列化中途失败（JSON 对象的键必须是字符串）时不触碰原文件
+        let invalid = HashMap::from([((1, 2), 3)]);
+        assert!(save_json(&invalid, &path).is_err());
+        assert_eq!(load_json::<BTreeMap<String, i32>>(&path)?["version"], 2);
+
+        // 没有遗留临时文件
+        assert_eq!(fs::read_dir(temp_dir.path())?.count(), 1);
+
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,174 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use clap::ValueEnum;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// 颜色输出模式
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// 仅当标准输出为终端且未设置 NO_COLOR 时启用颜色
+    Auto,
+    /// 始终启用颜色
+    Always,
+    /// 始终禁用颜色
+    Never,
+}
+
+/// 是否启用 ANSI 颜色
+static COLOR_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// 根据颜色模式初始化样式输出
+///
+/// 应在程序启动时调用一次；未调用时默认不输出颜色
+pub fn init(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Auto => {
+            std::io::stdout().is_terminal()
+                && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        }
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    COLOR_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// 当前是否启用颜色
+pub fn color_enabled() -> bool {
+    COLOR_ENABLED.load(Ordering::Relaxed)
+}
+
+/// 使用 ANSI SGR 代码包裹文本（颜色禁用时原样返回）
+fn paint(text: &str, code: &str) -> String {
+    if color_enabled() {
+        format!("\x1b[{code}m{text}\x1b[0m")
+    } else {
+        text.to_string()
+    }
+}
+
+/// 粗体
+pub fn bold(text: &str) -> String {
+    paint(text, "1")
+}
+
+/// 暗色（用于次要信息）
+pub fn dim(text: &str) -> String {
+    paint(text, "2")
+}
+
+/// 绿色（用于成功信息）
+pub fn green(text: &str) -> String {
+    paint(text, "32")
+}
+
+/// 黄色（用于警告信息）
+pub fn yellow(text: &str) -> String {
+    paint(text, "33")
+}
+
+/// 红色（用于错误信息）
+pub fn red(text: &str) -> String {
+    paint(text, "31")
+}
+
+/// 计算文本在终端中的显示宽度（中日韩等全角字符占两列）
+pub fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c as u32 {
+            0x1100..=0x115F
+            | 0x2E80..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6 => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+/// 将数据格式化为按列对齐的表格
+///
+/// 表头以粗体显示，列宽按显示宽度计算，行末不留多余空格
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| display_width(h)).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            if i < widths.len() {
+                widths[i] = widths[i].max(display_width(cell));
+            }
+        }
+    }
+
+    let format_row = |cells: Vec<String>, plain: Vec<&str>| -> String {
+        let last = cells.len().saturating_sub(1);
+        let mut line = String::new();
+        for (i, (cell, text)) in cells.iter().zip(plain).enumerate() {
+            line.push_str(cell);
+            if i < last {
+                line.push_str(&" ".repeat(widths[i] - display_width(text) + 2));
+            }
+        }
+        line
+    };
+
+    let mut output = format_row(headers.iter().map(|h| bold(h)).collect(), headers.to_vec());
+    for row in rows {
+        output.push('\n');
+        output.push_str(&format_row(
+            row.clone(),
+            row.iter().map(|c| c.as_str()).collect(),
+        ));
+    }
+    output
+}
+
+/// 将字节数格式化为易读的大小（如 `1.5 MiB`）
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("软件源"), 6);
+        assert_eq!(display_width("a软b"), 4);
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024), "3.0 MiB");
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let rows = vec![
+            vec!["official".to_string(), "官方仓库".to_string()],
+            vec!["beta".to_string(), "Beta".to_string()],
+        ];
+        let output = table(&["ID", "名称"], &rows);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], "official  官方仓库");
+        assert_eq!(lines[2], "beta      Beta");
+    }
+}
@@ -3,7 +3,14 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// 默认的内存快照上限（字节）
+///
+/// 删除或覆盖文件时，不超过该大小的原始内容保存在内存中，更大的内容写入临时磁盘日志，
+/// 回滚时再从日志读回，避免涉及大文件的事务占用过多内存
+pub const DEFAULT_SPILL_THRESHOLD: u64 = 1024 * 1024;
 
 /// 表示文件系统操作的类型
 #[derive(Debug, Clone)]
@@ -14,23 +21,63 @@ pub enum Operation {
     Remove {
         path: PathBuf,
         /// 被删除文件的原始内容（用于回滚）
-        content: Vec<u8>,
+        content: Snapshot,
     },
     /// 移动/重命名文件操作
     Move {
         from: PathBuf,
         to: PathBuf,
         /// 目标路径的原始内容（如果存在，用于回滚）
-        original_dest_content: Option<Vec<u8>>,
+        original_dest_content: Option<Snapshot>,
+    },
+    /// 原地修改文件操作（由调用方直接写入）
+    Modify {
+        path: PathBuf,
+        /// 修改前的原始内容（文件原本不存在时为空，回滚时删除该文件）
+        original_content: Option<Snapshot>,
+    },
+    /// 创建目录操作（每级新建的目录各记录一次）
+    CreateDir { path: PathBuf },
+    /// 删除目录操作
+    RemoveDir {
+        path: PathBuf,
+        /// 目录被移动到的磁盘日志位置（随事务结束删除，回滚时移回原位置）
+        archived: PathBuf,
     },
 }
 
+/// 用于回滚的文件内容快照
+#[derive(Debug, Clone)]
+pub enum Snapshot {
+    /// 保存在内存中的内容
+    Memory(Vec<u8>),
+    /// 写入磁盘日志的内容（日志文件路径）
+    Journal(PathBuf),
+}
+
+impl Snapshot {
+    /// 将快照内容写回指定路径
+    fn restore(&self, path: &Path) -> Result<()> {
+        match self {
+            Snapshot::Memory(content) => std::fs::write(path, content)?,
+            Snapshot::Journal(journal_path) => {
+                std::fs::copy(journal_path, path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// 事务管理器
 pub struct Transaction {
     /// 操作日志，记录事务中执行的所有操作
     log: Vec<Operation>,
     /// 事务是否已提交
     committed: bool,
+    /// 内存快照上限（字节），更大的文件内容写入磁盘日志
+    spill_threshold: u64,
+    /// 磁盘日志目录（首次需要时创建，事务结束时随之删除）
+    journal: Option<TempDir>,
 }
 
 impl Transaction {
@@ -39,6 +86,8 @@ impl Transaction {
         Self {
             log: Vec::new(),
             committed: false,
+            spill_threshold: DEFAULT_SPILL_THRESHOLD,
+            journal: None,
         }
     }
 
@@ -74,7 +123,7 @@ impl Transaction {
                         crate::fsxg::create_directory(parent)
                             .with_context(|| format!("无法创建父目录: {}", parent.display()))?;
                     }
-                    std::fs::write(&path, content).with_context(|| {
+                    content.restore(&path).with_context(|| {
                         format!("无法回滚删除操作: 写入文件失败 {}", path.display())
                     })?;
                 }
@@ -95,17 +144,92 @@ impl Transaction {
 
                         // 如果目标位置原来有文件，需要恢复
                         if let Some(content) = original_dest_content {
-                            std::fs::write(&to, content).with_context(|| {
+                            content.restore(&to).with_context(|| {
                                 format!("无法恢复目标位置的原始文件: {}", to.display())
                             })?;
                         }
                     }
                 }
+                Operation::Modify {
+                    path,
+                    original_content,
+                } => match original_content {
+                    // 回滚修改：写回原始内容
+                    Some(content) => content.restore(&path).with_context(|| {
+                        format!("无法回滚修改操作: 写入文件失败 {}", path.display())
+                    })?,
+                    // 文件原本不存在：删除
+                    None => {
+                        if path.exists() {
+                            std::fs::remove_file(&path).with_context(|| {
+                                format!("无法回滚修改操作: 删除文件失败 {}", path.display())
+                            })?;
+                        }
+                    }
+                },
+                Operation::CreateDir { path } => {
+                    // 回滚创建目录：事务中写入的内容已先被撤销，仅删除仍为空的目录，
+                    // 保留事务之外写入的内容
+                    if path.is_dir()
+                        && std::fs::read_dir(&path)
+                            .with_context(|| format!("无法读取目录: {}", path.display()))?
+                            .next()
+                            .is_none()
+                    {
+                        std::fs::remove_dir(&path).with_context(|| {
+                            format!("无法回滚创建目录操作: 删除目录失败 {}", path.display())
+                        })?;
+                    }
+                }
+                Operation::RemoveDir { path, archived } => {
+                    // 回滚删除目录：清除之后在原位置创建的内容，再将日志中的目录移回
+                    if path.exists() {
+                        crate::fsxg::remove_directory(&path)?;
+                    }
+                    if let Some(parent) = path.parent() {
+                        crate::fsxg::create_directory(parent)
+                            .with_context(|| format!("无法创建父目录: {}", parent.display()))?;
+                    }
+                    move_dir(&archived, &path).with_context(|| {
+                        format!(
+                            "无法回滚删除目录操作: 移动失败 {} -> {}",
+                            archived.display(),
+                            path.display()
+                        )
+                    })?;
+                }
             }
         }
         Ok(())
     }
 
+    /// 为即将被删除或覆盖的文件创建快照
+    fn snapshot(&mut self, path: &Path) -> Result<Snapshot> {
+        let size = std::fs::metadata(path)
+            .with_context(|| format!("无法读取文件信息: {}", path.display()))?
+            .len();
+        if size <= self.spill_threshold {
+            let content = std::fs::read(path)
+                .with_context(|| format!("无法读取文件内容: {}", path.display()))?;
+            return Ok(Snapshot::Memory(content));
+        }
+
+        // 大文件直接复制到磁盘日志，不经过内存
+        let journal_path = self.journal_path()?;
+        std::fs::copy(path, &journal_path)
+            .with_context(|| format!("无法写入事务日志: {}", path.display()))?;
+        Ok(Snapshot::Journal(journal_path))
+    }
+
+    /// 为下一项操作分配磁盘日志中的路径（首次调用时创建日志目录）
+    fn journal_path(&mut self) -> Result<PathBuf> {
+        let journal = match &mut self.journal {
+            Some(journal) => journal,
+            journal => journal.insert(TempDir::new().with_context(|| "无法创建事务日志目录")?),
+        };
+        Ok(journal.path().join(self.log.len().to_string()))
+    }
+
     /// 在事务中安全地创建文件
     pub fn safe_create(&mut self, path: &std::path::Path, content: &[u8]) -> Result<()> {
         if path.exists() {
@@ -130,6 +254,76 @@ impl Transaction {
         Ok(())
     }
 
+    /// 记录即将被原地修改（或新建）的文件
+    ///
+    /// 保存文件当前内容的快照，之后由调用方直接写入；回滚时写回原始内容，文件原本不存在时将其删除
+    pub fn safe_modify(&mut self, path: &std::path::Path) -> Result<()> {
+        if path.is_dir() {
+            return Err(anyhow::anyhow!("路径是目录，不能修改: {}", path.display()));
+        }
+
+        let original_content = if path.exists() {
+            Some(self.snapshot(path)?)
+        } else {
+            None
+        };
+
+        // 记录操作到日志
+        self.log.push(Operation::Modify {
+            path: path.to_path_buf(),
+            original_content,
+        });
+
+        Ok(())
+    }
+
+    /// 在事务中安全地创建目录（包括缺失的上级目录）
+    ///
+    /// 已存在的目录不做记录；回滚时由深到浅删除新建的目录
+    pub fn safe_create_dir(&mut self, path: &std::path::Path) -> Result<()> {
+        if path.is_file() {
+            return Err(anyhow::anyhow!(
+                "路径是文件，不能创建目录: {}",
+                path.display()
+            ));
+        }
+
+        // 从最上层缺失的目录开始逐级创建，每级单独记录
+        let missing: Vec<&Path> = path
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .collect();
+        for dir in missing.into_iter().rev() {
+            std::fs::create_dir(dir).with_context(|| format!("创建目录失败: {}", dir.display()))?;
+            self.log.push(Operation::CreateDir {
+                path: dir.to_path_buf(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// 在事务中安全地删除目录（递归）
+    ///
+    /// 目录被整体移动到磁盘日志中，事务结束时随日志删除，回滚时移回原位置
+    pub fn safe_remove_dir(&mut self, path: &std::path::Path) -> Result<()> {
+        if !path.is_dir() {
+            return Err(anyhow::anyhow!("目录不存在: {}", path.display()));
+        }
+
+        // 执行删除操作（移动到磁盘日志）
+        let archived = self.journal_path()?;
+        move_dir(path, &archived).with_context(|| format!("删除目录失败: {}", path.display()))?;
+
+        // 记录操作到日志
+        self.log.push(Operation::RemoveDir {
+            path: path.to_path_buf(),
+            archived,
+        });
+
+        Ok(())
+    }
+
     /// 在事务中安全地删除文件
     pub fn safe_remove(&mut self, path: &std::path::Path) -> Result<()> {
         if !path.exists() {
@@ -140,9 +334,8 @@ impl Transaction {
             return Err(anyhow::anyhow!("路径是目录，不能删除: {}", path.display()));
         }
 
-        // 读取文件内容用于回滚
-        let content =
-            std::fs::read(path).with_context(|| format!("无法读取文件内容: {}", path.display()))?;
+        // 保存文件内容用于回滚
+        let content = self.snapshot(path)?;
 
         // 执行删除操作
         std::fs::remove_file(path).with_context(|| format!("删除文件失败: {}", path.display()))?;
@@ -174,10 +367,7 @@ impl Transaction {
             if to.is_dir() {
                 return Err(anyhow::anyhow!("目标路径是目录: {}", to.display()));
             }
-            Some(
-                std::fs::read(to)
-                    .with_context(|| format!("无法读取目标文件内容: {}", to.display()))?,
-            )
+            Some(self.snapshot(to)?)
         } else {
             None
         };
@@ -203,6 +393,31 @@ impl Transaction {
     }
 }
 
+/// 移动目录；两端不在同一文件系统时改为逐个复制后删除原目录
+fn move_dir(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_dir(from, to)?;
+    crate::fsxg::remove_directory(from)
+}
+
+/// 递归复制目录内容
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir(to).with_context(|| format!("创建目录失败: {}", to.display()))?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dest)?;
+        } else {
+            std::fs::copy(entry.path(), &dest)
+                .with_context(|| format!("复制文件失败: {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -283,6 +498,123 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_transaction_rollback_modify() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let existing_path = temp_dir.path().join("existing.txt");
+        let new_path = temp_dir.path().join("new.txt");
+        fs::write(&existing_path, b"original")?;
+
+        let mut tx = Transaction::begin();
+        tx.safe_modify(&existing_path)?;
+        fs::write(&existing_path, b"modified")?;
+        tx.safe_modify(&new_path)?;
+        fs::write(&new_path, b"created")?;
+
+        // 回滚后已有文件恢复原始内容，新建的文件被删除
+        tx.rollback()?;
+        assert_eq!(fs::read(&existing_path)?, b"original");
+        assert!(!new_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_rollback_nested_dirs() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let root = temp_dir.path().join("packages");
+        let nested = root.join("app/1.0.0/assets");
+
+        let mut tx = Transaction::begin();
+        tx.safe_create_dir(&nested)?;
+        tx.safe_create(&nested.join("app.js"), b"console.log(1)")?;
+        assert!(nested.is_dir());
+
+        // 回滚后逐级删除新建的目录（事务中创建的文件先被删除）
+        tx.rollback()?;
+        assert!(!root.exists());
+
+        // 事务之外写入内容的目录被保留
+        fs::create_dir(&root)?;
+        let mut tx = Transaction::begin();
+        tx.safe_create_dir(&nested)?;
+        fs::write(root.join("app/keep.txt"), b"keep")?;
+        tx.rollback()?;
+        assert!(!root.join("app/1.0.0").exists());
+        assert!(root.join("app/keep.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_rollback_remove_dir() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().join("app/1.0.0");
+        fs::create_dir_all(dir.join("assets/js"))?;
+        fs::write(dir.join("index.html"), b"hello")?;
+        fs::write(dir.join("assets/js/app.js"), b"console.log(1)")?;
+
+        // 回滚时恢复被删除的嵌套目录及其内容，之后在原位置写入的内容被清除
+        let mut tx = Transaction::begin();
+        tx.safe_remove_dir(&dir)?;
+        assert!(!dir.exists());
+        fs::create_dir(&dir)?;
+        fs::write(dir.join("stray.txt"), b"stray")?;
+        tx.rollback()?;
+        assert_eq!(fs::read(dir.join("index.html"))?, b"hello");
+        assert_eq!(fs::read(dir.join("assets/js/app.js"))?, b"console.log(1)");
+        assert!(!dir.join("stray.txt").exists());
+
+        // 提交后目录被删除，不在原位置留下存档
+        let mut tx = Transaction::begin();
+        tx.safe_remove_dir(&dir)?;
+        tx.commit()?;
+        assert!(!dir.exists());
+        assert_eq!(fs::read_dir(temp_dir.path().join("app"))?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_spill_to_journal() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let small_path = temp_dir.path().join("small.txt");
+        let large_path = temp_dir.path().join("large.txt");
+        fs::write(&small_path, b"tiny")?;
+        fs::write(&large_path, b"larger than the threshold")?;
+
+        let mut tx = Transaction::begin();
+        tx.spill_threshold = 8;
+        tx.safe_remove(&small_path)?;
+        tx.safe_remove(&large_path)?;
+
+        // 小文件保存在内存中，大文件写入磁盘日志
+        assert!(matches!(
+            &tx.log[0],
+            Operation::Remove {
+                content: Snapshot::Memory(_),
+                ..
+            }
+        ));
+        let Operation::Remove {
+            content: Snapshot::Journal(journal_path),
+            ..
+        } = &tx.log[1]
+        else {
+            panic!("大文件应写入磁盘日志");
+        };
+        let journal_path = journal_path.clone();
+        assert!(journal_path.exists());
+
+        // 回滚后恢复两个文件，日志目录随事务删除
+        tx.rollback()?;
+        assert_eq!(fs::read(&small_path)?, b"tiny");
+        assert_eq!(fs::read(&large_path)?, b"larger than the threshold");
+        assert!(!journal_path.exists());
+
+        Ok(())
+    }
+
     #[test]
     fn test_transaction_rollback_move() -> Result<()> {
         let temp_dir = TempDir::new()?;
@@ -2,24 +2,30 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use anyhow::{Result, anyhow, bail};
+use clap::ValueEnum;
+use std::cmp::Ordering;
+use std::fmt;
+
 /// 版本比较
-/// 
-/// 比较两个版本在版本清单中的行号（越高越新）
-/// 
+///
+/// 比较两个版本在版本清单中的行号（越高越新），不解析版本号。
+/// 语义化版本应使用 [`compare_semver`]，本函数仅作为无法解析时的退回
+///
 /// # Arguments
-/// 
+///
 /// * `version1` - 第一个版本号
 /// * `version2` - 第二个版本号
 /// * `versions` - 版本清单，按从旧到新顺序排列
-/// 
+///
 /// # Returns
-/// 
+///
 /// * `1` 如果 version1 更新
 /// * `-1` 如果 version2 更新
-/// * `0` 如果版本相同
-/// 
+/// * `0` 如果版本相同，或两个版本都不在清单中
+///
 /// # Examples
-/// 
+///
 /// ```
 /// let versions = vec!["1.0.0".to_string(), "1.1.0".to_string(), "2.0.0".to_string()];
 /// assert_eq!(compare("2.0.0", "1.1.0", &versions), 1);
@@ -27,76 +33,997 @@
 /// assert_eq!(compare("1.1.0", "1.1.0", &versions), 0);
 /// ```
 pub fn compare(version1: &str, version2: &str, versions: &[String]) -> i32 {
-    // 如果版本号相同，返回0
     if version1 == version2 {
         return 0;
     }
-    
+
     // 查找版本在清单中的位置（索引）
     let pos1 = versions.iter().position(|v| v == version1);
     let pos2 = versions.iter().position(|v| v == version2);
-    
+
     match (pos1, pos2) {
         // 两个版本都存在，比较位置
-        (Some(p1), Some(p2)) => {
-            if p1 > p2 {
-                1 // version1 更新
-            } else {
-                -1 // version2 更新
-            }
-        },
-        // 只有一个版本存在
-        (Some(_), None) => 1,  // version1 存在，认为它更新
-        (None, Some(_)) => -1, // version2 存在，认为它更新
-        // 两个版本都不存在
-        (None, None) => 0,     // 无法比较，认为相同
+        (Some(p1), Some(p2)) if p1 > p2 => 1,
+        (Some(_), Some(_)) => -1,
+        // 只有一个版本存在时认为它更新
+        (Some(_), None) => 1,
+        (None, Some(_)) => -1,
+        // 两个版本都不存在，无法比较
+        (None, None) => 0,
     }
 }
 
+/// 语义化版本比较
+///
+/// 按 SemVer 优先级（[`SemVer::cmp_precedence`]）比较两个版本，忽略构建元数据；
+/// 任一版本无法解析时返回 `None`，由调用方决定如何退回（如按版本清单中的位置比较，见 [`compare`]）
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(compare_semver("1.10.0", "1.9.0"), Some(Ordering::Greater));
+/// assert_eq!(compare_semver("1.0.0-beta.1", "1.0.0"), Some(Ordering::Less));
+/// assert_eq!(compare_semver("nightly", "1.0.0"), None);
+/// ```
+pub fn compare_semver(version1: &str, version2: &str) -> Option<Ordering> {
+    Some(parse(version1).ok()?.cmp_precedence(&parse(version2).ok()?))
+}
+
+/// 语义化版本排序
+///
+/// 按 [`parse_lossy`] 解析后用 [`Version::cmp_lossy`] 比较，总能给出结果：
+/// 均为语义化版本时按优先级比较（忽略构建元数据），否则退回到按原始字符串比较。
+/// 用于对版本列表排序；需要区分"无法比较"时使用 [`compare_semver`]
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(semver_ordering("1.10.0", "1.9.0"), Ordering::Greater);
+/// assert_eq!(semver_ordering("1.0.0+a", "1.0.0+b"), Ordering::Equal);
+/// assert_eq!(semver_ordering("1.0.0", "nightly"), "1.0.0".cmp("nightly"));
+/// ```
+pub fn semver_ordering(version1: &str, version2: &str) -> Ordering {
+    parse_lossy(version1).cmp_lossy(&parse_lossy(version2))
+}
+
 /// 获取最新版本
-/// 
-/// 从版本清单中获取最新版本（最后一个）
-/// 
+///
+/// 从版本清单中获取最后一个版本，即按清单行序最后加入的版本，不解析版本号。
+/// 与 [`get_latest_semver`] 的区别：清单未按版本号排序时（如先加入 2.0.0 再加入修复版 1.5.0）
+/// 它返回 1.5.0 而不是版本号最高的 2.0.0。解析 `latest`、升级等需要"最高版本"的场景应使用
+/// [`get_latest_semver`]；只有版本号无法按语义化版本比较、清单顺序是唯一可靠的新旧依据时才使用本函数
+///
 /// # Arguments
-/// 
+///
 /// * `versions` - 版本清单，按从旧到新顺序排列
-/// 
+///
 /// # Returns
-/// 
-/// * `Some(&str)` 最新版本号的引用
+///
+/// * `Some(&str)` 最后加入的版本号的引用
 /// * `None` 如果版本清单为空
-/// 
+///
 /// # Examples
-/// 
+///
 /// ```
-/// let versions = vec!["1.0.0".to_string(), "1.1.0".to_string(), "2.0.0".to_string()];
-/// assert_eq!(get_latest(&versions), Some("2.0.0"));
+/// let versions = vec!["1.0.0".to_string(), "2.0.0".to_string(), "1.5.0".to_string()];
+/// assert_eq!(get_latest(&versions), Some("1.5.0"));
+/// assert_eq!(get_latest_semver(&versions), Some("2.0.0"));
 /// ```
 pub fn get_latest(versions: &[String]) -> Option<&str> {
-    versions.last().map(|s| s.as_str())
+    versions.last().map(String::as_str)
+}
+
+/// 获取语义化版本最高的版本
+///
+/// 按 [`Version::cmp_lossy`] 取最大值，与版本在清单中的顺序无关；
+/// 多个版本优先级相同时（如仅构建元数据不同）取靠后的一个
+///
+/// # Arguments
+///
+/// * `versions` - 版本列表（任意顺序）
+///
+/// # Returns
+///
+/// * `Some(&str)` 最高版本号的引用
+/// * `None` 如果版本列表为空
+///
+/// # Examples
+///
+/// ```
+/// let versions = vec!["1.10.0".to_string(), "2.0.0".to_string(), "1.9.0".to_string()];
+/// assert_eq!(get_latest_semver(&versions), Some("2.0.0"));
+/// ```
+pub fn get_latest_semver(versions: &[String]) -> Option<&str> {
+    Preference::Newest.select(versions.iter().map(String::as_str))
+}
+
+/// 语义化版本
+///
+/// 按 SemVer 2.0.0 解析的版本号，保留预发布标识和构建元数据
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemVer {
+    /// 主版本号
+    pub major: u64,
+    /// 次版本号
+    pub minor: u64,
+    /// 修订号
+    pub patch: u64,
+    /// 预发布标识（`-` 之后按 `.` 分隔的部分）
+    pub pre: Vec<String>,
+    /// 构建元数据（`+` 之后的部分），不参与版本优先级比较
+    pub build: Option<String>,
+}
+
+impl SemVer {
+    /// 按版本优先级比较
+    ///
+    /// 忽略构建元数据；带预发布标识的版本低于对应的正式版本
+    pub fn cmp_precedence(&self, other: &SemVer) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| compare_pre(&self.pre, &other.pre))
+    }
+
+    /// 递增指定的版本号部分
+    ///
+    /// 低位版本号归零，预发布标识和构建元数据清空
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let version = parse("1.2.3-rc.1+build.5").unwrap();
+    /// assert_eq!(version.bump(BumpLevel::Minor).to_string(), "1.3.0");
+    /// ```
+    pub fn bump(&self, level: BumpLevel) -> SemVer {
+        let (major, minor, patch) = match level {
+            BumpLevel::Major => (self.major + 1, 0, 0),
+            BumpLevel::Minor => (self.major, self.minor + 1, 0),
+            BumpLevel::Patch => (self.major, self.minor, self.patch + 1),
+        };
+        SemVer {
+            major,
+            minor,
+            patch,
+            pre: Vec::new(),
+            build: None,
+        }
+    }
+}
+
+impl fmt::Display for SemVer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.pre.is_empty() {
+            write!(f, "-{}", self.pre.join("."))?;
+        }
+        if let Some(build) = &self.build {
+            write!(f, "+{build}")?;
+        }
+        Ok(())
+    }
+}
+
+/// 要递增的版本号部分
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BumpLevel {
+    /// 主版本号
+    Major,
+    /// 次版本号
+    Minor,
+    /// 修订号
+    Patch,
+}
+
+/// 比较预发布标识
+fn compare_pre(pre1: &[String], pre2: &[String]) -> Ordering {
+    // 没有预发布标识的版本优先级更高
+    match (pre1.is_empty(), pre2.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        (false, false) => {}
+    }
+
+    for (a, b) in pre1.iter().zip(pre2) {
+        let ordering = match (a.parse::<u64>(), b.parse::<u64>()) {
+            // 纯数字标识按数值比较
+            (Ok(a), Ok(b)) => a.cmp(&b),
+            // 数字标识低于字母数字标识
+            (Ok(_), Err(_)) => Ordering::Less,
+            (Err(_), Ok(_)) => Ordering::Greater,
+            // 字母数字标识按 ASCII 顺序比较
+            (Err(_), Err(_)) => a.cmp(b),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    // 前缀相同时，标识更多的优先级更高
+    pre1.len().cmp(&pre2.len())
 }
 
 /// 版本解析
-/// 
-/// 解析版本字符串，提取主要版本信息
-/// 目前直接返回原版本字符串
-/// 
+///
+/// 按 SemVer 2.0.0 解析版本字符串，构建元数据保留在结果中
+///
 /// # Arguments
-/// 
+///
 /// * `version` - 版本字符串
-/// 
+///
+/// # Returns
+///
+/// * `Ok(SemVer)` 解析后的版本
+/// * `Err` 如果版本字符串不符合语义化版本格式，错误信息说明不符合的部分
+///
+/// # Examples
+///
+/// ```
+/// let version = parse("1.2.3-rc.1+build.5")?;
+/// assert_eq!(version.pre, vec!["rc", "1"]);
+/// assert_eq!(version.build.as_deref(), Some("build.5"));
+/// assert!(parse("139402853dw3d3").is_err());
+/// ```
+pub fn parse(version: &str) -> Result<SemVer> {
+    // 分离构建元数据
+    let (rest, build) = match version.split_once('+') {
+        Some((rest, build)) => (rest, Some(build)),
+        None => (version, None),
+    };
+    if let Some(build) = build
+        && !is_valid_identifiers(build)
+    {
+        bail!("版本 {version} 的构建元数据无效: \"{build}\"");
+    }
+
+    // 分离预发布标识
+    let (core, pre) = match rest.split_once('-') {
+        Some((core, pre)) => (core, Some(pre)),
+        None => (rest, None),
+    };
+    if let Some(pre) = pre
+        && !is_valid_identifiers(pre)
+    {
+        bail!("版本 {version} 的预发布标识无效: \"{pre}\"");
+    }
+
+    // 解析主版本号、次版本号和修订号
+    let numbers = core
+        .split('.')
+        .map(|part| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                bail!("版本 {version} 中的 \"{part}\" 不是数字");
+            }
+            part.parse::<u64>()
+                .map_err(|_| anyhow!("版本 {version} 中的 \"{part}\" 超出范围"))
+        })
+        .collect::<Result<Vec<u64>>>()?;
+    let [major, minor, patch] = numbers[..] else {
+        bail!("版本 {version} 应为 主版本号.次版本号.修订号 三个部分，如 1.2.3");
+    };
+
+    Ok(SemVer {
+        major,
+        minor,
+        patch,
+        pre: pre
+            .map(|pre| pre.split('.').map(str::to_string).collect())
+            .unwrap_or_default(),
+        build: build.map(str::to_string),
+    })
+}
+
+/// 宽松解析的版本
+///
+/// 不符合语义化版本的版本方案（如 `nightly`、`2024.1`）作为不透明字符串保留
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Version {
+    /// 语义化版本
+    SemVer(SemVer),
+    /// 无法解析的版本字符串，只能按字符串比较
+    Opaque(String),
+}
+
+impl Version {
+    /// 比较两个版本
+    ///
+    /// 均为语义化版本时按版本优先级比较，否则退回到按原始字符串比较
+    pub fn cmp_lossy(&self, other: &Version) -> Ordering {
+        match (self, other) {
+            (Version::SemVer(a), Version::SemVer(b)) => a.cmp_precedence(b),
+            _ => self.to_string().cmp(&other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::SemVer(version) => version.fmt(f),
+            Version::Opaque(version) => f.write_str(version),
+        }
+    }
+}
+
+/// 宽松的版本解析
+///
+/// 符合语义化版本时返回解析结果，否则将整个字符串作为不透明版本保留
+///
+/// # Examples
+///
+/// ```
+/// assert!(matches!(parse_lossy("1.0.0"), Version::SemVer(_)));
+/// assert_eq!(parse_lossy("nightly"), Version::Opaque("nightly".to_string()));
+/// ```
+pub fn parse_lossy(version: &str) -> Version {
+    match parse(version) {
+        Ok(semver) => Version::SemVer(semver),
+        Err(_) => Version::Opaque(version.to_string()),
+    }
+}
+
+/// 检查以 `.` 分隔的标识是否均非空且仅包含 `[0-9A-Za-z-]`
+fn is_valid_identifiers(identifiers: &str) -> bool {
+    identifiers.split('.').all(|identifier| {
+        !identifier.is_empty()
+            && identifier
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    })
+}
+
+/// 版本约束检查
+///
+/// 检查版本是否满足约束，约束之间以 `,` 分隔表示同时满足。
+/// 支持 `=`、`>`、`>=`、`<`、`<=`、`^`、`~` 和 `*`，省略运算符时等同于 `=`。
+/// 约束中的版本号可以省略低位（如 `^1.2`、`>=1.0, <2.0`），省略的部分不参与 `=`、`>`、`<=` 的比较，
+/// 在 `>=`、`<`、`^`、`~` 中视为 0。构建元数据不参与比较
+///
+/// # Arguments
+///
+/// * `version` - 版本号
+/// * `constraint` - 版本约束，如 `>=1.0.0, <2.0.0`
+///
 /// # Returns
-/// 
-/// * 解析后的版本字符串
-/// 
+///
+/// * `true` 如果版本满足约束
+/// * `false` 如果不满足，或版本、约束无法解析
+///
+/// # Examples
+///
+/// ```
+/// assert!(matches("1.2.3+build", "^1.2.0"));
+/// assert!(matches("1.9.0", "^1.2"));
+/// assert!(!matches("2.0.0", ">=1.0, <2.0"));
+/// ```
+pub fn matches(version: &str, constraint: &str) -> bool {
+    let Ok(version) = parse(version) else {
+        return false;
+    };
+
+    constraint
+        .split(',')
+        .map(str::trim)
+        .all(|constraint| matches_single(&version, constraint))
+}
+
+/// 检查版本是否满足依赖声明中的版本要求
+///
+/// 规则与 [`matches`] 相同：构建元数据不参与比较，仅相差构建元数据的版本视为同一版本
+///
+/// # Examples
+///
+/// ```
+/// assert!(satisfies("1.0.0+b", "=1.0.0+a"));
+/// assert!(!satisfies("1.0.0-rc.1", ">=1.0.0"));
+/// ```
+pub fn satisfies(version: &str, requirement: &str) -> bool {
+    matches(version, requirement)
+}
+
+/// 解析约束中的版本号，允许省略次版本号和修订号
+///
+/// 返回补零后的版本和实际给出的部分数；省略了低位时不允许预发布标识和构建元数据
+fn parse_partial(target: &str) -> Option<(SemVer, usize)> {
+    if let Ok(version) = parse(target) {
+        return Some((version, 3));
+    }
+
+    let parts = target
+        .split('.')
+        .map(|part| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            part.parse::<u64>().ok()
+        })
+        .collect::<Option<Vec<u64>>>()?;
+    let (major, minor) = match parts[..] {
+        [major] => (major, 0),
+        [major, minor] => (major, minor),
+        _ => return None,
+    };
+    let version = SemVer {
+        major,
+        minor,
+        patch: 0,
+        pre: Vec::new(),
+        build: None,
+    };
+    Some((version, parts.len()))
+}
+
+/// 判断版本约束能否解析（具体版本号也是有效的约束）
+///
 /// # Examples
-/// 
+///
 /// ```
-/// assert_eq!(parse("1.2.3"), "1.2.3");
-/// assert_eq!(parse("139402853dw3d3"), "139402853dw3d3");
+/// assert!(is_valid_constraint(">=1.0, <2.0"));
+/// assert!(!is_valid_constraint("^latest"));
 /// ```
-pub fn parse(version: &str) -> &str {
-    // 目前直接返回原版本字符串
-    // 未来可以添加更复杂的解析逻辑
-    version
-}
\ No newline at end of file
+pub fn is_valid_constraint(constraint: &str) -> bool {
+    constraint
+        .split(',')
+        .map(str::trim)
+        .all(|constraint| constraint == "*" || split_constraint(constraint).is_some())
+}
+
+/// 将单个约束拆分为运算符、版本号和版本号中实际给出的部分数，无运算符时视为 `=`
+fn split_constraint(constraint: &str) -> Option<(&'static str, SemVer, usize)> {
+    // 按长度从长到短匹配运算符
+    let (op, target) = ["<=", ">=", "<", ">", "=", "^", "~"]
+        .iter()
+        .find_map(|op| constraint.strip_prefix(op).map(|rest| (*op, rest.trim())))
+        .unwrap_or(("=", constraint));
+    let (target, given) = parse_partial(target)?;
+    Some((op, target, given))
+}
+
+/// 检查版本是否满足单个约束
+fn matches_single(version: &SemVer, constraint: &str) -> bool {
+    if constraint == "*" {
+        return true;
+    }
+
+    let Some((op, target, given)) = split_constraint(constraint) else {
+        return false;
+    };
+
+    // 完整版本号按优先级比较，省略低位时只比较给出的部分
+    let ordering = version.cmp_precedence(&target);
+    let numbers = |v: &SemVer| [v.major, v.minor, v.patch];
+    let prefix = |len: usize| numbers(version)[..len].cmp(&numbers(&target)[..len]);
+    let exact = |len: usize| {
+        if len == 3 { ordering } else { prefix(len) }
+    };
+    match op {
+        "=" => exact(given) == Ordering::Equal,
+        ">" => exact(given) == Ordering::Greater,
+        ">=" => ordering != Ordering::Less,
+        "<" => ordering == Ordering::Less,
+        "<=" => exact(given) != Ordering::Greater,
+        // 兼容更新：不修改最左侧的非零版本号（给出的部分全为 0 时均不修改）
+        "^" => {
+            let len = numbers(&target)[..given]
+                .iter()
+                .position(|&number| number != 0)
+                .map_or(given, |index| index + 1);
+            ordering != Ordering::Less && prefix(len) == Ordering::Equal
+        }
+        // 补丁更新：主版本号和次版本号不变（只给出主版本号时仅主版本号不变）
+        "~" => ordering != Ordering::Less && prefix(given.min(2)) == Ordering::Equal,
+        _ => false,
+    }
+}
+
+/// 判断版本字符串是否为约束而非具体版本
+///
+/// 以运算符开头、包含 `,` 或为 `*` 时视为约束
+///
+/// # Examples
+///
+/// ```
+/// assert!(is_requirement("^1.2.0"));
+/// assert!(!is_requirement("1.2.0"));
+/// ```
+pub fn is_requirement(version: &str) -> bool {
+    version == "*" || version.contains(',') || version.starts_with(['=', '>', '<', '^', '~'])
+}
+
+/// 多个版本满足约束时的选择偏好
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Preference {
+    /// 选择语义化版本最高者
+    #[default]
+    Newest,
+    /// 选择语义化版本最低者（用于测试最低支持版本）
+    Lowest,
+}
+
+impl Preference {
+    /// 按偏好从版本中选出一个
+    ///
+    /// 按 [`Version::cmp_lossy`] 取最高或最低者；优先级相同时（如仅构建元数据不同）
+    /// 最高者取靠后的一个，最低者取靠前的一个
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let versions = ["1.0.0", "1.2.0", "2.0.0"];
+    /// let satisfying = || versions.into_iter().filter(|v| matches(v, "^1.0.0"));
+    /// assert_eq!(Preference::Newest.select(satisfying()), Some("1.2.0"));
+    /// assert_eq!(Preference::Lowest.select(satisfying()), Some("1.0.0"));
+    /// ```
+    pub fn select<'a>(self, versions: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+        let parsed = versions
+            .into_iter()
+            .map(|version| (parse_lossy(version), version));
+        let compare = |a: &(Version, &str), b: &(Version, &str)| a.0.cmp_lossy(&b.0);
+        match self {
+            Preference::Newest => parsed.max_by(compare),
+            Preference::Lowest => parsed.min_by(compare),
+        }
+        .map(|(_, version)| version)
+    }
+}
+
+/// 从候选版本中选出满足约束的版本
+///
+/// 按约束过滤后交给 [`Preference::select`] 选择
+///
+/// # Arguments
+///
+/// * `candidates` - 可用版本列表
+/// * `requirement` - 版本约束，如 `>=1.0.0, <2.0.0`
+/// * `preference` - 多个版本满足约束时的选择偏好
+///
+/// # Returns
+///
+/// * `Some(&str)` 按偏好选出的版本
+/// * `None` 如果没有满足约束的版本
+///
+/// # Examples
+///
+/// ```
+/// let versions = vec!["1.0.0".to_string(), "1.2.0".to_string(), "2.0.0".to_string()];
+/// assert_eq!(select_satisfying(&versions, "^1.0.0", Preference::Newest), Some("1.2.0"));
+/// assert_eq!(select_satisfying(&versions, "^1.0.0", Preference::Lowest), Some("1.0.0"));
+/// ```
+pub fn select_satisfying<'a>(
+    candidates: &'a [String],
+    requirement: &str,
+    preference: Preference,
+) -> Option<&'a str> {
+    preference.select(
+        candidates
+            .iter()
+            .map(String::as_str)
+            .filter(|candidate| matches(candidate, requirement)),
+    )
+}
+
+/// 从候选版本中选出满足约束的最高版本
+///
+/// 等价于以 [`Preference::Newest`] 调用 [`select_satisfying`]
+///
+/// # Arguments
+///
+/// * `candidates` - 可用版本列表
+/// * `constraint` - 版本约束，如 `^1.2`、`~1.2.0`、`>=1.0, <2.0`
+///
+/// # Returns
+///
+/// * `Some(&str)` 满足约束的语义化版本最高者
+/// * `None` 如果没有满足约束的版本
+///
+/// # Examples
+///
+/// ```
+/// let versions = vec!["1.2.0".to_string(), "1.10.1".to_string(), "2.0.0".to_string()];
+/// assert_eq!(max_matching(&versions, "^1.2"), Some("1.10.1"));
+/// ```
+pub fn max_matching<'a>(candidates: &'a [String], constraint: &str) -> Option<&'a str> {
+    select_satisfying(candidates, constraint, Preference::Newest)
+}
+
+/// 依赖版本约束及其请求方
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    /// 提出约束的软件包（如 `app:1.0.0`，直接安装的请求可写为 `命令行`）
+    pub requester: String,
+    /// 版本约束，如 `>=1.0.0, <2.0.0`
+    pub requirement: String,
+}
+
+/// 为依赖选出同时满足所有约束的版本
+///
+/// 依赖解析时应先收集同一依赖ID上的全部约束（直接请求与传递依赖），
+/// 再统一求交集，而不是按遍历顺序逐个安装、让后出现的请求覆盖先前的版本。
+///
+/// # Arguments
+///
+/// * `dependency` - 依赖的软件包ID（用于错误信息）
+/// * `constraints` - 该依赖上收集到的所有约束
+/// * `candidates` - 可用版本列表
+///
+/// # Returns
+///
+/// * 满足全部约束的候选版本中语义化版本最高者
+/// * 无交集时返回错误，列出每个请求方及其约束
+///
+/// # Examples
+///
+/// ```
+/// let constraints = vec![
+///     Constraint { requester: "a:1.0.0".to_string(), requirement: "^1.0.0".to_string() },
+///     Constraint { requester: "b:2.0.0".to_string(), requirement: "<1.5.0".to_string() },
+/// ];
+/// let candidates = vec!["1.2.0".to_string(), "1.6.0".to_string()];
+/// assert_eq!(resolve_constraints("lib", &constraints, &candidates)?, "1.2.0");
+/// ```
+pub fn resolve_constraints(
+    dependency: &str,
+    constraints: &[Constraint],
+    candidates: &[String],
+) -> Result<String> {
+    // 以 `,` 连接的约束表示同时满足
+    let combined = if constraints.is_empty() {
+        "*".to_string()
+    } else {
+        constraints
+            .iter()
+            .map(|constraint| constraint.requirement.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    max_matching(candidates, &combined)
+        .map(str::to_string)
+        .ok_or_else(|| {
+            let details: Vec<String> = constraints
+                .iter()
+                .map(|constraint| {
+                    format!("{} 要求 {}", constraint.requester, constraint.requirement)
+                })
+                .collect();
+            anyhow!(
+                "依赖版本冲突: {} 没有同时满足以下约束的版本（可用版本: {}）\n  {}",
+                dependency,
+                if candidates.is_empty() {
+                    "无".to_string()
+                } else {
+                    candidates.join(", ")
+                },
+                details.join("\n  ")
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keeps_build_metadata() {
+        let version = parse("1.2.3-rc.1+build.5").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (1, 2, 3));
+        assert_eq!(version.pre, vec!["rc", "1"]);
+        assert_eq!(version.build.as_deref(), Some("build.5"));
+
+        assert!(parse("1.2").is_err());
+        assert!(parse("1.2.3+").is_err());
+        assert!(parse("139402853dw3d3").is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        let version = parse("10.20.30").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (10, 20, 30));
+        assert!(version.pre.is_empty());
+        assert_eq!(version.build, None);
+
+        // 预发布标识可以包含 `-`，构建元数据中的 `-` 不视为预发布标识
+        let version = parse("1.0.0-alpha-1.x").unwrap();
+        assert_eq!(version.pre, vec!["alpha-1", "x"]);
+        let version = parse("1.0.0+build-7").unwrap();
+        assert!(version.pre.is_empty());
+        assert_eq!(version.build.as_deref(), Some("build-7"));
+
+        // 解析结果可原样输出
+        for input in ["1.0.0-rc.1+build.5", "0.0.1", "2.1.0-beta"] {
+            assert_eq!(parse(input).unwrap().to_string(), input);
+        }
+
+        // 错误信息说明不符合的部分
+        let error = |input: &str| parse(input).unwrap_err().to_string();
+        assert!(error("1.2").contains("三个部分"));
+        assert!(error("1.2.3.4").contains("三个部分"));
+        assert!(error("1.x.3").contains("\"x\" 不是数字"));
+        assert!(error("v1.2.3").contains("不是数字"));
+        assert!(error("1.2.3-").contains("预发布标识无效"));
+        assert!(error("1.2.3-rc..1").contains("预发布标识无效"));
+        assert!(error("1.2.3+a+b").contains("构建元数据无效"));
+        assert!(error("99999999999999999999.0.0").contains("超出范围"));
+    }
+
+    #[test]
+    fn test_parse_lossy() {
+        assert!(matches!(
+            parse_lossy("1.0.0-rc.1+build.5"),
+            Version::SemVer(_)
+        ));
+        assert_eq!(
+            parse_lossy("nightly"),
+            Version::Opaque("nightly".to_string())
+        );
+        assert_eq!(parse_lossy("2024.1").to_string(), "2024.1");
+
+        // 两侧均为语义化版本时按优先级比较，否则按字符串比较
+        assert_eq!(
+            parse_lossy("1.10.0").cmp_lossy(&parse_lossy("1.9.0")),
+            Ordering::Greater
+        );
+        assert_eq!(
+            parse_lossy("1.10").cmp_lossy(&parse_lossy("1.9")),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_bump() {
+        let version = parse("1.2.3-rc.1+build.5").unwrap();
+        assert_eq!(version.to_string(), "1.2.3-rc.1+build.5");
+        assert_eq!(version.bump(BumpLevel::Major).to_string(), "2.0.0");
+        assert_eq!(version.bump(BumpLevel::Minor).to_string(), "1.3.0");
+        assert_eq!(version.bump(BumpLevel::Patch).to_string(), "1.2.4");
+    }
+
+    #[test]
+    fn test_cmp_lossy_ignores_build_metadata() {
+        assert_eq!(semver_ordering("1.0.0+a", "1.0.0+b"), Ordering::Equal);
+        assert_eq!(semver_ordering("1.0.0+a", "1.0.0"), Ordering::Equal);
+        assert_eq!(semver_ordering("1.0.1+a", "1.0.0+b"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_cmp_lossy() {
+        assert_eq!(semver_ordering("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(semver_ordering("1.9.0", "1.10.0"), Ordering::Less);
+        assert_eq!(
+            semver_ordering("1.0.0-beta.1", "1.0.0-beta.2"),
+            Ordering::Less
+        );
+        assert_eq!(
+            semver_ordering("1.0.0-beta.11", "1.0.0-beta.2"),
+            Ordering::Greater
+        );
+        assert_eq!(semver_ordering("1.0.0", "1.0.0-rc.1"), Ordering::Greater);
+        // 任一侧不是语义化版本时按字符串比较
+        assert_eq!(semver_ordering("1.0", "1.0.0"), "1.0".cmp("1.0.0"));
+        assert_eq!(semver_ordering("1.0.0", "nightly"), "1.0.0".cmp("nightly"));
+    }
+
+    #[test]
+    fn test_cmp_lossy_pre_release() {
+        assert_eq!(semver_ordering("1.0.0-rc.1", "1.0.0"), Ordering::Less);
+        assert_eq!(semver_ordering("1.0.0", "1.0.0-rc.1"), Ordering::Greater);
+        assert_eq!(semver_ordering("1.0.0-rc.1", "1.0.0-rc.2"), Ordering::Less);
+        assert_eq!(
+            semver_ordering("1.0.0-alpha", "1.0.0-alpha.1"),
+            Ordering::Less
+        );
+        assert_eq!(semver_ordering("1.0.0-2", "1.0.0-10"), Ordering::Less);
+        assert_eq!(
+            semver_ordering("1.0.0-rc.1+a", "1.0.0-rc.1+b"),
+            Ordering::Equal
+        );
+    }
+
+    #[test]
+    fn test_compare() {
+        let versions: Vec<String> = ["r9", "r10"].iter().map(|v| v.to_string()).collect();
+        assert_eq!(compare("r10", "r9", &versions), 1);
+        assert_eq!(compare("r9", "r10", &versions), -1);
+        assert_eq!(compare("r9", "r9", &versions), 0);
+        assert_eq!(compare("r11", "r9", &versions), -1);
+        assert_eq!(compare("r11", "r12", &versions), 0);
+    }
+
+    #[test]
+    fn test_compare_semver() {
+        assert_eq!(compare_semver("1.10.0", "1.9.0"), Some(Ordering::Greater));
+        assert_eq!(compare_semver("1.9.0", "1.10.0"), Some(Ordering::Less));
+        assert_eq!(
+            compare_semver("1.0.0-beta.1", "1.0.0-beta.2"),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            compare_semver("1.0.0-beta.11", "1.0.0-beta.2"),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            compare_semver("1.0.0", "1.0.0-rc.1"),
+            Some(Ordering::Greater)
+        );
+        // 任一侧无法解析时交由调用方退回
+        assert_eq!(compare_semver("1.0", "1.0.0"), None);
+        assert_eq!(compare_semver("1.0.0", "nightly"), None);
+    }
+
+    #[test]
+    fn test_compare_semver_ignores_build_metadata() {
+        assert_eq!(compare_semver("1.0.0+a", "1.0.0+b"), Some(Ordering::Equal));
+        assert_eq!(compare_semver("1.0.0-rc.1", "1.0.0"), Some(Ordering::Less));
+        assert_eq!(
+            compare_semver("1.0.0-rc.1+b", "1.0.0+a"),
+            Some(Ordering::Less)
+        );
+    }
+
+    #[test]
+    fn test_satisfies() {
+        assert!(satisfies("1.0.0+b", "=1.0.0+a"));
+        assert!(satisfies("1.0.0+a", ">=1.0.0, <=1.0.0+b"));
+        assert!(!satisfies("1.0.0-rc.1", ">=1.0.0"));
+        assert!(satisfies("1.0.0-rc.1+build", "<1.0.0"));
+    }
+
+    #[test]
+    fn test_get_latest_semver() {
+        let versions: Vec<String> = ["1.10.0", "2.0.0-rc.1", "1.9.0"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        // 按行序取最后一个，按版本号取最高者
+        assert_eq!(get_latest(&versions), Some("1.9.0"));
+        assert_eq!(get_latest_semver(&versions), Some("2.0.0-rc.1"));
+        assert_eq!(get_latest(&[]), None);
+        assert_eq!(get_latest_semver(&[]), None);
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("1.0.0+b", "=1.0.0+a"));
+        assert!(matches("1.2.3+build", "^1.2.0"));
+        assert!(!matches("2.0.0", "^1.2.0"));
+        assert!(matches("0.2.5", "^0.2.1"));
+        assert!(!matches("0.3.0", "^0.2.1"));
+        assert!(matches("1.2.9", "~1.2.3"));
+        assert!(!matches("1.3.0", "~1.2.3"));
+        assert!(matches("1.5.0", ">=1.0.0, <2.0.0"));
+        assert!(!matches("2.0.0", ">=1.0.0, <2.0.0"));
+        assert!(!matches("1.0.0-rc.1", ">=1.0.0"));
+        assert!(matches("3.1.4", "*"));
+        assert!(!matches("not-a-version", "*"));
+
+        // 省略低位的约束
+        assert!(matches("1.9.3", "^1.2"));
+        assert!(!matches("1.1.9", "^1.2"));
+        assert!(!matches("2.0.0", "^1.2"));
+        assert!(matches("0.2.9", "^0.2"));
+        assert!(!matches("0.3.0", "^0.2"));
+        assert!(matches("0.9.0", "^0"));
+        assert!(!matches("1.0.0", "^0"));
+        assert!(matches("1.2.7", "~1.2"));
+        assert!(!matches("1.3.0", "~1.2"));
+        assert!(matches("1.9.0", "~1"));
+        assert!(matches("1.2.5", "=1.2"));
+        assert!(matches("1.2.5", "1.2"));
+        assert!(!matches("1.3.0", "=1.2"));
+        assert!(matches("1.3.0", ">1.2"));
+        assert!(!matches("1.2.9", ">1.2"));
+        assert!(matches("1.2.9", "<=1.2"));
+        assert!(matches("1.5.0", ">=1.0,<2.0"));
+        assert!(!matches("2.0.0", ">=1.0,<2.0"));
+        assert!(!matches("1.0.0", "^1.2-rc"));
+        assert!(!matches("1.0.0", "^1.x"));
+    }
+
+    #[test]
+    fn test_preference_select() {
+        let versions = ["1.10.0", "1.2.0", "2.0.0", "1.9.0"];
+        let satisfying = |constraint| {
+            versions
+                .into_iter()
+                .filter(move |version| matches(version, constraint))
+        };
+        assert_eq!(
+            Preference::Newest.select(satisfying("^1.2.0")),
+            Some("1.10.0")
+        );
+        assert_eq!(
+            Preference::Lowest.select(satisfying("^1.2.0")),
+            Some("1.2.0")
+        );
+        assert_eq!(Preference::Newest.select(satisfying(">=3.0.0")), None);
+
+        // 优先级相同时最高者取靠后的一个，最低者取靠前的一个
+        let builds = ["1.0.0+a", "1.0.0+b"];
+        assert_eq!(Preference::Newest.select(builds), Some("1.0.0+b"));
+        assert_eq!(Preference::Lowest.select(builds), Some("1.0.0+a"));
+
+        let versions = ["1.2.0", "1.10.1", "1.2.9", "2.0.0"];
+        let newest = |constraint| {
+            Preference::Newest.select(
+                versions
+                    .into_iter()
+                    .filter(|version| matches(version, constraint)),
+            )
+        };
+        assert_eq!(newest("^1.2"), Some("1.10.1"));
+        assert_eq!(newest("~1.2.0"), Some("1.2.9"));
+        assert_eq!(newest(">=1.0, <2.0"), Some("1.10.1"));
+        assert_eq!(newest("^3"), None);
+
+        assert!(is_requirement(">=1.0.0, <2.0.0"));
+        assert!(is_requirement("*"));
+        assert!(!is_requirement("1.0.0"));
+        assert!(!is_requirement("latest"));
+    }
+
+    #[test]
+    fn test_select_satisfying() {
+        let versions: Vec<String> = ["1.10.0", "1.2.0", "2.0.0", "1.9.0"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            select_satisfying(&versions, "^1.2.0", Preference::Newest),
+            Some("1.10.0")
+        );
+        assert_eq!(
+            select_satisfying(&versions, "^1.2.0", Preference::Lowest),
+            Some("1.2.0")
+        );
+        assert_eq!(
+            select_satisfying(&versions, ">=3.0.0", Preference::Newest),
+            None
+        );
+    }
+
+    #[test]
+    fn test_max_matching() {
+        let versions: Vec<String> = ["1.2.0", "1.10.1", "1.2.9", "2.0.0"]
+            .iter()
+            .map(|version| version.to_string())
+            .collect();
+        assert_eq!(max_matching(&versions, "^1.2"), Some("1.10.1"));
+        assert_eq!(max_matching(&versions, "~1.2.0"), Some("1.2.9"));
+        assert_eq!(max_matching(&versions, ">=1.0, <2.0"), Some("1.10.1"));
+        assert_eq!(max_matching(&versions, "^3"), None);
+    }
+
+    #[test]
+    fn test_resolve_constraints() -> Result<()> {
+        let candidates: Vec<String> = ["1.0.0", "1.4.0", "1.6.0", "2.0.0"]
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        let constraint = |requester: &str, requirement: &str| Constraint {
+            requester: requester.to_string(),
+            requirement: requirement.to_string(),
+        };
+
+        // 取交集中的最高版本
+        let constraints = vec![
+            constraint("a:1.0.0", "^1.0.0"),
+            constraint("b:2.0.0", "<1.5.0"),
+        ];
+        assert_eq!(
+            resolve_constraints("lib", &constraints, &candidates)?,
+            "1.4.0"
+        );
+
+        // 无交集时报告请求方和约束
+        let constraints = vec![
+            constraint("a:1.0.0", "^1.0.0"),
+            constraint("b:2.0.0", ">=2.0.0"),
+        ];
+        let error = resolve_constraints("lib", &constraints, &candidates)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("a:1.0.0 要求 ^1.0.0"));
+        assert!(error.contains("b:2.0.0 要求 >=2.0.0"));
+
+        Ok(())
+    }
+}